@@ -0,0 +1,234 @@
+use std::cell::{Cell, RefCell};
+use std::fs::OpenOptions;
+use std::io::Write;
+
+use crate::graph::Graph;
+use crate::mac::MAC;
+use crate::pcap::{file_header, record};
+use crate::traffic::Transmission;
+
+
+// An interface sees every frame on its cable, a subinterface only those of its VLAN
+#[derive(Clone, PartialEq)]
+pub enum CapturePoint {
+    Interface { mac: MAC, port: MAC, vlan: Option<u16> },
+    Link(MAC, MAC)
+}
+
+impl CapturePoint {
+    pub fn interface(graph: &Graph, mac: MAC) -> Result<Self, CaptureError> {
+        if let Some((router, subinterface)) = graph.search_subinterface_with_mac(mac.clone()) {
+            let port = router.nic(&subinterface.parent).mac.clone();
+            return Ok(CapturePoint::Interface { mac, port, vlan: Some(subinterface.vlan) });
+        }
+        match graph.nic_with_mac(mac.clone()) {
+            Some(_) => Ok(CapturePoint::Interface { port: mac.clone(), mac, vlan: None }),
+            None => Err(CaptureError::NotAnInterface(mac)),
+        }
+    }
+
+    pub fn link(graph: &Graph, mac1: MAC, mac2: MAC) -> Result<Self, CaptureError> {
+        match graph.are_connected(mac1.clone(), mac2.clone()) {
+            true => Ok(CapturePoint::Link(mac1, mac2)),
+            false => Err(CaptureError::NotALink(mac1, mac2)),
+        }
+    }
+
+    fn sees(&self, transmission: &Transmission) -> bool {
+        let ends = |a: &MAC, b: &MAC| (transmission.from == *a && transmission.to == *b) || (transmission.from == *b && transmission.to == *a);
+        match self {
+            CapturePoint::Interface { port, vlan, .. } => {
+                (transmission.from == *port || transmission.to == *port) && (vlan.is_none() || transmission.tag == *vlan)
+            },
+            CapturePoint::Link(a, b) => ends(a, b),
+        }
+    }
+}
+
+pub enum CaptureError {
+    NotAnInterface(MAC),
+    NotALink(MAC, MAC),
+    AlreadyCapturing(String),
+    NotCapturing(String),
+    WriteError(String, String)
+}
+
+impl std::fmt::Display for CaptureError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            CaptureError::NotAnInterface(mac) => write!(f, "{} is not an interface", mac),
+            CaptureError::NotALink(mac1, mac2) => write!(f, "{} and {} are not linked", mac1, mac2),
+            CaptureError::AlreadyCapturing(file) => write!(f, "A capture is already written to {}", file),
+            CaptureError::NotCapturing(file) => write!(f, "No capture is written to {}", file),
+            CaptureError::WriteError(file, e) => write!(f, "Error writing {}: {}", file, e),
+        }
+    }
+}
+
+#[derive(Clone)]
+pub struct Capture {
+    pub point: CapturePoint,
+    pub file: String
+}
+
+// Frames are appended to the files as they are sent, so that a capture can be read while it runs.
+// A capture that fails to write is kept with its error, and no longer written, until stopped
+#[derive(Clone)]
+pub struct Captures {
+    captures: Vec<Capture>,
+    last: Cell<u64>,
+    failures: RefCell<Vec<(String, String)>>
+}
+
+impl Default for Captures {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Captures {
+    pub fn new() -> Self {
+        Captures { captures: Vec::new(), last: Cell::new(0), failures: RefCell::new(Vec::new()) }
+    }
+
+    pub fn failure(&self, file: &str) -> Option<String> {
+        self.failures.borrow().iter().find(|(f, _)| f == file).map(|(_, e)| e.clone())
+    }
+
+    pub fn list(&self) -> &Vec<Capture> {
+        &self.captures
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.captures.is_empty()
+    }
+
+    pub fn start(&mut self, point: CapturePoint, file: String) -> Result<(), CaptureError> {
+        if self.captures.iter().any(|c| c.file == file) {
+            return Err(CaptureError::AlreadyCapturing(file));
+        }
+        std::fs::write(&file, file_header()).map_err(|e| CaptureError::WriteError(file.clone(), e.to_string()))?;
+        self.captures.push(Capture { point, file });
+        Ok(())
+    }
+
+    pub fn stop(&mut self, file: &str) -> Result<(), CaptureError> {
+        match self.captures.iter().position(|c| c.file == file) {
+            Some(idx) => {
+                self.captures.remove(idx);
+                self.failures.borrow_mut().retain(|(f, _)| f != file);
+                Ok(())
+            },
+            None => Err(CaptureError::NotCapturing(file.to_string()))
+        }
+    }
+
+    // Start in microseconds of an exchange at `time` in milliseconds: exchanges happening at the
+    // same simulated time follow each other, so that timestamps never go back
+    pub fn stamp(&self, time: u64, transmissions: &[Transmission]) -> u64 {
+        let start = (time * 1000).max(self.last.get());
+        if let Some(end) = transmissions.iter().map(|t| start + t.offset).max() {
+            self.last.set(end + 1);
+        }
+        start
+    }
+
+    // `start` in microseconds of simulated time, the transmissions being offset from it
+    pub fn record(&self, start: u64, transmissions: &[Transmission]) {
+        for capture in self.captures.iter().filter(|c| self.failure(&c.file).is_none()) {
+            let mut bytes: Vec<u8> = Vec::new();
+            for transmission in transmissions.iter().filter(|t| capture.point.sees(t)) {
+                bytes.extend(record(start + transmission.offset, &transmission.frame.encode(transmission.tag)));
+            }
+            if bytes.is_empty() {
+                continue;
+            }
+            let written = OpenOptions::new().append(true).open(&capture.file).and_then(|mut file| file.write_all(&bytes));
+            if let Err(e) = written {
+                let error = CaptureError::WriteError(capture.file.clone(), e.to_string());
+                self.failures.borrow_mut().push((capture.file.clone(), error.to_string()));
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::commands::ping;
+    use crate::load::{DataFormat, parse_data};
+    use crate::packet::Frame;
+
+    const OFFICE: &str = "
+routers:
+  - name: R1
+    lan: {ip: 192.168.1.1, netmask: 24}
+    wan: {ip: 10.0.0.1, netmask: 30}
+devices:
+  - {name: PC1, ip: 192.168.1.10, netmask: 24}
+  - {name: PC2, ip: 192.168.1.11, netmask: 24}
+switches:
+  - name: SW1
+connections:
+  - {from: R1.lan, to: SW1}
+  - {from: PC1, to: SW1}
+  - {from: PC2, to: SW1}
+";
+
+    fn office() -> Graph {
+        let mut graph = Graph::new();
+        let report = graph.load_data(parse_data("test", OFFICE, DataFormat::Yaml).unwrap_or_else(|e| panic!("{}", e)));
+        assert!(report.rejected().is_empty());
+        graph
+    }
+
+    fn mac(graph: &Graph, name: &str) -> MAC {
+        graph.resolve(name).unwrap_or_else(|e| panic!("{}", e))
+    }
+
+    // The captured length of every record of a pcap file
+    fn records(bytes: &[u8]) -> Vec<u32> {
+        let mut lengths: Vec<u32> = Vec::new();
+        let mut idx = 24;
+        while idx < bytes.len() {
+            let length = u32::from_le_bytes([bytes[idx + 8], bytes[idx + 9], bytes[idx + 10], bytes[idx + 11]]);
+            lengths.push(length);
+            idx += 16 + length as usize;
+        }
+        lengths
+    }
+
+    #[test]
+    fn stamps_never_go_back() {
+        let captures = Captures::new();
+        let frame = Frame::new(MAC::EUI48(2, 0, 0, 0, 0, 2), MAC::EUI48(2, 0, 0, 0, 0, 1), 0x0800, Vec::new());
+        let transmissions = [Transmission { from: MAC::EUI48(2, 0, 0, 0, 0, 1), to: MAC::EUI48(2, 0, 0, 0, 0, 2), offset: 50, tag: None, frame }];
+        assert_eq!(captures.stamp(1, &transmissions), 1000);
+        assert_eq!(captures.stamp(1, &transmissions), 1051);
+        assert_eq!(captures.stamp(2, &transmissions), 2000);
+    }
+
+    #[test]
+    fn points_only_see_their_cable() {
+        let mut graph = office();
+        let dir = std::env::temp_dir().join(format!("network-simulator-capture-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap_or_else(|e| panic!("{}", e));
+        let (on_pc2, on_router) = (dir.join("pc2.pcap").display().to_string(), dir.join("r1.pcap").display().to_string());
+        let pc2 = CapturePoint::interface(&graph, mac(&graph, "PC2")).unwrap_or_else(|e| panic!("{}", e));
+        let uplink = CapturePoint::link(&graph, mac(&graph, "SW1"), mac(&graph, "R1.lan")).unwrap_or_else(|e| panic!("{}", e));
+        graph.captures.start(pc2.clone(), on_pc2.clone()).unwrap_or_else(|e| panic!("{}", e));
+        graph.captures.start(uplink, on_router.clone()).unwrap_or_else(|e| panic!("{}", e));
+        assert!(matches!(graph.captures.start(pc2, on_pc2.clone()), Err(CaptureError::AlreadyCapturing(_))));
+        let nic = |name: &str| graph.nic_with_mac(mac(&graph, name)).unwrap_or_else(|| panic!("no {}", name));
+        ping(&graph, nic("PC1"), nic("PC2").ip);
+        let (pc2_bytes, router_bytes) = (std::fs::read(&on_pc2), std::fs::read(&on_router));
+        std::fs::remove_dir_all(&dir).unwrap_or_else(|e| panic!("{}", e));
+        let (pc2_bytes, router_bytes) = (pc2_bytes.unwrap_or_else(|e| panic!("{}", e)), router_bytes.unwrap_or_else(|e| panic!("{}", e)));
+        assert_eq!(pc2_bytes[..24], file_header());
+        // ARP request and reply, echo request and reply
+        assert_eq!(records(&pc2_bytes), [60, 60, 74, 74]);
+        // Only the flooded request reaches the router
+        assert_eq!(records(&router_bytes), [60]);
+        assert!(CapturePoint::link(&graph, mac(&graph, "PC1"), mac(&graph, "PC2")).is_err());
+    }
+}
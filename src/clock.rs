@@ -0,0 +1,317 @@
+use serde::{Deserialize, Serialize};
+
+use crate::commands::ping;
+use crate::dns;
+use crate::graph::Graph;
+use crate::state::Element;
+use crate::traffic;
+
+
+// Durations are written "500ms", "10s", "2m" or "1h", a bare number being seconds
+pub fn parse_duration(s: &str) -> Option<u64> {
+    let (value, unit) = match s.find(|c: char| !c.is_ascii_digit()) {
+        Some(idx) => s.split_at(idx),
+        None => (s, "s"),
+    };
+    let factor = match unit {
+        "ms" => 1,
+        "s" => 1000,
+        "m" => 60 * 1000,
+        "h" => 60 * 60 * 1000,
+        _ => return None,
+    };
+    value.parse::<u64>().ok()?.checked_mul(factor)
+}
+
+pub fn format_time(ms: u64) -> String {
+    format!("{}.{:03}s", ms / 1000, ms % 1000)
+}
+
+// Nodes and links are designated by name and only resolved when the action runs
+#[derive(Clone, Serialize, Deserialize)]
+#[serde(tag = "action", rename_all = "snake_case")]
+pub enum Action {
+    FailLink { from: String, to: String },
+    RepairLink { from: String, to: String },
+    FailNode { node: String },
+    RepairNode { node: String },
+    ExpireLease { node: String },
+    Ping { from: String, to: String }
+}
+
+impl std::fmt::Display for Action {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Action::FailLink { from, to } => write!(f, "fail link {} <-> {}", from, to),
+            Action::RepairLink { from, to } => write!(f, "repair link {} <-> {}", from, to),
+            Action::FailNode { node } => write!(f, "fail node {}", node),
+            Action::RepairNode { node } => write!(f, "repair node {}", node),
+            Action::ExpireLease { node } => write!(f, "expire lease of {}", node),
+            Action::Ping { from, to } => write!(f, "ping {} from {}", to, from),
+        }
+    }
+}
+
+impl Action {
+    fn run(&self, graph: &mut Graph) -> Result<String, String> {
+        let link = |graph: &Graph, from: &str, to: &str| -> Result<Element, String> {
+            let a = graph.resolve(from).map_err(|e| e.to_string())?;
+            let b = graph.resolve(to).map_err(|e| e.to_string())?;
+            Ok(Element::Link(a, b))
+        };
+        let node = |graph: &Graph, node: &str| graph.resolve_node(node).map(Element::Node).map_err(|e| e.to_string());
+        match self {
+            Action::FailLink { from, to } | Action::RepairLink { from, to } => {
+                let failed = matches!(self, Action::FailLink { .. });
+                graph.set_failed(link(graph, from, to)?, failed).map_err(|e| e.to_string())?;
+                Ok(format!("link {} <-> {} {}", from, to, if failed { "failed" } else { "repaired" }))
+            },
+            Action::FailNode { node: name } | Action::RepairNode { node: name } => {
+                let failed = matches!(self, Action::FailNode { .. });
+                graph.set_failed(node(graph, name)?, failed).map_err(|e| e.to_string())?;
+                Ok(format!("node {} {}", name, if failed { "failed" } else { "repaired" }))
+            },
+            Action::ExpireLease { node } => expire_lease(graph, node),
+            Action::Ping { from, to } => {
+                let mac = graph.resolve(from).map_err(|e| e.to_string())?;
+                let ip = dns::resolve(graph, &mac, to).map_err(|e| e.to_string())?;
+                let nic = graph.nic_with_mac(mac).ok_or(format!("{} has no interface to ping from", from))?;
+                Ok(format!("ping {} from {}: {}", to, from, ping(graph, nic, ip)))
+            },
+        }
+    }
+}
+
+// Devices hold their address as a lease: on expiry it is released, then requested again,
+// which also lets a device without an address retry once the network is back
+fn expire_lease(graph: &mut Graph, name: &str) -> Result<String, String> {
+    let device = graph.devices.iter().find(|d| d.name == name).ok_or(format!("Unknown device {}", name))?;
+    let expired = if device.nic.is_localhost() { format!("{} had no lease", name) } else { format!("lease of {} expired", name) };
+    let mut nic = device.nic.clone();
+    nic.set_localhost();
+    graph.update_nic(nic.mac.clone(), nic.clone()).map_err(|e| e.to_string())?;
+    let start = nic.clone();
+    match graph.breadth_first_search_and_dhcp_connection(&mut nic, &start) {
+        Ok((_, name_server)) => {
+            graph.update_nic(nic.mac.clone(), nic.clone()).map_err(|e| e.to_string())?;
+            graph.lease_name_server(&nic.mac, name_server);
+            traffic::observe(graph, |graph| traffic::dhcp(graph, &nic.mac, &nic.ip));
+            Ok(format!("{}, renewed {}/{}", expired, nic.ip.to_ddn(), nic.netmask_u8()))
+        },
+        Err(e) => Ok(format!("{}, not renewed: {}", expired, e)),
+    }
+}
+
+#[derive(Clone, Serialize, Deserialize)]
+pub struct ScheduledAction {
+    pub id: u32,
+    pub at: u64,
+    pub event: Action
+}
+
+#[derive(Clone, Serialize, Deserialize)]
+pub struct TimelineEntry {
+    pub at: u64,
+    pub event: String
+}
+
+impl std::fmt::Display for TimelineEntry {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "[{:>11}] {}", format_time(self.at), self.event)
+    }
+}
+
+// Simulated time in milliseconds, only moving when advanced
+#[derive(Clone, Serialize, Deserialize)]
+pub struct Clock {
+    now: u64,
+    next_id: u32,
+    scheduled: Vec<ScheduledAction>,
+    timeline: Vec<TimelineEntry>
+}
+
+impl std::fmt::Display for Clock {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", format_time(self.now))
+    }
+}
+
+impl Default for Clock {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Clock {
+    pub fn new() -> Self {
+        Clock { now: 0, next_id: 1, scheduled: Vec::new(), timeline: Vec::new() }
+    }
+
+    pub fn now(&self) -> u64 {
+        self.now
+    }
+
+    pub fn scheduled(&self) -> &Vec<ScheduledAction> {
+        &self.scheduled
+    }
+
+    pub fn timeline(&self) -> &Vec<TimelineEntry> {
+        &self.timeline
+    }
+
+    pub fn log(&mut self, event: String) {
+        self.timeline.push(TimelineEntry { at: self.now, event });
+    }
+
+    pub fn schedule(&mut self, delay: u64, event: Action) -> u32 {
+        let id = self.next_id;
+        self.next_id += 1;
+        self.log(format!("scheduled #{} {} at {}", id, event, format_time(self.now + delay)));
+        self.scheduled.push(ScheduledAction { id, at: self.now + delay, event });
+        id
+    }
+
+    pub fn cancel(&mut self, id: u32) -> bool {
+        match self.scheduled.iter().position(|s| s.id == id) {
+            Some(idx) => {
+                let cancelled = self.scheduled.remove(idx);
+                self.log(format!("cancelled #{} {}", id, cancelled.event));
+                true
+            },
+            None => false
+        }
+    }
+
+    // Earliest first, in the order they were scheduled on a tie
+    fn next_due(&mut self, until: u64) -> Option<ScheduledAction> {
+        let idx = self.scheduled.iter().enumerate()
+            .filter(|(_, s)| s.at <= until)
+            .min_by_key(|(_, s)| (s.at, s.id))
+            .map(|(idx, _)| idx)?;
+        Some(self.scheduled.remove(idx))
+    }
+}
+
+// Runs an action now, its outcome being written in the timeline
+pub fn execute(graph: &mut Graph, action: &Action) -> Result<String, String> {
+    let result = action.run(graph);
+    match &result {
+        Ok(outcome) => graph.clock.log(outcome.clone()),
+        Err(e) => graph.clock.log(format!("{}: {}", action, e)),
+    }
+    result
+}
+
+// Moves the clock forward, running the actions that fall due on the way at their own time
+pub fn advance(graph: &mut Graph, duration: u64) -> Vec<TimelineEntry> {
+    let first = graph.clock.timeline.len();
+    let until = graph.clock.now + duration;
+    while let Some(due) = graph.clock.next_due(until) {
+        graph.clock.now = due.at;
+        let _ = execute(graph, &due.event);
+    }
+    graph.clock.now = until;
+    graph.clock.timeline[first..].to_vec()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::load::{DataFormat, parse_data};
+
+    const OFFICE: &str = "
+routers:
+  - name: R1
+    lan: {ip: 192.168.1.1, netmask: 24}
+    wan: {ip: 10.0.0.1, netmask: 30}
+devices:
+  - {name: PC1, ip: 192.168.1.10, netmask: 24}
+switches:
+  - name: SW1
+connections:
+  - {from: R1.lan, to: SW1}
+  - {from: PC1, to: SW1}
+";
+
+    fn office() -> Graph {
+        let mut graph = Graph::new();
+        let report = graph.load_data(parse_data("test", OFFICE, DataFormat::Yaml).unwrap_or_else(|e| panic!("{}", e)));
+        assert!(report.rejected().is_empty());
+        graph
+    }
+
+    fn link(graph: &Graph) -> Element {
+        let mac = |name: &str| graph.resolve_node(name).unwrap_or_else(|e| panic!("{}", e));
+        Element::Link(mac("PC1"), mac("SW1"))
+    }
+
+    fn fail() -> Action {
+        Action::FailLink { from: "PC1".to_string(), to: "SW1".to_string() }
+    }
+
+    fn repair() -> Action {
+        Action::RepairLink { from: "PC1".to_string(), to: "SW1".to_string() }
+    }
+
+    #[test]
+    fn durations_take_a_unit() {
+        assert_eq!(parse_duration("500ms"), Some(500));
+        assert_eq!(parse_duration("10s"), Some(10_000));
+        assert_eq!(parse_duration("10"), Some(10_000));
+        assert_eq!(parse_duration("2m"), Some(120_000));
+        assert_eq!(parse_duration("1h"), Some(3_600_000));
+        for invalid in ["", "s", "1d", "1.5s", "-1s", "99999999999999999999h"] {
+            assert_eq!(parse_duration(invalid), None, "{}", invalid);
+        }
+    }
+
+    #[test]
+    fn times_are_printed_in_seconds() {
+        assert_eq!(format_time(0), "0.000s");
+        assert_eq!(format_time(61_005), "61.005s");
+    }
+
+    #[test]
+    fn actions_run_when_they_fall_due() {
+        let mut graph = office();
+        graph.clock.schedule(5000, repair());
+        graph.clock.schedule(2000, fail());
+        advance(&mut graph, 1000);
+        assert!(!graph.is_failed(&link(&graph)));
+        let ran = advance(&mut graph, 1000);
+        assert!(graph.is_failed(&link(&graph)));
+        assert!(ran.len() == 1 && ran[0].at == 2000 && ran[0].event == "link PC1 <-> SW1 failed");
+        let ran = advance(&mut graph, 10_000);
+        assert!(!graph.is_failed(&link(&graph)));
+        assert!(ran.len() == 1 && ran[0].at == 5000);
+        assert!(graph.clock.now() == 12_000 && graph.clock.scheduled().is_empty());
+    }
+
+    #[test]
+    fn ties_run_in_scheduling_order() {
+        let mut graph = office();
+        graph.clock.schedule(1000, fail());
+        graph.clock.schedule(1000, repair());
+        let ran = advance(&mut graph, 1000);
+        assert_eq!(ran.iter().map(|e| e.event.as_str()).collect::<Vec<&str>>(), ["link PC1 <-> SW1 failed", "link PC1 <-> SW1 repaired"]);
+        assert!(!graph.is_failed(&link(&graph)));
+    }
+
+    #[test]
+    fn cancelled_actions_never_run() {
+        let mut graph = office();
+        let id = graph.clock.schedule(1000, fail());
+        assert!(graph.clock.cancel(id));
+        assert!(!graph.clock.cancel(id));
+        assert!(advance(&mut graph, 1000).is_empty());
+        assert!(!graph.is_failed(&link(&graph)));
+    }
+
+    #[test]
+    fn failing_actions_are_logged() {
+        let mut graph = office();
+        let missing = Action::FailNode { node: "PC9".to_string() };
+        assert!(execute(&mut graph, &missing).is_err());
+        assert!(graph.clock.timeline().last().is_some_and(|e| e.event.starts_with("fail node PC9: ")));
+    }
+}
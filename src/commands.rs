@@ -1,6 +1,7 @@
 use crate::graph::Graph;
 use crate::ip::IP;
 use crate::nic::NIC;
+use crate::traffic;
 
 
 pub enum PingStatus {
@@ -18,8 +19,28 @@ impl std::fmt::Display for PingStatus {
 }
 
 pub fn ping(graph: &Graph, src: NIC, dest: IP) -> PingStatus {
+    traffic::observe(graph, |graph| traffic::ping(graph, &src, &dest));
     if !graph.breadth_first_search_ip(src.mac.clone(), dest.clone()) {
         return PingStatus::Timeout;
     }
     PingStatus::Success
 }
+
+pub fn traceroute(graph: &Graph, src: NIC, dest: IP) -> Option<Vec<IP>> {
+    traffic::observe(graph, |graph| traffic::traceroute(graph, &src, &dest));
+    let path = graph.path_to_ip(src.mac.clone(), dest.clone())?;
+    let mut hops: Vec<IP> = Vec::new();
+    for i in 1..path.len() {
+        // A router answers with the interface the probe entered through, unless it is the destination itself
+        if let (Some(current), Some(next)) = (graph.router_with_mac(&path[i]), path.get(i + 1)) {
+            let transit = current.has_mac(next);
+            let entered = !current.has_mac(&path[i - 1]);
+            if transit && entered && i + 1 < path.len() - 1
+                && let Some(nic) = graph.nic_with_mac(path[i].clone()) {
+                hops.push(nic.ip);
+            }
+        }
+    }
+    hops.push(dest);
+    Some(hops)
+}
@@ -1,12 +1,18 @@
-use crate::graph::{Graph, GraphError};
+use crate::dns::Record;
+use crate::graph::{Graph, GraphError, NodeType};
 use crate::ip::IP;
+use crate::mac::MAC;
 use crate::nic::NIC;
+use crate::service::Service;
 
 
 #[derive(Clone)]
 pub struct Device {
     pub name: String,
-    pub nic: NIC
+    pub nic: NIC,
+    pub name_server: Option<IP>,
+    pub zone: Option<Vec<Record>>,
+    pub services: Vec<Service>
 }
 
 impl std::fmt::Display for Device {
@@ -16,18 +22,22 @@ impl std::fmt::Display for Device {
 }
 
 impl Device {
-    fn new(name: String) -> Self {
+    fn new(name: String, mac: MAC) -> Self {
         Self {
             name: name.to_string(),
-            nic: NIC::new(
+            nic: NIC::with_mac(
                 IP::V4(127, 0, 0, 1),
                 IP::V4(255, 0, 0, 0),
-            )
+                mac,
+            ),
+            name_server: None,
+            zone: None,
+            services: Vec::new()
         }
     }
 }
 
 pub fn create_device(name: String, graph: &mut Graph) -> Result<(), GraphError> {
-    let device = Device::new(name);
+    let device = Device::new(name, graph.new_mac(&NodeType::Device));
     graph.append_device(device)
 }
@@ -7,6 +7,8 @@ pub struct DHCP {
     pub netmask: IP,
     pub first_ip: IP,
     pub last_ip: IP,
+    // Handed to the clients with their lease
+    pub name_server: Option<IP>
 }
 
 pub enum DhcpError {
@@ -42,13 +44,15 @@ impl DHCP {
             return Err(DhcpError::NotEnoughAddressableIPs);
         }
         // Check 2 : The start and end IP addresses must be in the same network as the NIC
-        let nic_start: NIC = NIC::new(
+        let nic_start: NIC = NIC::with_mac(
             first_ip.clone(),
             nic.netmask.clone(),
+            nic.mac.clone(),
         );
-        let nic_end: NIC = NIC::new(
+        let nic_end: NIC = NIC::with_mac(
             last_ip.clone(),
             nic.netmask.clone(),
+            nic.mac.clone(),
         );
         if ! nic.same_network(nic_start) || ! nic.same_network(nic_end) {
             return Err(DhcpError::IPsNotInSameNetwork);
@@ -61,7 +65,8 @@ impl DHCP {
         Ok (DHCP {
             netmask: nic.netmask.clone(),
             last_ip: last_ip.clone(),
-            first_ip: first_ip.clone()
+            first_ip: first_ip.clone(),
+            name_server: None
         })
     }
 }
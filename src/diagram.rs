@@ -0,0 +1,260 @@
+use crate::colors::Colors;
+use crate::graph::Graph;
+use crate::mac::MAC;
+
+
+// Text with its printable length, colors excluded
+struct Cell {
+    text: String,
+    width: usize
+}
+
+impl Cell {
+    fn new() -> Self {
+        Cell { text: String::new(), width: 0 }
+    }
+
+    fn push(&mut self, s: &str, color: &str) {
+        if !self.text.is_empty() {
+            self.text.push_str("  ");
+            self.width += 2;
+        }
+        self.text.push_str(&format!("{}{}{}", color, s, Colors::RESET));
+        self.width += s.chars().count();
+    }
+}
+
+fn node_cell(graph: &Graph, mac: &MAC) -> Cell {
+    let mut cell = Cell::new();
+    let name = graph.name_with_mac(mac.clone()).unwrap_or(mac.to_hex());
+    cell.push(&name, Colors::BOLD);
+    if let Some(nic) = graph.nic_with_mac(mac.clone()) {
+        cell.push(&format!("{}/{}", nic.ip.to_ddn(), nic.netmask_u8()), Colors::BRIGHT_BLUE);
+        cell.push(&nic.mac.to_hex(), Colors::DIM);
+    } else {
+        cell.push("switch", Colors::BRIGHT_BLUE);
+        cell.push(&mac.to_hex(), Colors::DIM);
+    }
+    cell
+}
+
+fn is_router(graph: &Graph, mac: &MAC) -> bool {
+    graph.search_router_with_mac(mac.clone()).is_some()
+}
+
+fn internal_link(graph: &Graph, mac1: &MAC, mac2: &MAC) -> bool {
+    match graph.search_router_with_mac(mac1.clone()) {
+        Some((router, _)) => router.nic_lan.mac == *mac2 || router.nic_wan.mac == *mac2,
+        None => false,
+    }
+}
+
+fn external_neighbors(graph: &Graph, mac: &MAC) -> Vec<MAC> {
+    graph.neighbors(mac.clone()).into_iter().filter(|n| !internal_link(graph, mac, n)).collect()
+}
+
+fn render_box(graph: &Graph, mac: &MAC, attached: bool, has_children: bool) -> Vec<String> {
+    let cell = node_cell(graph, mac);
+    let color = if is_router(graph, mac) {
+        Colors::CYAN
+    } else if graph.search_switch_with_mac(mac.clone()).is_some() {
+        Colors::MAGENTA
+    } else {
+        Colors::GREEN
+    };
+    let width = cell.width + 2;
+    let left = if attached { "┤" } else { "│" };
+    let bottom = if has_children {
+        format!("└─┬{}┘", "─".repeat(width - 2))
+    } else {
+        format!("└{}┘", "─".repeat(width))
+    };
+    vec![
+        format!("{}┌{}┐{}", color, "─".repeat(width), Colors::RESET),
+        format!("{}{}{} {} {}│{}", color, left, Colors::RESET, cell.text, color, Colors::RESET),
+        format!("{}{}{}", color, bottom, Colors::RESET),
+    ]
+}
+
+// Expects `mac` to be already marked as visited
+fn render_tree(graph: &Graph, mac: &MAC, attached: bool, visited: &mut Vec<MAC>) -> Vec<String> {
+    let children: Vec<MAC> = external_neighbors(graph, mac).into_iter().filter(|n| !visited.contains(n)).collect();
+    // Claim the children first so that loops are drawn once
+    visited.extend(children.iter().cloned());
+    let mut lines = render_box(graph, mac, attached, !children.is_empty());
+    for (i, child) in children.iter().enumerate() {
+        let last = i == children.len() - 1;
+        for (j, line) in render_tree(graph, child, true, visited).into_iter().enumerate() {
+            let prefix = match (j, last) {
+                (1, false) => "  ├──",
+                (1, true) => "  └──",
+                (0, _) | (_, false) => "  │  ",
+                (_, true) => "     ",
+            };
+            lines.push(format!("{}{}", prefix, line));
+        }
+    }
+    lines
+}
+
+// Links between routers interfaces and devices, grouped by segment
+fn segments(graph: &Graph) -> Vec<Vec<MAC>> {
+    let mut macs: Vec<MAC> = Vec::new();
+    for router in &graph.routers {
+        macs.push(router.nic_lan.mac.clone());
+        macs.push(router.nic_wan.mac.clone());
+    }
+    macs.extend(graph.devices.iter().map(|d| d.nic.mac.clone()));
+    macs.extend(graph.switches.iter().map(|s| s.mac.clone()));
+
+    let mut segments: Vec<Vec<MAC>> = Vec::new();
+    let mut seen: Vec<MAC> = Vec::new();
+    for mac in macs {
+        if seen.contains(&mac) || external_neighbors(graph, &mac).is_empty() {
+            continue;
+        }
+        let mut segment: Vec<MAC> = vec![mac.clone()];
+        let mut i = 0;
+        while i < segment.len() {
+            for neighbor in external_neighbors(graph, &segment[i]) {
+                if !segment.contains(&neighbor) {
+                    segment.push(neighbor);
+                }
+            }
+            i += 1;
+        }
+        seen.extend(segment.iter().cloned());
+        segments.push(segment);
+    }
+    segments
+}
+
+fn segment_title(graph: &Graph, segment: &[MAC]) -> String {
+    for mac in segment {
+        if let Some(nic) = graph.nic_with_mac(mac.clone()) && !nic.is_localhost() {
+            return format!("{}/{}", nic.network_address().to_ddn(), nic.netmask_u8());
+        }
+    }
+    "unaddressed".to_string()
+}
+
+pub fn render(graph: &Graph) -> String {
+    let mut out: Vec<String> = Vec::new();
+    for segment in segments(graph) {
+        let title = segment_title(graph, &segment);
+        out.push(format!("{}═══ {} {}{}", Colors::YELLOW, title, "═".repeat(40usize.saturating_sub(title.len())), Colors::RESET));
+        // Gateways are drawn on top of their segment
+        let root = segment.iter().find(|m| is_router(graph, m)).unwrap_or(&segment[0]).clone();
+        let mut visited: Vec<MAC> = vec![root.clone()];
+        out.extend(render_tree(graph, &root, false, &mut visited));
+        out.push(String::new());
+    }
+
+    let isolated: Vec<MAC> = graph.devices.iter().map(|d| d.nic.mac.clone())
+        .chain(graph.switches.iter().map(|s| s.mac.clone()))
+        .filter(|mac| external_neighbors(graph, mac).is_empty())
+        .collect();
+    if !isolated.is_empty() {
+        out.push(format!("{}═══ not connected {}{}", Colors::YELLOW, "═".repeat(27), Colors::RESET));
+        for mac in &isolated {
+            out.extend(render_box(graph, mac, false, false));
+        }
+        out.push(String::new());
+    }
+
+    if !graph.routers.is_empty() {
+        out.push(format!("{}═══ routers {}{}", Colors::YELLOW, "═".repeat(33), Colors::RESET));
+        for router in &graph.routers {
+            out.push(format!("{}{}{}  lan {}/{} {}⇄{} wan {}/{}",
+                Colors::BOLD, router.name, Colors::RESET,
+                router.nic_lan.network_address().to_ddn(), router.nic_lan.netmask_u8(),
+                Colors::CYAN, Colors::RESET,
+                router.nic_wan.network_address().to_ddn(), router.nic_wan.netmask_u8()));
+            for subinterface in &router.subinterfaces {
+                out.push(format!("  {}{}.{}{} {}/{}",
+                    Colors::CYAN, subinterface.parent.to_string().to_lowercase(), subinterface.vlan, Colors::RESET,
+                    subinterface.nic.network_address().to_ddn(), subinterface.nic.netmask_u8()));
+            }
+        }
+    }
+    out.join("\n")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::load::{DataFormat, parse_data};
+
+    const OFFICE: &str = "
+routers:
+  - name: R1
+    lan: {ip: 192.168.1.1, netmask: 24, mac: '02:00:00:00:00:01'}
+    wan: {ip: 10.0.0.1, netmask: 30, mac: '02:00:00:00:00:02'}
+devices:
+  - {name: PC1, ip: 192.168.1.10, netmask: 24, mac: '02:00:00:00:00:10'}
+  - {name: PC2, ip: 192.168.1.11, netmask: 24, mac: '02:00:00:00:00:11'}
+  - {name: PC3, mac: '02:00:00:00:00:12'}
+switches:
+  - {name: SW1, mac: '02:00:00:00:00:20'}
+connections:
+  - {from: R1.lan, to: SW1}
+  - {from: PC1, to: SW1}
+  - {from: PC2, to: SW1}
+";
+
+    // The diagram as printed without colors
+    fn plain(content: &str) -> String {
+        let mut graph = Graph::new();
+        let report = graph.load_data(parse_data("test", content, DataFormat::Yaml).unwrap_or_else(|e| panic!("{}", e)));
+        assert!(report.rejected().is_empty());
+        let mut out = String::new();
+        let mut escape = false;
+        for c in render(&graph).chars() {
+            match c {
+                '\x1b' => escape = true,
+                'm' if escape => escape = false,
+                c if !escape => out.push(c),
+                _ => {},
+            }
+        }
+        out
+    }
+
+    #[test]
+    fn segments_are_drawn_from_their_gateway() {
+        let expected = [
+            "═══ 192.168.1.0/24 ══════════════════════════",
+            "┌───────────────────────────────────────────┐",
+            "│ R1.lan  192.168.1.1/24  02:00:00:00:00:01 │",
+            "└─┬─────────────────────────────────────────┘",
+            "  │  ┌────────────────────────────────┐",
+            "  └──┤ SW1  switch  02:00:00:00:00:20 │",
+            "     └─┬──────────────────────────────┘",
+            "       │  ┌─────────────────────────────────────────┐",
+            "       ├──┤ PC1  192.168.1.10/24  02:00:00:00:00:10 │",
+            "       │  └─────────────────────────────────────────┘",
+            "       │  ┌─────────────────────────────────────────┐",
+            "       └──┤ PC2  192.168.1.11/24  02:00:00:00:00:11 │",
+            "          └─────────────────────────────────────────┘",
+            "",
+            "═══ not connected ═══════════════════════════",
+            "┌─────────────────────────────────────┐",
+            "│ PC3  127.0.0.1/8  02:00:00:00:00:12 │",
+            "└─────────────────────────────────────┘",
+            "",
+            "═══ routers ═════════════════════════════════",
+            "R1  lan 192.168.1.0/24 ⇄ wan 10.0.0.0/30",
+        ];
+        assert_eq!(plain(OFFICE), expected.join("\n"));
+    }
+
+    #[test]
+    fn loops_are_drawn_once() {
+        let looped = OFFICE.replace("  - {from: PC2, to: SW1}\n", "  - {from: PC2, to: SW1}\n  - {from: SW1, to: SW2}\n  - {from: SW2, to: SW3}\n  - {from: SW3, to: SW1}\n")
+            .replace("switches:\n", "switches:\n  - {name: SW2}\n  - {name: SW3}\n");
+        let diagram = plain(&looped);
+        for name in ["SW1", "SW2", "SW3"] {
+            assert_eq!(diagram.matches(&format!(" {}  switch", name)).count(), 1, "{}", name);
+        }
+    }
+}
@@ -0,0 +1,427 @@
+use std::net::Ipv6Addr;
+
+use crate::graph::Graph;
+use crate::ip::IP;
+use crate::load::LoadedDnsRecord;
+use crate::mac::MAC;
+use crate::packet::{DNS_NOERROR, DNS_NXDOMAIN, DNS_PORT, DNS_TYPE_A, DNS_TYPE_AAAA, DNS_TYPE_CNAME, DNS_TYPE_PTR, Dns, DnsAnswer, PROTOCOL_UDP, checksum, dns_name, udp};
+use crate::traffic::{self, Packet, Payload};
+use crate::transport::{EPHEMERAL_PORTS, Transport};
+
+
+// A query left unanswered is sent once more after 5s, as resolvers do by default
+const QUERY_ATTEMPTS: u64 = 2;
+const QUERY_TIMEOUT: u64 = 5_000_000;
+
+// Longest chain of aliases followed by a server
+const MAX_CNAMES: usize = 8;
+
+#[derive(Clone, Copy, PartialEq)]
+pub enum RecordType {
+    A,
+    Aaaa,
+    Ptr,
+    Cname
+}
+
+impl std::fmt::Display for RecordType {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            RecordType::A => write!(f, "A"),
+            RecordType::Aaaa => write!(f, "AAAA"),
+            RecordType::Ptr => write!(f, "PTR"),
+            RecordType::Cname => write!(f, "CNAME"),
+        }
+    }
+}
+
+impl RecordType {
+    pub fn from_string(s: &str) -> Option<Self> {
+        match s.to_uppercase().as_str() {
+            "A" => Some(RecordType::A),
+            "AAAA" => Some(RecordType::Aaaa),
+            "PTR" => Some(RecordType::Ptr),
+            "CNAME" => Some(RecordType::Cname),
+            _ => None,
+        }
+    }
+
+    pub fn code(&self) -> u16 {
+        match self {
+            RecordType::A => DNS_TYPE_A,
+            RecordType::Aaaa => DNS_TYPE_AAAA,
+            RecordType::Ptr => DNS_TYPE_PTR,
+            RecordType::Cname => DNS_TYPE_CNAME,
+        }
+    }
+}
+
+#[derive(Clone, PartialEq)]
+pub enum RecordData {
+    A(IP),
+    Aaaa(Ipv6Addr),
+    Ptr(String),
+    Cname(String)
+}
+
+#[derive(Clone, PartialEq)]
+pub struct Record {
+    pub name: String,
+    pub data: RecordData
+}
+
+impl std::fmt::Display for Record {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{:<32} {:<5} {}", self.name, self.kind().to_string(), self.value())
+    }
+}
+
+impl Record {
+    pub fn new(name: &str, kind: RecordType, value: &str) -> Result<Self, DnsError> {
+        let invalid = || DnsError::InvalidRecord(format!("{} {} {}", name, kind, value));
+        if canonical(name).is_empty() {
+            return Err(invalid());
+        }
+        let data = match kind {
+            RecordType::A => RecordData::A(IP::from_string(value).ok_or_else(invalid)?),
+            RecordType::Aaaa => RecordData::Aaaa(value.parse::<Ipv6Addr>().map_err(|_| invalid())?),
+            RecordType::Ptr | RecordType::Cname if canonical(value).is_empty() => return Err(invalid()),
+            RecordType::Ptr => RecordData::Ptr(canonical(value)),
+            RecordType::Cname => RecordData::Cname(canonical(value)),
+        };
+        Ok(Record { name: normalize(name), data })
+    }
+
+    pub fn from_loaded(loaded: &LoadedDnsRecord) -> Result<Self, DnsError> {
+        let kind = RecordType::from_string(&loaded.kind)
+            .ok_or(DnsError::InvalidRecord(format!("{} {} {}", loaded.name, loaded.kind, loaded.value)))?;
+        Record::new(&loaded.name, kind, &loaded.value)
+    }
+
+    pub fn to_loaded(&self) -> LoadedDnsRecord {
+        LoadedDnsRecord { name: self.name.clone(), kind: self.kind().to_string(), value: self.value() }
+    }
+
+    pub fn kind(&self) -> RecordType {
+        match self.data {
+            RecordData::A(_) => RecordType::A,
+            RecordData::Aaaa(_) => RecordType::Aaaa,
+            RecordData::Ptr(_) => RecordType::Ptr,
+            RecordData::Cname(_) => RecordType::Cname,
+        }
+    }
+
+    pub fn value(&self) -> String {
+        match &self.data {
+            RecordData::A(ip) => ip.to_ddn(),
+            RecordData::Aaaa(ip) => ip.to_string(),
+            RecordData::Ptr(name) | RecordData::Cname(name) => name.clone(),
+        }
+    }
+
+    fn answer(&self) -> DnsAnswer {
+        let data = match &self.data {
+            RecordData::A(ip) => ip.to_u32().to_be_bytes().to_vec(),
+            RecordData::Aaaa(ip) => ip.octets().to_vec(),
+            RecordData::Ptr(name) | RecordData::Cname(name) => dns_name(name),
+        };
+        DnsAnswer { name: self.name.clone(), kind: self.kind().code(), data }
+    }
+}
+
+fn canonical(name: &str) -> String {
+    name.trim_end_matches('.').to_lowercase()
+}
+
+// Names compare without case nor trailing dot, an address standing for its reverse name
+pub fn normalize(name: &str) -> String {
+    match IP::from_string(name) {
+        Some(IP::V4(a, b, c, d)) => format!("{}.{}.{}.{}.in-addr.arpa", d, c, b, a),
+        None => canonical(name),
+    }
+}
+
+// The records a zone answers for `name`, aliases being followed; None when the name does not exist
+pub fn answer(zone: &[Record], name: &str, kind: RecordType) -> Option<Vec<Record>> {
+    let mut answers: Vec<Record> = Vec::new();
+    let mut current = normalize(name);
+    for _ in 0..=MAX_CNAMES {
+        let owned: Vec<&Record> = zone.iter().filter(|r| r.name == current).collect();
+        if owned.is_empty() {
+            // An alias pointing outside of the zone still answers
+            return (!answers.is_empty()).then_some(answers);
+        }
+        match owned.iter().find_map(|r| match &r.data { RecordData::Cname(target) => Some((r, target)), _ => None }) {
+            Some((alias, target)) if kind != RecordType::Cname => {
+                answers.push((*alias).clone());
+                current = target.clone();
+            },
+            _ => {
+                answers.extend(owned.into_iter().filter(|r| r.kind() == kind).cloned());
+                return Some(answers);
+            },
+        }
+    }
+    Some(answers)
+}
+
+pub enum DnsError {
+    InvalidRecord(String),
+    NotADevice(MAC),
+    NotAServer(MAC),
+    NoNameServer,
+    ServerUnreachable(IP),
+    Refused(IP),
+    NxDomain(String),
+    NoAddress(String)
+}
+
+impl std::fmt::Display for DnsError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            DnsError::InvalidRecord(record) => write!(f, "Invalid DNS record '{}'", record),
+            DnsError::NotADevice(mac) => write!(f, "{} is not a device", mac),
+            DnsError::NotAServer(mac) => write!(f, "{} cannot run a DNS server", mac),
+            DnsError::NoNameServer => write!(f, "No name server configured"),
+            DnsError::ServerUnreachable(ip) => write!(f, "Name server {} unreachable", ip),
+            DnsError::Refused(ip) => write!(f, "Name server {} refused the query (no DNS service)", ip),
+            DnsError::NxDomain(name) => write!(f, "Unknown host {} (NXDOMAIN)", name),
+            DnsError::NoAddress(name) => write!(f, "{} has no IPv4 address", name),
+        }
+    }
+}
+
+// The zone served by a device or a router, None when it runs no DNS server
+pub fn zone_of<'a>(graph: &'a Graph, node: &MAC) -> Option<&'a Vec<Record>> {
+    match graph.router_with_mac(node) {
+        Some(router) => router.zone.as_ref(),
+        None => graph.devices.iter().find(|d| d.nic.mac == *node).and_then(|d| d.zone.as_ref()),
+    }
+}
+
+fn zone_mut<'a>(graph: &'a mut Graph, node: &MAC) -> Result<&'a mut Option<Vec<Record>>, DnsError> {
+    if let Some(router) = graph.routers.iter_mut().find(|r| r.has_mac(node)) {
+        return Ok(&mut router.zone);
+    }
+    match graph.devices.iter_mut().find(|d| d.nic.mac == *node) {
+        Some(device) => Ok(&mut device.zone),
+        None => Err(DnsError::NotAServer(node.clone())),
+    }
+}
+
+pub fn set_server(graph: &mut Graph, node: &MAC, enabled: bool) -> Result<(), DnsError> {
+    let zone = zone_mut(graph, node)?;
+    match enabled {
+        true => *zone = Some(zone.take().unwrap_or_default()),
+        false => *zone = None,
+    }
+    Ok(())
+}
+
+// Adding a record starts the server if needed
+pub fn add_record(graph: &mut Graph, node: &MAC, record: Record) -> Result<(), DnsError> {
+    let zone = zone_mut(graph, node)?.get_or_insert_with(Vec::new);
+    if !zone.contains(&record) {
+        zone.push(record);
+    }
+    Ok(())
+}
+
+pub fn remove_records(graph: &mut Graph, node: &MAC, name: &str) -> Result<(), DnsError> {
+    let name = normalize(name);
+    let zone = zone_mut(graph, node)?.as_mut().ok_or(DnsError::NotAServer(node.clone()))?;
+    match zone.iter().any(|r| r.name == name) {
+        true => {
+            zone.retain(|r| r.name != name);
+            Ok(())
+        },
+        false => Err(DnsError::NxDomain(name)),
+    }
+}
+
+pub fn set_name_server(graph: &mut Graph, host: &MAC, name_server: Option<IP>) -> Result<(), DnsError> {
+    match graph.devices.iter_mut().find(|d| d.nic.mac == *host) {
+        Some(device) => {
+            device.name_server = name_server;
+            Ok(())
+        },
+        None => Err(DnsError::NotADevice(host.clone())),
+    }
+}
+
+// Asks the name server of `host`, the query and its response going through the network
+pub fn query(graph: &Graph, host: &MAC, name: &str, kind: RecordType) -> Result<Vec<Record>, DnsError> {
+    let device = graph.devices.iter().find(|d| d.nic.mac == *host).ok_or(DnsError::NoNameServer)?;
+    let (nic, server) = (device.nic.clone(), device.name_server.clone().ok_or(DnsError::NoNameServer)?);
+    let name = normalize(name);
+    let zone = graph.hop_path_to_ip(host.clone(), server.clone())
+        .and_then(|path| path.last().map(|(mac, _)| mac.clone()))
+        .map(|mac| zone_of(graph, &mac));
+    // Derived from the name, so that queries take nothing from the random generator
+    let id = checksum(name.as_bytes());
+    let port = graph.sockets.ephemeral_port(host, Transport::Udp).unwrap_or(*EPHEMERAL_PORTS.start());
+    let message = |response: bool, rcode: u8, answers: &[Record]| Dns {
+        id, response, rcode, name: name.clone(), kind: kind.code(), answers: answers.iter().map(Record::answer).collect()
+    }.encode();
+    let query = || Payload::Data(PROTOCOL_UDP, udp(&nic.ip, &server, port, DNS_PORT, &message(false, DNS_NOERROR, &[])));
+    let (packets, result) = match zone {
+        None => {
+            let attempts = (0..QUERY_ATTEMPTS).map(|i| Packet { reply: false, after: if i == 0 { 0 } else { QUERY_TIMEOUT }, payload: query() });
+            (attempts.collect(), Err(DnsError::ServerUnreachable(server.clone())))
+        },
+        Some(None) => (vec![
+            Packet { reply: false, after: 0, payload: query() },
+            Packet { reply: true, after: 0, payload: Payload::PortUnreachable },
+        ], Err(DnsError::Refused(server.clone()))),
+        Some(Some(zone)) => {
+            let answers = answer(zone, &name, kind);
+            let rcode = if answers.is_some() { DNS_NOERROR } else { DNS_NXDOMAIN };
+            let response = udp(&server, &nic.ip, DNS_PORT, port, &message(true, rcode, answers.as_deref().unwrap_or_default()));
+            (vec![
+                Packet { reply: false, after: 0, payload: query() },
+                Packet { reply: true, after: 0, payload: Payload::Data(PROTOCOL_UDP, response) },
+            ], answers.ok_or(DnsError::NxDomain(name.clone())))
+        },
+    };
+    // A host asking itself sends nothing on the network
+    if nic.ip != server {
+        traffic::observe(graph, |graph| traffic::conversation(graph, &nic, &server, &packets));
+    }
+    result
+}
+
+// Addresses are taken as they are, names give their first IPv4 address
+pub fn resolve(graph: &Graph, host: &MAC, name: &str) -> Result<IP, DnsError> {
+    if let Some(ip) = IP::from_string(name) {
+        return Ok(ip);
+    }
+    query(graph, host, name, RecordType::A)?.into_iter()
+        .find_map(|r| match r.data {
+            RecordData::A(ip) => Some(ip),
+            _ => None,
+        })
+        .ok_or(DnsError::NoAddress(normalize(name)))
+}
+
+// The name of an address, or the IPv4 and IPv6 addresses of a name
+pub fn lookup(graph: &Graph, host: &MAC, name: &str) -> Result<Vec<Record>, DnsError> {
+    if IP::from_string(name).is_some() {
+        return query(graph, host, name, RecordType::Ptr);
+    }
+    let mut records = query(graph, host, name, RecordType::A)?;
+    for record in query(graph, host, name, RecordType::Aaaa)? {
+        if !records.contains(&record) {
+            records.push(record);
+        }
+    }
+    Ok(records)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::load::{DataFormat, parse_data};
+
+    const LAB: &str = "
+routers:
+  - name: R1
+    lan: {ip: 192.168.2.1, netmask: 24}
+    wan: {ip: 10.0.0.1, netmask: 30}
+devices:
+  - name: NS
+    ip: 192.168.2.53
+    netmask: 24
+    zone:
+      - {name: www.lab, type: A, value: 192.168.2.5}
+      - {name: web.lab, type: CNAME, value: www.lab}
+      - {name: 192.168.2.5, type: PTR, value: www.lab}
+  - {name: SRV, ip: 192.168.2.5, netmask: 24, name_server: 192.168.2.53}
+  - {name: PC, ip: 192.168.2.10, netmask: 24, name_server: 192.168.2.5}
+switches:
+  - name: SW1
+connections:
+  - {from: R1.lan, to: SW1}
+  - {from: NS, to: SW1}
+  - {from: SRV, to: SW1}
+  - {from: PC, to: SW1}
+";
+
+    fn lab() -> Graph {
+        let mut graph = Graph::new();
+        let report = graph.load_data(parse_data("test", LAB, DataFormat::Yaml).unwrap_or_else(|e| panic!("{}", e)));
+        assert!(report.rejected().is_empty());
+        graph
+    }
+
+    fn mac(graph: &Graph, name: &str) -> MAC {
+        graph.resolve_node(name).unwrap_or_else(|e| panic!("{}", e))
+    }
+
+    fn record(name: &str, kind: RecordType, value: &str) -> Record {
+        Record::new(name, kind, value).unwrap_or_else(|e| panic!("{}", e))
+    }
+
+    #[test]
+    fn names_are_normalized() {
+        assert_eq!(normalize("WWW.Lab."), "www.lab");
+        assert_eq!(normalize("192.168.2.5"), "5.2.168.192.in-addr.arpa");
+        assert!(Record::new("www.lab", RecordType::A, "not an address").is_err());
+        assert!(Record::new(".", RecordType::Cname, "www.lab").is_err());
+    }
+
+    #[test]
+    fn aliases_are_followed() {
+        let zone = vec![record("www.lab", RecordType::A, "192.168.2.5"), record("web.lab", RecordType::Cname, "www.lab")];
+        let answers = answer(&zone, "Web.Lab", RecordType::A).unwrap_or_else(|| panic!("no answer"));
+        assert_eq!(answers.iter().map(|r| r.to_string()).collect::<Vec<String>>(), [
+            format!("{:<32} {:<5} {}", "web.lab", "CNAME", "www.lab"),
+            format!("{:<32} {:<5} {}", "www.lab", "A", "192.168.2.5"),
+        ]);
+        // A name without records of the type exists all the same
+        assert!(answer(&zone, "www.lab", RecordType::Aaaa).is_some_and(|a| a.is_empty()));
+        assert!(answer(&zone, "ftp.lab", RecordType::A).is_none());
+    }
+
+    #[test]
+    fn alias_loops_end() {
+        let zone = vec![record("a.lab", RecordType::Cname, "b.lab"), record("b.lab", RecordType::Cname, "a.lab")];
+        assert_eq!(answer(&zone, "a.lab", RecordType::A).map(|a| a.len()), Some(MAX_CNAMES + 1));
+    }
+
+    #[test]
+    fn hosts_resolve_through_their_name_server() {
+        let graph = lab();
+        let srv = mac(&graph, "SRV");
+        assert!(resolve(&graph, &srv, "web.lab").is_ok_and(|ip| ip == IP::V4(192, 168, 2, 5)));
+        let names = lookup(&graph, &srv, "192.168.2.5").unwrap_or_else(|e| panic!("{}", e));
+        assert!(names.len() == 1 && names[0].value() == "www.lab");
+        assert!(matches!(resolve(&graph, &srv, "ftp.lab"), Err(DnsError::NxDomain(name)) if name == "ftp.lab"));
+    }
+
+    #[test]
+    fn servers_without_a_zone_refuse() {
+        let mut graph = lab();
+        let pc = mac(&graph, "PC");
+        assert!(matches!(resolve(&graph, &pc, "www.lab"), Err(DnsError::Refused(_))));
+        set_name_server(&mut graph, &pc, Some(IP::V4(192, 168, 2, 99))).unwrap_or_else(|e| panic!("{}", e));
+        assert!(matches!(resolve(&graph, &pc, "www.lab"), Err(DnsError::ServerUnreachable(_))));
+        set_name_server(&mut graph, &pc, None).unwrap_or_else(|e| panic!("{}", e));
+        assert!(matches!(resolve(&graph, &pc, "www.lab"), Err(DnsError::NoNameServer)));
+        // Addresses need no server
+        assert!(resolve(&graph, &pc, "192.168.2.5").is_ok());
+    }
+
+    #[test]
+    fn zones_are_edited_in_place() {
+        let mut graph = lab();
+        let (ns, srv) = (mac(&graph, "NS"), mac(&graph, "SRV"));
+        add_record(&mut graph, &ns, record("ftp.lab", RecordType::A, "192.168.2.6")).unwrap_or_else(|e| panic!("{}", e));
+        assert!(resolve(&graph, &srv, "ftp.lab").is_ok_and(|ip| ip == IP::V4(192, 168, 2, 6)));
+        remove_records(&mut graph, &ns, "www.lab").unwrap_or_else(|e| panic!("{}", e));
+        assert!(matches!(resolve(&graph, &srv, "www.lab"), Err(DnsError::NxDomain(_))));
+        // The alias still exists, pointing nowhere
+        assert!(matches!(resolve(&graph, &srv, "web.lab"), Err(DnsError::NoAddress(_))));
+        set_server(&mut graph, &ns, false).unwrap_or_else(|e| panic!("{}", e));
+        assert!(zone_of(&graph, &ns).is_none());
+    }
+}
@@ -0,0 +1,230 @@
+use crate::graph::Graph;
+use crate::mac::MAC;
+use crate::nic::NIC;
+
+
+#[derive(Clone)]
+pub enum ExportFormat {
+    Dot,
+    Mermaid
+}
+
+impl std::fmt::Display for ExportFormat {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            ExportFormat::Dot => write!(f, "dot"),
+            ExportFormat::Mermaid => write!(f, "mermaid"),
+        }
+    }
+}
+
+impl ExportFormat {
+    pub fn from_string(s: &str) -> Option<Self> {
+        match s.to_lowercase().as_str() {
+            "dot" | "graphviz" | "gv" => Some(ExportFormat::Dot),
+            "mermaid" | "mmd" => Some(ExportFormat::Mermaid),
+            _ => None,
+        }
+    }
+}
+
+enum NodeShape {
+    Router,
+    Device,
+    Switch
+}
+
+struct ExportNode {
+    id: String,
+    shape: NodeShape,
+    lines: Vec<String>,
+    macs: Vec<MAC>
+}
+
+struct ExportLink {
+    from: String,
+    to: String,
+    label: String
+}
+
+fn nic_label(prefix: &str, nic: &NIC) -> Vec<String> {
+    vec![
+        format!("{}{}/{}", prefix, nic.ip.to_ddn(), nic.netmask_u8()),
+        nic.mac.to_hex(),
+    ]
+}
+
+fn collect(graph: &Graph) -> (Vec<ExportNode>, Vec<ExportLink>) {
+    let mut nodes: Vec<ExportNode> = Vec::new();
+    for (idx, router) in graph.routers.iter().enumerate() {
+        let mut lines = vec![router.name.clone()];
+        lines.extend(nic_label("lan ", &router.nic_lan));
+        lines.extend(nic_label("wan ", &router.nic_wan));
+        for subinterface in &router.subinterfaces {
+            lines.extend(nic_label(&format!("{}.{} ", subinterface.parent.to_string().to_lowercase(), subinterface.vlan), &subinterface.nic));
+        }
+        nodes.push(ExportNode {
+            id: format!("r{}", idx),
+            shape: NodeShape::Router,
+            lines,
+            macs: router.macs(),
+        });
+    }
+    for (idx, device) in graph.devices.iter().enumerate() {
+        let mut lines = vec![device.name.clone()];
+        lines.extend(nic_label("", &device.nic));
+        nodes.push(ExportNode {
+            id: format!("d{}", idx),
+            shape: NodeShape::Device,
+            lines,
+            macs: vec![device.nic.mac.clone()],
+        });
+    }
+    for (idx, switch) in graph.switches.iter().enumerate() {
+        nodes.push(ExportNode {
+            id: format!("s{}", idx),
+            shape: NodeShape::Switch,
+            lines: vec![switch.name.clone(), switch.mac.to_hex()],
+            macs: vec![switch.mac.clone()],
+        });
+    }
+
+    let tree = graph.spanning_tree();
+    let mut links: Vec<ExportLink> = Vec::new();
+    for (mac1, mac2) in graph.all_connections() {
+        let from = nodes.iter().find(|n| n.macs.contains(mac1));
+        let to = nodes.iter().find(|n| n.macs.contains(mac2));
+        let (from, to) = match (from, to) {
+            (Some(from), Some(to)) if from.id != to.id => (from, to),
+            _ => continue,
+        };
+        let label = match graph.nic_with_mac(mac1.clone()).or(graph.nic_with_mac(mac2.clone())) {
+            _ if !graph.link_up(mac1, mac2) => "down".to_string(),
+            Some(nic) => format!("{}/{}", nic.network_address().to_ddn(), nic.netmask_u8()),
+            None if tree.blocked(mac1, mac2) => "blocked".to_string(),
+            None => String::new(),
+        };
+        links.push(ExportLink { from: from.id.clone(), to: to.id.clone(), label });
+    }
+    (nodes, links)
+}
+
+fn dot_escape(s: &str) -> String {
+    s.replace('\\', "\\\\").replace('"', "\\\"")
+}
+
+fn mermaid_escape(s: &str) -> String {
+    s.replace('"', "#quot;")
+}
+
+pub fn to_dot(graph: &Graph) -> String {
+    let (nodes, links) = collect(graph);
+    let mut out = String::from("graph network {\n");
+    out.push_str("    node [fontname=\"Helvetica\"];\n");
+    out.push_str("    edge [fontname=\"Helvetica\", fontsize=10];\n");
+    for node in &nodes {
+        let shape = match node.shape {
+            NodeShape::Router => "box, style=\"rounded,filled\", fillcolor=\"lightblue\"",
+            NodeShape::Device => "ellipse",
+            NodeShape::Switch => "box3d",
+        };
+        let label: Vec<String> = node.lines.iter().map(|l| dot_escape(l)).collect();
+        out.push_str(&format!("    {} [shape={}, label=\"{}\"];\n", node.id, shape, label.join("\\n")));
+    }
+    for link in &links {
+        out.push_str(&format!("    {} -- {} [label=\"{}\"];\n", link.from, link.to, dot_escape(&link.label)));
+    }
+    out.push_str("}\n");
+    out
+}
+
+pub fn to_mermaid(graph: &Graph) -> String {
+    let (nodes, links) = collect(graph);
+    let mut out = String::from("graph TD\n");
+    for node in &nodes {
+        let label: Vec<String> = node.lines.iter().map(|l| mermaid_escape(l)).collect();
+        let label = label.join("<br/>");
+        match node.shape {
+            NodeShape::Router => out.push_str(&format!("    {}{{{{\"{}\"}}}}\n", node.id, label)),
+            NodeShape::Device => out.push_str(&format!("    {}([\"{}\"])\n", node.id, label)),
+            NodeShape::Switch => out.push_str(&format!("    {}[[\"{}\"]]\n", node.id, label)),
+        }
+    }
+    for link in &links {
+        out.push_str(&format!("    {} ---|\"{}\"| {}\n", link.from, mermaid_escape(&link.label), link.to));
+    }
+    out
+}
+
+pub fn export(graph: &Graph, format: ExportFormat) -> String {
+    match format {
+        ExportFormat::Dot => to_dot(graph),
+        ExportFormat::Mermaid => to_mermaid(graph),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::load::{DataFormat, parse_data};
+    use crate::state::Element;
+
+    const OFFICE: &str = "
+routers:
+  - name: R1
+    lan: {ip: 192.168.1.1, netmask: 24, mac: '02:00:00:00:00:01'}
+    wan: {ip: 10.0.0.1, netmask: 30, mac: '02:00:00:00:00:02'}
+devices:
+  - {name: PC1, ip: 192.168.1.10, netmask: 24, mac: '02:00:00:00:00:10'}
+connections:
+  - {from: PC1, to: R1.lan}
+";
+
+    fn office() -> Graph {
+        let mut graph = Graph::new();
+        let report = graph.load_data(parse_data("test", OFFICE, DataFormat::Yaml).unwrap_or_else(|e| panic!("{}", e)));
+        assert!(report.rejected().is_empty());
+        graph
+    }
+
+    #[test]
+    fn dot_lists_nodes_then_links() {
+        assert_eq!(to_dot(&office()), concat!(
+            "graph network {\n",
+            "    node [fontname=\"Helvetica\"];\n",
+            "    edge [fontname=\"Helvetica\", fontsize=10];\n",
+            "    r0 [shape=box, style=\"rounded,filled\", fillcolor=\"lightblue\", label=\"R1\\nlan 192.168.1.1/24\\n02:00:00:00:00:01\\nwan 10.0.0.1/30\\n02:00:00:00:00:02\"];\n",
+            "    d0 [shape=ellipse, label=\"PC1\\n192.168.1.10/24\\n02:00:00:00:00:10\"];\n",
+            "    d0 -- r0 [label=\"192.168.1.0/24\"];\n",
+            "}\n",
+        ));
+    }
+
+    #[test]
+    fn mermaid_uses_a_shape_per_node_type() {
+        assert_eq!(to_mermaid(&office()), concat!(
+            "graph TD\n",
+            "    r0{{\"R1<br/>lan 192.168.1.1/24<br/>02:00:00:00:00:01<br/>wan 10.0.0.1/30<br/>02:00:00:00:00:02\"}}\n",
+            "    d0([\"PC1<br/>192.168.1.10/24<br/>02:00:00:00:00:10\"])\n",
+            "    d0 ---|\"192.168.1.0/24\"| r0\n",
+        ));
+    }
+
+    #[test]
+    fn links_that_are_down_are_labelled() {
+        let mut graph = office();
+        let (pc1, lan) = (MAC::EUI48(2, 0, 0, 0, 0, 0x10), MAC::EUI48(2, 0, 0, 0, 0, 1));
+        graph.set_failed(Element::Link(pc1, lan), true).unwrap_or_else(|e| panic!("{}", e));
+        assert!(to_dot(&graph).contains("    d0 -- r0 [label=\"down\"];\n"));
+        assert!(to_mermaid(&graph).contains("    d0 ---|\"down\"| r0\n"));
+    }
+
+    #[test]
+    fn labels_are_escaped() {
+        assert_eq!(dot_escape("a\"b\\c"), "a\\\"b\\\\c");
+        assert_eq!(mermaid_escape("a\"b"), "a#quot;b");
+        assert!(matches!(ExportFormat::from_string("GV"), Some(ExportFormat::Dot)));
+        assert!(matches!(ExportFormat::from_string("mmd"), Some(ExportFormat::Mermaid)));
+        assert!(ExportFormat::from_string("svg").is_none());
+    }
+}
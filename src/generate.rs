@@ -0,0 +1,403 @@
+use crate::graph::Graph;
+use crate::ip::IP;
+use crate::load::{LoadReport, LoadedConnections, LoadedData, LoadedDevices, LoadedRouter, LoadedRouterDHCP, LoadedRouterInterface, LoadedSwitch};
+use crate::switch::DEFAULT_PORTS;
+
+
+const MAX_NODES: usize = 4096;
+
+pub enum Shape {
+    Star { hosts: usize },
+    Chain { routers: usize },
+    Tree { depth: usize, fanout: usize, hosts: usize },
+    Mesh { routers: usize }
+}
+
+impl std::fmt::Display for Shape {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Shape::Star { hosts } => write!(f, "star of {} hosts", hosts),
+            Shape::Chain { routers } => write!(f, "chain of {} routers", routers),
+            Shape::Tree { depth, fanout, hosts } => write!(f, "tree of depth {} and fanout {} with {} hosts per leaf", depth, fanout, hosts),
+            Shape::Mesh { routers } => write!(f, "mesh of {} routers", routers),
+        }
+    }
+}
+
+pub enum GenerateError {
+    UnknownShape(String),
+    MissingParameter(String),
+    InvalidParameter(String),
+    TooLarge(usize),
+    AddressSpaceExhausted
+}
+
+impl std::fmt::Display for GenerateError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            GenerateError::UnknownShape(shape) => write!(f, "Unknown Shape {} (star, chain, tree or mesh)", shape),
+            GenerateError::MissingParameter(usage) => write!(f, "Missing Parameter ({})", usage),
+            GenerateError::InvalidParameter(value) => write!(f, "Invalid Parameter {} (a positive number is expected)", value),
+            GenerateError::TooLarge(nodes) => write!(f, "Too Large ({} nodes, at most {})", nodes, MAX_NODES),
+            GenerateError::AddressSpaceExhausted => write!(f, "Address Space Exhausted"),
+        }
+    }
+}
+
+impl Shape {
+    pub fn usage(shape: &str) -> Option<&'static str> {
+        match shape {
+            "star" => Some("star <hosts>"),
+            "chain" => Some("chain <routers>"),
+            "tree" => Some("tree <depth> <fanout> <hosts>"),
+            "mesh" => Some("mesh <routers>"),
+            _ => None,
+        }
+    }
+
+    // Returns the shape and the number of arguments it used
+    pub fn from_args(args: &[String]) -> Result<(Self, usize), GenerateError> {
+        let name = match args.first() {
+            Some(name) => name.to_lowercase(),
+            None => return Err(GenerateError::UnknownShape(String::new())),
+        };
+        let usage = Shape::usage(&name).ok_or(GenerateError::UnknownShape(name.clone()))?;
+        let count = usage.split(' ').count() - 1;
+        if args.len() < count + 1 {
+            return Err(GenerateError::MissingParameter(usage.to_string()));
+        }
+        let mut values: Vec<usize> = Vec::new();
+        for arg in &args[1..=count] {
+            match arg.parse::<usize>() {
+                Ok(value) if value > 0 => values.push(value),
+                _ => return Err(GenerateError::InvalidParameter(arg.clone())),
+            }
+        }
+        let shape = match name.as_str() {
+            "star" => Shape::Star { hosts: values[0] },
+            "chain" => Shape::Chain { routers: values[0] },
+            "tree" => Shape::Tree { depth: values[0], fanout: values[1], hosts: values[2] },
+            _ => Shape::Mesh { routers: values[0] },
+        };
+        Ok((shape, count + 1))
+    }
+
+    fn nodes(&self) -> usize {
+        match self {
+            Shape::Star { hosts } => hosts.saturating_add(2),
+            Shape::Chain { routers } => routers.saturating_add(2),
+            Shape::Tree { depth, fanout, hosts } => {
+                let mut routers: usize = 0;
+                let mut level: usize = 1;
+                for _ in 0..*depth {
+                    routers = routers.saturating_add(level);
+                    level = level.saturating_mul(*fanout);
+                }
+                // Each router has a switch, leaves have hosts behind it
+                let leaves = level / fanout;
+                routers.saturating_mul(2).saturating_add(leaves.saturating_mul(*hosts))
+            },
+            Shape::Mesh { routers } => routers.saturating_mul(2).saturating_add(1),
+        }
+    }
+}
+
+// Hands out aligned subnets one after the other
+struct SubnetAllocator {
+    next: u32,
+    end: u32
+}
+
+struct Subnet {
+    network: u32,
+    prefix: u8
+}
+
+impl Subnet {
+    fn host(&self, index: usize) -> String {
+        IP::from_u32(self.network + index as u32).to_ddn()
+    }
+
+    // `pool` is a range of host indexes
+    fn interface(&self, index: usize, pool: Option<(usize, usize)>) -> LoadedRouterInterface {
+        LoadedRouterInterface {
+            ip: self.host(index),
+            netmask: self.prefix,
+            mac: None,
+            dhcp: pool.map(|(first, last)| LoadedRouterDHCP { first_ip: self.host(first), last_ip: self.host(last), name_server: None }),
+            subinterfaces: None,
+            shutdown: None,
+        }
+    }
+}
+
+impl SubnetAllocator {
+    fn new(start: IP, end: IP) -> Self {
+        SubnetAllocator { next: start.to_u32(), end: end.to_u32() }
+    }
+
+    fn allocate(&mut self, hosts: usize) -> Result<Subnet, GenerateError> {
+        // Network and broadcast addresses come on top of the hosts
+        let size = u32::try_from(hosts).ok()
+            .and_then(|hosts| hosts.checked_add(2))
+            .and_then(u32::checked_next_power_of_two)
+            .ok_or(GenerateError::AddressSpaceExhausted)?
+            .max(4);
+        let network = self.next.div_ceil(size).checked_mul(size).ok_or(GenerateError::AddressSpaceExhausted)?;
+        match network.checked_add(size - 1) {
+            Some(last) if last <= self.end => {
+                self.next = last + 1;
+                Ok(Subnet { network, prefix: (32 - size.trailing_zeros()) as u8 })
+            },
+            _ => Err(GenerateError::AddressSpaceExhausted),
+        }
+    }
+}
+
+// DHCP pools hold at least two addresses
+fn pool_size(hosts: usize) -> usize {
+    hosts.max(2)
+}
+
+struct Builder {
+    data: LoadedData,
+    lans: SubnetAllocator,
+    transits: SubnetAllocator
+}
+
+impl Builder {
+    fn new() -> Self {
+        Builder {
+            data: LoadedData {
+                routers: Some(Vec::new()),
+                devices: Some(Vec::new()),
+                switches: Some(Vec::new()),
+                connections: Some(Vec::new()),
+                ..LoadedData::new()
+            },
+            lans: SubnetAllocator::new(IP::V4(10, 0, 0, 0), IP::V4(10, 255, 255, 255)),
+            transits: SubnetAllocator::new(IP::V4(172, 16, 0, 0), IP::V4(172, 31, 255, 255))
+        }
+    }
+
+    fn router(&mut self, name: &str, lan: LoadedRouterInterface, wan: LoadedRouterInterface) {
+        self.data.routers.get_or_insert_default().push(LoadedRouter { name: name.to_string(), lan, wan, zone: None, failed: None });
+    }
+
+    fn host(&mut self, name: &str) {
+        self.data.devices.get_or_insert_default().push(LoadedDevices { name: name.to_string(), ip: None, netmask: None, mac: None, name_server: None, zone: None, services: None, shutdown: None, failed: None });
+    }
+
+    fn switch(&mut self, name: &str, links: usize) {
+        self.data.switches.get_or_insert_default().push(LoadedSwitch { name: name.to_string(), ports: Some(links.max(DEFAULT_PORTS)), mac: None, priority: None, vlans: None, failed: None });
+    }
+
+    fn connect(&mut self, from: &str, to: &str) {
+        self.data.connections.get_or_insert_default().push(LoadedConnections { from: from.to_string(), to: to.to_string(), shutdown: None, failed: None });
+    }
+
+    // Unused uplink, addressed so that it is not left on 127.0.0.1
+    fn uplink(&mut self) -> Result<LoadedRouterInterface, GenerateError> {
+        Ok(self.transits.allocate(2)?.interface(1, None))
+    }
+
+    // LAN served by DHCP, hosts are behind a switch when there are several of them
+    fn hosts_lan(&mut self, endpoint: &str, switch: &str, hosts: &[String]) -> Result<LoadedRouterInterface, GenerateError> {
+        let subnet = self.lans.allocate(1 + pool_size(hosts.len()))?;
+        match hosts {
+            [host] => self.connect(host, endpoint),
+            _ => {
+                self.switch(switch, hosts.len() + 1);
+                self.connect(switch, endpoint);
+                for host in hosts {
+                    self.connect(host, switch);
+                }
+            }
+        }
+        for host in hosts {
+            self.host(host);
+        }
+        Ok(subnet.interface(1, Some((2, 1 + pool_size(hosts.len())))))
+    }
+
+    fn star(&mut self, hosts: usize) -> Result<(), GenerateError> {
+        let names: Vec<String> = (1..=hosts).map(|i| format!("H{}", i)).collect();
+        let lan = self.hosts_lan("R1.lan", "SW1", &names)?;
+        let wan = self.uplink()?;
+        self.router("R1", lan, wan);
+        Ok(())
+    }
+
+    // R1.lan and the WAN of the last router serve a host each, routers are linked WAN to LAN
+    fn chain(&mut self, routers: usize) -> Result<(), GenerateError> {
+        let mut lans: Vec<LoadedRouterInterface> = vec![self.hosts_lan("R1.lan", "SW1", &["H1".to_string()])?];
+        let mut wans: Vec<LoadedRouterInterface> = Vec::new();
+        for i in 1..routers {
+            let transit = self.transits.allocate(2)?;
+            wans.push(transit.interface(1, None));
+            lans.push(transit.interface(2, None));
+            self.connect(&format!("R{}.wan", i), &format!("R{}.lan", i + 1));
+        }
+        let last = format!("R{}.wan", routers);
+        wans.push(self.hosts_lan(&last, "SW2", &["H2".to_string()])?);
+        for (i, (lan, wan)) in lans.into_iter().zip(wans).enumerate() {
+            self.router(&format!("R{}", i + 1), lan, wan);
+        }
+        Ok(())
+    }
+
+    // Every router but the leaves has a switch on its LAN where its children plug their WAN
+    fn tree(&mut self, name: &str, wan: LoadedRouterInterface, depth: usize, fanout: usize, hosts: usize) -> Result<(), GenerateError> {
+        let suffix = &name[1..];
+        let switch = format!("SW{}", suffix);
+        let endpoint = format!("{}.lan", name);
+        if depth == 1 {
+            let names: Vec<String> = (1..=hosts).map(|i| format!("H{}-{}", suffix, i)).collect();
+            let lan = self.hosts_lan(&endpoint, &switch, &names)?;
+            self.router(name, lan, wan);
+            return Ok(());
+        }
+        let subnet = self.lans.allocate(1 + fanout)?;
+        self.router(name, subnet.interface(1, None), wan);
+        self.switch(&switch, fanout + 1);
+        self.connect(&switch, &endpoint);
+        for i in 1..=fanout {
+            let child = format!("{}-{}", name, i);
+            self.connect(&format!("{}.wan", child), &switch);
+            self.tree(&child, subnet.interface(1 + i, None), depth - 1, fanout, hosts)?;
+        }
+        Ok(())
+    }
+
+    // Router WANs share a backbone switch, so that every router is adjacent to every other one
+    fn mesh(&mut self, routers: usize) -> Result<(), GenerateError> {
+        let backbone = self.transits.allocate(routers)?;
+        self.switch("BACKBONE", routers);
+        for i in 1..=routers {
+            let name = format!("R{}", i);
+            let lan = self.hosts_lan(&format!("{}.lan", name), &format!("SW{}", i), &[format!("H{}", i)])?;
+            self.router(&name, lan, backbone.interface(i, None));
+            self.connect(&format!("{}.wan", name), "BACKBONE");
+        }
+        Ok(())
+    }
+}
+
+pub fn generate(shape: &Shape) -> Result<LoadedData, GenerateError> {
+    let nodes = shape.nodes();
+    if nodes > MAX_NODES {
+        return Err(GenerateError::TooLarge(nodes));
+    }
+    let mut builder = Builder::new();
+    match shape {
+        Shape::Star { hosts } => builder.star(*hosts)?,
+        Shape::Chain { routers } => builder.chain(*routers)?,
+        Shape::Tree { depth, fanout, hosts } => {
+            let wan = builder.uplink()?;
+            builder.tree("R1", wan, *depth, *fanout, *hosts)?;
+        },
+        Shape::Mesh { routers } => builder.mesh(*routers)?,
+    }
+    if builder.data.switches.as_ref().is_some_and(|s| s.is_empty()) {
+        builder.data.switches = None;
+    }
+    Ok(builder.data)
+}
+
+pub fn generate_graph(shape: &Shape) -> Result<(Graph, LoadReport), GenerateError> {
+    let data = generate(shape)?;
+    let mut graph = Graph::new();
+    let report = graph.load_data(data);
+    Ok((graph, report))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::commands::{PingStatus, ping};
+    use crate::graph::GraphError;
+    use crate::load::{DataFormat, LoadIssue, LoadStatus, parse_data};
+
+    fn args(line: &str) -> Vec<String> {
+        line.split_whitespace().map(str::to_string).collect()
+    }
+
+    fn generated(shape: Shape) -> Graph {
+        let (graph, report) = generate_graph(&shape).unwrap_or_else(|e| panic!("{}", e));
+        assert!(report.rejected().is_empty() && report.entries.iter().all(|e| e.warnings.is_empty()));
+        graph
+    }
+
+    fn reaches(graph: &Graph, from: &str, to: &str) -> bool {
+        let nic = |name: &str| graph.resolve(name).ok().and_then(|mac| graph.nic_with_mac(mac)).unwrap_or_else(|| panic!("no {}", name));
+        let (src, dest) = (nic(from), nic(to));
+        assert!(!src.is_localhost() && !dest.is_localhost());
+        matches!(ping(graph, src, dest.ip), PingStatus::Success)
+    }
+
+    #[test]
+    fn shapes_parse_with_their_parameters() {
+        assert!(matches!(Shape::from_args(&args("Tree 3 2 5 out.yaml")), Ok((Shape::Tree { depth: 3, fanout: 2, hosts: 5 }, 4))));
+        assert!(matches!(Shape::from_args(&args("star 10")), Ok((Shape::Star { hosts: 10 }, 2))));
+        assert!(matches!(Shape::from_args(&args("ring 4")), Err(GenerateError::UnknownShape(_))));
+        assert!(matches!(Shape::from_args(&args("tree 3 2")), Err(GenerateError::MissingParameter(_))));
+        assert!(matches!(Shape::from_args(&args("chain 0")), Err(GenerateError::InvalidParameter(_))));
+        assert!(matches!(Shape::from_args(&[]), Err(GenerateError::UnknownShape(_))));
+    }
+
+    #[test]
+    fn sizes_are_checked_before_building() {
+        assert_eq!(Shape::Tree { depth: 3, fanout: 2, hosts: 5 }.nodes(), 7 * 2 + 4 * 5);
+        assert!(matches!(generate(&Shape::Star { hosts: MAX_NODES }), Err(GenerateError::TooLarge(_))));
+        assert!(matches!(generate(&Shape::Tree { depth: 100, fanout: 1000, hosts: 1 }), Err(GenerateError::TooLarge(usize::MAX))));
+    }
+
+    #[test]
+    fn subnets_are_aligned_and_bounded() {
+        let mut allocator = SubnetAllocator::new(IP::V4(10, 0, 0, 0), IP::V4(10, 0, 0, 255));
+        let small = allocator.allocate(2).unwrap_or_else(|e| panic!("{}", e));
+        assert!(small.network == IP::V4(10, 0, 0, 0).to_u32() && small.prefix == 30);
+        let large = allocator.allocate(100).unwrap_or_else(|e| panic!("{}", e));
+        assert!(large.network == IP::V4(10, 0, 0, 128).to_u32() && large.prefix == 25);
+        assert!(matches!(allocator.allocate(2), Err(GenerateError::AddressSpaceExhausted)));
+    }
+
+    #[test]
+    fn generated_hosts_reach_each_other() {
+        let star = generated(Shape::Star { hosts: 3 });
+        assert!(reaches(&star, "H1", "H3"));
+        let chain = generated(Shape::Chain { routers: 3 });
+        assert!(reaches(&chain, "H1", "H2"));
+        let tree = generated(Shape::Tree { depth: 2, fanout: 2, hosts: 2 });
+        assert!(tree.switches.len() == 3 && reaches(&tree, "H1-1-1", "H1-2-2"));
+        let mesh = generated(Shape::Mesh { routers: 3 });
+        assert!(reaches(&mesh, "H1", "H3"));
+    }
+
+    #[test]
+    fn switches_take_as_many_cables_as_they_have_ports() {
+        let data = "
+routers:
+  - name: R1
+    lan: {ip: 192.168.1.1, netmask: 24}
+    wan: {ip: 10.0.0.1, netmask: 30}
+devices:
+  - {name: PC1, ip: 192.168.1.10, netmask: 24}
+  - {name: PC2, ip: 192.168.1.11, netmask: 24}
+switches:
+  - {name: SW1, ports: 2}
+connections:
+  - {from: R1.lan, to: SW1}
+  - {from: PC1, to: SW1}
+  - {from: PC2, to: SW1}
+";
+        let mut graph = Graph::new();
+        let report = graph.load_data(parse_data("test", data, DataFormat::Yaml).unwrap_or_else(|e| panic!("{}", e)));
+        let rejected = report.rejected();
+        assert!(rejected.len() == 1 && rejected[0].subject.contains("PC2"));
+        assert!(matches!(&rejected[0].status, LoadStatus::Rejected(LoadIssue::ConnectionError(GraphError::MaxConnectionReached(_)))));
+        let mut large = Builder::new();
+        large.switch("SW1", 100);
+        assert_eq!(large.data.switches.and_then(|s| s[0].ports), Some(100));
+    }
+}
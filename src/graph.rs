@@ -1,18 +1,32 @@
-use crate::colors::Colors;
+use std::cell::OnceCell;
+
 use crate::device::Device;
 use crate::dhcp::DHCP;
 use crate::dhcp::DhcpError;
+use crate::dns::Record;
 use crate::ip::IP;
-use crate::load::LoadedData;
-use crate::mac::MAC;
+use crate::load::{LoadEntry, LoadEntryKind, LoadIssue, LoadReport, LoadedConnections, LoadedData, LoadedDevices, LoadedDnsRecord, LoadedRouter, LoadedRouterDHCP, LoadedRouterInterface, LoadedService, LoadedSubInterface, LoadedSwitch, LoadedSwitchPort};
+use crate::mac::{MacGenerator, MAC};
 use crate::nic::{NIC,IpAddressType};
-use crate::router::{Router, RouterInterface};
+use crate::random::Random;
+use crate::router::{Router, RouterInterface, SubInterface};
+use crate::service::{Service, ServiceError};
+use crate::capture::Captures;
+use crate::clock::Clock;
+use crate::state::{Element, State, States};
+use crate::stp::SpanningTree;
+use crate::switch::{DEFAULT_PORTS, DEFAULT_PRIORITY, DEFAULT_VLAN, PortMode, Switch, valid_priority, valid_vlan};
+use crate::tables::Tables;
+use crate::trace::Trace;
+use crate::transport::Sockets;
+use crate::traffic;
 
 
 #[derive(Clone)]
 pub enum NodeType {
     Router,
-    Device
+    Device,
+    Switch
 }
 
 impl std::fmt::Display for NodeType {
@@ -20,6 +34,7 @@ impl std::fmt::Display for NodeType {
         match self {
             NodeType::Router => write!(f, "Router"),
             NodeType::Device => write!(f, "Device"),
+            NodeType::Switch => write!(f, "Switch"),
         }
     }
 }
@@ -28,7 +43,16 @@ pub enum GraphError {
     AlreadyExistingMacAddress,
     ConnectionAlreadyExists,
     MaxConnectionReached(MAC),
-    ConnectionNotPossible
+    ConnectionNotPossible,
+    ConnectionNotFound,
+    NodeNotFound(MAC),
+    NeighborOnOtherNetwork(MAC),
+    AlreadyExistingName(String),
+    UnknownNode(String),
+    InterfaceRequired(String),
+    DhcpFailed(DhcpError),
+    InvalidVlan(u16),
+    InvalidBridgePriority(u16)
 }
 
 impl std::fmt::Display for GraphError {
@@ -37,17 +61,72 @@ impl std::fmt::Display for GraphError {
             GraphError::AlreadyExistingMacAddress => write!(f, "Already Existing MAC Address"),
             GraphError::ConnectionAlreadyExists => write!(f, "Connection Already Exists"),
             GraphError::MaxConnectionReached(mac) => write!(f, "Max Connection Reached for MAC {}", mac),
-            GraphError::ConnectionNotPossible => write!(f, "Connection Not Possible (Incompatible networks and no DHCP available)")
+            GraphError::ConnectionNotPossible => write!(f, "Connection Not Possible (Incompatible networks and no DHCP available)"),
+            GraphError::ConnectionNotFound => write!(f, "Connection Not Found"),
+            GraphError::NodeNotFound(mac) => write!(f, "No Node With MAC {}", mac),
+            GraphError::NeighborOnOtherNetwork(mac) => write!(f, "Neighbor {} Would Be On Another Network", mac),
+            GraphError::AlreadyExistingName(name) => write!(f, "Already Existing Name {}", name),
+            GraphError::UnknownNode(name) => write!(f, "Unknown Node {}", name),
+            GraphError::InterfaceRequired(name) => write!(f, "Interface Required ({0}.lan or {0}.wan)", name),
+            GraphError::DhcpFailed(e) => write!(f, "DHCP Attribution Failed ({})", e),
+            GraphError::InvalidVlan(vlan) => write!(f, "Invalid VLAN {} (1 to 4094)", vlan),
+            GraphError::InvalidBridgePriority(priority) => write!(f, "Invalid Bridge Priority {} (a multiple of 4096)", priority)
         }
     }
 }
 
+// A frame in transit: the node it reached and, on a switch, the VLAN it belongs to
+pub type Hop = (MAC, u16);
+
+// What loading applies once every link is made: states would hide part of the network from the
+// address checks and DHCP, and devices saved without address must not get one on reload
+struct Deferred {
+    states: Vec<(Element, bool, bool)>,
+    unaddressed: Vec<MAC>
+}
+
+impl Deferred {
+    fn new() -> Self {
+        Deferred { states: Vec::new(), unaddressed: Vec::new() }
+    }
+}
+
 #[derive(Clone)]
 pub struct Graph {
     nodes: Vec<(MAC, NodeType)>,
     connections: Vec<(MAC, MAC)>,
     pub routers: Vec<Router>,
-    pub devices: Vec<Device>
+    pub devices: Vec<Device>,
+    pub switches: Vec<Switch>,
+    pub random: Random,
+    pub mac_generator: MacGenerator,
+    pub clock: Clock,
+    pub captures: Captures,
+    pub trace: Trace,
+    pub tables: Tables,
+    pub sockets: Sockets,
+    states: States,
+    // Computed on first use, dropped whenever links, their states or bridges change
+    spanning_tree: OnceCell<SpanningTree>
+}
+
+// The network as it is edited, without the runtime state of the simulation (clock, random generator,
+// sockets, captures, trace and learned tables), which keeps running when an edit is undone
+#[derive(Clone)]
+pub struct Topology {
+    nodes: Vec<(MAC, NodeType)>,
+    connections: Vec<(MAC, MAC)>,
+    routers: Vec<Router>,
+    devices: Vec<Device>,
+    switches: Vec<Switch>,
+    mac_generator: MacGenerator,
+    states: States
+}
+
+impl Default for Graph {
+    fn default() -> Self {
+        Self::new()
+    }
 }
 
 impl Graph {
@@ -56,16 +135,61 @@ impl Graph {
             nodes: Vec::new(),
             connections: Vec::new(),
             routers: Vec::new(),
-            devices: Vec::new()
+            devices: Vec::new(),
+            switches: Vec::new(),
+            random: Random::new(None),
+            mac_generator: MacGenerator::new(),
+            clock: Clock::new(),
+            captures: Captures::new(),
+            trace: Trace::new(),
+            tables: Tables::new(),
+            sockets: Sockets::new(),
+            states: States::new(),
+            spanning_tree: OnceCell::new()
+        }
+    }
+
+    pub fn topology(&self) -> Topology {
+        Topology {
+            nodes: self.nodes.clone(),
+            connections: self.connections.clone(),
+            routers: self.routers.clone(),
+            devices: self.devices.clone(),
+            switches: self.switches.clone(),
+            mac_generator: self.mac_generator.clone(),
+            states: self.states.clone()
         }
     }
 
+    // Sockets of devices that no longer exist are closed with them
+    pub fn restore_topology(&mut self, topology: Topology) {
+        self.nodes = topology.nodes;
+        self.connections = topology.connections;
+        self.routers = topology.routers;
+        self.devices = topology.devices;
+        self.switches = topology.switches;
+        self.mac_generator = topology.mac_generator;
+        self.states = topology.states;
+        let devices = &self.devices;
+        self.sockets.retain(|s| devices.iter().any(|d| d.nic.mac == s.host));
+        self.topology_changed();
+    }
+
+    pub fn new_mac(&mut self, node: &NodeType) -> MAC {
+        self.mac_generator.generate(self.random.rng(), node)
+    }
+
     pub fn append_router(&mut self, router: Router) -> Result<(), GraphError> {
-        if self.node_type_with_mac(router.nic_lan.mac.clone()).is_some() || self.node_type_with_mac(router.nic_wan.mac.clone()).is_some() {
+        let macs = router.macs();
+        if macs.iter().enumerate().any(|(i, mac)| self.node_type_with_mac(mac.clone()).is_some() || macs[..i].contains(mac)) {
             return Err(GraphError::AlreadyExistingMacAddress);
         }
-        self.nodes.push((router.nic_lan.mac.clone(), NodeType::Router));
-        self.nodes.push((router.nic_wan.mac.clone(), NodeType::Router));
+        if self.name_exists(&router.name) {
+            return Err(GraphError::AlreadyExistingName(router.name));
+        }
+        for mac in macs {
+            self.nodes.push((mac, NodeType::Router));
+        }
         self.routers.push(router);
         Ok(())
     }
@@ -74,69 +198,116 @@ impl Graph {
         if self.node_type_with_mac(device.nic.mac.clone()).is_some() {
             return Err(GraphError::AlreadyExistingMacAddress);
         }
+        if self.name_exists(&device.name) {
+            return Err(GraphError::AlreadyExistingName(device.name));
+        }
         self.nodes.push((device.nic.mac.clone(), NodeType::Device));
         self.devices.push(device);
         Ok(())
     }
 
+    pub fn append_switch(&mut self, switch: Switch) -> Result<(), GraphError> {
+        if self.node_type_with_mac(switch.mac.clone()).is_some() {
+            return Err(GraphError::AlreadyExistingMacAddress);
+        }
+        if self.name_exists(&switch.name) {
+            return Err(GraphError::AlreadyExistingName(switch.name));
+        }
+        self.nodes.push((switch.mac.clone(), NodeType::Switch));
+        self.switches.push(switch);
+        self.topology_changed();
+        Ok(())
+    }
+
+    fn check_free_port(&self, mac: &MAC) -> Result<(), GraphError> {
+        // Subinterfaces share the cable of their parent interface
+        if self.search_subinterface_with_mac(mac.clone()).is_some() {
+            return Err(GraphError::ConnectionNotPossible);
+        }
+        let connections = self.connections(mac.clone());
+        let max = match self.node_type_with_mac(mac.clone()) {
+            Some(NodeType::Device) => 1,
+            Some(NodeType::Router) => 2,
+            Some(NodeType::Switch) => self.search_switch_with_mac(mac.clone()).map(|s| s.ports).unwrap_or(0),
+            None => return Err(GraphError::ConnectionNotPossible),
+        };
+        if connections.len() >= max {
+            return Err(GraphError::MaxConnectionReached(mac.clone()));
+        }
+        Ok(())
+    }
+
     pub fn append_connection(&mut self, nic1: NIC, nic2: NIC) -> Result<(), GraphError> {
         if self.are_connected(nic1.mac.clone(), nic2.mac.clone()) {
             return Err(GraphError::ConnectionAlreadyExists);
         }
         for mac in &[nic1.mac.clone(), nic2.mac.clone()] {
-            let connections = self.connections(mac.clone());
-            let node_t = self.node_type_with_mac(mac.clone());
-            if let Some(t) = node_t {
-                match t {
-                    NodeType::Device => {
-                        if connections.len() >= 1 {
-                            return Err(GraphError::MaxConnectionReached(mac.clone()));
-                        }
-                    },
-                    NodeType::Router => {
-                        if connections.len() >= 2 {
-                            return Err(GraphError::MaxConnectionReached(mac.clone()));
-                        }
-                    }
-                }
-            } else {
-                return Err(GraphError::ConnectionNotPossible);
-            }
+            self.check_free_port(mac)?;
         }
         if !nic1.same_network(nic2.clone()) {
             return Err(GraphError::ConnectionNotPossible);
         }
         self.connections.push((nic1.mac.clone(), nic2.mac.clone()));
+        self.topology_changed();
         Ok(())
     }
 
+    // Addressing is checked by the caller against the NICs already on the switch, or left as it is for a
+    // device without address; the switch, if any, comes first
+    pub fn append_switch_connection(&mut self, switch: MAC, mac: MAC) -> Result<(), GraphError> {
+        if self.are_connected(switch.clone(), mac.clone()) {
+            return Err(GraphError::ConnectionAlreadyExists);
+        }
+        self.check_free_port(&switch)?;
+        self.check_free_port(&mac)?;
+        self.connections.push((switch, mac));
+        self.topology_changed();
+        Ok(())
+    }
+
+    // NICs sharing the VLAN `vlan` of `switch`, switches excluded
+    pub fn segment_nics(&self, switch: MAC, vlan: u16) -> Vec<NIC> {
+        self.reachable((switch, vlan), false).into_iter()
+            .filter_map(|((mac, _), _)| self.nic_with_mac(mac))
+            .collect()
+    }
+
+    // Access VLAN of the port `mac` is plugged in, the native VLAN for a trunk
+    pub fn port_vlan(&self, switch: MAC, mac: &MAC) -> u16 {
+        match self.search_switch_with_mac(switch) {
+            Some(switch) => switch.port_mode(mac).ingress(None).unwrap_or(DEFAULT_VLAN),
+            None => DEFAULT_VLAN
+        }
+    }
+
     pub fn append_internal_router_connection(&mut self, mac_lan: MAC, mac_wan: MAC) -> Result<(), GraphError> {
         if self.are_connected(mac_lan.clone(), mac_wan.clone()) {
             return Err(GraphError::ConnectionAlreadyExists);
         }
         self.connections.push((mac_lan.clone(), mac_wan.clone()));
+        self.topology_changed();
         Ok(())
     }
 
-    pub fn show(&self) {
-        println!("--- Network Graph ---");
-        println!("Nodes:");
-        for (mac, device_type) in &self.nodes {
-            println!("- {} ({})", mac, device_type);
+    pub fn remove_connection(&mut self, mac1: MAC, mac2: MAC) -> Result<(), GraphError> {
+        // The link between the LAN and WAN interfaces of a router is not a cable
+        if let Some((router, _)) = self.search_router_with_mac(mac1.clone())
+            && (router.nic_lan.mac == mac2 || router.nic_wan.mac == mac2) {
+            return Err(GraphError::ConnectionNotFound);
         }
-        println!("Connections:");
-        for (mac1, mac2) in &self.connections {
-            println!("- {} <-> {}", mac1, mac2);
+        match self.connections.iter().position(|(m1, m2)| (*m1 == mac1 && *m2 == mac2) || (*m1 == mac2 && *m2 == mac1)) {
+            Some(idx) => {
+                self.connections.remove(idx);
+                self.states.forget(&Element::Link(mac1, mac2));
+                self.topology_changed();
+                Ok(())
+            },
+            None => Err(GraphError::ConnectionNotFound)
         }
+    }
 
-        println!("\n--- Routers ---");
-        for router in &self.routers {
-            println!("{}", router);
-        }
-        println!("\n--- Devices ---");
-        for device in &self.devices {
-            println!("{}", device);
-        }
+    pub fn all_connections(&self) -> &Vec<(MAC, MAC)> {
+        &self.connections
     }
 
     pub fn neighbors(&self, mac: MAC) -> Vec<MAC> {
@@ -170,98 +341,201 @@ impl Graph {
         false
     }
 
+    // Cables of a node, without the internal link between the interfaces of a router
+    fn links(&self, mac: &MAC) -> Vec<MAC> {
+        let router = self.router_with_mac(mac);
+        self.neighbors(mac.clone()).into_iter().filter(|n| !router.is_some_and(|r| r.has_mac(n))).collect()
+    }
+
+    // Where a frame sent by `from`, tagged or not, ends up once received by `to`
+    fn deliver(&self, from: &MAC, to: MAC, tag: Option<u16>) -> Option<Hop> {
+        if !self.interface_up(&to) {
+            return None;
+        }
+        if let Some(switch) = self.switches.iter().find(|s| s.mac == to) {
+            return switch.port_mode(from).ingress(tag).map(|vlan| (to, vlan));
+        }
+        match tag {
+            None => Some((to, 0)),
+            // Only a router interface with a subinterface of that VLAN accepts tagged frames
+            Some(vlan) => {
+                let (router, interface) = self.search_router_with_mac(to)?;
+                let subinterface = router.subinterface(&interface, vlan)?;
+                self.interface_up(&subinterface.nic.mac).then(|| (subinterface.nic.mac.clone(), 0))
+            }
+        }
+    }
+
+    // With `routed`, a router also forwards frames from one of its interfaces to the others
+    fn next_hops(&self, (mac, vlan): &Hop, routed: bool, tree: &SpanningTree) -> Vec<Hop> {
+        let mut hops: Vec<Hop> = Vec::new();
+        if !self.interface_up(mac) {
+            return hops;
+        }
+        if let Some(switch) = self.switches.iter().find(|s| s.mac == *mac) {
+            for neighbor in self.neighbors(mac.clone()) {
+                if tree.blocked(mac, &neighbor) || !self.link_up(mac, &neighbor) {
+                    continue;
+                }
+                if let Some(tag) = switch.port_mode(&neighbor).egress(*vlan)
+                    && let Some(hop) = self.deliver(mac, neighbor, tag) {
+                    hops.push(hop);
+                }
+            }
+            return hops;
+        }
+        let (port, tag) = match self.search_subinterface_with_mac(mac.clone()) {
+            Some((router, subinterface)) => (router.nic(&subinterface.parent).mac.clone(), Some(subinterface.vlan)),
+            None => (mac.clone(), None)
+        };
+        for neighbor in self.links(&port) {
+            if self.link_up(&port, &neighbor) && let Some(hop) = self.deliver(&port, neighbor, tag) {
+                hops.push(hop);
+            }
+        }
+        if routed && let Some(router) = self.router_with_mac(mac) {
+            hops.extend(router.macs().into_iter().filter(|m| m != mac && self.interface_up(m)).map(|m| (m, 0)));
+        }
+        hops
+    }
+
+    // Every hop reachable from `start` in breadth first order, with the index of the hop it came from
+    fn reachable(&self, start: Hop, routed: bool) -> Vec<(Hop, Option<usize>)> {
+        let tree = self.spanning_tree();
+        let mut visited: Vec<(Hop, Option<usize>)> = vec![(start, None)];
+        let mut current = 0;
+        while current < visited.len() {
+            for hop in self.next_hops(&visited[current].0, routed, tree) {
+                if !visited.iter().any(|(h, _)| *h == hop) {
+                    visited.push((hop, Some(current)));
+                }
+            }
+            current += 1;
+        }
+        visited
+    }
+
+    // Kept until a change of the topology, a switch or a link state
+    pub fn spanning_tree(&self) -> &SpanningTree {
+        self.spanning_tree.get_or_init(|| SpanningTree::compute(self))
+    }
+
+    fn topology_changed(&mut self) {
+        self.spanning_tree.take();
+    }
+
     pub fn breadth_first_search(&self, start:MAC) -> Vec<MAC> {
         let mut visited: Vec<MAC> = Vec::new();
-        let mut queue: Vec<MAC> = Vec::new();
-        queue.push(start.clone());
-        visited.push(start.clone());
-
-        while !queue.is_empty() {
-            let mut queue_temp: Vec<MAC> = Vec::new();
-            for current_mac in &queue {
-                for neighbor in self.neighbors(current_mac.clone()) {
-                    if ! visited.contains(&neighbor) {
-                        queue_temp.push(neighbor.clone());
-                        visited.push(neighbor.clone());
-                    }
-                }
+        for ((mac, _), _) in self.reachable((start, 0), true) {
+            if !visited.contains(&mac) {
+                visited.push(mac);
             }
-            queue = queue_temp;
         }
         visited
     }
 
     pub fn breadth_first_search_ip(&self, start:MAC, ip: IP) -> bool {
-        let accessibles = self.breadth_first_search(start);
-        for mac in accessibles {
-            let t = self.node_type_with_mac(mac.clone());
-            match t {
-                Some(NodeType::Device) => {
-                    for device in &self.devices {
-                        if device.nic.mac == mac && device.nic.ip == ip {
-                            return true;
-                        }
-                    }
-                },
-                Some(NodeType::Router) => {
-                    for router in &self.routers {
-                        if router.nic_lan.mac == mac && router.nic_lan.ip == ip {
-                            return true;
-                        }
-                        if router.nic_wan.mac == mac && router.nic_wan.ip == ip {
-                            return true;
-                        }
-                    }
-                },
-                None => {}
-            }
+        self.breadth_first_search(start).into_iter()
+            .any(|mac| self.nic_with_mac(mac).is_some_and(|nic| nic.ip == ip))
+    }
+
+    fn path_where(&self, start: MAC, end: impl Fn(&MAC) -> bool) -> Option<Vec<Hop>> {
+        let visited = self.reachable((start, 0), true);
+        let mut cursor = visited.iter().position(|((mac, _), _)| end(mac));
+        let mut path: Vec<Hop> = Vec::new();
+        while let Some(idx) = cursor {
+            path.push(visited[idx].0.clone());
+            cursor = visited[idx].1;
         }
-        false
+        if path.is_empty() {
+            return None;
+        }
+        path.reverse();
+        Some(path)
     }
 
-    pub fn breadth_first_search_and_dhcp_connection(&mut self, nic_src:&mut NIC, nic_dest: &NIC) -> Result<IP, DhcpError> {
+    pub fn hop_path_to_ip(&self, start: MAC, ip: IP) -> Option<Vec<Hop>> {
+        self.path_where(start, |mac| self.nic_with_mac(mac.clone()).is_some_and(|nic| nic.ip == ip))
+    }
+
+    pub fn hop_path_to_mac(&self, start: MAC, end: &MAC) -> Option<Vec<Hop>> {
+        self.path_where(start, |mac| mac == end)
+    }
+
+    pub fn path_to_ip(&self, start: MAC, ip: IP) -> Option<Vec<MAC>> {
+        self.hop_path_to_ip(start, ip).map(|path| path.into_iter().map(|(mac, _)| mac).collect())
+    }
+
+    // Hops reached by a broadcast from `start`, with the index of the hop that forwarded it
+    pub fn flood(&self, start: MAC) -> Vec<(Hop, Option<usize>)> {
+        self.reachable((start, 0), false)
+    }
+
+    // The cable a frame crosses between two hops and its 802.1Q tag there, None within a router
+    pub fn wire(&self, from: &Hop, to: &Hop) -> Option<(MAC, MAC, Option<u16>)> {
+        if self.router_with_mac(&from.0).is_some_and(|r| r.has_mac(&to.0)) {
+            return None;
+        }
+        let port = |mac: &MAC| match self.search_subinterface_with_mac(mac.clone()) {
+            Some((router, subinterface)) => (router.nic(&subinterface.parent).mac.clone(), Some(subinterface.vlan)),
+            None => (mac.clone(), None),
+        };
+        let ((from_port, from_tag), (to_port, _)) = (port(&from.0), port(&to.0));
+        let tag = match self.switches.iter().find(|s| s.mac == from.0) {
+            Some(switch) => switch.port_mode(&to_port).egress(from.1).flatten(),
+            None => from_tag,
+        };
+        Some((from_port, to_port, tag))
+    }
+
+    // A name server set statically is kept when the pool has none
+    pub fn lease_name_server(&mut self, mac: &MAC, name_server: Option<IP>) {
+        if let Some(name_server) = name_server
+            && let Some(device) = self.devices.iter_mut().find(|d| d.nic.mac == *mac) {
+            device.name_server = Some(name_server);
+        }
+    }
+
+    // Returns the leased address with the name server of the pool, which the caller applies once connected
+    pub fn breadth_first_search_and_dhcp_connection(&mut self, nic_src:&mut NIC, nic_dest: &NIC) -> Result<(IP, Option<IP>), DhcpError> {
         let mut last_dhcp_error: DhcpError = DhcpError::NoDHCPServerFound;
         let accessibles = self.breadth_first_search(nic_dest.mac.clone());
         for mac in accessibles {
-            let router_idx = self.routers.iter().position(|r| (r.nic_lan.mac == mac) || (r.nic_wan.mac == mac));
-            if let Some(idx) = router_idx {
-                let interface: RouterInterface = if self.routers[idx].nic_lan.mac == mac {
-                    RouterInterface::LAN
-                } else {
-                    RouterInterface::WAN
-                };
+            let offer = if let Some((router, interface)) = self.search_router_with_mac(mac.clone()) {
                 let dhcp = match interface {
-                    RouterInterface::LAN => &self.routers[idx].dhcp_lan,
-                    RouterInterface::WAN => &self.routers[idx].dhcp_wan,
+                    RouterInterface::LAN => &router.dhcp_lan,
+                    RouterInterface::WAN => &router.dhcp_wan,
                 };
-                if dhcp.is_some() {
-                    let netmask = dhcp.as_ref().unwrap().netmask.clone();
-                    let tmp_graph = self.clone();
-                    let ip_r = self.routers[idx].get_next_dhcp_ip(&tmp_graph, interface);
-                    match ip_r {
-                        Ok(ip) => {
-                            let nic_test = NIC {
-                                mac: nic_src.mac.clone(),
-                                ip: ip.clone(),
-                                netmask: netmask.clone()
-                            };
-                            match nic_test.ip_address_type() {
-                                IpAddressType::HostAddress => {
-                                    nic_src.ip = ip.clone();
-                                    nic_src.netmask = netmask;
-                                    return Ok(ip);
-                                },
-                                IpAddressType::NetworkAddress => {
-                                    last_dhcp_error = DhcpError::ReturnedNetworkAddress;
-                                },
-                                IpAddressType::BroadcastAddress => {
-                                    last_dhcp_error = DhcpError::ReturnedBroadcastAddress;
-                                }
+                dhcp.as_ref().map(|d| (d.netmask.clone(), d.name_server.clone(), router.get_next_dhcp_ip(self, interface.clone())))
+            } else if let Some((_, subinterface)) = self.search_subinterface_with_mac(mac.clone()) {
+                subinterface.dhcp.as_ref().map(|d| (d.netmask.clone(), d.name_server.clone(), subinterface.get_next_dhcp_ip(self)))
+            } else {
+                None
+            };
+            if let Some((netmask, name_server, ip_r)) = offer {
+                match ip_r {
+                    Ok(ip) => {
+                        let nic_test = NIC {
+                            mac: nic_src.mac.clone(),
+                            ip: ip.clone(),
+                            netmask: netmask.clone()
+                        };
+                        match nic_test.ip_address_type() {
+                            IpAddressType::HostAddress => {
+                                nic_src.ip = ip.clone();
+                                nic_src.netmask = netmask;
+                                return Ok((ip, name_server));
+                            },
+                            IpAddressType::NetworkAddress => {
+                                last_dhcp_error = DhcpError::ReturnedNetworkAddress;
+                            },
+                            IpAddressType::BroadcastAddress => {
+                                last_dhcp_error = DhcpError::ReturnedBroadcastAddress;
                             }
-                        },
-                        Err(e) => {
-                            last_dhcp_error = e;
                         }
+                    },
+                    Err(e) => {
+                        last_dhcp_error = e;
                     }
                 }
             }
@@ -269,6 +543,68 @@ impl Graph {
         Err(last_dhcp_error)
     }
 
+    pub fn name_exists(&self, name: &str) -> bool {
+        self.routers.iter().any(|r| r.name == name) || self.devices.iter().any(|d| d.name == name)
+            || self.switches.iter().any(|s| s.name == name)
+    }
+
+    // Accepts a MAC address, a device name, or a router interface written name.lan / name.wan / name.lan.10
+    pub fn resolve(&self, reference: &str) -> Result<MAC, GraphError> {
+        if let Some(mac) = MAC::from_string(reference) && self.node_type_with_mac(mac.clone()).is_some() {
+            return Ok(mac);
+        }
+        if let Some(device) = self.devices.iter().find(|d| d.name == reference) {
+            return Ok(device.nic.mac.clone());
+        }
+        if let Some(switch) = self.switches.iter().find(|s| s.name == reference) {
+            return Ok(switch.mac.clone());
+        }
+        if self.routers.iter().any(|r| r.name == reference) {
+            return Err(GraphError::InterfaceRequired(reference.to_string()));
+        }
+        if let Some((name, interface)) = reference.rsplit_once('.')
+            && let Some(router) = self.routers.iter().find(|r| r.name == name) {
+            match interface.to_lowercase().as_str() {
+                "lan" => return Ok(router.nic_lan.mac.clone()),
+                "wan" => return Ok(router.nic_wan.mac.clone()),
+                _ => {}
+            }
+        }
+        if let Some((parent, vlan)) = reference.rsplit_once('.')
+            && let Ok(vlan) = vlan.parse::<u16>()
+            && let Ok(parent) = self.resolve(parent)
+            && let Some((router, interface)) = self.search_router_with_mac(parent)
+            && let Some(subinterface) = router.subinterface(&interface, vlan) {
+            return Ok(subinterface.nic.mac.clone());
+        }
+        Err(GraphError::UnknownNode(reference.to_string()))
+    }
+
+    // Like `resolve`, a router being designated by its name alone
+    pub fn resolve_node(&self, reference: &str) -> Result<MAC, GraphError> {
+        match self.routers.iter().find(|r| r.name == reference) {
+            Some(router) => Ok(router.nic_lan.mac.clone()),
+            None => self.resolve(reference),
+        }
+    }
+
+    pub fn name_with_mac(&self, mac: MAC) -> Option<String> {
+        if let Some((router, interface)) = self.search_router_with_mac(mac.clone()) {
+            return Some(match interface {
+                RouterInterface::LAN => format!("{}.lan", router.name),
+                RouterInterface::WAN => format!("{}.wan", router.name),
+            });
+        }
+        if let Some((router, subinterface)) = self.search_subinterface_with_mac(mac.clone()) {
+            let parent = self.name_with_mac(router.nic(&subinterface.parent).mac.clone())?;
+            return Some(format!("{}.{}", parent, subinterface.vlan));
+        }
+        if let Some(switch) = self.search_switch_with_mac(mac.clone()) {
+            return Some(switch.name);
+        }
+        self.search_device_with_mac(mac).map(|d| d.name)
+    }
+
     pub fn node_type_with_mac(&self, mac: MAC) -> Option<NodeType> {
         for (node_mac, device_type) in &self.nodes {
             if *node_mac == mac {
@@ -278,7 +614,7 @@ impl Graph {
         None
     }
 
-    fn search_router_with_mac(&self, mac: MAC) -> Option<(Router, RouterInterface)> {
+    pub fn search_router_with_mac(&self, mac: MAC) -> Option<(Router, RouterInterface)> {
         for router in &self.routers {
             if router.nic_lan.mac == mac {
                 return Some((router.clone(), RouterInterface::LAN));
@@ -290,7 +626,25 @@ impl Graph {
         None
     }
 
-    fn search_device_with_mac(&self, mac: MAC) -> Option<Device> {
+    pub fn search_subinterface_with_mac(&self, mac: MAC) -> Option<(Router, SubInterface)> {
+        for router in &self.routers {
+            if let Some(subinterface) = router.subinterfaces.iter().find(|s| s.nic.mac == mac) {
+                return Some((router.clone(), subinterface.clone()));
+            }
+        }
+        None
+    }
+
+    pub fn subinterface_mut_with_mac(&mut self, mac: MAC) -> Option<&mut SubInterface> {
+        self.routers.iter_mut().flat_map(|r| r.subinterfaces.iter_mut()).find(|s| s.nic.mac == mac)
+    }
+
+    // The router owning an interface or a subinterface
+    pub fn router_with_mac(&self, mac: &MAC) -> Option<&Router> {
+        self.routers.iter().find(|r| r.has_mac(mac))
+    }
+
+    pub fn search_device_with_mac(&self, mac: MAC) -> Option<Device> {
         for device in &self.devices {
             if device.nic.mac == mac {
                 return Some(device.clone());
@@ -299,8 +653,12 @@ impl Graph {
         None
     }
 
+    pub fn search_switch_with_mac(&self, mac: MAC) -> Option<Switch> {
+        self.switches.iter().find(|s| s.mac == mac).cloned()
+    }
+
     pub fn nic_with_mac(&self, mac: MAC) -> Option<NIC> {
-        return match self.node_type_with_mac(mac.clone()) {
+        match self.node_type_with_mac(mac.clone()) {
             Some(NodeType::Router) => {
                 let router = self.search_router_with_mac(mac.clone());
                 match router {
@@ -310,7 +668,7 @@ impl Graph {
                             RouterInterface::WAN => r.nic_wan.clone(),
                         }
                     )},
-                    None => None
+                    None => self.search_subinterface_with_mac(mac.clone()).map(|(_, s)| s.nic)
                 }
             },
             Some(NodeType::Device) => {
@@ -320,8 +678,8 @@ impl Graph {
                     None => None
                 }
             },
-            None => None
-        };
+            Some(NodeType::Switch) | None => None
+        }
     }
 
     pub fn update_nic(&mut self, mac: MAC, new_nic: NIC) -> Result<(), GraphError> {
@@ -336,6 +694,10 @@ impl Graph {
                         router.nic_wan = new_nic;
                         return Ok(());
                     }
+                    if let Some(subinterface) = router.subinterfaces.iter_mut().find(|s| s.nic.mac == mac) {
+                        subinterface.nic = new_nic;
+                        return Ok(());
+                    }
                 }
                 Err(GraphError::ConnectionNotPossible)
             },
@@ -348,250 +710,866 @@ impl Graph {
                 }
                 Err(GraphError::ConnectionNotPossible)
             },
-            None => Err(GraphError::ConnectionNotPossible)
+            Some(NodeType::Switch) | None => Err(GraphError::ConnectionNotPossible)
         }
     }
 
-    pub fn load_data(&mut self, loaded_data: LoadedData) {
-        let mut routers_loaded: u32 = 0;
-        let mut devices_loaded: u32 = 0;
-        let mut connections_loaded: u32 = 0;
-        let loaded_routers = match loaded_data.routers {
-            Some(e) => e,
-            _ => {Vec::new()}
-        };
-        let loaded_devices = match loaded_data.devices {
-            Some(e) => e,
-            _ => {Vec::new()}
+    pub fn configure_nic(&mut self, mac: MAC, ip: IP, netmask: IP) -> Result<(), GraphError> {
+        let mut nic = match self.nic_with_mac(mac.clone()) {
+            Some(nic) => nic,
+            None => return Err(GraphError::NodeNotFound(mac))
         };
-        let loaded_connections = match loaded_data.connections {
-            Some(e) => e,
-            _ => {Vec::new()}
+        nic.ip = ip;
+        nic.netmask = netmask;
+        self.check_segment(&nic)?;
+        self.update_nic(mac, nic)
+    }
+
+    // Every NIC sharing the VLAN of `nic` has to be on its network
+    fn check_segment(&self, nic: &NIC) -> Result<(), GraphError> {
+        for ((other, _), _) in self.reachable((nic.mac.clone(), 0), false) {
+            if other != nic.mac && let Some(other_nic) = self.nic_with_mac(other.clone()) && !nic.same_network(other_nic) {
+                return Err(GraphError::NeighborOnOtherNetwork(other));
+            }
+        }
+        Ok(())
+    }
+
+    pub fn append_subinterface(&mut self, router: MAC, subinterface: SubInterface) -> Result<(), GraphError> {
+        if !valid_vlan(subinterface.vlan) {
+            return Err(GraphError::InvalidVlan(subinterface.vlan));
+        }
+        if self.node_type_with_mac(subinterface.nic.mac.clone()).is_some() {
+            return Err(GraphError::AlreadyExistingMacAddress);
+        }
+        let name = self.name_with_mac(router.clone()).unwrap_or_default();
+        let r = match self.routers.iter_mut().find(|r| r.has_mac(&router)) {
+            Some(r) => r,
+            None => return Err(GraphError::NodeNotFound(router))
         };
+        if r.subinterface(&subinterface.parent, subinterface.vlan).is_some() {
+            return Err(GraphError::AlreadyExistingName(format!("{}.{}", name, subinterface.vlan)));
+        }
+        self.nodes.push((subinterface.nic.mac.clone(), NodeType::Router));
+        r.subinterfaces.push(subinterface);
+        Ok(())
+    }
 
-        for r in loaded_routers {
-            let dhcp_lan_first_ip: Option<IP> = match &r.lan.dhcp {
-                Some(dhcp) => match IP::from_string(dhcp.first_ip.as_str()) {
-                    Some(ip) => Some(ip), None => {
-                        println!("{}Skipping{} DHCP for router {} LAN due to invalid first IP.", Colors::YELLOW, Colors::RESET, r.name);
-                        None
-                    }
-                }, _ => None,
-            };
-            let dhcp_lan_last_ip: Option<IP> = match &r.lan.dhcp {
-                Some(dhcp) => match IP::from_string(dhcp.last_ip.as_str()) {
-                    Some(ip) => Some(ip), None => {
-                        println!("{}Skipping{} DHCP for router {} LAN due to invalid last IP.", Colors::YELLOW, Colors::RESET, r.name);
-                        None
-                    }
-                }, _ => None,
-            };
-            let dhcp_wan_first_ip: Option<IP> = match &r.wan.dhcp {
-                Some(dhcp) => match IP::from_string(dhcp.first_ip.as_str()) {
-                    Some(ip) => Some(ip), None => {
-                        println!("{}Skipping{} DHCP for router {} WAN due to invalid first IP.", Colors::YELLOW, Colors::RESET, r.name);
-                        None
-                    }
-                }, _ => None,
-            };
-            let dhcp_wan_last_ip: Option<IP> = match &r.wan.dhcp {
-                Some(dhcp) => match IP::from_string(dhcp.last_ip.as_str()) {
-                    Some(ip) => Some(ip), None => {
-                        println!("{}Skipping{} DHCP for router {} WAN due to invalid last IP.", Colors::YELLOW, Colors::RESET, r.name);
-                        None
-                    }
-                }, _ => None,
-            };
-            let nic_lan = NIC {
-                mac: match r.lan.mac {
-                    Some(mac_str) => match MAC::from_string(mac_str.as_str()) {
-                        Some(mac) => mac, None => {
-                            println!("{}Skipping{} router {} due to invalid LAN MAC.", Colors::YELLOW, Colors::RESET, r.name);
-                            continue;
-                        }
-                    }, None => {
-                        MAC::new()
-                    }
-                },
-                ip: match IP::from_string(r.lan.ip.as_str()) {
-                    Some(ip) => ip, None => {
-                        println!("{}Skipping{} router {} due to invalid LAN IP.", Colors::YELLOW, Colors::RESET, r.name);
-                        continue;
-                    },
-                },
-                netmask: IP::from_cidr(r.lan.netmask),
-            };
-            let nic_wan = NIC {
-                mac: match r.wan.mac {
-                    Some(mac_str) => match MAC::from_string(mac_str.as_str()) {
-                        Some(mac) => mac, None => {
-                            println!("{}Skipping{} router {} due to invalid WAN MAC.", Colors::YELLOW, Colors::RESET, r.name);
-                            continue;
-                        }
-                    }, None => {
-                        MAC::new()
-                    }
-                },
-                ip: match IP::from_string(r.wan.ip.as_str()) {
-                    Some(ip) => ip, None => {
-                        println!("{}Skipping{} router {} due to invalid WAN IP.", Colors::YELLOW, Colors::RESET, r.name);
-                        continue;
-                    },
-                },
-                netmask: IP::from_cidr(r.wan.netmask),
-            };
-            match self.append_router( Router {
-                name: r.name.clone(),
-                nic_lan: nic_lan.clone(),
-                nic_wan: nic_wan.clone(),
-                dhcp_lan: match (dhcp_lan_first_ip, dhcp_lan_last_ip) {
-                    (Some(first_ip), Some(last_ip)) => match DHCP::new(
-                        nic_lan,
-                        first_ip,
-                        last_ip
-                    ) { Ok(dhcp) => Some(dhcp),
-                        Err(e) => {
-                            println!("{}Skipping{} DHCP for router {} LAN due to error: {}", Colors::YELLOW, Colors::RESET, r.name, e);
-                            None
-                        }
-                    }, _ => None,
-                },
-                dhcp_wan: match (dhcp_wan_first_ip, dhcp_wan_last_ip) {
-                    (Some(first_ip), Some(last_ip)) => match DHCP::new(
-                        nic_wan,
-                        first_ip,
-                        last_ip
-                    ) { Ok(dhcp) => Some(dhcp),
-                        Err(e) => {
-                            println!("{}Skipping{} DHCP for router {} WAN due to error: {}", Colors::YELLOW, Colors::RESET, r.name, e);
-                            None
-                        }
-                    }, _ => None,
+    pub fn remove_subinterface(&mut self, mac: MAC) -> Result<(), GraphError> {
+        for router in &mut self.routers {
+            if let Some(idx) = router.subinterfaces.iter().position(|s| s.nic.mac == mac) {
+                router.subinterfaces.remove(idx);
+                self.nodes.retain(|(m, _)| *m != mac);
+                self.states.forget_mac(&mac);
+                self.topology_changed();
+                return Ok(());
+            }
+        }
+        Err(GraphError::NodeNotFound(mac))
+    }
+
+    // Routers are designated by their LAN interface, whichever interface is given
+    pub fn node_key(&self, mac: &MAC) -> MAC {
+        match self.router_with_mac(mac) {
+            Some(router) => router.nic_lan.mac.clone(),
+            None => mac.clone(),
+        }
+    }
+
+    fn element_key(&self, element: Element) -> Result<Element, GraphError> {
+        match element {
+            Element::Link(a, b) => {
+                if !self.are_connected(a.clone(), b.clone()) || self.router_with_mac(&a).is_some_and(|r| r.has_mac(&b)) {
+                    return Err(GraphError::ConnectionNotFound);
                 }
-            }) {
-                Ok(_) => {routers_loaded += 1;},
-                Err(e) => {
-                    println!("{}Error{} adding router {}: {}", Colors::RED, Colors::RESET, r.name, e);
+                Ok(Element::Link(a, b))
+            },
+            Element::Interface(mac) => match self.nic_with_mac(mac.clone()) {
+                Some(_) => Ok(Element::Interface(mac)),
+                None => Err(GraphError::NodeNotFound(mac))
+            },
+            Element::Node(mac) => match self.node_type_with_mac(mac.clone()) {
+                Some(_) => Ok(Element::Node(self.node_key(&mac))),
+                None => Err(GraphError::NodeNotFound(mac))
+            }
+        }
+    }
+
+    pub fn set_shutdown(&mut self, element: Element, shutdown: bool) -> Result<(), GraphError> {
+        let element = self.element_key(element)?;
+        self.states.set_shutdown(element, shutdown);
+        self.topology_changed();
+        Ok(())
+    }
+
+    pub fn set_failed(&mut self, element: Element, failed: bool) -> Result<(), GraphError> {
+        let element = self.element_key(element)?;
+        self.states.set_failed(element, failed);
+        self.topology_changed();
+        Ok(())
+    }
+
+    pub fn is_shutdown(&self, element: &Element) -> bool {
+        match element {
+            Element::Node(mac) => self.states.is_shutdown(&Element::Node(self.node_key(mac))),
+            _ => self.states.is_shutdown(element),
+        }
+    }
+
+    pub fn is_failed(&self, element: &Element) -> bool {
+        match element {
+            Element::Node(mac) => self.states.is_failed(&Element::Node(self.node_key(mac))),
+            _ => self.states.is_failed(element),
+        }
+    }
+
+    pub fn admin_state(&self, element: &Element) -> State {
+        State::from_up(!self.is_shutdown(element))
+    }
+
+    pub fn oper_state(&self, element: &Element) -> State {
+        State::from_up(match element {
+            Element::Link(a, b) => self.link_up(a, b),
+            Element::Interface(mac) => self.interface_up(mac),
+            Element::Node(mac) => !self.states.is_down(&Element::Node(self.node_key(mac))),
+        })
+    }
+
+    // An interface of a switch is the switch itself, a subinterface also depends on its parent
+    pub fn interface_up(&self, mac: &MAC) -> bool {
+        if self.states.is_down(&Element::Node(self.node_key(mac))) || self.states.is_down(&Element::Interface(mac.clone())) {
+            return false;
+        }
+        match self.search_subinterface_with_mac(mac.clone()) {
+            Some((router, subinterface)) => self.interface_up(&router.nic(&subinterface.parent).mac),
+            None => true,
+        }
+    }
+
+    pub fn link_up(&self, mac1: &MAC, mac2: &MAC) -> bool {
+        !self.states.is_down(&Element::Link(mac1.clone(), mac2.clone())) && self.interface_up(mac1) && self.interface_up(mac2)
+    }
+
+    pub fn set_bridge_priority(&mut self, switch: MAC, priority: u16) -> Result<(), GraphError> {
+        if !valid_priority(priority) {
+            return Err(GraphError::InvalidBridgePriority(priority));
+        }
+        match self.switches.iter_mut().find(|s| s.mac == switch) {
+            Some(s) => {
+                s.priority = priority;
+                self.topology_changed();
+                Ok(())
+            },
+            None => Err(GraphError::NodeNotFound(switch))
+        }
+    }
+
+    // `port` is the node plugged in the port, its addressing has to match the VLANs it joins
+    pub fn set_port_mode(&mut self, switch: MAC, port: MAC, mode: PortMode) -> Result<(), GraphError> {
+        let vlans = match &mode {
+            PortMode::Access(vlan) => vec![*vlan],
+            PortMode::Trunk(vlans) => vlans.clone(),
+        };
+        if let Some(vlan) = vlans.into_iter().find(|v| !valid_vlan(*v)) {
+            return Err(GraphError::InvalidVlan(vlan));
+        }
+        if self.node_type_with_mac(port.clone()).is_none() {
+            return Err(GraphError::NodeNotFound(port));
+        }
+        let previous = match self.switches.iter_mut().find(|s| s.mac == switch) {
+            Some(s) => {
+                let previous = s.port_mode(&port);
+                s.set_port_mode(port.clone(), mode);
+                previous
+            },
+            None => return Err(GraphError::NodeNotFound(switch))
+        };
+        let mut nics: Vec<NIC> = self.nic_with_mac(port.clone()).into_iter().collect();
+        if let Some((router, interface)) = self.search_router_with_mac(port.clone()) {
+            nics.extend(router.subinterfaces.into_iter().filter(|s| s.parent == interface).map(|s| s.nic));
+        }
+        for nic in nics {
+            if let Err(e) = self.check_segment(&nic) {
+                if let Some(s) = self.switches.iter_mut().find(|s| s.mac == switch) {
+                    s.set_port_mode(port, previous);
                 }
+                return Err(e);
             }
         }
+        Ok(())
+    }
+
+    pub fn rename_node(&mut self, mac: MAC, name: String) -> Result<(), GraphError> {
+        if self.name_exists(&name) {
+            return Err(GraphError::AlreadyExistingName(name));
+        }
+        for router in &mut self.routers {
+            if router.has_mac(&mac) {
+                router.name = name;
+                return Ok(());
+            }
+        }
+        for switch in &mut self.switches {
+            if switch.mac == mac {
+                switch.name = name;
+                return Ok(());
+            }
+        }
+        for device in &mut self.devices {
+            if device.nic.mac == mac {
+                device.name = name;
+                return Ok(());
+            }
+        }
+        Err(GraphError::NodeNotFound(mac))
+    }
+
+    pub fn router_mut_with_mac(&mut self, mac: MAC) -> Option<(&mut Router, RouterInterface)> {
+        for router in &mut self.routers {
+            if router.nic_lan.mac == mac {
+                return Some((router, RouterInterface::LAN));
+            }
+            if router.nic_wan.mac == mac {
+                return Some((router, RouterInterface::WAN));
+            }
+        }
+        None
+    }
+
+    pub fn load_data(&mut self, loaded_data: LoadedData) -> LoadReport {
+        let mut report = LoadReport::new();
+        if let Some(seed) = loaded_data.seed {
+            self.random.reseed(seed);
+        }
+        if let Some(macs) = &loaded_data.macs {
+            for (node, oui) in macs.ouis() {
+                self.mac_generator.set_oui(&node, MAC::oui_from_string(oui));
+            }
+        }
+        let loaded_routers = loaded_data.routers.unwrap_or_default();
+        let loaded_devices = loaded_data.devices.unwrap_or_default();
+        let loaded_switches = loaded_data.switches.unwrap_or_default();
+        let loaded_connections = loaded_data.connections.unwrap_or_default();
+
+        let mut deferred = Deferred::new();
+        for r in loaded_routers {
+            report.entries.push(self.load_router_with(&r, &mut deferred));
+        }
 
         for d in loaded_devices {
-            match self.append_device( Device {
-                name: d.name.clone(),
-                nic: NIC {
-                    mac: match d.mac {
-                        Some(mac_str) => match MAC::from_string(mac_str.as_str()) {
-                            Some(mac) => mac, None => {
-                                println!("{}Skipping{} device {} due to invalid MAC.", Colors::YELLOW, Colors::RESET, d.name);
-                                continue;
-                            }
-                        }, None => {
-                            MAC::new()
-                        }
-                    },
-                    ip: match d.ip {
-                        Some(ip_str) => match IP::from_string(ip_str.as_str()) {
-                            Some(ip) => ip, None => {
-                                println!("{}Skipping{} device {} due to invalid IP.", Colors::YELLOW, Colors::RESET, d.name);
-                                continue;
-                            }
-                        }, None => {
-                            IP::V4(127, 0, 0, 1)
-                        }
-                    },
-                    netmask: match d.netmask {
-                        Some(cidr) => IP::from_cidr(cidr),
-                        None => IP::from_cidr(8),
-                    },
-                }
-            } ) {
-                Ok(_) => {devices_loaded += 1;},
-                Err(e) => {
-                    println!("{}Error{} adding device {}: {}", Colors::RED, Colors::RESET, d.name, e);
+            report.entries.push(self.load_device_with(&d, &mut deferred));
+        }
+
+        let mut loaded_ports: Vec<(usize, String, Vec<LoadedSwitchPort>)> = Vec::new();
+        for mut s in loaded_switches {
+            let ports = s.vlans.take();
+            let name = s.name.clone();
+            report.entries.push(self.load_switch_with(&s, &mut deferred));
+            if let Some(ports) = ports && report.entries.last().is_some_and(|e| e.accepted()) {
+                loaded_ports.push((report.entries.len() - 1, name, ports));
+            }
+        }
+
+        // Once every node exists, as ports may designate switches declared later, and before connections get their DHCP addresses
+        for (idx, name, ports) in loaded_ports {
+            for port in ports {
+                if let Err(issue) = self.load_switch_port(&name, port) {
+                    report.entries[idx].warnings.push(issue);
                 }
             }
         }
 
         for c in loaded_connections {
-            let mac_src = match MAC::from_string(&c.from) {
-                Some(mac) => mac, None => {
-                    println!("{}Skipping{} connection from {} to {} due to invalid source MAC.", Colors::YELLOW, Colors::RESET, c.from, c.to);
-                    continue;
-                }
-            };
-            let mac_dest = match MAC::from_string(&c.to) {
-                Some(mac) => mac, None => {
-                    println!("{}Skipping{} connection from {} to {} due to invalid destination MAC.", Colors::YELLOW, Colors::RESET, c.from, c.to);
-                    continue;
+            report.entries.push(self.load_connection_with(&c, &mut deferred));
+        }
+        self.apply_deferred(deferred);
+        report
+    }
+
+    // Inverse of `load_data`, with explicit MAC addresses so that a saved topology reloads identically
+    pub fn to_loaded_data(&self) -> LoadedData {
+        let flag = |set: bool| set.then_some(true);
+        let pool = |dhcp: &Option<DHCP>| dhcp.as_ref().map(|d| LoadedRouterDHCP {
+            first_ip: d.first_ip.to_ddn(),
+            last_ip: d.last_ip.to_ddn(),
+            name_server: d.name_server.as_ref().map(IP::to_ddn)
+        });
+        let zone = |zone: &Option<Vec<Record>>| zone.as_ref().map(|records| records.iter().map(Record::to_loaded).collect());
+        let interface = |r: &Router, interface: RouterInterface, dhcp: &Option<DHCP>| {
+            let nic = r.nic(&interface);
+            let subinterfaces: Vec<LoadedSubInterface> = r.subinterfaces.iter().filter(|s| s.parent == interface).map(|s| LoadedSubInterface {
+                vlan: s.vlan,
+                ip: s.nic.ip.to_ddn(),
+                netmask: s.nic.netmask_u8(),
+                mac: Some(s.nic.mac.to_hex()),
+                dhcp: pool(&s.dhcp),
+                shutdown: flag(self.is_shutdown(&Element::Interface(s.nic.mac.clone()))),
+            }).collect();
+            LoadedRouterInterface {
+                ip: nic.ip.to_ddn(),
+                netmask: nic.netmask_u8(),
+                mac: Some(nic.mac.to_hex()),
+                dhcp: pool(dhcp),
+                subinterfaces: if subinterfaces.is_empty() { None } else { Some(subinterfaces) },
+                shutdown: flag(self.is_shutdown(&Element::Interface(nic.mac.clone()))),
+            }
+        };
+        let routers: Vec<LoadedRouter> = self.routers.iter().map(|r| LoadedRouter {
+            name: r.name.clone(),
+            lan: interface(r, RouterInterface::LAN, &r.dhcp_lan),
+            wan: interface(r, RouterInterface::WAN, &r.dhcp_wan),
+            zone: zone(&r.zone),
+            failed: flag(self.is_failed(&Element::Node(r.nic_lan.mac.clone()))),
+        }).collect();
+        let devices: Vec<LoadedDevices> = self.devices.iter().map(|d| LoadedDevices {
+            name: d.name.clone(),
+            ip: Some(d.nic.ip.to_ddn()),
+            netmask: Some(d.nic.netmask_u8()),
+            mac: Some(d.nic.mac.to_hex()),
+            name_server: d.name_server.as_ref().map(IP::to_ddn),
+            zone: zone(&d.zone),
+            services: Some(d.services.iter().map(Service::to_loaded).collect::<Vec<_>>()).filter(|s| !s.is_empty()),
+            shutdown: flag(self.is_shutdown(&Element::Interface(d.nic.mac.clone()))),
+            failed: flag(self.is_failed(&Element::Node(d.nic.mac.clone()))),
+        }).collect();
+        let switches: Vec<LoadedSwitch> = self.switches.iter().map(|s| {
+            let ports: Vec<LoadedSwitchPort> = s.port_modes.iter().filter_map(|(mac, mode)| {
+                let port = self.name_with_mac(mac.clone())?;
+                Some(match mode {
+                    PortMode::Access(vlan) => LoadedSwitchPort { port, access: Some(*vlan), trunk: None },
+                    PortMode::Trunk(vlans) => LoadedSwitchPort { port, access: None, trunk: Some(vlans.clone()) },
+                })
+            }).collect();
+            LoadedSwitch {
+                name: s.name.clone(),
+                ports: Some(s.ports),
+                mac: Some(s.mac.to_hex()),
+                priority: if s.priority == DEFAULT_PRIORITY { None } else { Some(s.priority) },
+                vlans: if ports.is_empty() { None } else { Some(ports) },
+                failed: flag(self.is_failed(&Element::Node(s.mac.clone()))),
+            }
+        }).collect();
+        let mut connections: Vec<LoadedConnections> = Vec::new();
+        for (mac1, mac2) in &self.connections {
+            if self.router_with_mac(mac1).is_some_and(|r| r.has_mac(mac2)) {
+                continue;
+            }
+            if let (Some(from), Some(to)) = (self.name_with_mac(mac1.clone()), self.name_with_mac(mac2.clone())) {
+                let link = Element::Link(mac1.clone(), mac2.clone());
+                connections.push(LoadedConnections { from, to, shutdown: flag(self.is_shutdown(&link)), failed: flag(self.is_failed(&link)) });
+            }
+        }
+        LoadedData {
+            routers: Some(routers),
+            devices: Some(devices),
+            switches: if switches.is_empty() { None } else { Some(switches) },
+            connections: Some(connections),
+            seed: Some(self.random.seed()),
+            macs: self.mac_generator.to_loaded(),
+            ..LoadedData::new()
+        }
+    }
+
+    fn load_mac(&mut self, mac: &Option<String>, field: &str, node: &NodeType) -> Result<MAC, LoadIssue> {
+        match mac {
+            Some(mac_str) => MAC::from_string(mac_str.as_str())
+                .ok_or(LoadIssue::InvalidMac { field: field.to_string(), value: mac_str.clone() }),
+            None => Ok(self.new_mac(node))
+        }
+    }
+
+    fn load_ip(ip: &str, field: &str) -> Result<IP, LoadIssue> {
+        IP::from_string(ip).ok_or(LoadIssue::InvalidIp { field: field.to_string(), value: ip.to_string() })
+    }
+
+    fn load_router_nic(&mut self, r: &LoadedRouter, interface: RouterInterface) -> Result<NIC, LoadIssue> {
+        let loaded = match interface {
+            RouterInterface::LAN => &r.lan,
+            RouterInterface::WAN => &r.wan,
+        };
+        let mac = self.load_mac(&loaded.mac, &format!("{} MAC", interface), &NodeType::Router)?;
+        let ip = Self::load_ip(&loaded.ip, &format!("{} IP", interface))?;
+        Ok(NIC { mac, ip, netmask: IP::from_cidr(loaded.netmask) })
+    }
+
+    // A broken DHCP pool does not prevent the router from being created
+    fn load_router_dhcp(dhcp: &Option<LoadedRouterDHCP>, nic: &NIC, interface: &str, warnings: &mut Vec<LoadIssue>) -> Option<DHCP> {
+        let dhcp = dhcp.as_ref()?;
+        let range = Self::load_ip(&dhcp.first_ip, &format!("{} DHCP first IP", interface))
+            .and_then(|first_ip| Self::load_ip(&dhcp.last_ip, &format!("{} DHCP last IP", interface)).map(|last_ip| (first_ip, last_ip)));
+        let (first_ip, last_ip) = match range {
+            Ok(range) => range,
+            Err(issue) => {
+                warnings.push(issue);
+                return None;
+            }
+        };
+        let name_server = match &dhcp.name_server {
+            Some(ip) => match Self::load_ip(ip, &format!("{} DHCP name server", interface)) {
+                Ok(ip) => Some(ip),
+                Err(issue) => {
+                    warnings.push(issue);
+                    None
                 }
-            };
-            match connection_with_mac(self,
-                mac_src.clone(),
-                mac_dest.clone()
-            ) {
-                true => { connections_loaded += 1;},
-                false => {
-                    println!("Trying to reconnect with reversed order of MACs...");
-                    match connection_with_mac(self, mac_dest.clone(), mac_src.clone()) {
-                        true => { connections_loaded += 1; },
-                        false => println!("{}Error{} connecting {} to {} (see above)", Colors::RED, Colors::RESET, c.from, c.to)
-                    }
+            },
+            None => None
+        };
+        match DHCP::new(nic.clone(), first_ip, last_ip) {
+            Ok(dhcp) => Some(DHCP { name_server, ..dhcp }),
+            Err(e) => {
+                warnings.push(LoadIssue::DhcpError(interface.to_string(), e));
+                None
+            }
+        }
+    }
+
+    // Invalid records are skipped, the server running with the others
+    fn load_zone(zone: &Option<Vec<LoadedDnsRecord>>, warnings: &mut Vec<LoadIssue>) -> Option<Vec<Record>> {
+        let mut records: Vec<Record> = Vec::new();
+        for loaded in zone.as_ref()? {
+            match Record::from_loaded(loaded) {
+                Ok(record) => records.push(record),
+                Err(e) => warnings.push(LoadIssue::DnsError(e))
+            }
+        }
+        Some(records)
+    }
+
+    // Like records, invalid services are skipped and so is a second service on the same port
+    fn load_services(services: &Option<Vec<LoadedService>>, warnings: &mut Vec<LoadIssue>) -> Vec<Service> {
+        let mut loaded_services: Vec<Service> = Vec::new();
+        for loaded in services.iter().flatten() {
+            match Service::from_loaded(loaded) {
+                Ok(service) if loaded_services.iter().any(|s| s.transport == service.transport && s.port == service.port) =>
+                    warnings.push(LoadIssue::ServiceError(ServiceError::PortInUse(service.transport, service.port))),
+                Ok(service) => loaded_services.push(service),
+                Err(e) => warnings.push(LoadIssue::ServiceError(e))
+            }
+        }
+        loaded_services
+    }
+
+    // Broken subinterfaces are skipped, like broken DHCP pools
+    fn load_subinterfaces(&mut self, loaded: &LoadedRouterInterface, parent: RouterInterface, warnings: &mut Vec<LoadIssue>) -> Vec<SubInterface> {
+        let mut subinterfaces: Vec<SubInterface> = Vec::new();
+        for s in loaded.subinterfaces.iter().flatten() {
+            let label = format!("{}.{}", parent, s.vlan);
+            if !valid_vlan(s.vlan) || subinterfaces.iter().any(|other| other.vlan == s.vlan) {
+                warnings.push(LoadIssue::InvalidVlan { field: format!("{} subinterface VLAN", parent), value: s.vlan.to_string() });
+                continue;
+            }
+            let nic = self.load_mac(&s.mac, &format!("{} MAC", label), &NodeType::Router)
+                .and_then(|mac| Self::load_ip(&s.ip, &format!("{} IP", label)).map(|ip| NIC { mac, ip, netmask: IP::from_cidr(s.netmask) }));
+            match nic {
+                Ok(nic) => subinterfaces.push(SubInterface {
+                    parent: parent.clone(),
+                    vlan: s.vlan,
+                    dhcp: Self::load_router_dhcp(&s.dhcp, &nic, &label, warnings),
+                    nic,
+                }),
+                Err(issue) => warnings.push(issue)
+            }
+        }
+        subinterfaces
+    }
+
+    pub fn load_router(&mut self, r: LoadedRouter) -> LoadEntry {
+        let mut deferred = Deferred::new();
+        let entry = self.load_router_with(&r, &mut deferred);
+        self.apply_deferred(deferred);
+        entry
+    }
+
+    fn load_router_with(&mut self, r: &LoadedRouter, deferred: &mut Deferred) -> LoadEntry {
+        let mut entry = LoadEntry::new(LoadEntryKind::Router, r.name.clone());
+        let nic_lan = match self.load_router_nic(r, RouterInterface::LAN) {
+            Ok(nic) => nic,
+            Err(issue) => return entry.reject(issue)
+        };
+        let nic_wan = match self.load_router_nic(r, RouterInterface::WAN) {
+            Ok(nic) => nic,
+            Err(issue) => return entry.reject(issue)
+        };
+        let mut subinterfaces = self.load_subinterfaces(&r.lan, RouterInterface::LAN, &mut entry.warnings);
+        subinterfaces.append(&mut self.load_subinterfaces(&r.wan, RouterInterface::WAN, &mut entry.warnings));
+        let mut states: Vec<(Element, bool, bool)> = vec![(Element::Node(nic_lan.mac.clone()), false, r.failed == Some(true))];
+        for (loaded, nic, parent) in [(&r.lan, &nic_lan, RouterInterface::LAN), (&r.wan, &nic_wan, RouterInterface::WAN)] {
+            states.push((Element::Interface(nic.mac.clone()), loaded.shutdown == Some(true), false));
+            for s in loaded.subinterfaces.iter().flatten() {
+                if let Some(subinterface) = subinterfaces.iter().find(|other| other.parent == parent && other.vlan == s.vlan) {
+                    states.push((Element::Interface(subinterface.nic.mac.clone()), s.shutdown == Some(true), false));
                 }
             }
         }
-        println!("{}Loaded{} {} routers, {} devices, and {} connections.", Colors::BLUE, Colors::RESET, routers_loaded, devices_loaded, connections_loaded);
+        let router = Router {
+            name: r.name.clone(),
+            dhcp_lan: Self::load_router_dhcp(&r.lan.dhcp, &nic_lan, "LAN", &mut entry.warnings),
+            dhcp_wan: Self::load_router_dhcp(&r.wan.dhcp, &nic_wan, "WAN", &mut entry.warnings),
+            nic_lan: nic_lan.clone(),
+            nic_wan: nic_wan.clone(),
+            subinterfaces,
+            zone: Self::load_zone(&r.zone, &mut entry.warnings),
+        };
+        match self.append_router(router) {
+            Ok(_) => {
+                let _ = self.append_internal_router_connection(nic_lan.mac, nic_wan.mac);
+                deferred.states.append(&mut states);
+                entry
+            },
+            Err(e) => entry.reject(LoadIssue::from_graph_error(e))
+        }
+    }
+
+    pub fn load_device(&mut self, d: LoadedDevices) -> LoadEntry {
+        let mut deferred = Deferred::new();
+        let entry = self.load_device_with(&d, &mut deferred);
+        self.apply_deferred(deferred);
+        entry
+    }
+
+    fn load_device_with(&mut self, d: &LoadedDevices, deferred: &mut Deferred) -> LoadEntry {
+        let mut entry = LoadEntry::new(LoadEntryKind::Device, d.name.clone());
+        let mac = match self.load_mac(&d.mac, "MAC", &NodeType::Device) {
+            Ok(mac) => mac,
+            Err(issue) => return entry.reject(issue)
+        };
+        let ip = match &d.ip {
+            Some(ip_str) => match Self::load_ip(ip_str, "IP") {
+                Ok(ip) => ip,
+                Err(issue) => return entry.reject(issue)
+            },
+            None => IP::V4(127, 0, 0, 1)
+        };
+        let netmask = match d.netmask {
+            Some(cidr) => IP::from_cidr(cidr),
+            None => IP::from_cidr(8),
+        };
+        let name_server = match &d.name_server {
+            Some(ip_str) => match Self::load_ip(ip_str, "name server") {
+                Ok(ip) => Some(ip),
+                Err(issue) => return entry.reject(issue)
+            },
+            None => None
+        };
+        let zone = Self::load_zone(&d.zone, &mut entry.warnings);
+        let services = Self::load_services(&d.services, &mut entry.warnings);
+        match self.append_device(Device { name: d.name.clone(), nic: NIC { mac: mac.clone(), ip: ip.clone(), netmask }, name_server, zone, services }) {
+            Ok(_) => {
+                deferred.states.push((Element::Interface(mac.clone()), d.shutdown == Some(true), false));
+                deferred.states.push((Element::Node(mac.clone()), false, d.failed == Some(true)));
+                // A device saved without address stays without one
+                if d.ip.is_some() && matches!(ip, IP::V4(127, _, _, _)) {
+                    deferred.unaddressed.push(mac);
+                }
+                entry
+            },
+            Err(e) => entry.reject(LoadIssue::from_graph_error(e))
+        }
+    }
+
+    pub fn load_switch(&mut self, s: LoadedSwitch) -> LoadEntry {
+        let mut deferred = Deferred::new();
+        let entry = self.load_switch_with(&s, &mut deferred);
+        self.apply_deferred(deferred);
+        entry
+    }
+
+    fn load_switch_with(&mut self, s: &LoadedSwitch, deferred: &mut Deferred) -> LoadEntry {
+        let mut entry = LoadEntry::new(LoadEntryKind::Switch, s.name.clone());
+        let mac = match self.load_mac(&s.mac, "MAC", &NodeType::Switch) {
+            Ok(mac) => mac,
+            Err(issue) => return entry.reject(issue)
+        };
+        let mut switch = Switch::new(s.name.clone(), mac, s.ports.unwrap_or(DEFAULT_PORTS));
+        match s.priority {
+            Some(priority) if valid_priority(priority) => switch.priority = priority,
+            Some(priority) => entry.warnings.push(LoadIssue::GraphError(GraphError::InvalidBridgePriority(priority))),
+            None => {}
+        }
+        let mac = switch.mac.clone();
+        match self.append_switch(switch) {
+            Ok(_) => {
+                deferred.states.push((Element::Node(mac), false, s.failed == Some(true)));
+                entry
+            },
+            Err(e) => entry.reject(LoadIssue::from_graph_error(e))
+        }
+    }
+
+    fn load_switch_port(&mut self, switch: &str, p: LoadedSwitchPort) -> Result<(), LoadIssue> {
+        let mode = match (p.access, p.trunk) {
+            (Some(vlan), None) => PortMode::Access(vlan),
+            (None, Some(vlans)) => PortMode::Trunk(vlans),
+            (access, _) => return Err(LoadIssue::InvalidVlan {
+                field: format!("mode of port {}", p.port),
+                value: if access.is_some() { "both access and trunk" } else { "neither access nor trunk" }.to_string()
+            })
+        };
+        let switch = self.resolve(switch).map_err(LoadIssue::UnknownNode)?;
+        let port = self.resolve(&p.port).map_err(LoadIssue::UnknownNode)?;
+        self.set_port_mode(switch, port, mode).map_err(LoadIssue::from_graph_error)
     }
 
+    pub fn load_connection(&mut self, c: LoadedConnections) -> LoadEntry {
+        let mut deferred = Deferred::new();
+        let entry = self.load_connection_with(&c, &mut deferred);
+        self.apply_deferred(deferred);
+        entry
+    }
+
+    fn load_connection_with(&mut self, c: &LoadedConnections, deferred: &mut Deferred) -> LoadEntry {
+        let entry = LoadEntry::new(LoadEntryKind::Connection, format!("{} <-> {}", c.from, c.to));
+        let mac_src = match self.resolve(&c.from) {
+            Ok(mac) => mac,
+            Err(e) => return entry.reject(LoadIssue::UnknownNode(e))
+        };
+        let mac_dest = match self.resolve(&c.to) {
+            Ok(mac) => mac,
+            Err(e) => return entry.reject(LoadIssue::UnknownNode(e))
+        };
+        // A device saved without address is linked as it was, whatever it is plugged in, rather than through DHCP
+        let unaddressed = deferred.unaddressed.contains(&mac_src) || deferred.unaddressed.contains(&mac_dest);
+        let connected = match (unaddressed, self.search_switch_with_mac(mac_dest.clone()).is_some()) {
+            (true, true) => self.append_switch_connection(mac_dest.clone(), mac_src.clone()),
+            (true, false) => self.append_switch_connection(mac_src.clone(), mac_dest.clone()),
+            (false, _) => connection_any_direction(self, mac_src.clone(), mac_dest.clone()).map(|_| ()),
+        };
+        match connected {
+            Ok(_) => {
+                deferred.states.push((Element::Link(mac_src, mac_dest), c.shutdown == Some(true), c.failed == Some(true)));
+                entry
+            },
+            Err(e) => entry.reject(LoadIssue::ConnectionError(e))
+        }
+    }
+
+    fn apply_deferred(&mut self, deferred: Deferred) {
+        for (element, shutdown, failed) in deferred.states {
+            self.states.set_shutdown(element.clone(), shutdown);
+            self.states.set_failed(element, failed);
+        }
+        self.topology_changed();
+    }
+
+}
+
+impl std::fmt::Display for Graph {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        writeln!(f, "--- Network Graph ---")?;
+        writeln!(f, "Nodes:")?;
+        for (mac, device_type) in &self.nodes {
+            writeln!(f, "- {} {} ({})", mac, self.name_with_mac(mac.clone()).unwrap_or_default(), device_type)?;
+        }
+        writeln!(f, "Connections:")?;
+        for (mac1, mac2) in &self.connections {
+            writeln!(f, "- {} <-> {}", mac1, mac2)?;
+        }
+
+        writeln!(f, "\n--- Routers ---")?;
+        for router in &self.routers {
+            writeln!(f, "{}", router)?;
+        }
+        writeln!(f, "\n--- Devices ---")?;
+        for device in &self.devices {
+            writeln!(f, "{}", device)?;
+        }
+        writeln!(f, "\n--- Switches ---")?;
+        for switch in &self.switches {
+            writeln!(f, "{}", switch)?;
+        }
+        Ok(())
+    }
 }
 
-pub fn connection_with_mac(graph: &mut Graph, mac_src: MAC, mac_dest: MAC) -> bool {
+// Connects two NICs, `mac_src` asking for an address by DHCP when it is not on the network of `mac_dest`.
+// Returns the address obtained by DHCP, if any.
+pub fn connection_with_mac(graph: &mut Graph, mac_src: MAC, mac_dest: MAC) -> Result<Option<IP>, GraphError> {
+    let src_is_switch = graph.search_switch_with_mac(mac_src.clone()).is_some();
+    let dest_is_switch = graph.search_switch_with_mac(mac_dest.clone()).is_some();
+    match (src_is_switch, dest_is_switch) {
+        (true, true) => return graph.append_switch_connection(mac_src, mac_dest).map(|_| None),
+        (true, false) => return connection_with_mac(graph, mac_dest, mac_src),
+        _ => {}
+    }
     let mut nic_src = match graph.nic_with_mac(mac_src.clone()) {
         Some(nic) => nic,
-        None => {
-            println!("Device with MAC address '{}' not found.", mac_src);
-            return false;
-        }
+        None => return Err(GraphError::NodeNotFound(mac_src))
     };
     let nic_src_original = nic_src.clone();
-    let nic_dest = match graph.nic_with_mac(mac_dest.clone()) {
-        Some(nic) => nic,
-        None => {
-            println!("Device with MAC address '{}' not found.", mac_dest);
-            return false;
-        }
+    // Behind a switch, the NIC has to join the network of the segment
+    let nic_dest = match (dest_is_switch, graph.nic_with_mac(mac_dest.clone())) {
+        (true, _) => match graph.segment_nics(mac_dest.clone(), graph.port_vlan(mac_dest.clone(), &mac_src)).into_iter().find(|n| !n.is_localhost()) {
+            Some(nic) => nic,
+            None => return graph.append_switch_connection(mac_dest, mac_src).map(|_| None)
+        },
+        (false, Some(nic)) => nic,
+        (false, None) => return Err(GraphError::NodeNotFound(mac_dest))
     };
+    let mut dhcp_ip: Option<IP> = None;
+    let mut name_server: Option<IP> = None;
     if !nic_src.same_network(nic_dest.clone()) {
         if graph.connections(nic_src.mac.clone()).is_empty() {
             nic_src.set_localhost();
         }
         if !nic_src.is_localhost() {
-            println!("Devices are not on the same network and {} could not connect to the network.", nic_src.mac);
-            return false;
+            return Err(GraphError::ConnectionNotPossible);
         }
         match graph.breadth_first_search_and_dhcp_connection(&mut nic_src, &nic_dest) {
-            Ok(ip) => {
-                println!("DHCP attribution succeed : {}", ip);
-                if let Err(e) = graph.update_nic(mac_src.clone(), nic_src.clone()) {
-                    println!("Error updating NIC: {}", e);
-                    return false;
-                }
-            }
-            Err(e) => {
-                println!("Error during DHCP attribution : {}", e);
-                return false;
+            Ok((ip, leased_name_server)) => {
+                graph.update_nic(mac_src.clone(), nic_src.clone())?;
+                dhcp_ip = Some(ip);
+                name_server = leased_name_server;
             }
+            Err(e) => return Err(GraphError::DhcpFailed(e))
         }
     }
-    match graph.append_connection(nic_src, nic_dest) {
-        Ok(_) => return true,
+    let appended = match dest_is_switch {
+        true => graph.append_switch_connection(mac_dest, nic_src.mac.clone()),
+        false => graph.append_connection(nic_src, nic_dest),
+    };
+    match appended {
+        Ok(_) => {
+            graph.lease_name_server(&mac_src, name_server);
+            if let Some(ip) = &dhcp_ip {
+                traffic::observe(graph, |graph| traffic::dhcp(graph, &mac_src, ip));
+            }
+            Ok(dhcp_ip)
+        },
         Err(e) => {
-            println!("Error connecting devices: {}", e);
-            match graph.update_nic(mac_src.clone(), nic_src_original.clone()) {
-                Ok(_) => {},
-                Err(e) => println!("Additionally, error reverting NIC changes: {}", e),
+            let _ = graph.update_nic(mac_src, nic_src_original);
+            Err(e)
+        }
+    }
+}
+
+// The DHCP client may be on either side of the link, so the reversed order is tried on failure.
+// When both fail, the addressing errors of a wrong direction give way to the cause found in the other
+pub fn connection_any_direction(graph: &mut Graph, mac1: MAC, mac2: MAC) -> Result<Option<IP>, GraphError> {
+    let first = match connection_with_mac(graph, mac1.clone(), mac2.clone()) {
+        Ok(ip) => return Ok(ip),
+        Err(e) => e
+    };
+    match connection_with_mac(graph, mac2, mac1) {
+        Ok(ip) => Ok(ip),
+        Err(second) if matches!(first, GraphError::ConnectionNotPossible | GraphError::DhcpFailed(_)) => Err(second),
+        Err(_) => Err(first)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::load::{DataFormat, parse_data};
+
+    const TWO_OFFICES: &str = "
+routers:
+  - name: R1
+    lan: {ip: 192.168.1.1, netmask: 24, dhcp: {first_ip: 192.168.1.10, last_ip: 192.168.1.20}}
+    wan: {ip: 10.0.0.1, netmask: 30}
+  - name: R2
+    lan: {ip: 192.168.2.1, netmask: 24, dhcp: {first_ip: 192.168.2.10, last_ip: 192.168.2.20}}
+    wan: {ip: 10.0.0.2, netmask: 30}
+devices:
+  - name: PC1
+  - name: PC2
+connections:
+  - {from: R1.wan, to: R2.wan}
+  - {from: PC1, to: R1.lan}
+  - {from: PC2, to: R2.lan}
+";
+
+    fn two_offices() -> Graph {
+        let mut graph = Graph::new();
+        let report = graph.load_data(parse_data("test", TWO_OFFICES, DataFormat::Yaml).unwrap_or_else(|e| panic!("{}", e)));
+        assert!(report.rejected().is_empty());
+        graph
+    }
+
+    #[test]
+    fn connecting_to_a_full_interface_reports_the_port_limit() {
+        let mut graph = two_offices();
+        crate::device::create_device("PC3".to_string(), &mut graph).unwrap_or_else(|e| panic!("{}", e));
+        let pc3 = graph.resolve("PC3").unwrap_or_else(|e| panic!("{}", e));
+        let lan = graph.resolve("R2.lan").unwrap_or_else(|e| panic!("{}", e));
+        for (a, b) in [(pc3.clone(), lan.clone()), (lan, pc3)] {
+            match connection_any_direction(&mut graph, a, b) {
+                Err(GraphError::MaxConnectionReached(_)) => {},
+                Err(e) => panic!("unexpected error {}", e),
+                Ok(_) => panic!("connected to a full interface"),
             }
         }
+        assert!(graph.nic_with_mac(graph.resolve("PC3").unwrap_or_else(|e| panic!("{}", e))).unwrap().is_localhost());
+    }
+
+    #[test]
+    fn references_resolve_to_the_interface_they_name() {
+        let graph = two_offices();
+        let r1 = graph.routers.iter().find(|r| r.name == "R1").unwrap();
+        let ok = |reference: &str| graph.resolve(reference).unwrap_or_else(|e| panic!("{}", e));
+        assert!(ok("R1.lan") == r1.nic_lan.mac && ok("R1.WAN") == r1.nic_wan.mac);
+        assert!(ok(&r1.nic_wan.mac.to_hex()) == r1.nic_wan.mac);
+        assert!(graph.resolve_node("R1").unwrap_or_else(|e| panic!("{}", e)) == r1.nic_lan.mac);
+        let pc1 = ok("PC1");
+        assert!(graph.name_with_mac(pc1.clone()).as_deref() == Some("PC1"));
+        assert!(graph.name_with_mac(ok("R2.wan")).as_deref() == Some("R2.wan"));
+        assert!(ok(&graph.name_with_mac(pc1.clone()).unwrap()) == pc1);
+    }
+
+    #[test]
+    fn ambiguous_and_unknown_references_are_rejected() {
+        let graph = two_offices();
+        assert!(matches!(graph.resolve("R1"), Err(GraphError::InterfaceRequired(name)) if name == "R1"));
+        for reference in ["PC9", "R1.dmz", "R1.lan.10", "02:00:00:00:00:99"] {
+            assert!(matches!(graph.resolve(reference), Err(GraphError::UnknownNode(name)) if name == reference));
+        }
+    }
+
+    #[test]
+    fn names_are_unique_across_node_types() {
+        let mut graph = two_offices();
+        assert!(graph.name_exists("R2") && graph.name_exists("PC2") && !graph.name_exists("PC3"));
+        for name in ["PC1", "R1"] {
+            match crate::device::create_device(name.to_string(), &mut graph) {
+                Err(GraphError::AlreadyExistingName(existing)) => assert_eq!(existing, name),
+                Err(e) => panic!("unexpected error {}", e),
+                Ok(_) => panic!("{} added twice", name),
+            }
+        }
+    }
+
+    #[test]
+    fn connections_return_the_address_leased_by_dhcp() {
+        let mut graph = Graph::new();
+        let data = "
+routers:
+  - name: R1
+    lan: {ip: 192.168.1.1, netmask: 24, dhcp: {first_ip: 192.168.1.10, last_ip: 192.168.1.11}}
+    wan: {ip: 10.0.0.1, netmask: 30}
+switches:
+  - name: SW1
+connections:
+  - {from: R1.lan, to: SW1}
+";
+        let report = graph.load_data(parse_data("test", data, DataFormat::Yaml).unwrap_or_else(|e| panic!("{}", e)));
+        assert!(report.rejected().is_empty() && report.entries.iter().all(|e| e.warnings.is_empty()));
+        for name in ["PC1", "PC2", "PC3"] {
+            crate::device::create_device(name.to_string(), &mut graph).unwrap_or_else(|e| panic!("{}", e));
+        }
+        let switch = graph.resolve("SW1").unwrap_or_else(|e| panic!("{}", e));
+        for (name, leased) in [("PC1", IP::V4(192, 168, 1, 10)), ("PC2", IP::V4(192, 168, 1, 11))] {
+            let mac = graph.resolve(name).unwrap_or_else(|e| panic!("{}", e));
+            match connection_any_direction(&mut graph, mac, switch.clone()) {
+                Ok(Some(ip)) => assert!(ip == leased),
+                Ok(None) => panic!("no address leased to {}", name),
+                Err(e) => panic!("{}", e),
+            }
+        }
+        let pc3 = graph.resolve("PC3").unwrap_or_else(|e| panic!("{}", e));
+        match connection_any_direction(&mut graph, pc3.clone(), switch) {
+            Err(e @ GraphError::DhcpFailed(_)) => assert!(e.to_string().starts_with("DHCP Attribution Failed")),
+            Err(e) => panic!("unexpected error {}", e),
+            Ok(_) => panic!("connected past the end of the pool"),
+        }
+        assert!(graph.connections(pc3).is_empty());
     }
-    return false;
 }
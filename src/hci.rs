@@ -1,18 +1,25 @@
 use std::io::{self, Write};
+use std::path::Path;
 
-use crate::commands::ping;
-use crate::device::create_device;
-use crate::graph::{Graph, connection_with_mac};
-use crate::ip::IP;
-use crate::load::load_data;
-use crate::mac::MAC;
-use crate::router::{create_router, RouterInterface};
+use network_simulator::colors::Colors;
+use network_simulator::commands::ping;
+use network_simulator::device::create_device;
+use network_simulator::export::{ExportFormat, export};
+use network_simulator::graph::{Graph, GraphError, connection_any_direction};
+use network_simulator::ip::IP;
+use network_simulator::load::{DataFormat, LoadReport, LoadStatus, load_data, save_data_as};
+use network_simulator::mac::MAC;
+use network_simulator::router::{create_router, RouterInterface};
+use network_simulator::script::{ScriptReport, StepOutcome, load_script, run_script};
+use network_simulator::snapshot::{restore_snapshot, save_snapshot};
+use network_simulator::validate::{Severity, ValidationReport};
 
 
 pub enum HciError {
     ErrorReadingStdin,
     ErrorParsingInput,
-    InvalidDataFormat
+    InvalidDataFormat,
+    UnknownNode(String)
 }
 
 impl std::fmt::Display for HciError {
@@ -20,7 +27,8 @@ impl std::fmt::Display for HciError {
         match self {
             HciError::ErrorReadingStdin => write!(f, "Error Reading Stdin"),
             HciError::ErrorParsingInput => write!(f, "Error Parsing Input"),
-            HciError::InvalidDataFormat => write!(f, "Invalid Data Format")
+            HciError::InvalidDataFormat => write!(f, "Invalid Data Format"),
+            HciError::UnknownNode(e) => write!(f, "Unknown Node ({})", e)
         }
     }
 }
@@ -57,45 +65,21 @@ pub fn prompt_confirmation(prompt: &str) -> Result<bool, HciError> {
 }
 
 pub fn prompt_u8(prompt: &str) -> Result<u8, HciError> {
-    loop {
-        match get_input(prompt) {
-            Ok(input) => {
-                match input.parse::<u8>() {
-                    Ok(num) => return Ok(num),
-                    Err(_) => {return Err(HciError::ErrorParsingInput);}
-                }
-            }
-            Err(e) => return Err(e),
-        }
-    }
+    let input = get_input(prompt)?;
+    input.parse::<u8>().map_err(|_| HciError::ErrorParsingInput)
 }
 
-pub fn prompt_mac(prompt: &str) -> Result<MAC, HciError> {
-    loop {
-        match get_input(prompt) {
-            Ok(input) => {
-                match MAC::from_string(&input) {
-                    Some(mac) => return Ok(mac),
-                    None => return Err(HciError::InvalidDataFormat),
-                }
-            }
-            Err(e) => return Err(e),
-        }
+pub fn prompt_node(graph: &Graph, prompt: &str) -> Result<MAC, HciError> {
+    let input = get_input(prompt)?;
+    match graph.resolve(&input) {
+        Ok(mac) => Ok(mac),
+        Err(e) => Err(HciError::UnknownNode(e.to_string())),
     }
 }
 
 pub fn prompt_ip(prompt: &str) -> Result<IP, HciError> {
-    loop {
-        match get_input(prompt) {
-            Ok(input) => {
-                match IP::from_string(&input) {
-                    Some(ip) => return Ok(ip),
-                    None => return Err(HciError::InvalidDataFormat),
-                }
-            }
-            Err(e) => return Err(e),
-        }
-    }
+    let input = get_input(prompt)?;
+    IP::from_string(&input).ok_or(HciError::InvalidDataFormat)
 }
 
 pub enum MenuOptions {
@@ -106,7 +90,18 @@ pub enum MenuOptions {
     ShowAll,
     Connection,
     Ping,
-    Load
+    Load,
+    Script,
+    Shell,
+    Validate,
+    Export,
+    Diagram,
+    Save,
+    Seed,
+    Snapshot,
+    Restore,
+    Undo,
+    Redo
 }
 
 pub fn menu() -> MenuOptions {
@@ -116,7 +111,18 @@ pub fn menu() -> MenuOptions {
     println!("3. Show all devices and networks");
     println!("4. Connect two devices");
     println!("5. Ping from a device to an IP");
-    println!("6. Load data from yaml file");
+    println!("6. Load data from a YAML / JSON / TOML file");
+    println!("7. Run a scenario script");
+    println!("8. Open the interactive shell");
+    println!("9. Validate the network");
+    println!("10. Export the network (DOT / Mermaid)");
+    println!("11. Show the network diagram");
+    println!("12. Save the network to a YAML / JSON / TOML file");
+    println!("13. Set the random seed");
+    println!("14. Save a snapshot of the simulation");
+    println!("15. Restore a snapshot");
+    println!("16. Undo the last change");
+    println!("17. Redo the last undone change");
     println!("0. Quit");
     println!("=================================");
     
@@ -129,15 +135,26 @@ pub fn menu() -> MenuOptions {
     };
 
     match choice {
-        0 => return MenuOptions::Exit,
-        1 => return MenuOptions::CreateRouter,
-        2 => return MenuOptions::CreateDevice,
-        3 => return MenuOptions::ShowAll,
-        4 => return MenuOptions::Connection,
-        5 => return MenuOptions::Ping,
-        6 => return MenuOptions::Load,
+        0 => MenuOptions::Exit,
+        1 => MenuOptions::CreateRouter,
+        2 => MenuOptions::CreateDevice,
+        3 => MenuOptions::ShowAll,
+        4 => MenuOptions::Connection,
+        5 => MenuOptions::Ping,
+        6 => MenuOptions::Load,
+        7 => MenuOptions::Script,
+        8 => MenuOptions::Shell,
+        9 => MenuOptions::Validate,
+        10 => MenuOptions::Export,
+        11 => MenuOptions::Diagram,
+        12 => MenuOptions::Save,
+        13 => MenuOptions::Seed,
+        14 => MenuOptions::Snapshot,
+        15 => MenuOptions::Restore,
+        16 => MenuOptions::Undo,
+        17 => MenuOptions::Redo,
         _ => {
-            return MenuOptions::Nothing;
+            MenuOptions::Nothing
         }
     }
 }
@@ -235,7 +252,7 @@ pub fn create_router_interactive(graph: &mut Graph) -> bool {
         Ok(_) => return true,
         Err(e) => println!("Error creating router: {}", e),
     }
-    return false;
+    false
 }
 
 pub fn create_device_interactive(graph: &mut Graph) -> bool {
@@ -251,35 +268,47 @@ pub fn create_device_interactive(graph: &mut Graph) -> bool {
         Ok(_) => return true,
         Err(e) => println!("Error creating device: {}", e),
     }
-    return false;
+    false
 }
 
 pub fn connection_interactive(graph: &mut Graph) -> bool {
-    let mac_src = match prompt_mac("Enter the MAC address of the device to connect: ") {
+    println!("Devices are designated by name or MAC address, router interfaces as name.lan / name.wan.");
+    let mac_src = match prompt_node(graph, "Enter the device to connect: ") {
         Ok(mac) => mac,
         Err(e) => {
-            println!("Error reading MAC address: {}", e);
+            println!("Error reading device: {}", e);
             return false;
         }
     };
-    let mac_dest = match prompt_mac("Enter the MAC address of the device to connect to: ") {
+    let mac_dest = match prompt_node(graph, "Enter the device to connect to: ") {
         Ok(mac) => mac,
         Err(e) => {
-            println!("Error reading MAC address: {}", e);
+            println!("Error reading device: {}", e);
             return false;
         }
     };
-    if connection_with_mac(graph, mac_src.clone(), mac_dest.clone()) {
-        return true;
+    print_connection_result(connection_any_direction(graph, mac_src, mac_dest))
+}
+
+pub fn print_connection_result(result: Result<Option<IP>, GraphError>) -> bool {
+    match result {
+        Ok(Some(ip)) => {
+            println!("DHCP attribution succeed : {}", ip);
+            true
+        },
+        Ok(None) => true,
+        Err(e) => {
+            println!("{}Error{} connecting devices: {}", Colors::RED, Colors::RESET, e);
+            false
+        }
     }
-    connection_with_mac(graph, mac_dest, mac_src)
 }
 
 pub fn ping_interactive(graph: &Graph) -> bool {
-    let source_mac = match prompt_mac("Enter the MAC address of the source device: ") {
+    let source_mac = match prompt_node(graph, "Enter the source device (name, name.lan / name.wan or MAC): ") {
         Ok(mac) => mac,
         Err(e) => {
-            println!("Error reading MAC address: {}", e);
+            println!("Error reading device: {}", e);
             return false;
         }
     };
@@ -292,19 +321,19 @@ pub fn ping_interactive(graph: &Graph) -> bool {
     };
     match graph.nic_with_mac(source_mac.clone()) {
         Some(nic) => {
-            let status = ping(&graph, nic, destination_ip);
+            let status = ping(graph, nic, destination_ip);
             println!("Ping status: {}", status);
-            return true;
+            true
         },
         None => {
             println!("Device with MAC address '{}' not found.", source_mac);
-            return false;
+            false
         }
-    };
+    }
 }
 
 pub fn load_interactive(graph: &mut Graph) -> bool {
-    let file_path = match get_input("Enter the path to the YAML file to load : ") {
+    let file_path = match get_input("Enter the path to the file to load (.yaml, .json or .toml) : ") {
         Ok(name) => name,
         Err(e) => {
             println!("Error reading file path: {}", e);
@@ -313,7 +342,7 @@ pub fn load_interactive(graph: &mut Graph) -> bool {
     };
     let data = load_data(&file_path);
     match data {
-        Ok(data) => graph.load_data(data),
+        Ok(data) => print_load_report(&graph.load_data(data)),
         Err(e) => {
             println!("Error loading data: {}", e);
             return false;
@@ -321,3 +350,223 @@ pub fn load_interactive(graph: &mut Graph) -> bool {
     };
     true
 }
+
+pub fn save_to(graph: &Graph, file_path: &str, format: Option<DataFormat>) -> bool {
+    let format = format.unwrap_or(DataFormat::from_path(file_path));
+    match save_data_as(file_path, &graph.to_loaded_data(), format) {
+        Ok(_) => {
+            println!("Network saved to {} ({}).", file_path, format);
+            true
+        },
+        Err(e) => {
+            println!("Error saving data: {}", e);
+            false
+        }
+    }
+}
+
+pub fn save_interactive(graph: &Graph) -> bool {
+    let file_path = match get_input("Enter the path of the file to write (.yaml, .json or .toml) : ") {
+        Ok(path) => path,
+        Err(e) => {
+            println!("Error reading file path: {}", e);
+            return false;
+        }
+    };
+    save_to(graph, &file_path, None)
+}
+
+pub fn snapshot_to(graph: &Graph, file_path: &str, format: Option<DataFormat>) -> bool {
+    let format = format.unwrap_or(DataFormat::from_path(file_path));
+    match save_snapshot(file_path, graph, format) {
+        Ok(_) => {
+            println!("Snapshot saved to {} ({}).", file_path, format);
+            true
+        },
+        Err(e) => {
+            println!("Error saving snapshot: {}", e);
+            false
+        }
+    }
+}
+
+pub fn restore_from(graph: &mut Graph, file_path: &str, format: Option<DataFormat>) -> bool {
+    match restore_snapshot(file_path, format.unwrap_or(DataFormat::from_path(file_path))) {
+        Ok(mut restored) => {
+            // Captures and the trace keep running across a restore, as they are not part of the network
+            restored.captures = graph.captures.clone();
+            restored.trace = graph.trace.clone();
+            *graph = restored;
+            println!("Snapshot restored from {}.", file_path);
+            true
+        },
+        Err(e) => {
+            println!("Error restoring snapshot: {}", e);
+            false
+        }
+    }
+}
+
+pub fn snapshot_interactive(graph: &Graph) -> bool {
+    let file_path = match get_input("Enter the path of the snapshot to write (.yaml, .json or .toml) : ") {
+        Ok(path) => path,
+        Err(e) => {
+            println!("Error reading file path: {}", e);
+            return false;
+        }
+    };
+    snapshot_to(graph, &file_path, None)
+}
+
+pub fn restore_interactive(graph: &mut Graph) -> bool {
+    let file_path = match get_input("Enter the path of the snapshot to restore : ") {
+        Ok(path) => path,
+        Err(e) => {
+            println!("Error reading file path: {}", e);
+            return false;
+        }
+    };
+    restore_from(graph, &file_path, None)
+}
+
+pub fn seed_interactive(graph: &mut Graph) -> bool {
+    println!("Current seed: {}", graph.random);
+    let seed = match get_input("Enter the new seed : ") {
+        Ok(seed) => seed,
+        Err(e) => {
+            println!("Error reading seed: {}", e);
+            return false;
+        }
+    };
+    match seed.parse::<u64>() {
+        Ok(seed) => {
+            graph.random.reseed(seed);
+            true
+        },
+        Err(_) => {
+            println!("Invalid seed, a positive number is expected.");
+            false
+        }
+    }
+}
+
+pub fn print_load_report(report: &LoadReport) {
+    for entry in &report.entries {
+        if let LoadStatus::Rejected(issue) = &entry.status {
+            println!("{}Skipping{} {} {}: {}", Colors::YELLOW, Colors::RESET, entry.kind, entry.subject, issue);
+        }
+        for warning in &entry.warnings {
+            println!("{}Warning{} {} {}: {}", Colors::YELLOW, Colors::RESET, entry.kind, entry.subject, warning);
+        }
+    }
+    println!("{}{}{}", Colors::BLUE, report, Colors::RESET);
+}
+
+pub fn print_script_report(report: &ScriptReport) {
+    if let Some(name) = &report.name {
+        println!("--- Scenario {} ---", name);
+    }
+    for result in &report.results {
+        match &result.outcome {
+            StepOutcome::Done => println!("{}[{}]{} {}", Colors::DIM, result.index, Colors::RESET, result.description),
+            StepOutcome::Passed => println!("{}[{}] PASS{} {}", Colors::GREEN, result.index, Colors::RESET, result.description),
+            StepOutcome::Failed(reason) => println!("{}[{}] FAIL{} {} : {}", Colors::RED, result.index, Colors::RESET, result.description, reason),
+            StepOutcome::Error(reason) => println!("{}[{}] ERROR{} {} : {}", Colors::RED, result.index, Colors::RESET, result.description, reason),
+        }
+    }
+    if !report.timeline.is_empty() {
+        println!("--- Timeline ---");
+        for entry in &report.timeline {
+            println!("{}", entry);
+        }
+    }
+    let color = if report.success() { Colors::GREEN } else { Colors::RED };
+    println!("{}{} passed, {} failed{}", color, report.passed(), report.failed(), Colors::RESET);
+}
+
+pub fn script_file(graph: &mut Graph, file_path: &str) -> Option<ScriptReport> {
+    match load_script(file_path) {
+        Ok(script) => {
+            let base_dir = Path::new(file_path).parent().unwrap_or(Path::new("."));
+            Some(run_script(graph, script, base_dir))
+        },
+        Err(e) => {
+            println!("Error loading script: {}", e);
+            None
+        }
+    }
+}
+
+pub fn script_interactive(graph: &mut Graph) -> bool {
+    let file_path = match get_input("Enter the path to the scenario script : ") {
+        Ok(name) => name,
+        Err(e) => {
+            println!("Error reading file path: {}", e);
+            return false;
+        }
+    };
+    match script_file(graph, &file_path) {
+        Some(report) => {
+            print_script_report(&report);
+            report.success()
+        },
+        None => false
+    }
+}
+
+pub fn print_validation_report(report: &ValidationReport) {
+    for diagnostic in &report.diagnostics {
+        let color = match diagnostic.severity {
+            Severity::Error => Colors::RED,
+            Severity::Warning => Colors::YELLOW,
+        };
+        println!("{}{}{} [{}] {}: {}", color, diagnostic.severity, Colors::RESET, diagnostic.kind, diagnostic.subject, diagnostic.message);
+    }
+    let color = if report.has_errors() { Colors::RED } else { Colors::GREEN };
+    println!("{}{} errors, {} warnings{}", color, report.errors(), report.warnings(), Colors::RESET);
+}
+
+pub fn export_to(graph: &Graph, format: ExportFormat, file_path: Option<&str>) -> bool {
+    let content = export(graph, format);
+    match file_path {
+        Some(path) => match std::fs::write(path, content) {
+            Ok(_) => true,
+            Err(e) => {
+                println!("Error writing {}: {}", path, e);
+                false
+            }
+        },
+        None => {
+            print!("{}", content);
+            true
+        }
+    }
+}
+
+pub fn export_interactive(graph: &Graph) -> bool {
+    let format = match get_input("Enter the format (dot / mermaid): ") {
+        Ok(input) => match ExportFormat::from_string(&input) {
+            Some(format) => format,
+            None => {
+                println!("Unknown format '{}'.", input);
+                return false;
+            }
+        },
+        Err(e) => {
+            println!("Error reading format: {}", e);
+            return false;
+        }
+    };
+    let file_path = match get_input("Enter the output file (empty to print): ") {
+        Ok(path) => path,
+        Err(e) => {
+            println!("Error reading file path: {}", e);
+            return false;
+        }
+    };
+    if file_path.is_empty() {
+        export_to(graph, format, None)
+    } else {
+        export_to(graph, format, Some(&file_path))
+    }
+}
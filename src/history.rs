@@ -0,0 +1,178 @@
+use crate::graph::{Graph, Topology};
+
+
+pub const MAX_HISTORY: usize = 100;
+
+// Each change keeps the topology as it was on the other side of the change, so that undoing it
+// also reverts its side effects (DHCP attributions, NIC updates, generated MACs). The runtime state
+// of the simulation is left as it is: the clock, sockets, captures and trace are not rewound
+struct Change {
+    description: String,
+    topology: Topology
+}
+
+pub struct History {
+    undo: Vec<Change>,
+    redo: Vec<Change>
+}
+
+impl Default for History {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl History {
+    pub fn new() -> Self {
+        History { undo: Vec::new(), redo: Vec::new() }
+    }
+
+    fn push(&mut self, description: &str, before: Topology) {
+        self.undo.push(Change { description: description.to_string(), topology: before });
+        if self.undo.len() > MAX_HISTORY {
+            self.undo.remove(0);
+        }
+        self.redo.clear();
+    }
+
+    // Runs an edit as a single step, the topology is left untouched when it fails
+    pub fn apply<T, E>(&mut self, graph: &mut Graph, description: &str, edit: impl FnOnce(&mut Graph) -> Result<T, E>) -> Result<T, E> {
+        let before = graph.topology();
+        match edit(graph) {
+            Ok(value) => {
+                self.push(description, before);
+                Ok(value)
+            },
+            Err(e) => {
+                graph.restore_topology(before);
+                Err(e)
+            }
+        }
+    }
+
+    // For edits that cannot fail as a whole, such as loading a file with some rejected entries
+    pub fn record<T>(&mut self, graph: &mut Graph, description: &str, edit: impl FnOnce(&mut Graph) -> T) -> T {
+        let before = graph.topology();
+        let value = edit(graph);
+        self.push(description, before);
+        value
+    }
+
+    // Returns the description of the change undone
+    pub fn undo(&mut self, graph: &mut Graph) -> Option<String> {
+        let change = self.undo.pop()?;
+        let after = graph.topology();
+        graph.restore_topology(change.topology);
+        self.redo.push(Change { description: change.description.clone(), topology: after });
+        Some(change.description)
+    }
+
+    pub fn redo(&mut self, graph: &mut Graph) -> Option<String> {
+        let change = self.redo.pop()?;
+        let before = graph.topology();
+        graph.restore_topology(change.topology);
+        self.undo.push(Change { description: change.description.clone(), topology: before });
+        Some(change.description)
+    }
+
+    // Oldest first
+    pub fn undo_list(&self) -> Vec<&str> {
+        self.undo.iter().map(|c| c.description.as_str()).collect()
+    }
+
+    pub fn redo_list(&self) -> Vec<&str> {
+        self.redo.iter().rev().map(|c| c.description.as_str()).collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::clock::advance;
+    use crate::device::create_device;
+    use crate::graph::{GraphError, connection_any_direction};
+    use crate::ip::IP;
+    use crate::load::{DataFormat, parse_data};
+    use crate::mac::MAC;
+    use crate::transport::{Transport, listen};
+
+    const OFFICE: &str = "
+routers:
+  - name: R1
+    lan: {ip: 192.168.1.1, netmask: 24, dhcp: {first_ip: 192.168.1.10, last_ip: 192.168.1.20}}
+    wan: {ip: 10.0.0.1, netmask: 30}
+switches:
+  - name: SW1
+connections:
+  - {from: R1.lan, to: SW1}
+";
+
+    fn office() -> Graph {
+        let mut graph = Graph::new();
+        let report = graph.load_data(parse_data("test", OFFICE, DataFormat::Yaml).unwrap_or_else(|e| panic!("{}", e)));
+        assert!(report.rejected().is_empty());
+        graph
+    }
+
+    fn mac(graph: &Graph, name: &str) -> MAC {
+        graph.resolve_node(name).unwrap_or_else(|e| panic!("{}", e))
+    }
+
+    // PC1 is created and plugged in SW1, obtaining an address by DHCP
+    fn add_pc1(graph: &mut Graph, history: &mut History) {
+        history.apply(graph, "create device PC1", |g| create_device("PC1".to_string(), g)).unwrap_or_else(|e| panic!("{}", e));
+        let (pc1, switch) = (mac(graph, "PC1"), mac(graph, "SW1"));
+        history.apply(graph, "connect PC1 SW1", |g| connection_any_direction(g, pc1, switch)).unwrap_or_else(|e| panic!("{}", e));
+    }
+
+    #[test]
+    fn undo_reverts_an_address_obtained_by_dhcp_and_redo_brings_it_back() {
+        let (mut graph, mut history) = (office(), History::new());
+        add_pc1(&mut graph, &mut history);
+        let pc1 = mac(&graph, "PC1");
+        let leased = graph.nic_with_mac(pc1.clone()).map(|nic| nic.ip).unwrap_or_else(|| panic!("no PC1"));
+        assert!(leased == IP::V4(192, 168, 1, 10));
+
+        assert_eq!(history.undo(&mut graph).as_deref(), Some("connect PC1 SW1"));
+        assert!(graph.nic_with_mac(pc1.clone()).is_some_and(|nic| nic.is_localhost()));
+        assert!(!graph.are_connected(pc1.clone(), mac(&graph, "SW1")));
+        assert_eq!(history.undo(&mut graph).as_deref(), Some("create device PC1"));
+        assert!(graph.resolve_node("PC1").is_err());
+
+        history.redo(&mut graph);
+        history.redo(&mut graph);
+        assert!(mac(&graph, "PC1") == pc1 && graph.nic_with_mac(pc1).is_some_and(|nic| nic.ip == leased));
+        assert!(history.redo_list().is_empty());
+    }
+
+    #[test]
+    fn undo_leaves_the_runtime_state_running() {
+        let (mut graph, mut history) = (office(), History::new());
+        add_pc1(&mut graph, &mut history);
+        let pc1 = mac(&graph, "PC1");
+        listen(&mut graph, pc1.clone(), Transport::Tcp, 80).unwrap_or_else(|e| panic!("{}", e));
+        advance(&mut graph, 5_000);
+        let learned = graph.tables.entries().2.len();
+
+        history.undo(&mut graph);
+        assert_eq!(graph.clock.now(), 5_000);
+        assert!(graph.sockets.is_listening(&pc1, Transport::Tcp, 80));
+        assert_eq!(graph.tables.entries().2.len(), learned);
+        // Once the device itself is undone, its sockets go with it
+        history.undo(&mut graph);
+        assert!(graph.sockets.list().is_empty());
+        assert_eq!(graph.clock.now(), 5_000);
+    }
+
+    #[test]
+    fn a_failed_edit_is_neither_applied_nor_recorded() {
+        let (mut graph, mut history) = (office(), History::new());
+        let result = history.apply(&mut graph, "create device PC1 twice", |g| {
+            create_device("PC1".to_string(), g)?;
+            create_device("PC1".to_string(), g)
+        });
+        assert!(matches!(result, Err(GraphError::AlreadyExistingName(_))));
+        assert!(graph.resolve_node("PC1").is_err());
+        assert!(history.undo_list().is_empty());
+    }
+}
@@ -0,0 +1,341 @@
+use std::path::{Path, PathBuf};
+use serde::{Deserialize, Serialize};
+
+use crate::ip::IP;
+use crate::load::{DataFormat, LoadError, LoadedConnections, LoadedData, LoadedDevices, LoadedDnsRecord, LoadedRouter, LoadedRouterDHCP, LoadedRouterInterface, LoadedSwitch, read_data};
+use crate::mac::MAC;
+
+
+#[derive(Serialize, Deserialize, Clone)]
+pub struct LoadedInclude {
+    pub file: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub prefix: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub ip_offset: Option<String>
+}
+
+impl std::fmt::Display for LoadedInclude {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", self.file)?;
+        if let Some(prefix) = &self.prefix {
+            write!(f, " (prefix {})", prefix)?;
+        }
+        Ok(())
+    }
+}
+
+// Everything declared by one file or one include instance
+struct Part {
+    origin: String,
+    routers: Vec<LoadedRouter>,
+    devices: Vec<LoadedDevices>,
+    switches: Vec<LoadedSwitch>,
+    connections: Vec<LoadedConnections>
+}
+
+impl Part {
+    fn new(origin: String) -> Self {
+        Part { origin, routers: Vec::new(), devices: Vec::new(), switches: Vec::new(), connections: Vec::new() }
+    }
+
+    fn names(&self) -> Vec<String> {
+        self.routers.iter().map(|r| r.name.clone())
+            .chain(self.devices.iter().map(|d| d.name.clone()))
+            .chain(self.switches.iter().map(|s| s.name.clone()))
+            .collect()
+    }
+
+    fn macs(&self) -> Vec<MAC> {
+        let mut macs: Vec<Option<&String>> = Vec::new();
+        for r in &self.routers {
+            for interface in [&r.lan, &r.wan] {
+                macs.push(interface.mac.as_ref());
+                macs.extend(interface.subinterfaces.iter().flatten().map(|s| s.mac.as_ref()));
+            }
+        }
+        macs.extend(self.devices.iter().map(|d| d.mac.as_ref()));
+        macs.extend(self.switches.iter().map(|s| s.mac.as_ref()));
+        macs.into_iter().flatten().filter_map(|m| MAC::from_string(m)).collect()
+    }
+
+    // Only LAN subnets belong to a building block, WAN subnets are shared with the outside
+    fn lan_subnets(&self) -> Vec<(String, u32, u32)> {
+        self.routers.iter().filter_map(|r| {
+            let ip = IP::from_string(&r.lan.ip)?;
+            let mask = IP::from_cidr(r.lan.netmask).to_u32();
+            Some((r.name.clone(), ip.to_u32() & mask, mask))
+        }).collect()
+    }
+}
+
+fn prefixed(prefix: &str, endpoint: &str) -> String {
+    match MAC::from_string(endpoint) {
+        Some(_) => endpoint.to_string(),
+        None => format!("{}{}", prefix, endpoint),
+    }
+}
+
+fn shifted(ip: &str, offset: u32) -> String {
+    match IP::from_string(ip) {
+        Some(ip) => IP::from_u32(ip.to_u32().wrapping_add(offset)).to_ddn(),
+        // Left as is so that the loader reports the invalid address
+        None => ip.to_string(),
+    }
+}
+
+fn shift_pool(dhcp: &mut LoadedRouterDHCP, offset: u32) {
+    dhcp.first_ip = shifted(&dhcp.first_ip, offset);
+    dhcp.last_ip = shifted(&dhcp.last_ip, offset);
+    if let Some(name_server) = &dhcp.name_server {
+        dhcp.name_server = Some(shifted(name_server, offset));
+    }
+}
+
+fn shift_interface(interface: &mut LoadedRouterInterface, offset: u32) {
+    interface.ip = shifted(&interface.ip, offset);
+    if let Some(dhcp) = &mut interface.dhcp {
+        shift_pool(dhcp, offset);
+    }
+    for subinterface in interface.subinterfaces.iter_mut().flatten() {
+        subinterface.ip = shifted(&subinterface.ip, offset);
+        if let Some(dhcp) = &mut subinterface.dhcp {
+            shift_pool(dhcp, offset);
+        }
+    }
+}
+
+// Names stay as they are, addresses move with the instance
+fn shift_zone(zone: &mut Option<Vec<LoadedDnsRecord>>, offset: u32) {
+    for record in zone.iter_mut().flatten().filter(|r| r.kind.eq_ignore_ascii_case("A")) {
+        record.value = shifted(&record.value, offset);
+    }
+}
+
+fn instantiate(part: &mut Part, prefix: &str, offset: u32) {
+    for r in &mut part.routers {
+        r.name = format!("{}{}", prefix, r.name);
+        shift_interface(&mut r.lan, offset);
+        shift_interface(&mut r.wan, offset);
+        shift_zone(&mut r.zone, offset);
+    }
+    for d in &mut part.devices {
+        d.name = format!("{}{}", prefix, d.name);
+        if let Some(ip) = &d.ip {
+            d.ip = Some(shifted(ip, offset));
+        }
+        if let Some(name_server) = &d.name_server {
+            d.name_server = Some(shifted(name_server, offset));
+        }
+        shift_zone(&mut d.zone, offset);
+    }
+    for s in &mut part.switches {
+        s.name = format!("{}{}", prefix, s.name);
+        for port in s.vlans.iter_mut().flatten() {
+            port.port = prefixed(prefix, &port.port);
+        }
+    }
+    for c in &mut part.connections {
+        c.from = prefixed(prefix, &c.from);
+        c.to = prefixed(prefix, &c.to);
+    }
+}
+
+fn conflicts(parts: &[Part]) -> Vec<String> {
+    let mut conflicts: Vec<String> = Vec::new();
+    for (i, a) in parts.iter().enumerate() {
+        for b in &parts[i + 1..] {
+            let names_b = b.names();
+            for name in a.names().into_iter().filter(|n| names_b.contains(n)) {
+                conflicts.push(format!("name {} is declared by {} and {}", name, a.origin, b.origin));
+            }
+            let macs_b = b.macs();
+            for mac in a.macs().into_iter().filter(|m| macs_b.contains(m)) {
+                conflicts.push(format!("MAC {} is declared by {} and {}", mac, a.origin, b.origin));
+            }
+            for (router_a, net_a, mask_a) in a.lan_subnets() {
+                for (router_b, net_b, mask_b) in b.lan_subnets() {
+                    let mask = mask_a & mask_b;
+                    if net_a & mask == net_b & mask {
+                        conflicts.push(format!("LAN of {} ({}) overlaps LAN of {} ({})", router_a, a.origin, router_b, b.origin));
+                    }
+                }
+            }
+        }
+    }
+    conflicts
+}
+
+fn flatten(data: LoadedData, origin: String, base_dir: &Path, stack: &mut Vec<PathBuf>) -> Result<Vec<Part>, LoadError> {
+    let mut own = Part::new(origin);
+    own.routers = data.routers.unwrap_or_default();
+    own.devices = data.devices.unwrap_or_default();
+    own.switches = data.switches.unwrap_or_default();
+    own.connections = data.connections.unwrap_or_default();
+    let mut parts: Vec<Part> = vec![own];
+
+    for include in data.includes.unwrap_or_default() {
+        let path = base_dir.join(&include.file);
+        let file_path = path.to_string_lossy().to_string();
+        let canonical = path.canonicalize().map_err(|_| LoadError::FileNotFound(file_path.clone()))?;
+        if stack.contains(&canonical) {
+            return Err(LoadError::IncludeCycle(file_path));
+        }
+        let offset = match &include.ip_offset {
+            Some(offset) => match IP::from_string(offset) {
+                Some(ip) => ip.to_u32(),
+                None => return Err(LoadError::ParseError(file_path, format!("invalid ip_offset '{}'", offset))),
+            },
+            None => 0,
+        };
+        let included = read_data(&file_path, DataFormat::from_path(&file_path))?;
+        stack.push(canonical);
+        let child_dir = path.parent().map(Path::to_path_buf).unwrap_or_default();
+        let mut instance = Part::new(include.to_string());
+        for mut part in flatten(included, include.to_string(), &child_dir, stack)? {
+            instantiate(&mut part, include.prefix.as_deref().unwrap_or(""), offset);
+            instance.routers.append(&mut part.routers);
+            instance.devices.append(&mut part.devices);
+            instance.switches.append(&mut part.switches);
+            instance.connections.append(&mut part.connections);
+        }
+        stack.pop();
+        parts.push(instance);
+    }
+
+    let conflicts = conflicts(&parts);
+    if !conflicts.is_empty() {
+        return Err(LoadError::IncludeConflict(conflicts));
+    }
+    Ok(parts)
+}
+
+// Replaces the `includes` of a topology read from `file_path` by the content of the included files
+pub fn resolve_includes(data: LoadedData, file_path: &str) -> Result<LoadedData, LoadError> {
+    if data.includes.is_none() {
+        return Ok(data);
+    }
+    let path = Path::new(file_path);
+    let base_dir = path.parent().map(Path::to_path_buf).unwrap_or_default();
+    let mut stack: Vec<PathBuf> = path.canonicalize().into_iter().collect();
+    let version = data.version;
+    let mut data = data;
+    // Only the settings of the including file apply
+    let seed = data.seed.take();
+    let macs = data.macs.take();
+    let parts = flatten(data, file_path.to_string(), &base_dir, &mut stack)?;
+
+    let mut merged = Part::new(file_path.to_string());
+    for mut part in parts {
+        merged.routers.append(&mut part.routers);
+        merged.devices.append(&mut part.devices);
+        merged.switches.append(&mut part.switches);
+        merged.connections.append(&mut part.connections);
+    }
+    Ok(LoadedData {
+        version,
+        seed,
+        macs,
+        includes: None,
+        routers: Some(merged.routers),
+        devices: Some(merged.devices),
+        switches: Some(merged.switches),
+        connections: Some(merged.connections)
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::graph::Graph;
+    use crate::load::load_data;
+
+    const OFFICE: &str = "
+routers:
+  - name: GW
+    lan: {ip: 192.168.1.1, netmask: 24, dhcp: {first_ip: 192.168.1.10, last_ip: 192.168.1.20}}
+    wan: {ip: 10.0.0.2, netmask: 30}
+devices:
+  - {name: PC, ip: 192.168.1.5, netmask: 24}
+connections:
+  - {from: PC, to: GW.lan}
+";
+
+    const CAMPUS: &str = "
+includes:
+  - {file: blocks/office.yaml, prefix: b1-}
+  - {file: blocks/office.yaml, prefix: b2-, ip_offset: 0.0.1.0}
+routers:
+  - name: C1
+    lan: {ip: 172.16.0.1, netmask: 24}
+    wan: {ip: 10.0.0.1, netmask: 30}
+connections:
+  - {from: C1.wan, to: b1-GW.wan}
+";
+
+    // Writes the files of a topology in a directory of its own, then loads `main`
+    fn load(name: &str, files: &[(&str, &str)], main: &str) -> Result<LoadedData, LoadError> {
+        let dir = std::env::temp_dir().join(format!("network-simulator-include-{}-{}", name, std::process::id()));
+        for (file, content) in files {
+            let path = dir.join(file);
+            std::fs::create_dir_all(path.parent().unwrap_or(&dir)).unwrap_or_else(|e| panic!("{}", e));
+            std::fs::write(path, content).unwrap_or_else(|e| panic!("{}", e));
+        }
+        let data = load_data(&dir.join(main).to_string_lossy());
+        std::fs::remove_dir_all(&dir).unwrap_or_else(|e| panic!("{}", e));
+        data
+    }
+
+    #[test]
+    fn instances_are_prefixed_and_shifted() {
+        let data = load("instances", &[("campus.yaml", CAMPUS), ("blocks/office.yaml", OFFICE)], "campus.yaml")
+            .unwrap_or_else(|e| panic!("{}", e));
+        assert!(data.includes.is_none());
+        let routers = data.routers.unwrap_or_default();
+        let b2 = routers.iter().find(|r| r.name == "b2-GW").unwrap_or_else(|| panic!("no b2-GW"));
+        assert!(b2.lan.ip == "192.168.2.1" && b2.wan.ip == "10.0.1.2");
+        assert!(b2.lan.dhcp.as_ref().is_some_and(|d| d.first_ip == "192.168.2.10" && d.last_ip == "192.168.2.20"));
+        let devices = data.devices.unwrap_or_default();
+        assert!(devices.iter().any(|d| d.name == "b1-PC" && d.ip.as_deref() == Some("192.168.1.5")));
+        let connections = data.connections.unwrap_or_default();
+        assert!(connections.iter().any(|c| c.from == "b2-PC" && c.to == "b2-GW.lan"));
+        assert_eq!(routers.len(), 3);
+    }
+
+    #[test]
+    fn included_topologies_load() {
+        let data = load("graph", &[("campus.yaml", CAMPUS), ("blocks/office.yaml", OFFICE)], "campus.yaml")
+            .unwrap_or_else(|e| panic!("{}", e));
+        let mut graph = Graph::new();
+        assert!(graph.load_data(data).rejected().is_empty());
+        assert!(graph.resolve("b2-PC").is_ok() && graph.are_connected(
+            graph.resolve("C1.wan").unwrap_or_else(|e| panic!("{}", e)),
+            graph.resolve("b1-GW.wan").unwrap_or_else(|e| panic!("{}", e))
+        ));
+    }
+
+    #[test]
+    fn colliding_instances_are_refused() {
+        let twice = "
+includes:
+  - {file: office.yaml}
+  - {file: office.yaml, prefix: b2-}
+";
+        match load("conflict", &[("main.yaml", twice), ("office.yaml", OFFICE)], "main.yaml") {
+            Err(LoadError::IncludeConflict(conflicts)) => {
+                assert!(conflicts.iter().any(|c| c.starts_with("LAN of GW")));
+                assert!(!conflicts.iter().any(|c| c.starts_with("name")));
+            },
+            Err(e) => panic!("{}", e),
+            Ok(_) => panic!("loaded overlapping LANs"),
+        }
+    }
+
+    #[test]
+    fn cycles_are_refused() {
+        let a = "includes: [{file: b.yaml, prefix: b-}]";
+        let b = "includes: [{file: a.yaml, prefix: a-}]";
+        assert!(matches!(load("cycle", &[("a.yaml", a), ("b.yaml", b)], "a.yaml"), Err(LoadError::IncludeCycle(_))));
+        assert!(matches!(load("missing", &[("a.yaml", "includes: [{file: none.yaml}]")], "a.yaml"), Err(LoadError::FileNotFound(_))));
+    }
+}
@@ -31,6 +31,15 @@ impl IP {
         Some(IP::V4(octets[0], octets[1], octets[2], octets[3]))
     }
 
+    pub fn from_cidr_string(s: &str) -> Option<(IP, IP)> {
+        let (ip, cidr) = s.split_once('/')?;
+        let cidr = match cidr.parse::<u8>() {
+            Ok(cidr) if cidr <= 32 => cidr,
+            _ => return None,
+        };
+        Some((IP::from_string(ip)?, IP::from_cidr(cidr)))
+    }
+
     pub fn from_cidr(cidr: u8) -> IP {
         let mut mask: u32 = 0;
         for i in 0..cidr {
@@ -43,6 +52,17 @@ impl IP {
         IP::V4(a, b, c, d)
     }
 
+    pub fn from_u32(value: u32) -> IP {
+        let [a, b, c, d] = value.to_be_bytes();
+        IP::V4(a, b, c, d)
+    }
+
+    pub fn to_u32(&self) -> u32 {
+        match self {
+            IP::V4(a, b, c, d) => u32::from_be_bytes([*a, *b, *c, *d]),
+        }
+    }
+
     pub fn to_ddn(&self) -> String {
         match self {
             IP::V4(a, b, c, d) => format!("{}.{}.{}.{}", a, b, c, d),
@@ -59,13 +79,13 @@ impl IP {
         match (self, other) {
             (IP::V4(a1, b1, c1, d1), IP::V4(a2, b2, c2, d2)) => {
                 if a1 != a2 {
-                    return a1 > a2;
+                    a1 > a2
                 } else if b1 != b2 {
-                    return b1 > b2;
+                    b1 > b2
                 } else if c1 != c2 {
-                    return c1 > c2;
+                    c1 > c2
                 } else {
-                    return d1 > d2;
+                    d1 > d2
                 }
             }
         }
@@ -109,3 +129,38 @@ impl PartialEq for IP {
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn dotted_decimal_addresses_are_parsed() {
+        assert!(IP::from_string("192.168.1.10") == Some(IP::V4(192, 168, 1, 10)));
+        for invalid in ["1.2.3", "1.2.3.4.5", "256.0.0.1", "a.b.c.d", "1..2.3", ""] {
+            assert!(IP::from_string(invalid).is_none(), "{} parsed", invalid);
+        }
+    }
+
+    #[test]
+    fn prefix_lengths_give_the_netmask() {
+        assert!(IP::from_cidr(24) == IP::V4(255, 255, 255, 0));
+        assert!(IP::from_cidr(20) == IP::V4(255, 255, 240, 0));
+        assert!(IP::from_cidr(0) == IP::V4(0, 0, 0, 0));
+        assert!(IP::from_cidr(32) == IP::V4(255, 255, 255, 255));
+        let (ip, netmask) = IP::from_cidr_string("10.0.0.1/30").unwrap();
+        assert!(ip == IP::V4(10, 0, 0, 1) && netmask == IP::V4(255, 255, 255, 252));
+        for invalid in ["10.0.0.1", "10.0.0.1/33", "10.0.0.1/", "10.0.0/24", "10.0.0.1/x"] {
+            assert!(IP::from_cidr_string(invalid).is_none(), "{} parsed", invalid);
+        }
+    }
+
+    #[test]
+    fn increment_carries_into_the_upper_octets() {
+        let mut ip = IP::V4(10, 0, 255, 255);
+        assert!(ip.increment().is_ok() && ip == IP::V4(10, 1, 0, 0));
+        assert!(ip.is_greater_than(&IP::V4(10, 0, 255, 255)) && !ip.is_greater_than(&ip.clone()));
+        assert!(IP::from_u32(ip.to_u32()) == ip && ip.to_u32() == 0x0a01_0000);
+        assert!(matches!(IP::V4(255, 255, 255, 255).increment(), Err(IpError::MaxIPReached)));
+    }
+}
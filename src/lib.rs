@@ -0,0 +1,38 @@
+pub mod capture;
+pub mod clock;
+pub mod colors;
+pub mod commands;
+pub mod device;
+pub mod diagram;
+pub mod dhcp;
+pub mod dns;
+pub mod export;
+pub mod generate;
+pub mod graph;
+pub mod history;
+pub mod include;
+pub mod ip;
+pub mod load;
+pub mod mac;
+pub mod nic;
+pub mod packet;
+pub mod pcap;
+pub mod random;
+pub mod router;
+pub mod script;
+pub mod service;
+pub mod snapshot;
+pub mod state;
+pub mod stp;
+pub mod switch;
+pub mod tables;
+pub mod trace;
+pub mod traffic;
+pub mod transport;
+pub mod validate;
+
+pub use crate::graph::{Graph, GraphError};
+pub use crate::ip::IP;
+pub use crate::load::{LoadError, LoadReport, LoadedData, load_data};
+pub use crate::mac::MAC;
+pub use crate::nic::NIC;
@@ -1,55 +1,406 @@
+use std::collections::BTreeMap;
 use std::fs::{read_to_string};
+use serde::de::DeserializeOwned;
 use serde::{Deserialize, Serialize};
 
+use crate::dhcp::DhcpError;
+use crate::dns::DnsError;
+use crate::graph::{GraphError, NodeType};
+use crate::include::{LoadedInclude, resolve_includes};
+use crate::mac::MAC;
+use crate::service::ServiceError;
+
 #[derive(Serialize, Deserialize)]
 pub struct LoadedRouter {
     pub name: String,
     pub lan: LoadedRouterInterface,
-    pub wan: LoadedRouterInterface
+    pub wan: LoadedRouterInterface,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub zone: Option<Vec<LoadedDnsRecord>>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub failed: Option<bool>
 }
 
 #[derive(Serialize, Deserialize)]
 pub struct LoadedRouterInterface {
     pub ip: String,
     pub netmask: u8,
+    #[serde(skip_serializing_if = "Option::is_none")]
     pub mac: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
     pub dhcp: Option<LoadedRouterDHCP>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub subinterfaces: Option<Vec<LoadedSubInterface>>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub shutdown: Option<bool>,
+}
+
+#[derive(Serialize, Deserialize)]
+pub struct LoadedSubInterface {
+    pub vlan: u16,
+    pub ip: String,
+    pub netmask: u8,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub mac: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub dhcp: Option<LoadedRouterDHCP>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub shutdown: Option<bool>,
 }
 
 #[derive(Serialize, Deserialize)]
 pub struct LoadedRouterDHCP {
     pub first_ip: String,
-    pub last_ip: String
+    pub last_ip: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub name_server: Option<String>
+}
+
+// An empty zone still runs a DNS server, answering NXDOMAIN
+#[derive(Serialize, Deserialize)]
+pub struct LoadedDnsRecord {
+    pub name: String,
+    #[serde(rename = "type")]
+    pub kind: String,
+    pub value: String
+}
+
+// `pages` (path to body) only apply to http services and `response` to custom ones
+#[derive(Serialize, Deserialize)]
+pub struct LoadedService {
+    pub transport: String,
+    pub port: u16,
+    #[serde(rename = "type")]
+    pub kind: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub pages: Option<BTreeMap<String, String>>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub response: Option<String>
 }
 
 
 #[derive(Serialize, Deserialize)]
 pub struct LoadedDevices {
     pub name: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
     pub ip: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
     pub netmask: Option<u8>,
-    pub mac: Option<String>
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub mac: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub name_server: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub zone: Option<Vec<LoadedDnsRecord>>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub services: Option<Vec<LoadedService>>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub shutdown: Option<bool>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub failed: Option<bool>
+}
+
+
+#[derive(Serialize, Deserialize)]
+pub struct LoadedSwitch {
+    pub name: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub ports: Option<usize>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub mac: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub priority: Option<u16>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub vlans: Option<Vec<LoadedSwitchPort>>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub failed: Option<bool>
+}
+
+// The port is designated by the node plugged in, as in connections
+#[derive(Serialize, Deserialize)]
+pub struct LoadedSwitchPort {
+    pub port: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub access: Option<u16>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub trunk: Option<Vec<u16>>
 }
 
 
 #[derive(Serialize, Deserialize)]
 pub struct LoadedConnections {
     pub from: String,
-    pub to: String
+    pub to: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub shutdown: Option<bool>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub failed: Option<bool>
+}
+
+
+// Vendor prefixes of the MAC addresses left out of the file
+#[derive(Serialize, Deserialize)]
+pub struct LoadedMacs {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub router_oui: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub device_oui: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub switch_oui: Option<String>
+}
+
+impl LoadedMacs {
+    pub fn ouis(&self) -> Vec<(NodeType, &String)> {
+        [(NodeType::Router, &self.router_oui), (NodeType::Device, &self.device_oui), (NodeType::Switch, &self.switch_oui)]
+            .into_iter()
+            .filter_map(|(node, oui)| oui.as_ref().map(|oui| (node, oui)))
+            .collect()
+    }
+
+    fn check(&self) -> Result<(), String> {
+        for (node, oui) in self.ouis() {
+            if MAC::oui_from_string(oui).is_none() {
+                return Err(format!("invalid {} OUI '{}' (a unicast prefix such as 00:1A:2B is expected)", node.to_string().to_lowercase(), oui));
+            }
+        }
+        Ok(())
+    }
 }
 
 
+pub const CURRENT_VERSION: u32 = 1;
+
 #[derive(Serialize, Deserialize)]
 pub struct LoadedData {
+    pub version: u32,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub seed: Option<u64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub macs: Option<LoadedMacs>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub includes: Option<Vec<LoadedInclude>>,
+    #[serde(skip_serializing_if = "Option::is_none")]
     pub routers: Option<Vec<LoadedRouter>>,
+    #[serde(skip_serializing_if = "Option::is_none")]
     pub devices: Option<Vec<LoadedDevices>>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub switches: Option<Vec<LoadedSwitch>>,
+    #[serde(skip_serializing_if = "Option::is_none")]
     pub connections: Option<Vec<LoadedConnections>>
 }
 
 
+impl Default for LoadedData {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl LoadedData {
+    pub fn new() -> Self {
+        LoadedData { version: CURRENT_VERSION, seed: None, macs: None, includes: None, routers: None, devices: None, switches: None, connections: None }
+    }
+}
+
+// Files written before the `version` key existed, newer sections being accepted for hand-written files
+#[derive(Deserialize)]
+struct LoadedDataV0 {
+    seed: Option<u64>,
+    macs: Option<LoadedMacs>,
+    includes: Option<Vec<LoadedInclude>>,
+    switches: Option<Vec<LoadedSwitch>>,
+    routers: Option<Vec<LoadedRouter>>,
+    devices: Option<Vec<LoadedDevices>>,
+    connections: Option<Vec<LoadedConnections>>
+}
+
+impl From<LoadedDataV0> for LoadedData {
+    fn from(data: LoadedDataV0) -> Self {
+        LoadedData { version: CURRENT_VERSION, seed: data.seed, macs: data.macs, includes: data.includes, routers: data.routers, devices: data.devices, switches: data.switches, connections: data.connections }
+    }
+}
+
+#[derive(Deserialize)]
+struct VersionProbe {
+    version: Option<u32>
+}
+
+
+#[derive(Clone, Copy, PartialEq)]
+pub enum DataFormat {
+    Yaml,
+    Json,
+    Toml
+}
+
+impl std::fmt::Display for DataFormat {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            DataFormat::Yaml => write!(f, "yaml"),
+            DataFormat::Json => write!(f, "json"),
+            DataFormat::Toml => write!(f, "toml"),
+        }
+    }
+}
+
+impl DataFormat {
+    pub fn from_string(s: &str) -> Option<Self> {
+        match s.to_lowercase().as_str() {
+            "yaml" | "yml" => Some(DataFormat::Yaml),
+            "json" => Some(DataFormat::Json),
+            "toml" => Some(DataFormat::Toml),
+            _ => None,
+        }
+    }
+
+    // Unknown or missing extensions fall back to YAML, the historical format
+    pub fn from_path(file_path: &str) -> Self {
+        std::path::Path::new(file_path).extension()
+            .and_then(|ext| DataFormat::from_string(&ext.to_string_lossy()))
+            .unwrap_or(DataFormat::Yaml)
+    }
+}
+
+
+#[derive(Clone, Copy, PartialEq)]
+pub enum LoadEntryKind {
+    Router,
+    Device,
+    Switch,
+    Connection
+}
+
+impl std::fmt::Display for LoadEntryKind {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            LoadEntryKind::Router => write!(f, "router"),
+            LoadEntryKind::Device => write!(f, "device"),
+            LoadEntryKind::Switch => write!(f, "switch"),
+            LoadEntryKind::Connection => write!(f, "connection"),
+        }
+    }
+}
+
+// `field` names the offending value, e.g. "LAN IP" or "DHCP first IP"
+pub enum LoadIssue {
+    InvalidMac { field: String, value: String },
+    InvalidIp { field: String, value: String },
+    DhcpError(String, DhcpError),
+    InvalidVlan { field: String, value: String },
+    DnsError(DnsError),
+    ServiceError(ServiceError),
+    DuplicateMac,
+    DuplicateName(String),
+    UnknownNode(GraphError),
+    ConnectionError(GraphError),
+    GraphError(GraphError)
+}
+
+impl std::fmt::Display for LoadIssue {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            LoadIssue::InvalidMac { field, value } => write!(f, "Invalid {} '{}'", field, value),
+            LoadIssue::InvalidIp { field, value } => write!(f, "Invalid {} '{}'", field, value),
+            LoadIssue::DhcpError(interface, e) => write!(f, "DHCP on {} skipped: {}", interface, e),
+            LoadIssue::InvalidVlan { field, value } => write!(f, "Invalid {} '{}'", field, value),
+            LoadIssue::DnsError(e) => write!(f, "{}, skipped", e),
+            LoadIssue::ServiceError(e) => write!(f, "{}, skipped", e),
+            LoadIssue::DuplicateMac => write!(f, "Duplicate MAC address"),
+            LoadIssue::DuplicateName(name) => write!(f, "Duplicate name {}", name),
+            LoadIssue::UnknownNode(e) => write!(f, "{}", e),
+            LoadIssue::ConnectionError(e) => write!(f, "Connection error: {}", e),
+            LoadIssue::GraphError(e) => write!(f, "{}", e),
+        }
+    }
+}
+
+impl LoadIssue {
+    pub fn from_graph_error(e: GraphError) -> Self {
+        match e {
+            GraphError::AlreadyExistingMacAddress => LoadIssue::DuplicateMac,
+            GraphError::AlreadyExistingName(name) => LoadIssue::DuplicateName(name),
+            e => LoadIssue::GraphError(e),
+        }
+    }
+}
+
+pub enum LoadStatus {
+    Accepted,
+    Rejected(LoadIssue)
+}
+
+// Non-fatal issues (e.g. an invalid DHCP pool) are kept in `warnings` of an accepted entry
+pub struct LoadEntry {
+    pub kind: LoadEntryKind,
+    pub subject: String,
+    pub status: LoadStatus,
+    pub warnings: Vec<LoadIssue>
+}
+
+impl LoadEntry {
+    pub fn new(kind: LoadEntryKind, subject: String) -> Self {
+        LoadEntry { kind, subject, status: LoadStatus::Accepted, warnings: Vec::new() }
+    }
+
+    pub fn reject(mut self, issue: LoadIssue) -> Self {
+        self.status = LoadStatus::Rejected(issue);
+        self
+    }
+
+    pub fn accepted(&self) -> bool {
+        matches!(self.status, LoadStatus::Accepted)
+    }
+}
+
+pub struct LoadReport {
+    pub entries: Vec<LoadEntry>
+}
+
+impl Default for LoadReport {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl LoadReport {
+    pub fn new() -> Self {
+        LoadReport { entries: Vec::new() }
+    }
+
+    pub fn accepted(&self, kind: LoadEntryKind) -> usize {
+        self.entries.iter().filter(|e| e.kind == kind && e.accepted()).count()
+    }
+
+    pub fn rejected(&self) -> Vec<&LoadEntry> {
+        self.entries.iter().filter(|e| !e.accepted()).collect()
+    }
+}
+
+impl std::fmt::Display for LoadReport {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "Loaded {} routers, {} devices, ", self.accepted(LoadEntryKind::Router), self.accepted(LoadEntryKind::Device))?;
+        let switches = self.accepted(LoadEntryKind::Switch);
+        if switches > 0 {
+            write!(f, "{} switches, ", switches)?;
+        }
+        write!(f, "and {} connections.", self.accepted(LoadEntryKind::Connection))?;
+        let rejected = self.rejected().len();
+        if rejected > 0 {
+            write!(f, " {} entries rejected.", rejected)?;
+        }
+        Ok(())
+    }
+}
+
+
 pub enum LoadError {
     FileNotFound(String),
-    ParseError(String, String)
+    ParseError(String, String),
+    SerializeError(String),
+    WriteError(String, String),
+    UnsupportedVersion(String, u32),
+    IncludeCycle(String),
+    IncludeConflict(Vec<String>)
 }
 
 impl std::fmt::Display for LoadError {
@@ -57,6 +408,11 @@ impl std::fmt::Display for LoadError {
         match self {
             LoadError::FileNotFound(file) => write!(f, "File {} not found", file),
             LoadError::ParseError(file, error) => write!(f, "Error parsing {} : {}", file, error),
+            LoadError::SerializeError(error) => write!(f, "Error serializing data : {}", error),
+            LoadError::WriteError(file, error) => write!(f, "Error writing {} : {}", file, error),
+            LoadError::UnsupportedVersion(file, version) => write!(f, "{} uses topology version {}, this simulator only reads versions up to {}", file, version, CURRENT_VERSION),
+            LoadError::IncludeCycle(file) => write!(f, "{} includes itself", file),
+            LoadError::IncludeConflict(conflicts) => write!(f, "Conflicting includes : {}", conflicts.join("; ")),
         }
     }
 }
@@ -67,6 +423,15 @@ fn file_exists(file_path: &str) -> bool {
 }
 
 pub fn load_data(file_path: &str) -> Result<LoadedData, LoadError> {
+    load_data_as(file_path, DataFormat::from_path(file_path))
+}
+
+pub fn load_data_as(file_path: &str, format: DataFormat) -> Result<LoadedData, LoadError> {
+    resolve_includes(read_data(file_path, format)?, file_path)
+}
+
+// Reads a single file, leaving its includes unresolved
+pub fn read_data(file_path: &str, format: DataFormat) -> Result<LoadedData, LoadError> {
     if !file_exists(file_path) {
         return Err(LoadError::FileNotFound(file_path.to_string()));
     }
@@ -76,12 +441,189 @@ pub fn load_data(file_path: &str) -> Result<LoadedData, LoadError> {
         Err(_) => return Err(LoadError::FileNotFound(file_path.to_string())),
     };
 
-    let loaded_data: LoadedData = match serde_yaml::from_str(&file_content) {
-        Ok(data) => data,
-        Err(e) => {
-            return Err(LoadError::ParseError(file_path.to_string(), e.to_string()))
-        }
+    parse_data(file_path, &file_content, format)
+}
+
+pub fn deserialize<T: DeserializeOwned>(content: &str, format: DataFormat) -> Result<T, String> {
+    match format {
+        DataFormat::Yaml => serde_yaml::from_str(content).map_err(|e| e.to_string()),
+        DataFormat::Json => serde_json::from_str(content).map_err(|e| e.to_string()),
+        DataFormat::Toml => toml::from_str(content).map_err(|e| e.to_string()),
+    }
+}
+
+// Older versions are read with their own schema then upgraded to the current one
+pub fn parse_data(source: &str, content: &str, format: DataFormat) -> Result<LoadedData, LoadError> {
+    let parse_error = |e: String| LoadError::ParseError(source.to_string(), e);
+    let probe: VersionProbe = deserialize(content, format).map_err(parse_error)?;
+    let data: LoadedData = match probe.version {
+        None | Some(0) => deserialize::<LoadedDataV0>(content, format).map(LoadedData::from).map_err(parse_error)?,
+        Some(CURRENT_VERSION) => deserialize(content, format).map_err(parse_error)?,
+        Some(version) => return Err(LoadError::UnsupportedVersion(source.to_string(), version)),
+    };
+    if let Some(macs) = &data.macs {
+        macs.check().map_err(parse_error)?;
+    }
+    Ok(data)
+}
+
+pub fn serialize<T: Serialize>(data: &T, format: DataFormat) -> Result<String, LoadError> {
+    let result = match format {
+        DataFormat::Yaml => serde_yaml::to_string(data).map_err(|e| e.to_string()),
+        DataFormat::Json => serde_json::to_string_pretty(data).map(|s| s + "\n").map_err(|e| e.to_string()),
+        DataFormat::Toml => toml::to_string(data).map_err(|e| e.to_string()),
     };
+    result.map_err(LoadError::SerializeError)
+}
+
+pub fn serialize_data(data: &LoadedData, format: DataFormat) -> Result<String, LoadError> {
+    serialize(data, format)
+}
+
+pub fn save_data(file_path: &str, data: &LoadedData) -> Result<(), LoadError> {
+    save_data_as(file_path, data, DataFormat::from_path(file_path))
+}
+
+pub fn save_data_as(file_path: &str, data: &LoadedData, format: DataFormat) -> Result<(), LoadError> {
+    let content = serialize_data(data, format)?;
+    std::fs::write(file_path, content).map_err(|e| LoadError::WriteError(file_path.to_string(), e.to_string()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::graph::Graph;
+
+    const BROKEN: &str = "
+routers:
+  - name: R1
+    lan: {ip: 192.168.1.1, netmask: 24, dhcp: {first_ip: 192.168.1.10, last_ip: 192.168.1.x}}
+    wan: {ip: 10.0.0.1, netmask: 30}
+  - name: R2
+    lan: {ip: 192.168.2.1, netmask: 24}
+    wan: {ip: 10.0.0.256, netmask: 30}
+devices:
+  - {name: PC1, ip: 192.168.1.10, netmask: 24}
+  - {name: PC1, ip: 192.168.1.11, netmask: 24}
+  - {name: PC2, mac: 'zz:00:00:00:00:01'}
+connections:
+  - {from: PC1, to: R1.lan}
+  - {from: PC9, to: R1.lan}
+";
+
+    fn loaded(content: &str) -> LoadReport {
+        let mut graph = Graph::new();
+        graph.load_data(parse_data("test", content, DataFormat::Yaml).unwrap_or_else(|e| panic!("{}", e)))
+    }
+
+    fn issue<'a>(report: &'a LoadReport, subject: &str) -> &'a LoadIssue {
+        match report.rejected().into_iter().find(|e| e.subject == subject).map(|e| &e.status) {
+            Some(LoadStatus::Rejected(issue)) => issue,
+            _ => panic!("{} not rejected", subject),
+        }
+    }
 
-    Ok(loaded_data)
+    #[test]
+    fn rejected_entries_carry_their_reason() {
+        let report = loaded(BROKEN);
+        assert_eq!(report.rejected().len(), 4);
+        assert!(matches!(issue(&report, "R2"), LoadIssue::InvalidIp { field, value } if field == "WAN IP" && value == "10.0.0.256"));
+        assert!(matches!(issue(&report, "PC1"), LoadIssue::DuplicateName(name) if name == "PC1"));
+        assert!(matches!(issue(&report, "PC2"), LoadIssue::InvalidMac { .. }));
+        assert!(report.rejected().iter().any(|e| e.kind == LoadEntryKind::Connection && matches!(e.status, LoadStatus::Rejected(LoadIssue::UnknownNode(_)))));
+        assert_eq!(issue(&report, "R2").to_string(), "Invalid WAN IP '10.0.0.256'");
+    }
+
+    #[test]
+    fn warnings_do_not_reject_their_entry() {
+        let report = loaded(BROKEN);
+        let r1 = report.entries.iter().find(|e| e.subject == "R1").unwrap();
+        assert!(r1.accepted());
+        assert!(matches!(r1.warnings.as_slice(), [LoadIssue::InvalidIp { field, .. }] if field == "LAN DHCP last IP"));
+    }
+
+    #[test]
+    fn the_summary_counts_accepted_and_rejected_entries() {
+        let report = loaded(BROKEN);
+        assert_eq!(report.to_string(), "Loaded 1 routers, 1 devices, and 1 connections. 4 entries rejected.");
+        assert_eq!(loaded("devices:\n  - name: PC1\n").to_string(), "Loaded 0 routers, 1 devices, and 0 connections.");
+    }
+
+    const OFFICE: &str = "
+routers:
+  - name: R1
+    lan: {ip: 192.168.1.1, netmask: 24, dhcp: {first_ip: 192.168.1.10, last_ip: 192.168.1.20}}
+    wan: {ip: 10.0.0.1, netmask: 30}
+devices:
+  - {name: PC1}
+switches:
+  - {name: SW1, ports: 8}
+connections:
+  - {from: R1.lan, to: SW1}
+  - {from: PC1, to: SW1}
+";
+
+    fn office() -> LoadedData {
+        let mut graph = Graph::new();
+        let report = graph.load_data(parse_data("test", OFFICE, DataFormat::Yaml).unwrap_or_else(|e| panic!("{}", e)));
+        assert!(report.rejected().is_empty());
+        graph.to_loaded_data()
+    }
+
+    #[test]
+    fn formats_are_named_by_the_extension() {
+        assert!(DataFormat::from_path("net.json") == DataFormat::Json);
+        assert!(DataFormat::from_path("dir/net.TOML") == DataFormat::Toml);
+        assert!(DataFormat::from_path("net.yml") == DataFormat::Yaml);
+        assert!(DataFormat::from_path("net.txt") == DataFormat::Yaml && DataFormat::from_path("net") == DataFormat::Yaml);
+        assert!(DataFormat::from_string("xml").is_none());
+    }
+
+    #[test]
+    fn every_format_reads_back_what_it_wrote() {
+        let data = office();
+        let yaml = serialize_data(&data, DataFormat::Yaml).unwrap_or_else(|e| panic!("{}", e));
+        for format in [DataFormat::Json, DataFormat::Toml] {
+            let written = serialize_data(&data, format).unwrap_or_else(|e| panic!("{}", e));
+            let read = parse_data("test", &written, format).unwrap_or_else(|e| panic!("{}", e));
+            assert_eq!(serialize_data(&read, DataFormat::Yaml).unwrap_or_else(|e| panic!("{}", e)), yaml, "{} differs", format);
+        }
+    }
+
+    #[test]
+    fn saved_files_are_read_in_the_format_of_their_extension() {
+        let data = office();
+        let path = std::env::temp_dir().join(format!("network-simulator-load-{}.toml", std::process::id()));
+        let path = path.to_string_lossy().to_string();
+        save_data(&path, &data).unwrap_or_else(|e| panic!("{}", e));
+        let content = std::fs::read_to_string(&path).unwrap();
+        let read = load_data(&path);
+        std::fs::remove_file(&path).unwrap();
+        assert!(content.contains("[[routers]]"));
+        let read = read.unwrap_or_else(|e| panic!("{}", e));
+        assert!(serialize_data(&read, DataFormat::Json).ok() == serialize_data(&data, DataFormat::Json).ok());
+        assert!(matches!(parse_data("net.json", "{\"devices\": [", DataFormat::Json), Err(LoadError::ParseError(file, _)) if file == "net.json"));
+    }
+
+    #[test]
+    fn files_without_a_version_are_upgraded() {
+        for content in ["devices:\n  - name: PC1\n", "version: 0\ndevices:\n  - name: PC1\n"] {
+            let data = parse_data("old.yaml", content, DataFormat::Yaml).unwrap_or_else(|e| panic!("{}", e));
+            assert!(data.version == CURRENT_VERSION && data.devices.is_some_and(|d| d.len() == 1));
+        }
+        let data = parse_data("old.json", "{\"devices\": [{\"name\": \"PC1\"}]}", DataFormat::Json).unwrap_or_else(|e| panic!("{}", e));
+        assert!(data.version == CURRENT_VERSION);
+        assert!(serialize_data(&LoadedData::new(), DataFormat::Yaml).unwrap_or_else(|e| panic!("{}", e)).starts_with(&format!("version: {}", CURRENT_VERSION)));
+    }
+
+    #[test]
+    fn newer_versions_are_refused() {
+        let newer = format!("version: {}\ndevices:\n  - name: PC1\n", CURRENT_VERSION + 1);
+        let error = match parse_data("new.yaml", &newer, DataFormat::Yaml) {
+            Err(e) => e,
+            Ok(_) => panic!("read a newer version"),
+        };
+        assert!(matches!(&error, LoadError::UnsupportedVersion(file, version) if file == "new.yaml" && *version == CURRENT_VERSION + 1));
+        assert_eq!(error.to_string(), format!("new.yaml uses topology version {}, this simulator only reads versions up to {}", CURRENT_VERSION + 1, CURRENT_VERSION));
+    }
 }
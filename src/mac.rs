@@ -1,6 +1,8 @@
 use rand::Rng;
 
 use crate::colors::Colors;
+use crate::graph::NodeType;
+use crate::load::LoadedMacs;
 
 #[derive(Clone)]
 pub enum MAC {
@@ -14,16 +16,24 @@ impl std::fmt::Display for MAC {
 }
 
 impl MAC {
-    pub fn new() -> Self {
-        let mut rng = rand::rng();
-        MAC::EUI48(
-            rng.random_range(0..=255),
-            rng.random_range(0..=255),
-            rng.random_range(0..=255),
-            rng.random_range(0..=255),
-            rng.random_range(0..=255),
-            rng.random_range(0..=255),
-        )
+    // Vendor prefix such as "00:1A:2B"
+    pub fn oui_from_string(s: &str) -> Option<[u8; 3]> {
+        let parts: Vec<&str> = s.split(':').collect();
+        if parts.len() != 3 {
+            return None;
+        }
+        let mut bytes = [0u8; 3];
+        for (i, part) in parts.iter().enumerate() {
+            match u8::from_str_radix(part, 16) {
+                Ok(byte) => bytes[i] = byte,
+                Err(_) => return None,
+            }
+        }
+        // Multicast prefixes cannot be used by a NIC
+        if bytes[0] & MULTICAST != 0 {
+            return None;
+        }
+        Some(bytes)
     }
 
     pub fn from_string(s: &str) -> Option<Self> {
@@ -41,6 +51,12 @@ impl MAC {
         Some(MAC::EUI48(bytes[0], bytes[1], bytes[2], bytes[3], bytes[4], bytes[5]))
     }
     
+    pub fn octets(&self) -> [u8; 6] {
+        match self {
+            MAC::EUI48(a, b, c, d, e, f) => [*a, *b, *c, *d, *e, *f],
+        }
+    }
+
     pub fn to_hex(&self) -> String {
         match self {
             MAC::EUI48(a, b, c, d, e, f) => format!("{:02X}:{:02X}:{:02X}:{:02X}:{:02X}:{:02X}", a, b, c, d, e, f),
@@ -57,3 +73,128 @@ impl PartialEq for MAC {
         }
     }
 }
+
+// Bit 1 of the first byte marks a locally administered address, bit 0 a multicast one
+const LOCALLY_ADMINISTERED: u8 = 0x02;
+const MULTICAST: u8 = 0x01;
+
+// Vendor prefixes per node type, the random part comes from the simulation RNG
+#[derive(Clone)]
+pub struct MacGenerator {
+    router_oui: Option<[u8; 3]>,
+    device_oui: Option<[u8; 3]>,
+    switch_oui: Option<[u8; 3]>
+}
+
+impl Default for MacGenerator {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl MacGenerator {
+    pub fn new() -> Self {
+        MacGenerator {
+            router_oui: None,
+            device_oui: None,
+            switch_oui: None
+        }
+    }
+
+    pub fn set_oui(&mut self, node: &NodeType, oui: Option<[u8; 3]>) {
+        match node {
+            NodeType::Router => self.router_oui = oui,
+            NodeType::Device => self.device_oui = oui,
+            NodeType::Switch => self.switch_oui = oui,
+        }
+    }
+
+    pub fn oui(&self, node: &NodeType) -> Option<[u8; 3]> {
+        match node {
+            NodeType::Router => self.router_oui,
+            NodeType::Device => self.device_oui,
+            NodeType::Switch => self.switch_oui,
+        }
+    }
+
+    pub fn to_loaded(&self) -> Option<LoadedMacs> {
+        let hex = |oui: Option<[u8; 3]>| oui.map(|[a, b, c]| format!("{:02X}:{:02X}:{:02X}", a, b, c));
+        match (self.router_oui, self.device_oui, self.switch_oui) {
+            (None, None, None) => None,
+            (router, device, switch) => Some(LoadedMacs { router_oui: hex(router), device_oui: hex(device), switch_oui: hex(switch) }),
+        }
+    }
+
+    // Unicast addresses, under the OUI of the node type or locally administered without one
+    pub fn generate(&self, rng: &mut impl Rng, node: &NodeType) -> MAC {
+        let bytes: [u8; 6] = rng.random();
+        let [a, b, c] = match self.oui(node) {
+            Some(oui) => oui,
+            None => [(bytes[0] | LOCALLY_ADMINISTERED) & !MULTICAST, bytes[1], bytes[2]],
+        };
+        MAC::EUI48(a, b, c, bytes[3], bytes[4], bytes[5])
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::graph::Graph;
+    use crate::load::{DataFormat, parse_data};
+    use crate::random::Random;
+
+    const VENDORS: &str = "
+macs:
+  router_oui: 00:1A:2B
+  switch_oui: 00:CC:DD
+routers:
+  - name: R1
+    lan: {ip: 192.168.1.1, netmask: 24}
+    wan: {ip: 10.0.0.1, netmask: 30}
+devices:
+  - {name: PC1, ip: 192.168.1.10, netmask: 24}
+switches:
+  - name: SW1
+";
+
+    #[test]
+    fn addresses_parse_from_colon_notation() {
+        assert!(MAC::from_string("00:1a:2B:3c:4D:5e") == Some(MAC::EUI48(0x00, 0x1A, 0x2B, 0x3C, 0x4D, 0x5E)));
+        assert!(MAC::from_string("00:1A:2B:3C:4D").is_none() && MAC::from_string("00:1A:2B:3C:4D:XX").is_none());
+        assert_eq!(MAC::EUI48(0x0A, 0, 0xFF, 1, 2, 3).to_hex(), "0A:00:FF:01:02:03");
+    }
+
+    #[test]
+    fn ouis_must_be_unicast() {
+        assert_eq!(MAC::oui_from_string("00:1A:2B"), Some([0x00, 0x1A, 0x2B]));
+        assert_eq!(MAC::oui_from_string("01:00:5E"), None);
+        assert_eq!(MAC::oui_from_string("00:1A"), None);
+        assert_eq!(MAC::oui_from_string("00:1A:2B:3C"), None);
+    }
+
+    #[test]
+    fn generated_addresses_are_unicast() {
+        let mut generator = MacGenerator::new();
+        let mut random = Random::new(Some(1));
+        for _ in 0..64 {
+            let [a, ..] = generator.generate(random.rng(), &NodeType::Device).octets();
+            assert!(a & LOCALLY_ADMINISTERED != 0 && a & MULTICAST == 0);
+        }
+        generator.set_oui(&NodeType::Router, Some([0x00, 0x1A, 0x2B]));
+        assert_eq!(generator.generate(random.rng(), &NodeType::Router).octets()[..3], [0x00, 0x1A, 0x2B]);
+        assert!(generator.to_loaded().is_some_and(|l| l.router_oui.as_deref() == Some("00:1A:2B") && l.device_oui.is_none()));
+        assert!(MacGenerator::new().to_loaded().is_none());
+    }
+
+    #[test]
+    fn node_types_take_their_vendor_prefix() {
+        let mut graph = Graph::new();
+        let report = graph.load_data(parse_data("test", VENDORS, DataFormat::Yaml).unwrap_or_else(|e| panic!("{}", e)));
+        assert!(report.rejected().is_empty());
+        let octets = |name: &str| graph.resolve(name).unwrap_or_else(|e| panic!("{}", e)).octets();
+        assert_eq!(octets("R1.lan")[..3], [0x00, 0x1A, 0x2B]);
+        assert_eq!(octets("R1.wan")[..3], [0x00, 0x1A, 0x2B]);
+        assert_eq!(octets("SW1")[..3], [0x00, 0xCC, 0xDD]);
+        assert!(octets("PC1")[0] & LOCALLY_ADMINISTERED != 0);
+    }
+}
@@ -1,21 +1,216 @@
-mod colors;
-mod commands;
-mod device;
-mod dhcp;
-mod graph;
 mod hci;
-mod ip;
-mod load;
-mod mac;
-mod nic;
-mod router;
+mod shell;
 
-use crate::graph::Graph;
+use network_simulator::{diagram, export, load, validate};
+use network_simulator::generate::{Shape, generate};
+use network_simulator::load::{DataFormat, LoadedData};
+use network_simulator::Graph;
+use network_simulator::history::History;
 use crate::hci::{menu, MenuOptions};
 
 
+struct FormatOptions {
+    from: Option<DataFormat>,
+    to: Option<DataFormat>
+}
+
+// Extracts `--from <format>` and `--to <format>` wherever they appear
+fn format_options(args: &[String]) -> Result<(Vec<String>, FormatOptions), String> {
+    let mut rest: Vec<String> = Vec::new();
+    let mut from: Option<DataFormat> = None;
+    let mut to: Option<DataFormat> = None;
+    let mut iter = args.iter();
+    while let Some(arg) = iter.next() {
+        if arg != "--from" && arg != "--to" {
+            rest.push(arg.clone());
+            continue;
+        }
+        let format = match iter.next().and_then(|f| DataFormat::from_string(f)) {
+            Some(format) => format,
+            None => return Err(format!("{} expects yaml, json or toml.", arg)),
+        };
+        if arg == "--from" { from = Some(format) } else { to = Some(format) }
+    }
+    Ok((rest, FormatOptions { from, to }))
+}
+
+// Extracts `--seed <number>`, which makes a run reproducible
+fn seed_option(args: &[String]) -> Result<(Vec<String>, Option<u64>), String> {
+    let mut rest: Vec<String> = Vec::new();
+    let mut seed: Option<u64> = None;
+    let mut iter = args.iter();
+    while let Some(arg) = iter.next() {
+        if arg != "--seed" {
+            rest.push(arg.clone());
+            continue;
+        }
+        match iter.next().and_then(|s| s.parse::<u64>().ok()) {
+            Some(value) => seed = Some(value),
+            None => return Err("--seed expects a positive number.".to_string()),
+        }
+    }
+    Ok((rest, seed))
+}
+
+fn new_graph(seed: Option<u64>) -> Graph {
+    let mut graph = Graph::new();
+    if let Some(seed) = seed {
+        graph.random.reseed(seed);
+    }
+    graph
+}
+
+fn load_topology(file_path: &str, format: Option<DataFormat>) -> Option<LoadedData> {
+    match load::load_data_as(file_path, format.unwrap_or(DataFormat::from_path(file_path))) {
+        Ok(data) => Some(data),
+        Err(e) => {
+            println!("Error loading data: {}", e);
+            None
+        }
+    }
+}
+
+fn run_cli(args: &[String], seed: Option<u64>) -> i32 {
+    let (args, FormatOptions { from, to }) = match format_options(args) {
+        Ok(options) => options,
+        Err(e) => {
+            println!("{}", e);
+            return 2;
+        }
+    };
+    match args.first().map(String::as_str) {
+        Some("run") if args.len() == 2 => {
+            let mut graph: Graph = new_graph(seed);
+            match hci::script_file(&mut graph, &args[1]) {
+                Some(report) => {
+                    hci::print_script_report(&report);
+                    if report.success() {
+                        0
+                    } else {
+                        println!("Run again with --seed {} to reproduce this run.", graph.random);
+                        1
+                    }
+                },
+                None => 2
+            }
+        },
+        Some("validate") if args.len() == 2 => {
+            match load_topology(&args[1], from) {
+                Some(data) => {
+                    let report = validate::validate_data(&data);
+                    hci::print_validation_report(&report);
+                    report.exit_code()
+                },
+                None => 2
+            }
+        },
+        Some("export") if args.len() == 3 || args.len() == 4 => {
+            let format = match export::ExportFormat::from_string(&args[2]) {
+                Some(format) => format,
+                None => {
+                    println!("Unknown format '{}', expected dot or mermaid.", args[2]);
+                    return 2;
+                }
+            };
+            let mut graph: Graph = new_graph(seed);
+            match load_topology(&args[1], from) {
+                Some(data) => hci::print_load_report(&graph.load_data(data)),
+                None => return 2
+            }
+            if hci::export_to(&graph, format, args.get(3).map(String::as_str)) { 0 } else { 2 }
+        },
+        Some("convert") if args.len() == 3 => {
+            let mut graph: Graph = new_graph(seed);
+            match load_topology(&args[1], from) {
+                Some(data) => hci::print_load_report(&graph.load_data(data)),
+                None => return 2
+            }
+            if hci::save_to(&graph, &args[2], to) { 0 } else { 2 }
+        },
+        Some("generate") if args.len() >= 2 => {
+            let (shape, used) = match Shape::from_args(&args[1..]) {
+                Ok(shape) => shape,
+                Err(e) => {
+                    println!("{}", e);
+                    return 2;
+                }
+            };
+            let data = match generate(&shape) {
+                Ok(data) => data,
+                Err(e) => {
+                    println!("{}", e);
+                    return 2;
+                }
+            };
+            match args.get(used + 1) {
+                Some(file_path) => match load::save_data_as(file_path, &data, to.unwrap_or(DataFormat::from_path(file_path))) {
+                    Ok(_) => {
+                        println!("Generated a {} in {}.", shape, file_path);
+                        0
+                    },
+                    Err(e) => {
+                        println!("Error saving data: {}", e);
+                        2
+                    }
+                },
+                None => match load::serialize_data(&data, to.unwrap_or(DataFormat::Yaml)) {
+                    Ok(content) => {
+                        print!("{}", content);
+                        0
+                    },
+                    Err(e) => {
+                        println!("{}", e);
+                        2
+                    }
+                }
+            }
+        },
+        Some("diagram") if args.len() == 2 => {
+            let mut graph: Graph = new_graph(seed);
+            match load_topology(&args[1], from) {
+                Some(data) => hci::print_load_report(&graph.load_data(data)),
+                None => return 2
+            }
+            println!("{}", diagram::render(&graph));
+            0
+        },
+        Some("shell") if args.len() == 1 => {
+            let mut graph: Graph = new_graph(seed);
+            shell::shell(&mut graph, &mut History::new());
+            0
+        },
+        Some("resume") if args.len() == 2 => {
+            let mut graph: Graph = new_graph(seed);
+            if !hci::restore_from(&mut graph, &args[1], from) {
+                return 2;
+            }
+            shell::shell(&mut graph, &mut History::new());
+            0
+        },
+        _ => {
+            println!("Usage: network-simulator [run <script.yaml> | validate <topology> | export <topology> <dot|mermaid> [output] | convert <topology> <output> | generate <star|chain|tree|mesh> <parameters> [output] | diagram <topology> | shell | resume <snapshot>]");
+            println!("Topologies are read and written as YAML, JSON or TOML according to their extension, or to --from / --to <yaml|json|toml>.");
+            println!("--seed <number> sets the seed of the simulation, so that a run can be reproduced exactly.");
+            2
+        }
+    }
+}
+
 fn main() {
-    let mut graph: Graph = Graph::new();
+    let args: Vec<String> = std::env::args().skip(1).collect();
+    let (args, seed) = match seed_option(&args) {
+        Ok(options) => options,
+        Err(e) => {
+            println!("{}", e);
+            std::process::exit(2);
+        }
+    };
+    if !args.is_empty() {
+        std::process::exit(run_cli(&args, seed));
+    }
+
+    let mut graph: Graph = new_graph(seed);
+    let mut history: History = History::new();
     let mut running = true;
 
     while running {
@@ -28,35 +223,73 @@ fn main() {
                 running = false;
             },
             MenuOptions::CreateRouter => {
-                match hci::create_router_interactive(&mut graph) {
-                    true => println!("Router created successfully."),
-                    false => {},
+                if history.apply(&mut graph, "create router", |g| hci::create_router_interactive(g).then_some(()).ok_or(())).is_ok() {
+                    println!("Router created successfully.");
                 }
             },
             MenuOptions::CreateDevice => {
-                match hci::create_device_interactive(&mut graph) {
-                    true => println!("Device created successfully."),
-                    false => {},
+                if history.apply(&mut graph, "create device", |g| hci::create_device_interactive(g).then_some(()).ok_or(())).is_ok() {
+                    println!("Device created successfully.");
                 }
             },
             MenuOptions::ShowAll => {
-                graph.show();
+                println!("{}", graph);
             },
             MenuOptions::Connection => {
-                match hci::connection_interactive(&mut graph) {
-                    true => println!("Devices connected successfully."),
-                    false => {},
+                if history.apply(&mut graph, "connect", |g| hci::connection_interactive(g).then_some(()).ok_or(())).is_ok() {
+                    println!("Devices connected successfully.");
                 }
             },
             MenuOptions::Ping => {
                 hci::ping_interactive(&graph);
             },
             MenuOptions::Load => {
-                match hci::load_interactive(&mut graph) {
-                    true => println!("Data loaded successfully."),
-                    false => {},
+                if history.apply(&mut graph, "load", |g| hci::load_interactive(g).then_some(()).ok_or(())).is_ok() {
+                    println!("Data loaded successfully.");
+                }
+            },
+            MenuOptions::Script => {
+                history.record(&mut graph, "run script", hci::script_interactive);
+            },
+            MenuOptions::Validate => {
+                hci::print_validation_report(&validate::validate_graph(&graph));
+            },
+            MenuOptions::Diagram => {
+                println!("{}", diagram::render(&graph));
+            },
+            MenuOptions::Export => {
+                hci::export_interactive(&graph);
+            },
+            MenuOptions::Save => {
+                hci::save_interactive(&graph);
+            },
+            MenuOptions::Snapshot => {
+                hci::snapshot_interactive(&graph);
+            },
+            MenuOptions::Restore => {
+                let _ = history.apply(&mut graph, "restore snapshot", |g| hci::restore_interactive(g).then_some(()).ok_or(()));
+            },
+            MenuOptions::Undo => {
+                match history.undo(&mut graph) {
+                    Some(description) => println!("Undone: {}", description),
+                    None => println!("Nothing to undo."),
                 }
             },
+            MenuOptions::Redo => {
+                match history.redo(&mut graph) {
+                    Some(description) => println!("Redone: {}", description),
+                    None => println!("Nothing to redo."),
+                }
+            },
+            MenuOptions::Seed => {
+                if hci::seed_interactive(&mut graph) {
+                    println!("Seed set to {}.", graph.random);
+                }
+            },
+            MenuOptions::Shell => {
+                wait = false;
+                shell::shell(&mut graph, &mut history);
+            },
             MenuOptions::Nothing => {
                 wait = false;
                 println!("Invalid option. Please try again.");
@@ -23,11 +23,11 @@ impl std::fmt::Display for NIC {
 }
 
 impl NIC {
-    pub fn new(ip: IP, netmask: IP) -> Self {
+    pub fn with_mac(ip: IP, netmask: IP, mac: MAC) -> Self {
         Self {
             ip,
             netmask,
-            mac: MAC::new()
+            mac
         }
     }
 
@@ -44,7 +44,7 @@ impl NIC {
                 network_address_bin.push('.');
             }
         }
-        return network_address_bin;
+        network_address_bin
     }
 
     pub fn network_address(&self) -> IP {
@@ -67,7 +67,7 @@ impl NIC {
                 break;
             }
         }
-        return cpt;
+        cpt
     }
 
     pub fn total_addressable_ips(&self) -> i32 {
@@ -80,7 +80,7 @@ impl NIC {
     }
 
     pub fn same_network(&self, r2: NIC) -> bool {
-        return self.network_address_bin() == r2.network_address_bin();
+        self.network_address_bin() == r2.network_address_bin()
     }
 
     pub fn ip_address_type(&self) -> IpAddressType {
@@ -90,14 +90,14 @@ impl NIC {
         }
         match next_ip.increment() {
             Ok(_) => {
-                if self.same_network(NIC {ip:next_ip, netmask:self.netmask.clone(), mac:MAC::new()}) {
-                    return IpAddressType::HostAddress;
+                if self.same_network(NIC {ip:next_ip, netmask:self.netmask.clone(), mac:self.mac.clone()}) {
+                    IpAddressType::HostAddress
                 } else {
-                    return IpAddressType::BroadcastAddress;
+                    IpAddressType::BroadcastAddress
                 }
             }
             Err(_) => {
-                return IpAddressType::BroadcastAddress;
+                IpAddressType::BroadcastAddress
             }
         }
     }
@@ -108,9 +108,6 @@ impl NIC {
     }
 
     pub fn is_localhost(&self) -> bool {
-        match self.ip {
-            IP::V4(a, _, _, _) if a == 127 => true,
-            _ => false
-        }
+        matches!(self.ip, IP::V4(127, _, _, _))
     }
 }
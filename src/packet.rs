@@ -0,0 +1,465 @@
+use crate::ip::IP;
+use crate::mac::MAC;
+
+
+pub const ETHERTYPE_IPV4: u16 = 0x0800;
+pub const ETHERTYPE_ARP: u16 = 0x0806;
+pub const ETHERTYPE_VLAN: u16 = 0x8100;
+
+pub const PROTOCOL_ICMP: u8 = 1;
+pub const PROTOCOL_TCP: u8 = 6;
+pub const PROTOCOL_UDP: u8 = 17;
+
+pub const DEFAULT_TTL: u8 = 64;
+
+pub const BROADCAST: MAC = MAC::EUI48(0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF);
+
+// An Ethernet frame as sent by a NIC, the 802.1Q tag being added by the links it crosses
+#[derive(Clone)]
+pub struct Frame {
+    pub destination: MAC,
+    pub source: MAC,
+    pub ethertype: u16,
+    pub payload: Vec<u8>
+}
+
+impl Frame {
+    pub fn new(destination: MAC, source: MAC, ethertype: u16, payload: Vec<u8>) -> Self {
+        Frame { destination, source, ethertype, payload }
+    }
+
+    // Without the frame check sequence, as captures usually are
+    pub fn encode(&self, tag: Option<u16>) -> Vec<u8> {
+        let mut bytes: Vec<u8> = Vec::with_capacity(18 + self.payload.len());
+        bytes.extend(self.destination.octets());
+        bytes.extend(self.source.octets());
+        if let Some(vlan) = tag {
+            bytes.extend(ETHERTYPE_VLAN.to_be_bytes());
+            bytes.extend((vlan & 0x0FFF).to_be_bytes());
+        }
+        bytes.extend(self.ethertype.to_be_bytes());
+        bytes.extend(&self.payload);
+        // Short frames are padded to the 60 bytes minimum
+        let minimum = if tag.is_some() { 64 } else { 60 };
+        if bytes.len() < minimum {
+            bytes.resize(minimum, 0);
+        }
+        bytes
+    }
+}
+
+fn ip_octets(ip: &IP) -> [u8; 4] {
+    ip.to_u32().to_be_bytes()
+}
+
+// Internet checksum of RFC 1071
+pub fn checksum(data: &[u8]) -> u16 {
+    let mut sum: u32 = 0;
+    for chunk in data.chunks(2) {
+        let word = match chunk {
+            [high, low] => u16::from_be_bytes([*high, *low]),
+            [high] => u16::from_be_bytes([*high, 0]),
+            _ => 0,
+        };
+        sum += word as u32;
+    }
+    while sum > 0xFFFF {
+        sum = (sum & 0xFFFF) + (sum >> 16);
+    }
+    !(sum as u16)
+}
+
+pub const ARP_REQUEST: u16 = 1;
+pub const ARP_REPLY: u16 = 2;
+
+pub fn arp(operation: u16, sender_mac: &MAC, sender_ip: &IP, target_mac: &MAC, target_ip: &IP) -> Vec<u8> {
+    let mut bytes: Vec<u8> = Vec::with_capacity(28);
+    bytes.extend(1u16.to_be_bytes());
+    bytes.extend(ETHERTYPE_IPV4.to_be_bytes());
+    bytes.extend([6, 4]);
+    bytes.extend(operation.to_be_bytes());
+    bytes.extend(sender_mac.octets());
+    bytes.extend(ip_octets(sender_ip));
+    bytes.extend(target_mac.octets());
+    bytes.extend(ip_octets(target_ip));
+    bytes
+}
+
+#[derive(Clone)]
+pub struct Ipv4 {
+    pub source: IP,
+    pub destination: IP,
+    pub ttl: u8,
+    pub protocol: u8,
+    pub identification: u16
+}
+
+impl Ipv4 {
+    // Header without options, followed by `payload`; none when the total length does not fit its 16 bits
+    pub fn encode(&self, payload: &[u8]) -> Option<Vec<u8>> {
+        let length = u16::try_from(20 + payload.len()).ok()?;
+        let mut bytes: Vec<u8> = Vec::with_capacity(length as usize);
+        bytes.extend([0x45, 0]);
+        bytes.extend(length.to_be_bytes());
+        bytes.extend(self.identification.to_be_bytes());
+        bytes.extend(0x4000u16.to_be_bytes());
+        bytes.extend([self.ttl, self.protocol, 0, 0]);
+        bytes.extend(ip_octets(&self.source));
+        bytes.extend(ip_octets(&self.destination));
+        let sum = checksum(&bytes);
+        bytes[10..12].copy_from_slice(&sum.to_be_bytes());
+        bytes.extend(payload);
+        Some(bytes)
+    }
+}
+
+pub const ICMP_ECHO_REPLY: u8 = 0;
+pub const ICMP_UNREACHABLE: u8 = 3;
+pub const ICMP_ECHO_REQUEST: u8 = 8;
+pub const ICMP_TIME_EXCEEDED: u8 = 11;
+
+pub const ICMP_PORT_UNREACHABLE: u8 = 3;
+
+// `rest` is the second word of the header, identifier and sequence number for an echo
+pub fn icmp(kind: u8, code: u8, rest: [u8; 4], data: &[u8]) -> Vec<u8> {
+    let mut bytes: Vec<u8> = vec![kind, code, 0, 0];
+    bytes.extend(rest);
+    bytes.extend(data);
+    let sum = checksum(&bytes);
+    bytes[2..4].copy_from_slice(&sum.to_be_bytes());
+    bytes
+}
+
+pub fn icmp_echo(kind: u8, identifier: u16, sequence: u16, data: &[u8]) -> Vec<u8> {
+    let [a, b] = identifier.to_be_bytes();
+    let [c, d] = sequence.to_be_bytes();
+    icmp(kind, 0, [a, b, c, d], data)
+}
+
+// Errors quote the header and first 8 bytes of the packet that caused them
+pub fn icmp_error(kind: u8, code: u8, packet: &[u8]) -> Vec<u8> {
+    icmp(kind, code, [0; 4], &packet[..packet.len().min(28)])
+}
+
+// Checksum of a UDP or TCP segment, over the pseudo-header of the IP addresses
+fn transport_checksum(source: &IP, destination: &IP, protocol: u8, segment: &[u8]) -> u16 {
+    let mut pseudo: Vec<u8> = Vec::with_capacity(12 + segment.len());
+    pseudo.extend(ip_octets(source));
+    pseudo.extend(ip_octets(destination));
+    pseudo.extend([0, protocol]);
+    pseudo.extend((segment.len() as u16).to_be_bytes());
+    pseudo.extend(segment);
+    checksum(&pseudo)
+}
+
+pub fn udp(source: &IP, destination: &IP, source_port: u16, destination_port: u16, data: &[u8]) -> Vec<u8> {
+    // A datagram too long for its length field cannot be carried by IPv4 either
+    let length = u16::try_from(8 + data.len()).unwrap_or(0);
+    let mut bytes: Vec<u8> = Vec::with_capacity(8 + data.len());
+    bytes.extend(source_port.to_be_bytes());
+    bytes.extend(destination_port.to_be_bytes());
+    bytes.extend(length.to_be_bytes());
+    bytes.extend([0, 0]);
+    bytes.extend(data);
+    // A computed checksum of 0 is sent as all ones, 0 meaning no checksum
+    let sum = match transport_checksum(source, destination, PROTOCOL_UDP, &bytes) {
+        0 => 0xFFFF,
+        sum => sum,
+    };
+    bytes[6..8].copy_from_slice(&sum.to_be_bytes());
+    bytes
+}
+
+pub const TCP_FIN: u8 = 0x01;
+pub const TCP_SYN: u8 = 0x02;
+pub const TCP_RST: u8 = 0x04;
+pub const TCP_PSH: u8 = 0x08;
+pub const TCP_ACK: u8 = 0x10;
+
+pub const TCP_WINDOW: u16 = 65535;
+
+#[derive(Clone)]
+pub struct Tcp {
+    pub source_port: u16,
+    pub destination_port: u16,
+    pub sequence: u32,
+    pub acknowledgment: u32,
+    pub flags: u8
+}
+
+impl Tcp {
+    // Header without options, followed by `data`
+    pub fn encode(&self, source: &IP, destination: &IP, data: &[u8]) -> Vec<u8> {
+        let mut bytes: Vec<u8> = Vec::with_capacity(20 + data.len());
+        bytes.extend(self.source_port.to_be_bytes());
+        bytes.extend(self.destination_port.to_be_bytes());
+        bytes.extend(self.sequence.to_be_bytes());
+        bytes.extend(self.acknowledgment.to_be_bytes());
+        bytes.extend([5 << 4, self.flags]);
+        bytes.extend(TCP_WINDOW.to_be_bytes());
+        bytes.extend([0, 0, 0, 0]);
+        bytes.extend(data);
+        let sum = transport_checksum(source, destination, PROTOCOL_TCP, &bytes);
+        bytes[16..18].copy_from_slice(&sum.to_be_bytes());
+        bytes
+    }
+}
+
+pub const DHCP_SERVER_PORT: u16 = 67;
+pub const DHCP_CLIENT_PORT: u16 = 68;
+
+#[derive(Clone, Copy)]
+pub enum DhcpMessage {
+    Discover = 1,
+    Offer = 2,
+    Request = 3,
+    Ack = 5
+}
+
+// One day, as the simulated leases only end when expired explicitly
+pub const DHCP_LEASE_TIME: u32 = 86400;
+
+// A BOOTP message with the DHCP options of the exchange
+pub struct Dhcp {
+    pub message: DhcpMessage,
+    pub transaction: u32,
+    pub client: MAC,
+    pub offered: Option<IP>,
+    pub server: IP,
+    pub netmask: IP,
+    pub name_server: Option<IP>
+}
+
+impl Dhcp {
+    pub fn encode(&self) -> Vec<u8> {
+        let from_server = matches!(self.message, DhcpMessage::Offer | DhcpMessage::Ack);
+        let unspecified = IP::V4(0, 0, 0, 0);
+        let mut bytes: Vec<u8> = Vec::with_capacity(300);
+        bytes.extend([if from_server { 2 } else { 1 }, 1, 6, 0]);
+        bytes.extend(self.transaction.to_be_bytes());
+        bytes.extend([0, 0]);
+        // Broadcast flag, the client having no address yet
+        bytes.extend(0x8000u16.to_be_bytes());
+        bytes.extend(ip_octets(&unspecified));
+        let yiaddr = if from_server { self.offered.clone().unwrap_or(unspecified.clone()) } else { unspecified.clone() };
+        bytes.extend(ip_octets(&yiaddr));
+        bytes.extend(ip_octets(if from_server { &self.server } else { &unspecified }));
+        bytes.extend(ip_octets(&unspecified));
+        bytes.extend(self.client.octets());
+        bytes.extend([0; 10]);
+        bytes.extend([0; 64 + 128]);
+        bytes.extend([99, 130, 83, 99]);
+        bytes.extend([53, 1, self.message as u8]);
+        match self.message {
+            DhcpMessage::Discover => {},
+            DhcpMessage::Request => {
+                if let Some(offered) = &self.offered {
+                    bytes.extend([50, 4]);
+                    bytes.extend(ip_octets(offered));
+                }
+                bytes.extend([54, 4]);
+                bytes.extend(ip_octets(&self.server));
+            },
+            DhcpMessage::Offer | DhcpMessage::Ack => {
+                bytes.extend([54, 4]);
+                bytes.extend(ip_octets(&self.server));
+                bytes.extend([51, 4]);
+                bytes.extend(DHCP_LEASE_TIME.to_be_bytes());
+                bytes.extend([1, 4]);
+                bytes.extend(ip_octets(&self.netmask));
+                if let Some(name_server) = &self.name_server {
+                    bytes.extend([6, 4]);
+                    bytes.extend(ip_octets(name_server));
+                }
+            },
+        }
+        bytes.push(255);
+        bytes
+    }
+}
+
+pub const DNS_PORT: u16 = 53;
+
+pub const DNS_TYPE_A: u16 = 1;
+pub const DNS_TYPE_CNAME: u16 = 5;
+pub const DNS_TYPE_PTR: u16 = 12;
+pub const DNS_TYPE_AAAA: u16 = 28;
+
+pub const DNS_NOERROR: u8 = 0;
+pub const DNS_NXDOMAIN: u8 = 3;
+
+// An hour, as zones only change when reloaded
+pub const DNS_TTL: u32 = 3600;
+
+// Labels without compression
+pub fn dns_name(name: &str) -> Vec<u8> {
+    let mut bytes: Vec<u8> = Vec::new();
+    for label in name.split('.').filter(|l| !l.is_empty()) {
+        let label = &label.as_bytes()[..label.len().min(63)];
+        bytes.push(label.len() as u8);
+        bytes.extend(label);
+    }
+    bytes.push(0);
+    bytes
+}
+
+// A record of the answer section, its data already encoded
+pub struct DnsAnswer {
+    pub name: String,
+    pub kind: u16,
+    pub data: Vec<u8>
+}
+
+// A query for one name, or the authoritative response to it when `response` is set
+pub struct Dns {
+    pub id: u16,
+    pub response: bool,
+    pub rcode: u8,
+    pub name: String,
+    pub kind: u16,
+    pub answers: Vec<DnsAnswer>
+}
+
+impl Dns {
+    pub fn encode(&self) -> Vec<u8> {
+        // Recursion desired, and authoritative answer in responses
+        let flags: u16 = match self.response {
+            true => 0x8000 | 0x0400 | 0x0100 | self.rcode as u16,
+            false => 0x0100,
+        };
+        let mut bytes: Vec<u8> = Vec::new();
+        bytes.extend(self.id.to_be_bytes());
+        bytes.extend(flags.to_be_bytes());
+        bytes.extend(1u16.to_be_bytes());
+        bytes.extend((self.answers.len() as u16).to_be_bytes());
+        bytes.extend([0, 0, 0, 0]);
+        bytes.extend(dns_name(&self.name));
+        bytes.extend(self.kind.to_be_bytes());
+        bytes.extend(1u16.to_be_bytes());
+        for answer in &self.answers {
+            bytes.extend(dns_name(&answer.name));
+            bytes.extend(answer.kind.to_be_bytes());
+            bytes.extend(1u16.to_be_bytes());
+            bytes.extend(DNS_TTL.to_be_bytes());
+            bytes.extend((answer.data.len() as u16).to_be_bytes());
+            bytes.extend(&answer.data);
+        }
+        bytes
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn hex(s: &str) -> Vec<u8> {
+        let s: String = s.split_whitespace().collect();
+        (0..s.len()).step_by(2).map(|i| u8::from_str_radix(&s[i..i + 2], 16).unwrap_or_else(|e| panic!("{}", e))).collect()
+    }
+
+    #[test]
+    fn checksum_matches_rfc_1071() {
+        assert_eq!(checksum(&hex("0001 f203 f4f5 f6f7")), 0x220D);
+        // An odd length is padded with a zero byte
+        assert_eq!(checksum(&[0x01]), !0x0100);
+    }
+
+    #[test]
+    fn ipv4_header_matches_a_known_packet() {
+        let header = Ipv4 { source: IP::V4(192, 168, 0, 1), destination: IP::V4(192, 168, 0, 199), ttl: 64, protocol: PROTOCOL_UDP, identification: 0 };
+        let packet = header.encode(&[0; 95]).unwrap_or_else(|| panic!("too long"));
+        assert_eq!(packet[..20], hex("4500 0073 0000 4000 4011 b861 c0a8 0001 c0a8 00c7"));
+        assert_eq!(checksum(&packet[..20]), 0);
+    }
+
+    #[test]
+    fn ipv4_refuses_what_its_length_cannot_hold() {
+        let header = Ipv4 { source: IP::V4(10, 0, 0, 1), destination: IP::V4(10, 0, 0, 2), ttl: 64, protocol: PROTOCOL_UDP, identification: 0 };
+        assert!(header.encode(&vec![0; 65515]).is_some());
+        assert!(header.encode(&vec![0; 65516]).is_none());
+    }
+
+    #[test]
+    fn icmp_echo_is_checksummed() {
+        assert_eq!(icmp_echo(ICMP_ECHO_REQUEST, 1, 1, b"abcd"), hex("0800 3337 0001 0001 61626364"));
+    }
+
+    #[test]
+    fn icmp_errors_quote_28_bytes() {
+        let quoted: Vec<u8> = (0..40).collect();
+        assert_eq!(icmp_error(ICMP_TIME_EXCEEDED, 0, &quoted)[8..], quoted[..28]);
+        assert_eq!(icmp_error(ICMP_TIME_EXCEEDED, 0, &quoted[..10])[8..], quoted[..10]);
+    }
+
+    #[test]
+    fn udp_checksum_covers_the_pseudo_header() {
+        let datagram = udp(&IP::V4(10, 0, 0, 1), &IP::V4(10, 0, 0, 2), 49152, 7, b"hello");
+        assert_eq!(datagram, hex("c000 0007 000d e7f7 68656c6c6f"));
+    }
+
+    #[test]
+    fn tcp_checksum_covers_the_pseudo_header() {
+        let syn = Tcp { source_port: 49152, destination_port: 80, sequence: 1, acknowledgment: 0, flags: TCP_SYN };
+        let segment = syn.encode(&IP::V4(10, 0, 0, 1), &IP::V4(10, 0, 0, 2), &[]);
+        assert_eq!(segment, hex("c000 0050 00000001 00000000 5002 ffff db8e 0000"));
+    }
+
+    #[test]
+    fn dns_query_layout() {
+        let query = Dns { id: 0x1234, response: false, rcode: DNS_NOERROR, name: "example.com.".to_string(), kind: DNS_TYPE_A, answers: Vec::new() };
+        assert_eq!(query.encode(), hex("1234 0100 0001 0000 0000 0000 076578616d706c6503636f6d00 0001 0001"));
+    }
+
+    #[test]
+    fn dns_response_layout() {
+        let answer = DnsAnswer { name: "example.com".to_string(), kind: DNS_TYPE_A, data: vec![93, 184, 216, 34] };
+        let response = Dns { id: 0x1234, response: true, rcode: DNS_NOERROR, name: "example.com".to_string(), kind: DNS_TYPE_A, answers: vec![answer] };
+        let bytes = response.encode();
+        assert_eq!(bytes[..12], hex("1234 8500 0001 0001 0000 0000"));
+        assert_eq!(bytes[29..], hex("076578616d706c6503636f6d00 0001 0001 00000e10 0004 5db8d822"));
+        let missing = Dns { id: 1, response: true, rcode: DNS_NXDOMAIN, name: "a".to_string(), kind: DNS_TYPE_A, answers: Vec::new() };
+        assert_eq!(missing.encode()[2..4], [0x85, 0x03]);
+    }
+
+    #[test]
+    fn arp_request_layout() {
+        let sender = MAC::EUI48(0x02, 0, 0, 0, 0, 1);
+        let unknown = MAC::EUI48(0, 0, 0, 0, 0, 0);
+        let request = arp(ARP_REQUEST, &sender, &IP::V4(10, 0, 0, 1), &unknown, &IP::V4(10, 0, 0, 2));
+        assert_eq!(request, hex("0001 0800 0604 0001 020000000001 0a000001 000000000000 0a000002"));
+    }
+
+    #[test]
+    fn frames_are_tagged_and_padded() {
+        let frame = Frame::new(BROADCAST, MAC::EUI48(0x02, 0, 0, 0, 0, 1), ETHERTYPE_ARP, vec![0xAB; 28]);
+        let untagged = frame.encode(None);
+        assert_eq!(untagged.len(), 60);
+        assert_eq!(untagged[..15], hex("ffffffffffff 020000000001 0806 ab"));
+        let tagged = frame.encode(Some(10));
+        assert_eq!(tagged.len(), 64);
+        assert_eq!(tagged[12..19], hex("8100 000a 0806 ab"));
+    }
+
+    #[test]
+    fn dhcp_offer_layout() {
+        let offer = Dhcp {
+            message: DhcpMessage::Offer, transaction: 0x01020304, client: MAC::EUI48(0x02, 0, 0, 0, 0, 1),
+            offered: Some(IP::V4(192, 168, 1, 10)), server: IP::V4(192, 168, 1, 1), netmask: IP::V4(255, 255, 255, 0), name_server: None
+        }.encode();
+        assert_eq!(offer[..20], hex("02010600 01020304 0000 8000 00000000 c0a8010a"));
+        assert_eq!(offer[20..24], hex("c0a80101"));
+        assert_eq!(offer[28..34], hex("020000000001"));
+        assert_eq!(offer[236..], hex("63825363 350102 3604c0a80101 330400015180 0104ffffff00 ff"));
+    }
+
+    #[test]
+    fn dhcp_request_names_the_offer_and_its_server() {
+        let request = Dhcp {
+            message: DhcpMessage::Request, transaction: 1, client: MAC::EUI48(0x02, 0, 0, 0, 0, 1),
+            offered: Some(IP::V4(192, 168, 1, 10)), server: IP::V4(192, 168, 1, 1), netmask: IP::V4(255, 255, 255, 0), name_server: None
+        }.encode();
+        assert_eq!(request[0], 1);
+        assert_eq!(request[16..24], [0; 8]);
+        assert_eq!(request[236..], hex("63825363 350103 3204c0a8010a 3604c0a80101 ff"));
+    }
+}
@@ -0,0 +1,58 @@
+// libpcap file format, microsecond timestamps and Ethernet link type
+const MAGIC: u32 = 0xA1B2C3D4;
+const VERSION_MAJOR: u16 = 2;
+const VERSION_MINOR: u16 = 4;
+const SNAPLEN: u32 = 65535;
+const LINKTYPE_ETHERNET: u32 = 1;
+
+pub fn file_header() -> Vec<u8> {
+    let mut bytes: Vec<u8> = Vec::with_capacity(24);
+    bytes.extend(MAGIC.to_le_bytes());
+    bytes.extend(VERSION_MAJOR.to_le_bytes());
+    bytes.extend(VERSION_MINOR.to_le_bytes());
+    bytes.extend(0i32.to_le_bytes());
+    bytes.extend(0u32.to_le_bytes());
+    bytes.extend(SNAPLEN.to_le_bytes());
+    bytes.extend(LINKTYPE_ETHERNET.to_le_bytes());
+    bytes
+}
+
+// `time` in microseconds of simulated time
+pub fn record(time: u64, frame: &[u8]) -> Vec<u8> {
+    let length = frame.len().min(SNAPLEN as usize);
+    let mut bytes: Vec<u8> = Vec::with_capacity(16 + length);
+    bytes.extend(((time / 1_000_000) as u32).to_le_bytes());
+    bytes.extend(((time % 1_000_000) as u32).to_le_bytes());
+    bytes.extend((length as u32).to_le_bytes());
+    bytes.extend((frame.len() as u32).to_le_bytes());
+    bytes.extend(&frame[..length]);
+    bytes
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn file_header_is_little_endian_ethernet() {
+        assert_eq!(file_header(), [
+            0xD4, 0xC3, 0xB2, 0xA1, 2, 0, 4, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0xFF, 0xFF, 0, 0, 1, 0, 0, 0
+        ]);
+    }
+
+    #[test]
+    fn records_split_the_time_in_seconds_and_microseconds() {
+        let frame = [0xAA; 60];
+        let bytes = record(3_000_042, &frame);
+        assert_eq!(bytes[..16], [3, 0, 0, 0, 42, 0, 0, 0, 60, 0, 0, 0, 60, 0, 0, 0]);
+        assert_eq!(bytes[16..], frame);
+    }
+
+    #[test]
+    fn records_are_cut_at_the_snapshot_length() {
+        let frame = vec![0; 70_000];
+        let bytes = record(0, &frame);
+        assert_eq!(bytes[8..16], [0xFF, 0xFF, 0, 0, 0x70, 0x11, 1, 0]);
+        assert_eq!(bytes.len(), 16 + 65535);
+    }
+}
@@ -0,0 +1,102 @@
+use rand::{Rng, SeedableRng};
+use rand_chacha::ChaCha12Rng;
+
+
+// Every random draw of a simulation goes through this context, so that a run is replayed by reusing its seed
+#[derive(Clone)]
+pub struct Random {
+    seed: u64,
+    rng: ChaCha12Rng
+}
+
+impl std::fmt::Display for Random {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", self.seed)
+    }
+}
+
+impl Random {
+    // Without a seed, one is drawn so that the run can still be reproduced afterwards
+    pub fn new(seed: Option<u64>) -> Self {
+        let seed = seed.unwrap_or_else(|| rand::rng().random());
+        Random { seed, rng: ChaCha12Rng::seed_from_u64(seed) }
+    }
+
+    // Resumes a sequence where `position` draws were already made
+    pub fn at(seed: u64, position: u128) -> Self {
+        let mut random = Random::new(Some(seed));
+        random.rng.set_word_pos(position);
+        random
+    }
+
+    pub fn seed(&self) -> u64 {
+        self.seed
+    }
+
+    pub fn position(&self) -> u128 {
+        self.rng.get_word_pos()
+    }
+
+    // Restarts the sequence of draws from the beginning
+    pub fn reseed(&mut self, seed: u64) {
+        *self = Random::new(Some(seed));
+    }
+
+    pub fn rng(&mut self) -> &mut ChaCha12Rng {
+        &mut self.rng
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::graph::Graph;
+    use crate::load::{DataFormat, parse_data};
+
+    const OFFICE: &str = "
+seed: 42
+routers:
+  - name: R1
+    lan: {ip: 192.168.1.1, netmask: 24}
+    wan: {ip: 10.0.0.1, netmask: 30}
+devices:
+  - {name: PC1, ip: 192.168.1.10, netmask: 24}
+switches:
+  - name: SW1
+";
+
+    fn draws(random: &mut Random) -> Vec<u32> {
+        (0..8).map(|_| random.rng().random()).collect()
+    }
+
+    fn addresses(data: &str) -> Vec<String> {
+        let mut graph = Graph::new();
+        let report = graph.load_data(parse_data("test", data, DataFormat::Yaml).unwrap_or_else(|e| panic!("{}", e)));
+        assert!(report.rejected().is_empty());
+        ["R1.lan", "R1.wan", "PC1", "SW1"].iter().map(|name| graph.resolve(name).unwrap_or_else(|e| panic!("{}", e)).to_hex()).collect()
+    }
+
+    #[test]
+    fn a_seed_replays_its_draws() {
+        assert_eq!(draws(&mut Random::new(Some(7))), draws(&mut Random::new(Some(7))));
+        assert_ne!(draws(&mut Random::new(Some(7))), draws(&mut Random::new(Some(8))));
+        let mut random = Random::new(Some(7));
+        let first = draws(&mut random);
+        random.reseed(7);
+        assert_eq!(draws(&mut random), first);
+    }
+
+    #[test]
+    fn a_sequence_resumes_at_its_position() {
+        let mut random = Random::new(Some(7));
+        draws(&mut random);
+        let mut resumed = Random::at(random.seed(), random.position());
+        assert_eq!(draws(&mut resumed), draws(&mut random));
+    }
+
+    #[test]
+    fn a_seeded_topology_always_yields_the_same_network() {
+        assert_eq!(addresses(OFFICE), addresses(OFFICE));
+        assert_ne!(addresses(OFFICE), addresses(&OFFICE.replace("seed: 42", "seed: 43")));
+    }
+}
@@ -1,7 +1,9 @@
 use crate::commands::{PingStatus, ping};
 use crate::dhcp::{DHCP, DhcpError};
-use crate::graph::{Graph, GraphError};
+use crate::dns::Record;
+use crate::graph::{Graph, GraphError, NodeType};
 use crate::ip::IP;
+use crate::mac::MAC;
 use crate::nic::NIC;
 
 
@@ -12,6 +14,46 @@ pub struct Router {
     pub nic_wan: NIC,
     pub dhcp_lan: Option<DHCP>,
     pub dhcp_wan: Option<DHCP>,
+    pub subinterfaces: Vec<SubInterface>,
+    pub zone: Option<Vec<Record>>,
+}
+
+// 802.1Q subinterface: frames tagged with `vlan` on the link of the `parent` interface
+#[derive(Clone)]
+pub struct SubInterface {
+    pub parent: RouterInterface,
+    pub vlan: u16,
+    pub nic: NIC,
+    pub dhcp: Option<DHCP>
+}
+
+impl std::fmt::Display for SubInterface {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}.{}:[{}] DHCP -> {}", self.parent, self.vlan, self.nic,
+            match &self.dhcp {
+                Some(dhcp) => format!(" Enabled : {} > {}", dhcp.first_ip, dhcp.last_ip),
+                None => "Disabled".to_string(),
+            }
+        )
+    }
+}
+
+impl SubInterface {
+    pub fn set_dhcp(&mut self, range: Option<(IP, IP)>) -> Result<(), DhcpError> {
+        let name_server = self.dhcp.as_ref().and_then(|d| d.name_server.clone());
+        self.dhcp = match range {
+            Some((first_ip, last_ip)) => Some(DHCP { name_server, ..DHCP::new(self.nic.clone(), first_ip, last_ip)? }),
+            None => None
+        };
+        Ok(())
+    }
+
+    pub fn get_next_dhcp_ip(&self, graph: &Graph) -> Result<IP, DhcpError> {
+        match &self.dhcp {
+            Some(dhcp) => next_dhcp_ip(graph, &self.nic, dhcp),
+            None => Err(DhcpError::DisabledDHCP)
+        }
+    }
 }
 
 impl std::fmt::Display for Router {
@@ -26,10 +68,15 @@ impl std::fmt::Display for Router {
                 Some(dhcp) => format!(" Enabled : {} > {}", dhcp.first_ip, dhcp.last_ip),
                 None => "Disabled".to_string(),
             }
-        )
+        )?;
+        for subinterface in &self.subinterfaces {
+            write!(f, "\n\t{}", subinterface)?;
+        }
+        Ok(())
     }
 }
 
+#[derive(Clone, PartialEq)]
 pub enum RouterInterface {LAN,WAN}
 
 impl std::fmt::Display for RouterInterface {
@@ -43,75 +90,115 @@ impl std::fmt::Display for RouterInterface {
 
 impl Router {
     fn new( name: String,
-            ip_lan:IP, netmask_lan:IP, ip_wan:IP, netmask_wan:IP,
+            nic_lan: NIC, nic_wan: NIC,
             dhcp_lan_first_ip: Option<IP>, dhcp_lan_last_ip: Option<IP>,
             dhcp_wan_first_ip: Option<IP>, dhcp_wan_last_ip: Option<IP>) -> Self {
-        let nic_lan: NIC = NIC::new(ip_lan, netmask_lan);
-        let nic_wan: NIC = NIC::new(ip_wan, netmask_wan);
         let dhcp_lan: Option<DHCP> = match (dhcp_lan_first_ip, dhcp_lan_last_ip) {
             (Some(first_ip), Some(last_ip)) => {
-                match DHCP::new(nic_lan.clone(), first_ip, last_ip) {
-                    Ok(dhcp) => Some(dhcp),
-                    Err(_) => None,
-                }
+                DHCP::new(nic_lan.clone(), first_ip, last_ip).ok()
             },
             _ => None,
         };
         let dhcp_wan: Option<DHCP> = match (dhcp_wan_first_ip, dhcp_wan_last_ip) {
             (Some(first_ip), Some(last_ip)) => {
-                match DHCP::new(nic_wan.clone(), first_ip, last_ip) {
-                    Ok(dhcp) => Some(dhcp),
-                    Err(_) => None,
-                }
+                DHCP::new(nic_wan.clone(), first_ip, last_ip).ok()
             },
             _ => None,
         };
-        Self { name, nic_lan, nic_wan, dhcp_lan, dhcp_wan }
+        Self { name, nic_lan, nic_wan, dhcp_lan, dhcp_wan, subinterfaces: Vec::new(), zone: None }
+    }
+
+    pub fn set_dhcp(&mut self, interface: RouterInterface, range: Option<(IP, IP)>) -> Result<(), DhcpError> {
+        let dhcp = match range {
+            Some((first_ip, last_ip)) => {
+                let nic = match interface {
+                    RouterInterface::LAN => self.nic_lan.clone(),
+                    RouterInterface::WAN => self.nic_wan.clone(),
+                };
+                // Changing the range keeps the name server of the pool
+                let name_server = self.dhcp_mut(&interface).as_ref().and_then(|d| d.name_server.clone());
+                Some(DHCP { name_server, ..DHCP::new(nic, first_ip, last_ip)? })
+            },
+            None => None
+        };
+        *self.dhcp_mut(&interface) = dhcp;
+        Ok(())
     }
 
-    pub fn get_next_dhcp_ip(&mut self, graph: &Graph, interface: RouterInterface) -> Result<IP, DhcpError> {
-        let dhcp = match interface {
+    pub fn dhcp_mut(&mut self, interface: &RouterInterface) -> &mut Option<DHCP> {
+        match interface {
             RouterInterface::LAN => &mut self.dhcp_lan,
             RouterInterface::WAN => &mut self.dhcp_wan,
+        }
+    }
+
+    pub fn get_next_dhcp_ip(&self, graph: &Graph, interface: RouterInterface) -> Result<IP, DhcpError> {
+        let (nic, dhcp) = match interface {
+            RouterInterface::LAN => (&self.nic_lan, &self.dhcp_lan),
+            RouterInterface::WAN => (&self.nic_wan, &self.dhcp_wan),
         };
-        let nic = match interface {
+        match dhcp {
+            Some(dhcp) => next_dhcp_ip(graph, nic, dhcp),
+            None => Err(DhcpError::DisabledDHCP)
+        }
+    }
+
+    pub fn subinterface(&self, parent: &RouterInterface, vlan: u16) -> Option<&SubInterface> {
+        self.subinterfaces.iter().find(|s| s.parent == *parent && s.vlan == vlan)
+    }
+
+    pub fn nic(&self, interface: &RouterInterface) -> &NIC {
+        match interface {
             RouterInterface::LAN => &self.nic_lan,
             RouterInterface::WAN => &self.nic_wan,
-        };
-        let dhcp = match dhcp {
-            Some(dhcp) => dhcp,
-            None => return Err(DhcpError::DisabledDHCP)
-        };
-        let mut ip_candidate: IP = dhcp.first_ip.clone();
-        loop {
-            if ip_candidate.is_greater_than(&dhcp.last_ip) {
-                return Err(DhcpError::NoMoreIPsAvailable);
-            }
-            match ping(graph, nic.clone(), ip_candidate.clone()) {
-                PingStatus::Success => {}
-                _ => {
-                    return Ok(ip_candidate.clone());
-                }
+        }
+    }
+
+    // Every interface of the router, subinterfaces included
+    pub fn macs(&self) -> Vec<MAC> {
+        let mut macs = vec![self.nic_lan.mac.clone(), self.nic_wan.mac.clone()];
+        macs.extend(self.subinterfaces.iter().map(|s| s.nic.mac.clone()));
+        macs
+    }
+
+    pub fn has_mac(&self, mac: &MAC) -> bool {
+        self.macs().contains(mac)
+    }
+}
+
+// First address of the pool that does not answer to a ping from the server
+fn next_dhcp_ip(graph: &Graph, nic: &NIC, dhcp: &DHCP) -> Result<IP, DhcpError> {
+    let mut ip_candidate: IP = dhcp.first_ip.clone();
+    loop {
+        if ip_candidate.is_greater_than(&dhcp.last_ip) {
+            return Err(DhcpError::NoMoreIPsAvailable);
+        }
+        match ping(graph, nic.clone(), ip_candidate.clone()) {
+            PingStatus::Success => {}
+            _ => {
+                return Ok(ip_candidate.clone());
             }
-            match ip_candidate.increment() {
-                Ok(()) => {},
-                Err(_) => return Err(DhcpError::NoMoreIPsAvailable)
-            };
         }
+        match ip_candidate.increment() {
+            Ok(()) => {},
+            Err(_) => return Err(DhcpError::NoMoreIPsAvailable)
+        };
     }
 }
 
+#[allow(clippy::too_many_arguments)]
 pub fn create_router( name: String, graph: &mut Graph,
                       ip_lan: IP, netmask_lan: IP, ip_wan: IP, netmask_wan: IP,
                       dhcp_lan_first_ip: Option<IP>, dhcp_lan_last_ip: Option<IP>,
                       dhcp_wan_first_ip: Option<IP>, dhcp_wan_last_ip: Option<IP>) -> Result<(), GraphError> {
+    let nic_lan: NIC = NIC::with_mac(ip_lan, netmask_lan, graph.new_mac(&NodeType::Router));
+    let nic_wan: NIC = NIC::with_mac(ip_wan, netmask_wan, graph.new_mac(&NodeType::Router));
     let router = Router::new(
-        name, ip_lan, netmask_lan, ip_wan, netmask_wan,
+        name, nic_lan, nic_wan,
         dhcp_lan_first_ip, dhcp_lan_last_ip, dhcp_wan_first_ip, dhcp_wan_last_ip
     );
-    match graph.append_internal_router_connection(router.nic_lan.mac.clone(), router.nic_wan.mac.clone()) {
-        Err(e) => return Err(e),
-        _ => {}
-    }
-    graph.append_router(router)
+    // The internal link is only added once the router is part of the graph
+    let (mac_lan, mac_wan) = (router.nic_lan.mac.clone(), router.nic_wan.mac.clone());
+    graph.append_router(router)?;
+    graph.append_internal_router_connection(mac_lan, mac_wan)
 }
@@ -0,0 +1,442 @@
+use std::fs::read_to_string;
+use std::path::Path;
+use serde::{Deserialize, Serialize};
+
+use crate::capture::{CaptureError, CapturePoint};
+use crate::clock::{Action, TimelineEntry, advance, execute, parse_duration};
+use crate::commands::{PingStatus, ping, traceroute};
+use crate::dns;
+use crate::graph::{Graph, GraphError};
+use crate::ip::IP;
+use crate::load::{DataFormat, LoadEntry, LoadError, LoadStatus, LoadedConnections, LoadedDevices, LoadedRouter, LoadedSwitch, load_data};
+use crate::mac::MAC;
+use crate::snapshot::{restore_snapshot, save_snapshot};
+use crate::transport::{self, OpenOutcome, RequestOutcome, Transport};
+
+
+#[derive(Serialize, Deserialize)]
+pub struct Script {
+    pub name: Option<String>,
+    pub steps: Vec<Step>
+}
+
+#[derive(Serialize, Deserialize)]
+#[serde(tag = "action", rename_all = "snake_case")]
+pub enum Step {
+    Load { file: String },
+    SaveSnapshot { file: String },
+    RestoreSnapshot { file: String },
+    CreateRouter(Box<LoadedRouter>),
+    CreateDevice(LoadedDevices),
+    CreateSwitch(LoadedSwitch),
+    Connect(LoadedConnections),
+    Disconnect(LoadedConnections),
+    FailLink(LoadedConnections),
+    RepairLink(LoadedConnections),
+    FailNode { node: String },
+    RepairNode { node: String },
+    ExpireLease { node: String },
+    Advance { by: String },
+    Schedule { after: String, event: Action },
+    CaptureInterface { interface: String, file: String },
+    CaptureLink { from: String, to: String, file: String },
+    StopCapture { file: String },
+    Listen { node: String, transport: Transport, port: u16 },
+    Open { from: String, transport: Transport, to: String, expect: Option<ExpectedOpen> },
+    Close { node: String, port: u16 },
+    Request { from: String, transport: Transport, to: String, data: Option<String>, expect: Option<ExpectedRequest>, contains: Option<String> },
+    Ping { from: String, to: String, expect: Option<ExpectedPing> },
+    Traceroute { from: String, to: String, expect: Option<Vec<String>> }
+}
+
+#[derive(Serialize, Deserialize, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub enum ExpectedPing {
+    Success,
+    Timeout
+}
+
+#[derive(Serialize, Deserialize, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub enum ExpectedOpen {
+    Connected,
+    Delivered,
+    Refused,
+    Timeout
+}
+
+#[derive(Serialize, Deserialize, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub enum ExpectedRequest {
+    Response,
+    NoResponse,
+    Refused,
+    Timeout
+}
+
+impl std::fmt::Display for Step {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Step::Load { file } => write!(f, "load {}", file),
+            Step::SaveSnapshot { file } => write!(f, "save snapshot {}", file),
+            Step::RestoreSnapshot { file } => write!(f, "restore snapshot {}", file),
+            Step::CreateRouter(r) => write!(f, "create router {}", r.name),
+            Step::CreateDevice(d) => write!(f, "create device {}", d.name),
+            Step::CreateSwitch(s) => write!(f, "create switch {}", s.name),
+            Step::Connect(c) => write!(f, "connect {} to {}", c.from, c.to),
+            Step::Disconnect(c) => write!(f, "disconnect {} from {}", c.from, c.to),
+            Step::FailLink(c) => write!(f, "fail link {} <-> {}", c.from, c.to),
+            Step::RepairLink(c) => write!(f, "repair link {} <-> {}", c.from, c.to),
+            Step::FailNode { node } => write!(f, "fail node {}", node),
+            Step::RepairNode { node } => write!(f, "repair node {}", node),
+            Step::ExpireLease { node } => write!(f, "expire lease of {}", node),
+            Step::Advance { by } => write!(f, "advance clock by {}", by),
+            Step::Schedule { after, event } => write!(f, "schedule {} in {}", event, after),
+            Step::CaptureInterface { interface, file } => write!(f, "capture {} to {}", interface, file),
+            Step::CaptureLink { from, to, file } => write!(f, "capture link {} <-> {} to {}", from, to, file),
+            Step::StopCapture { file } => write!(f, "stop capture to {}", file),
+            Step::Listen { node, transport, port } => write!(f, "listen on {}/{} on {}", port, transport, node),
+            Step::Open { from, transport, to, .. } => write!(f, "open {} {} from {}", transport, to, from),
+            Step::Close { node, port } => write!(f, "close port {} on {}", port, node),
+            Step::Request { from, transport, to, .. } => write!(f, "request {} {} from {}", transport, to, from),
+            Step::Ping { from, to, .. } => write!(f, "ping {} from {}", to, from),
+            Step::Traceroute { from, to, .. } => write!(f, "traceroute {} from {}", to, from),
+        }
+    }
+}
+
+pub enum StepOutcome {
+    Done,
+    Passed,
+    Failed(String),
+    Error(String)
+}
+
+pub struct StepResult {
+    pub index: usize,
+    pub description: String,
+    pub outcome: StepOutcome
+}
+
+// `timeline` holds what the clock logged while the script ran
+pub struct ScriptReport {
+    pub name: Option<String>,
+    pub results: Vec<StepResult>,
+    pub timeline: Vec<TimelineEntry>
+}
+
+impl ScriptReport {
+    pub fn passed(&self) -> usize {
+        self.results.iter().filter(|r| matches!(r.outcome, StepOutcome::Passed)).count()
+    }
+
+    pub fn failed(&self) -> usize {
+        self.results.iter().filter(|r| matches!(r.outcome, StepOutcome::Failed(_) | StepOutcome::Error(_))).count()
+    }
+
+    pub fn success(&self) -> bool {
+        self.failed() == 0
+    }
+}
+
+pub fn load_script(file_path: &str) -> Result<Script, LoadError> {
+    let file_content = match read_to_string(file_path) {
+        Ok(content) => content,
+        Err(_) => return Err(LoadError::FileNotFound(file_path.to_string())),
+    };
+    match serde_yaml::from_str(&file_content) {
+        Ok(script) => Ok(script),
+        Err(e) => Err(LoadError::ParseError(file_path.to_string(), e.to_string()))
+    }
+}
+
+fn resolve(graph: &Graph, s: &str) -> Result<MAC, String> {
+    graph.resolve(s).map_err(|e| e.to_string())
+}
+
+// A node or interface name stands for its address, names the graph does not know are looked up through DNS
+fn target(graph: &Graph, host: &MAC, to: &str) -> Result<IP, String> {
+    match graph.resolve(to) {
+        Ok(mac) => graph.nic_with_mac(mac).filter(|nic| !nic.is_localhost()).map(|nic| nic.ip).ok_or(format!("{} has no IP address", to)),
+        Err(GraphError::UnknownNode(_)) => dns::resolve(graph, host, to).map_err(|e| e.to_string()),
+        Err(e) => Err(e.to_string()),
+    }
+}
+
+fn entry_outcome(entry: LoadEntry) -> StepOutcome {
+    match entry.status {
+        LoadStatus::Accepted => StepOutcome::Done,
+        LoadStatus::Rejected(issue) => StepOutcome::Error(issue.to_string())
+    }
+}
+
+fn action_outcome(graph: &mut Graph, action: Action) -> StepOutcome {
+    match execute(graph, &action) {
+        Ok(_) => StepOutcome::Done,
+        Err(e) => StepOutcome::Error(e)
+    }
+}
+
+fn capture_outcome(graph: &mut Graph, point: Result<CapturePoint, CaptureError>, file: String) -> StepOutcome {
+    match point.and_then(|point| graph.captures.start(point, file)) {
+        Ok(_) => StepOutcome::Done,
+        Err(e) => StepOutcome::Error(e.to_string())
+    }
+}
+
+fn parse_delay(s: &str) -> Result<u64, String> {
+    parse_duration(s).ok_or(format!("Invalid duration '{}'", s))
+}
+
+fn run_step(graph: &mut Graph, step: Step, base_dir: &Path) -> StepOutcome {
+    match step {
+        Step::Load { file } => {
+            let path = base_dir.join(&file);
+            match load_data(&path.to_string_lossy()) {
+                Ok(data) => {
+                    let report = graph.load_data(data);
+                    let rejected: Vec<String> = report.rejected().iter().filter_map(|entry| match &entry.status {
+                        LoadStatus::Rejected(issue) => Some(format!("{} {} ({})", entry.kind, entry.subject, issue)),
+                        LoadStatus::Accepted => None,
+                    }).collect();
+                    match rejected.is_empty() {
+                        true => StepOutcome::Done,
+                        false => StepOutcome::Error(format!("{} entries rejected: {}", rejected.len(), rejected.join(", "))),
+                    }
+                },
+                Err(e) => StepOutcome::Error(e.to_string())
+            }
+        },
+        Step::SaveSnapshot { file } => {
+            let path = base_dir.join(&file).to_string_lossy().to_string();
+            match save_snapshot(&path, graph, DataFormat::from_path(&path)) {
+                Ok(_) => StepOutcome::Done,
+                Err(e) => StepOutcome::Error(e.to_string())
+            }
+        },
+        Step::RestoreSnapshot { file } => {
+            let path = base_dir.join(&file).to_string_lossy().to_string();
+            match restore_snapshot(&path, DataFormat::from_path(&path)) {
+                Ok(mut restored) => {
+                    restored.captures = graph.captures.clone();
+                    restored.trace = graph.trace.clone();
+                    *graph = restored;
+                    StepOutcome::Done
+                },
+                Err(e) => StepOutcome::Error(e.to_string())
+            }
+        },
+        Step::CreateRouter(r) => entry_outcome(graph.load_router(*r)),
+        Step::CreateDevice(d) => entry_outcome(graph.load_device(d)),
+        Step::CreateSwitch(s) => entry_outcome(graph.load_switch(s)),
+        Step::Connect(c) => entry_outcome(graph.load_connection(c)),
+        Step::Disconnect(c) => {
+            let (mac_src, mac_dest) = match (resolve(graph, &c.from), resolve(graph, &c.to)) {
+                (Ok(a), Ok(b)) => (a, b),
+                (Err(e), _) | (_, Err(e)) => return StepOutcome::Error(e)
+            };
+            match graph.remove_connection(mac_src, mac_dest) {
+                Ok(_) => StepOutcome::Done,
+                Err(e) => StepOutcome::Error(e.to_string())
+            }
+        },
+        Step::FailLink(c) => action_outcome(graph, Action::FailLink { from: c.from, to: c.to }),
+        Step::RepairLink(c) => action_outcome(graph, Action::RepairLink { from: c.from, to: c.to }),
+        Step::FailNode { node } => action_outcome(graph, Action::FailNode { node }),
+        Step::RepairNode { node } => action_outcome(graph, Action::RepairNode { node }),
+        Step::ExpireLease { node } => action_outcome(graph, Action::ExpireLease { node }),
+        Step::Advance { by } => match parse_delay(&by) {
+            Ok(duration) => {
+                advance(graph, duration);
+                StepOutcome::Done
+            },
+            Err(e) => StepOutcome::Error(e)
+        },
+        Step::Schedule { after, event } => match parse_delay(&after) {
+            Ok(delay) => {
+                graph.clock.schedule(delay, event);
+                StepOutcome::Done
+            },
+            Err(e) => StepOutcome::Error(e)
+        },
+        Step::CaptureInterface { interface, file } => {
+            let point = match resolve(graph, &interface) {
+                Ok(mac) => CapturePoint::interface(graph, mac),
+                Err(e) => return StepOutcome::Error(e)
+            };
+            capture_outcome(graph, point, base_dir.join(&file).to_string_lossy().to_string())
+        },
+        Step::CaptureLink { from, to, file } => {
+            let point = match (resolve(graph, &from), resolve(graph, &to)) {
+                (Ok(a), Ok(b)) => CapturePoint::link(graph, a, b),
+                (Err(e), _) | (_, Err(e)) => return StepOutcome::Error(e)
+            };
+            capture_outcome(graph, point, base_dir.join(&file).to_string_lossy().to_string())
+        },
+        Step::StopCapture { file } => match graph.captures.stop(&base_dir.join(&file).to_string_lossy()) {
+            Ok(_) => StepOutcome::Done,
+            Err(e) => StepOutcome::Error(e.to_string())
+        },
+        Step::Listen { node, transport, port } => match resolve(graph, &node) {
+            Ok(host) => match transport::listen(graph, host, transport, port) {
+                Ok(_) => StepOutcome::Done,
+                Err(e) => StepOutcome::Error(e.to_string())
+            },
+            Err(e) => StepOutcome::Error(e)
+        },
+        Step::Open { from, transport, to, expect } => {
+            let address = to.split_once(':')
+                .and_then(|(ip, port)| Some((IP::from_string(ip)?, port.parse::<u16>().ok()?)))
+                .ok_or(format!("Invalid address '{}' (ip:port)", to));
+            let (host, (ip, port)) = match (resolve(graph, &from), address) {
+                (Ok(host), Ok(address)) => (host, address),
+                (Err(e), _) | (_, Err(e)) => return StepOutcome::Error(e)
+            };
+            let outcome = match transport::open(graph, host, transport, ip, port) {
+                Ok(outcome) => outcome,
+                Err(e) => return StepOutcome::Error(e.to_string())
+            };
+            let got = match outcome {
+                OpenOutcome::Connected(_) => ExpectedOpen::Connected,
+                OpenOutcome::Delivered => ExpectedOpen::Delivered,
+                OpenOutcome::Refused => ExpectedOpen::Refused,
+                OpenOutcome::Timeout(_) => ExpectedOpen::Timeout,
+            };
+            match expect {
+                None => StepOutcome::Done,
+                Some(expected) if expected == got => StepOutcome::Passed,
+                Some(_) => StepOutcome::Failed(format!("Connection was {}", outcome))
+            }
+        },
+        Step::Close { node, port } => match resolve(graph, &node) {
+            Ok(host) => match transport::close(graph, host, port) {
+                Ok(_) => StepOutcome::Done,
+                Err(e) => StepOutcome::Error(e.to_string())
+            },
+            Err(e) => StepOutcome::Error(e)
+        },
+        // `contains` checks the response, which it implies
+        Step::Request { from, transport, to, data, expect, contains } => {
+            let host = match resolve(graph, &from) {
+                Ok(host) => host,
+                Err(e) => return StepOutcome::Error(e)
+            };
+            let address = to.rsplit_once(':')
+                .and_then(|(name, port)| Some((name, port.parse::<u16>().ok()?)))
+                .ok_or(format!("Invalid address '{}' (host:port)", to));
+            let (ip, port) = match address.and_then(|(name, port)| Ok((dns::resolve(graph, &host, name).map_err(|e| e.to_string())?, port))) {
+                Ok(address) => address,
+                Err(e) => return StepOutcome::Error(e)
+            };
+            let outcome = match transport::request(graph, host, transport, ip, port, data.unwrap_or_default().as_bytes()) {
+                Ok(outcome) => outcome,
+                Err(e) => return StepOutcome::Error(e.to_string())
+            };
+            let got = match outcome {
+                RequestOutcome::Response(_) => ExpectedRequest::Response,
+                RequestOutcome::NoResponse => ExpectedRequest::NoResponse,
+                RequestOutcome::Refused => ExpectedRequest::Refused,
+                RequestOutcome::Timeout(_) => ExpectedRequest::Timeout,
+            };
+            let expect = expect.or(contains.as_ref().map(|_| ExpectedRequest::Response));
+            let failure = match &outcome {
+                RequestOutcome::Response(response) => format!("Response was {:?}", String::from_utf8_lossy(response)),
+                _ => format!("Request was {}", outcome),
+            };
+            match (expect, contains, &outcome) {
+                (None, _, _) => StepOutcome::Done,
+                (Some(expected), _, _) if expected != got => StepOutcome::Failed(failure),
+                (Some(_), Some(text), RequestOutcome::Response(response)) if !String::from_utf8_lossy(response).contains(&text) => StepOutcome::Failed(failure),
+                (Some(_), _, _) => StepOutcome::Passed,
+            }
+        },
+        Step::Ping { from, to, expect } => {
+            let mac_src = match resolve(graph, &from) {
+                Ok(mac) => mac,
+                Err(e) => return StepOutcome::Error(e)
+            };
+            let ip_dest = match target(graph, &mac_src, &to) {
+                Ok(ip) => ip,
+                Err(e) => return StepOutcome::Error(e)
+            };
+            let nic = match graph.nic_with_mac(mac_src) {
+                Some(nic) => nic,
+                None => return StepOutcome::Error(format!("Device {} not found", from))
+            };
+            let status = ping(graph, nic, ip_dest);
+            let got = match status {
+                PingStatus::Success => ExpectedPing::Success,
+                PingStatus::Timeout => ExpectedPing::Timeout,
+            };
+            match expect {
+                None => StepOutcome::Done,
+                Some(expected) if expected == got => StepOutcome::Passed,
+                Some(_) => StepOutcome::Failed(format!("Ping status was {}", status))
+            }
+        },
+        Step::Traceroute { from, to, expect } => {
+            let mac_src = match resolve(graph, &from) {
+                Ok(mac) => mac,
+                Err(e) => return StepOutcome::Error(e)
+            };
+            let ip_dest = match target(graph, &mac_src, &to) {
+                Ok(ip) => ip,
+                Err(e) => return StepOutcome::Error(e)
+            };
+            let nic = match graph.nic_with_mac(mac_src) {
+                Some(nic) => nic,
+                None => return StepOutcome::Error(format!("Device {} not found", from))
+            };
+            let hops: Option<Vec<String>> = traceroute(graph, nic, ip_dest)
+                .map(|hops| hops.iter().map(|ip| ip.to_ddn()).collect());
+            match (expect, hops) {
+                (None, _) => StepOutcome::Done,
+                (Some(_), None) => StepOutcome::Failed("Destination unreachable".to_string()),
+                (Some(expected), Some(hops)) if expected == hops => StepOutcome::Passed,
+                (Some(_), Some(hops)) => StepOutcome::Failed(format!("Route was {}", hops.join(" > ")))
+            }
+        }
+    }
+}
+
+pub fn run_script(graph: &mut Graph, script: Script, base_dir: &Path) -> ScriptReport {
+    let mut results: Vec<StepResult> = Vec::new();
+    let first = graph.clock.timeline().len();
+    for (index, step) in script.steps.into_iter().enumerate() {
+        let description = step.to_string();
+        let outcome = run_step(graph, step, base_dir);
+        results.push(StepResult { index: index + 1, description, outcome });
+    }
+    let timeline = graph.clock.timeline().get(first..).map(|entries| entries.to_vec()).unwrap_or_default();
+    ScriptReport { name: script.name, results, timeline }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // The first YAML block of the README that starts with `start`
+    fn readme_block(start: &str) -> String {
+        include_str!("../README.md").split("```yaml\n").skip(1)
+            .filter_map(|block| block.split("```").next())
+            .find(|block| block.starts_with(start))
+            .unwrap_or_else(|| panic!("no README block starting with {}", start))
+            .to_string()
+    }
+
+    #[test]
+    fn readme_scenario_passes_on_readme_topology() {
+        let dir = std::env::temp_dir().join(format!("network-simulator-readme-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        std::fs::write(dir.join("topology.yaml"), readme_block("version: 1\nrouters:")).unwrap();
+        let script: Script = serde_yaml::from_str(&readme_block("name: two offices")).unwrap();
+        let report = run_script(&mut Graph::new(), script, &dir);
+        std::fs::remove_dir_all(&dir).unwrap();
+        let failures: Vec<String> = report.results.iter().filter_map(|r| match &r.outcome {
+            StepOutcome::Failed(e) | StepOutcome::Error(e) => Some(format!("{}: {}", r.description, e)),
+            _ => None,
+        }).collect();
+        assert!(failures.is_empty(), "{:?}", failures);
+        assert_eq!(report.passed(), 3);
+    }
+}
@@ -0,0 +1,279 @@
+use std::collections::BTreeMap;
+
+use crate::graph::Graph;
+use crate::load::LoadedService;
+use crate::mac::MAC;
+use crate::transport::Transport;
+
+
+// Served by an http service without pages
+const DEFAULT_PAGE: (&str, &str) = ("/", "It works!");
+
+#[derive(Clone, PartialEq)]
+pub enum Handler {
+    Echo,
+    Http(BTreeMap<String, String>),
+    Custom(String)
+}
+
+impl std::fmt::Display for Handler {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Handler::Echo => write!(f, "echo"),
+            Handler::Http(_) => write!(f, "http"),
+            Handler::Custom(_) => write!(f, "custom"),
+        }
+    }
+}
+
+#[derive(Clone, PartialEq)]
+pub struct Service {
+    pub transport: Transport,
+    pub port: u16,
+    pub handler: Handler
+}
+
+impl std::fmt::Display for Service {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}/{} {}", self.port, self.transport, self.handler)
+    }
+}
+
+impl Service {
+    pub fn new(transport: Transport, port: u16, handler: Handler) -> Result<Self, ServiceError> {
+        let service = Service { transport, port, handler };
+        match (&service.handler, transport, port) {
+            (_, _, 0) | (Handler::Http(_), Transport::Udp, _) => Err(ServiceError::InvalidService(service.to_string())),
+            _ => Ok(service),
+        }
+    }
+
+    pub fn from_loaded(loaded: &LoadedService) -> Result<Self, ServiceError> {
+        let invalid = || ServiceError::InvalidService(format!("{}/{} {}", loaded.port, loaded.transport, loaded.kind));
+        let transport = Transport::from_string(&loaded.transport).ok_or_else(invalid)?;
+        let handler = match (loaded.kind.to_lowercase().as_str(), &loaded.response) {
+            ("echo", None) => Handler::Echo,
+            ("http", None) => Handler::Http(loaded.pages.clone().unwrap_or_default()),
+            ("custom", Some(response)) => Handler::Custom(response.clone()),
+            _ => return Err(invalid()),
+        };
+        if loaded.pages.is_some() && !matches!(handler, Handler::Http(_)) {
+            return Err(invalid());
+        }
+        Service::new(transport, loaded.port, handler)
+    }
+
+    pub fn to_loaded(&self) -> LoadedService {
+        let (pages, response) = match &self.handler {
+            Handler::Echo => (None, None),
+            Handler::Http(pages) => (Some(pages.clone()).filter(|p| !p.is_empty()), None),
+            Handler::Custom(response) => (None, Some(response.clone())),
+        };
+        LoadedService { transport: self.transport.to_string(), port: self.port, kind: self.handler.to_string(), pages, response }
+    }
+
+    // Echo and http wait for a request, a custom service also greets a client that sends nothing
+    pub fn respond(&self, request: &[u8]) -> Option<Vec<u8>> {
+        let text = String::from_utf8_lossy(request);
+        match &self.handler {
+            _ if request.is_empty() && !matches!(self.handler, Handler::Custom(_)) => None,
+            Handler::Echo => Some(request.to_vec()),
+            Handler::Http(pages) => Some(http_response(pages, &text).into_bytes()),
+            Handler::Custom(response) => Some(response.replace("{request}", text.trim_end()).into_bytes()),
+        }
+    }
+}
+
+// Only the request line matters: `GET /path` or `HEAD /path`
+fn http_response(pages: &BTreeMap<String, String>, request: &str) -> String {
+    let mut words = request.lines().next().unwrap_or_default().split_whitespace();
+    let page = |path: &str| match pages.is_empty() {
+        true => (path == DEFAULT_PAGE.0).then_some(DEFAULT_PAGE.1.to_string()),
+        false => pages.get(path).cloned(),
+    };
+    let (status, body, length) = match (words.next(), words.next()) {
+        (Some(method @ ("GET" | "HEAD")), Some(path)) => match page(path) {
+            Some(body) if method == "HEAD" => ("200 OK", String::new(), body.len()),
+            Some(body) => {
+                let length = body.len();
+                ("200 OK", body, length)
+            },
+            None => ("404 Not Found", String::new(), 0),
+        },
+        (Some(_), Some(_)) => ("405 Method Not Allowed", String::new(), 0),
+        _ => ("400 Bad Request", String::new(), 0),
+    };
+    format!("HTTP/1.0 {}\r\nContent-Length: {}\r\n\r\n{}", status, length, body)
+}
+
+pub enum ServiceError {
+    InvalidService(String),
+    NotADevice(MAC),
+    PortInUse(Transport, u16),
+    NoService(Transport, u16),
+    NotHttp(u16)
+}
+
+impl std::fmt::Display for ServiceError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            ServiceError::InvalidService(service) => write!(f, "Invalid service '{}'", service),
+            ServiceError::NotADevice(mac) => write!(f, "{} is not a device", mac),
+            ServiceError::PortInUse(transport, port) => write!(f, "Port {}/{} already in use", port, transport),
+            ServiceError::NoService(transport, port) => write!(f, "No service on port {}/{}", port, transport),
+            ServiceError::NotHttp(port) => write!(f, "The service on port {}/tcp is not http", port),
+        }
+    }
+}
+
+// The service a device runs on a port
+pub fn service_at<'a>(graph: &'a Graph, host: &MAC, transport: Transport, port: u16) -> Option<&'a Service> {
+    graph.devices.iter().find(|d| d.nic.mac == *host)?
+        .services.iter().find(|s| s.transport == transport && s.port == port)
+}
+
+fn services_mut<'a>(graph: &'a mut Graph, host: &MAC) -> Result<&'a mut Vec<Service>, ServiceError> {
+    match graph.devices.iter_mut().find(|d| d.nic.mac == *host) {
+        Some(device) => Ok(&mut device.services),
+        None => Err(ServiceError::NotADevice(host.clone())),
+    }
+}
+
+// A service replaces the one already running on its port, but not a listening socket
+pub fn add_service(graph: &mut Graph, host: &MAC, service: Service) -> Result<(), ServiceError> {
+    if graph.sockets.is_listening(host, service.transport, service.port) {
+        return Err(ServiceError::PortInUse(service.transport, service.port));
+    }
+    let services = services_mut(graph, host)?;
+    services.retain(|s| s.transport != service.transport || s.port != service.port);
+    services.push(service);
+    services.sort_by_key(|s| (s.port, s.transport.to_string()));
+    Ok(())
+}
+
+pub fn remove_service(graph: &mut Graph, host: &MAC, transport: Transport, port: u16) -> Result<(), ServiceError> {
+    let services = services_mut(graph, host)?;
+    let idx = services.iter().position(|s| s.transport == transport && s.port == port)
+        .ok_or(ServiceError::NoService(transport, port))?;
+    services.remove(idx);
+    Ok(())
+}
+
+// Starts an http service on the port when there is none yet
+pub fn add_page(graph: &mut Graph, host: &MAC, port: u16, path: &str, body: &str) -> Result<(), ServiceError> {
+    if service_at(graph, host, Transport::Tcp, port).is_none() {
+        add_service(graph, host, Service::new(Transport::Tcp, port, Handler::Http(BTreeMap::new()))?)?;
+    }
+    let services = services_mut(graph, host)?;
+    match services.iter_mut().find(|s| s.transport == Transport::Tcp && s.port == port).map(|s| &mut s.handler) {
+        Some(Handler::Http(pages)) => {
+            pages.insert(path.to_string(), body.to_string());
+            Ok(())
+        },
+        _ => Err(ServiceError::NotHttp(port)),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::ip::IP;
+    use crate::load::{DataFormat, parse_data};
+    use crate::transport::{OpenOutcome, RequestOutcome, listen, open, request};
+
+    const LAB: &str = "
+routers:
+  - name: R1
+    lan: {ip: 192.168.1.1, netmask: 24}
+    wan: {ip: 10.0.0.1, netmask: 30}
+devices:
+  - {name: PC, ip: 192.168.1.10, netmask: 24}
+  - name: SRV
+    ip: 192.168.1.5
+    netmask: 24
+    services:
+      - {transport: tcp, port: 80, type: http, pages: {/: home, /about: about}}
+      - {transport: udp, port: 7, type: echo}
+      - {transport: tcp, port: 2323, type: custom, response: 'you said {request}'}
+switches:
+  - name: SW1
+connections:
+  - {from: R1.lan, to: SW1}
+  - {from: PC, to: SW1}
+  - {from: SRV, to: SW1}
+";
+
+    fn lab() -> (Graph, MAC, MAC) {
+        let mut graph = Graph::new();
+        let report = graph.load_data(parse_data("test", LAB, DataFormat::Yaml).unwrap_or_else(|e| panic!("{}", e)));
+        assert!(report.rejected().is_empty());
+        let mac = |name: &str| graph.resolve_node(name).unwrap_or_else(|e| panic!("{}", e));
+        let (pc, srv) = (mac("PC"), mac("SRV"));
+        (graph, pc, srv)
+    }
+
+    fn text(outcome: RequestOutcome) -> String {
+        match outcome {
+            RequestOutcome::Response(data) => String::from_utf8_lossy(&data).to_string(),
+            other => panic!("{}", other),
+        }
+    }
+
+    #[test]
+    fn http_answers_the_request_line() {
+        let pages = BTreeMap::from([("/".to_string(), "home".to_string())]);
+        assert_eq!(http_response(&pages, "GET / HTTP/1.0\r\n"), "HTTP/1.0 200 OK\r\nContent-Length: 4\r\n\r\nhome");
+        assert_eq!(http_response(&pages, "HEAD /"), "HTTP/1.0 200 OK\r\nContent-Length: 4\r\n\r\n");
+        assert_eq!(http_response(&pages, "GET /missing"), "HTTP/1.0 404 Not Found\r\nContent-Length: 0\r\n\r\n");
+        assert_eq!(http_response(&pages, "POST /"), "HTTP/1.0 405 Method Not Allowed\r\nContent-Length: 0\r\n\r\n");
+        assert_eq!(http_response(&pages, "hello"), "HTTP/1.0 400 Bad Request\r\nContent-Length: 0\r\n\r\n");
+        assert!(http_response(&BTreeMap::new(), "GET /").ends_with(DEFAULT_PAGE.1));
+    }
+
+    #[test]
+    fn only_custom_services_greet_silent_clients() {
+        let echo = Service::new(Transport::Udp, 7, Handler::Echo).unwrap_or_else(|e| panic!("{}", e));
+        assert!(echo.respond(b"").is_none());
+        assert_eq!(echo.respond(b"ping"), Some(b"ping".to_vec()));
+        let custom = Service::new(Transport::Tcp, 23, Handler::Custom("hi {request}".to_string())).unwrap_or_else(|e| panic!("{}", e));
+        assert_eq!(custom.respond(b""), Some(b"hi ".to_vec()));
+        assert_eq!(custom.respond(b"there\n"), Some(b"hi there".to_vec()));
+    }
+
+    #[test]
+    fn invalid_services_are_rejected() {
+        assert!(Service::new(Transport::Udp, 80, Handler::Http(BTreeMap::new())).is_err());
+        assert!(Service::new(Transport::Tcp, 0, Handler::Echo).is_err());
+        let loaded = LoadedService { transport: "tcp".to_string(), port: 7, kind: "echo".to_string(), pages: Some(BTreeMap::new()), response: None };
+        assert!(Service::from_loaded(&loaded).is_err());
+        let custom = Service::new(Transport::Tcp, 23, Handler::Custom("hi".to_string())).unwrap_or_else(|e| panic!("{}", e));
+        assert!(Service::from_loaded(&custom.to_loaded()).is_ok_and(|s| s == custom));
+    }
+
+    #[test]
+    fn requests_reach_the_services_of_a_device() {
+        let (mut graph, pc, _) = lab();
+        let srv_ip = IP::V4(192, 168, 1, 5);
+        let page = text(request(&mut graph, pc.clone(), Transport::Tcp, srv_ip.clone(), 80, b"GET /about").unwrap_or_else(|e| panic!("{}", e)));
+        assert!(page.ends_with("\r\n\r\nabout"));
+        assert_eq!(text(request(&mut graph, pc.clone(), Transport::Udp, srv_ip.clone(), 7, b"ping").unwrap_or_else(|e| panic!("{}", e))), "ping");
+        assert_eq!(text(request(&mut graph, pc.clone(), Transport::Tcp, srv_ip.clone(), 2323, b"").unwrap_or_else(|e| panic!("{}", e))), "you said ");
+        assert!(matches!(request(&mut graph, pc.clone(), Transport::Udp, srv_ip.clone(), 80, b"x"), Ok(RequestOutcome::Refused)));
+        // The connection of a request does not outlive it
+        assert!(graph.sockets.list().is_empty());
+        assert!(matches!(open(&mut graph, pc, Transport::Tcp, srv_ip, 80), Ok(OpenOutcome::Connected(_))));
+    }
+
+    #[test]
+    fn services_and_sockets_share_the_ports() {
+        let (mut graph, _, srv) = lab();
+        assert!(listen(&mut graph, srv.clone(), Transport::Tcp, 80).is_err());
+        listen(&mut graph, srv.clone(), Transport::Tcp, 8080).unwrap_or_else(|e| panic!("{}", e));
+        assert!(matches!(add_page(&mut graph, &srv, 8080, "/", "x"), Err(ServiceError::PortInUse(Transport::Tcp, 8080))));
+        assert!(matches!(add_page(&mut graph, &srv, 2323, "/", "x"), Err(ServiceError::NotHttp(2323))));
+        add_page(&mut graph, &srv, 8000, "/", "new").unwrap_or_else(|e| panic!("{}", e));
+        assert!(service_at(&graph, &srv, Transport::Tcp, 8000).is_some_and(|s| s.respond(b"GET /").is_some_and(|r| r.ends_with(b"new"))));
+        remove_service(&mut graph, &srv, Transport::Udp, 7).unwrap_or_else(|e| panic!("{}", e));
+        assert!(matches!(remove_service(&mut graph, &srv, Transport::Udp, 7), Err(ServiceError::NoService(Transport::Udp, 7))));
+    }
+}
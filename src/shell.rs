@@ -0,0 +1,1513 @@
+use rustyline::completion::{Completer, Pair};
+use rustyline::error::ReadlineError;
+use rustyline::highlight::Highlighter;
+use rustyline::hint::Hinter;
+use rustyline::history::DefaultHistory;
+use rustyline::validate::Validator;
+use rustyline::{Context, Editor, Helper};
+
+use network_simulator::capture::CapturePoint;
+use network_simulator::clock::{self, Action, format_time, parse_duration};
+use network_simulator::colors::Colors;
+use network_simulator::commands::{ping, traceroute};
+use network_simulator::device::create_device;
+use network_simulator::diagram::render;
+use network_simulator::dns::{self, Record, RecordType};
+use network_simulator::graph::{Graph, NodeType, connection_any_direction};
+use network_simulator::history::History;
+use network_simulator::export::ExportFormat;
+use crate::hci::{export_to, print_load_report, restore_from, save_to, snapshot_to, print_script_report, print_validation_report, script_file};
+use network_simulator::ip::IP;
+use network_simulator::load::load_data;
+use network_simulator::mac::MAC;
+use network_simulator::nic::NIC;
+use network_simulator::state::{Element, State};
+use network_simulator::router::{RouterInterface, SubInterface, create_router};
+use network_simulator::service::{self, Handler, Service};
+use network_simulator::stp::PortState;
+use network_simulator::switch::{DEFAULT_PRIORITY, PortMode, Switch, create_switch};
+use network_simulator::trace::Protocol;
+use network_simulator::transport::{self, CloseOutcome, OpenOutcome, RequestOutcome, Transport};
+use network_simulator::validate::validate_graph;
+
+
+#[derive(Clone, PartialEq)]
+enum Mode {
+    Global,
+    User(MAC),
+    Privileged(MAC),
+    Config(MAC),
+    Interface(MAC, MAC)
+}
+
+const GLOBAL_COMMANDS: &[&str] = &[
+    "node <node>",
+    "create router <name>",
+    "create device <name>",
+    "create switch <name>",
+    "connect <endpoint> <endpoint>",
+    "disconnect <endpoint> <endpoint>",
+    "switchport <switch> <endpoint> access <vlan>",
+    "switchport <switch> <endpoint> trunk <vlans>",
+    "spanning-tree <switch> priority <number>",
+    "shutdown link <endpoint> <endpoint>",
+    "no shutdown link <endpoint> <endpoint>",
+    "fail link <endpoint> <endpoint>",
+    "repair link <endpoint> <endpoint>",
+    "fail node <node>",
+    "repair node <node>",
+    "expire lease <node>",
+    "advance <duration>",
+    "schedule <duration> fail link <endpoint> <endpoint>",
+    "schedule <duration> repair link <endpoint> <endpoint>",
+    "schedule <duration> fail node <node>",
+    "schedule <duration> repair node <node>",
+    "schedule <duration> expire lease <node>",
+    "schedule <duration> ping <endpoint> <host>",
+    "no schedule <number>",
+    "capture <endpoint> <file>",
+    "capture link <endpoint> <endpoint> <file>",
+    "no capture <file>",
+    "trace",
+    "trace node <node>",
+    "trace protocol <protocol>",
+    "trace ip <ip>",
+    "no trace",
+    "listen <node> <transport> <port>",
+    "no listen <node> <transport> <port>",
+    "open <node> <transport> <address>",
+    "close <node> <port>",
+    "request <node> <transport> <address>",
+    "request <node> <transport> <address> <text>",
+    "load <file>",
+    "save <file>",
+    "snapshot save <file>",
+    "snapshot restore <file>",
+    "run <file>",
+    "show nodes",
+    "show topology",
+    "show diagram",
+    "show vlans",
+    "show spanning-tree",
+    "show links",
+    "show clock",
+    "show schedule",
+    "show timeline",
+    "show captures",
+    "show trace",
+    "show sockets",
+    "show dns",
+    "show services",
+    "show seed",
+    "show history",
+    "undo",
+    "redo",
+    "seed <number>",
+    "validate",
+    "export <format>",
+    "export <format> <file>",
+    "help",
+    "exit",
+    "quit",
+];
+
+const USER_COMMANDS: &[&str] = &[
+    "enable",
+    "ping <host>",
+    "traceroute <host>",
+    "nslookup <host>",
+    "show ip route",
+    "show interfaces",
+    "show arp",
+    "help",
+    "exit",
+];
+
+const PRIVILEGED_COMMANDS: &[&str] = &[
+    "configure",
+    "configure terminal",
+    "disable",
+    "ping <host>",
+    "traceroute <host>",
+    "nslookup <host>",
+    "show ip route",
+    "show interfaces",
+    "show arp",
+    "show running-config",
+    "help",
+    "exit",
+];
+
+const CONFIG_COMMANDS: &[&str] = &[
+    "hostname <name>",
+    "interface <interface>",
+    "no interface <interface>",
+    "ip name-server <ip>",
+    "no ip name-server",
+    "ip dns server",
+    "no ip dns server",
+    "ip host <name> <type> <value>",
+    "no ip host <name>",
+    "service <transport> <port> echo",
+    "service <transport> <port> http",
+    "service <transport> <port> http page <path> <text>",
+    "service <transport> <port> custom <text>",
+    "no service <transport> <port>",
+    "help",
+    "exit",
+    "end",
+];
+
+const INTERFACE_COMMANDS: &[&str] = &[
+    "ip address <address>",
+    "ip address <ip> <netmask>",
+    "ip dhcp pool <ip> <ip>",
+    "no ip dhcp pool",
+    "ip dhcp name-server <ip>",
+    "no ip dhcp name-server",
+    "shutdown",
+    "no shutdown",
+    "help",
+    "exit",
+    "end",
+];
+
+// Sockets and services run on devices, so their commands are added to the user and privileged modes of a device
+const DEVICE_COMMANDS: &[&str] = &[
+    "listen <transport> <port>",
+    "no listen <transport> <port>",
+    "open <transport> <address>",
+    "close <port>",
+    "request <transport> <address>",
+    "request <transport> <address> <text>",
+    "show sockets",
+    "show services",
+];
+
+const SWITCH_USER_COMMANDS: &[&str] = &[
+    "enable",
+    "show interfaces",
+    "show mac address-table",
+    "show vlans",
+    "show spanning-tree",
+    "help",
+    "exit",
+];
+
+const SWITCH_PRIVILEGED_COMMANDS: &[&str] = &[
+    "configure",
+    "configure terminal",
+    "disable",
+    "show interfaces",
+    "show mac address-table",
+    "show vlans",
+    "show spanning-tree",
+    "show running-config",
+    "help",
+    "exit",
+];
+
+const SWITCH_CONFIG_COMMANDS: &[&str] = &[
+    "hostname <name>",
+    "spanning-tree priority <number>",
+    "switchport <endpoint> access <vlan>",
+    "switchport <endpoint> trunk <vlans>",
+    "help",
+    "exit",
+    "end",
+];
+
+fn mode_commands(graph: &Graph, mode: &Mode) -> Vec<&'static str> {
+    let switch = mode_node(mode).is_some_and(|node| graph.search_switch_with_mac(node.clone()).is_some());
+    let mut commands = match (mode, switch) {
+        (Mode::Global, _) => GLOBAL_COMMANDS,
+        (Mode::User(_), false) => USER_COMMANDS,
+        (Mode::User(_), true) => SWITCH_USER_COMMANDS,
+        (Mode::Privileged(_), false) => PRIVILEGED_COMMANDS,
+        (Mode::Privileged(_), true) => SWITCH_PRIVILEGED_COMMANDS,
+        (Mode::Config(_), false) => CONFIG_COMMANDS,
+        (Mode::Config(_), true) => SWITCH_CONFIG_COMMANDS,
+        (Mode::Interface(_, _), _) => INTERFACE_COMMANDS,
+    }.to_vec();
+    if let Mode::User(node) | Mode::Privileged(node) = mode
+        && graph.search_device_with_mac(node.clone()).is_some() {
+        let at = commands.iter().position(|c| *c == "help").unwrap_or(commands.len());
+        commands.splice(at..at, DEVICE_COMMANDS.iter().copied());
+    }
+    commands
+}
+
+fn mode_node(mode: &Mode) -> Option<&MAC> {
+    match mode {
+        Mode::Global => None,
+        Mode::User(node) | Mode::Privileged(node) | Mode::Config(node) | Mode::Interface(node, _) => Some(node),
+    }
+}
+
+fn is_placeholder(word: &str) -> bool {
+    word.starts_with('<')
+}
+
+// Expands abbreviated keywords ("sh ip ro") to the single command they designate;
+// a trailing <text> takes the rest of the line
+fn parse_command(commands: &[&str], input: &str) -> Result<(String, Vec<String>), String> {
+    let words: Vec<&str> = input.split_whitespace().collect();
+    let mut matches: Vec<(&str, Vec<String>, bool)> = Vec::new();
+    for command in commands {
+        let pattern: Vec<&str> = command.split_whitespace().collect();
+        let text = pattern.last() == Some(&"<text>");
+        if pattern.len() != words.len() && !(text && words.len() > pattern.len()) {
+            continue;
+        }
+        let mut args: Vec<String> = Vec::new();
+        let mut exact = true;
+        let mut ok = true;
+        for (i, (expected, word)) in pattern.iter().zip(words.iter()).enumerate() {
+            if text && i == pattern.len() - 1 {
+                args.push(words[i..].join(" "));
+            } else if is_placeholder(expected) {
+                args.push(word.to_string());
+            } else if expected.starts_with(&word.to_lowercase()) {
+                exact &= *expected == word.to_lowercase();
+            } else {
+                ok = false;
+                break;
+            }
+        }
+        if ok {
+            matches.push((command, args, exact));
+        }
+    }
+    if let Some((command, args, _)) = matches.iter().find(|(_, _, exact)| *exact) {
+        return Ok((command.to_string(), args.clone()));
+    }
+    let incomplete = commands.iter().any(|command| {
+        let pattern: Vec<&str> = command.split_whitespace().collect();
+        pattern.len() > words.len() && pattern.iter().zip(words.iter())
+            .all(|(expected, word)| is_placeholder(expected) || expected.starts_with(&word.to_lowercase()))
+    });
+    match matches.len() {
+        0 if incomplete => Err("% Incomplete command".to_string()),
+        0 => Err("% Invalid input detected".to_string()),
+        1 => Ok((matches[0].0.to_string(), matches[0].1.clone())),
+        _ => Err(format!("% Ambiguous command: \"{}\"", input)),
+    }
+}
+
+struct ShellHelper {
+    commands: Vec<&'static str>,
+    nodes: Vec<String>,
+    endpoints: Vec<String>,
+    switches: Vec<String>,
+    interfaces: Vec<String>
+}
+
+impl Completer for ShellHelper {
+    type Candidate = Pair;
+
+    fn complete(&self, line: &str, pos: usize, _ctx: &Context<'_>) -> rustyline::Result<(usize, Vec<Pair>)> {
+        let before = &line[..pos];
+        let start = before.rfind(' ').map(|i| i + 1).unwrap_or(0);
+        let typed: Vec<&str> = before[..start].split_whitespace().collect();
+        let partial = &before[start..];
+        let mut candidates: Vec<String> = Vec::new();
+        for command in &self.commands {
+            let pattern: Vec<&str> = command.split_whitespace().collect();
+            if pattern.len() <= typed.len() {
+                continue;
+            }
+            let prefix_matches = pattern.iter().zip(typed.iter())
+                .all(|(expected, word)| is_placeholder(expected) || expected.starts_with(&word.to_lowercase()));
+            if !prefix_matches {
+                continue;
+            }
+            let words: Vec<String> = match pattern[typed.len()] {
+                "<node>" => self.nodes.clone(),
+                "<endpoint>" => self.endpoints.clone(),
+                "<switch>" => self.switches.clone(),
+                "<interface>" => self.interfaces.clone(),
+                "<format>" => vec!["dot".to_string(), "mermaid".to_string()],
+                "<transport>" => vec!["tcp".to_string(), "udp".to_string()],
+                "<type>" => vec!["A".to_string(), "AAAA".to_string(), "PTR".to_string(), "CNAME".to_string()],
+                word if is_placeholder(word) => Vec::new(),
+                word => vec![word.to_string()],
+            };
+            for word in words {
+                if word.starts_with(partial) && !candidates.contains(&word) {
+                    candidates.push(word);
+                }
+            }
+        }
+        let pairs = candidates.into_iter()
+            .map(|word| Pair { display: word.clone(), replacement: format!("{} ", word) })
+            .collect();
+        Ok((start, pairs))
+    }
+}
+
+impl Hinter for ShellHelper {
+    type Hint = String;
+}
+
+impl Highlighter for ShellHelper {}
+
+impl Validator for ShellHelper {}
+
+impl Helper for ShellHelper {}
+
+fn node_name(graph: &Graph, mac: &MAC) -> String {
+    if let Some((router, _)) = graph.search_router_with_mac(mac.clone()) {
+        return router.name;
+    }
+    if let Some(switch) = graph.search_switch_with_mac(mac.clone()) {
+        return switch.name;
+    }
+    match graph.search_device_with_mac(mac.clone()) {
+        Some(device) => device.name,
+        None => mac.to_hex(),
+    }
+}
+
+fn interface_names(graph: &Graph, mac: &MAC) -> Vec<String> {
+    interfaces_of(graph, mac).into_iter().map(|(name, _)| name).collect()
+}
+
+// A router is entered through its LAN interface, whichever of its interfaces is named
+fn find_node(graph: &Graph, name: &str) -> Option<MAC> {
+    graph.resolve_node(name).ok().map(|mac| graph.node_key(&mac))
+}
+
+fn parse_interface(name: &str) -> Option<RouterInterface> {
+    match name.to_lowercase().as_str() {
+        "lan" => Some(RouterInterface::LAN),
+        "wan" => Some(RouterInterface::WAN),
+        _ => None,
+    }
+}
+
+// "lan.10" designates the subinterface of VLAN 10 on the LAN interface
+fn parse_subinterface(name: &str) -> Option<(RouterInterface, u16)> {
+    let (parent, vlan) = name.split_once('.')?;
+    Some((parse_interface(parent)?, vlan.parse().ok()?))
+}
+
+fn find_interface(graph: &Graph, node: &MAC, name: &str) -> Option<MAC> {
+    match graph.search_router_with_mac(node.clone()) {
+        Some((router, _)) => match (parse_interface(name), parse_subinterface(name)) {
+            (Some(interface), _) => Some(router.nic(&interface).mac.clone()),
+            (None, Some((parent, vlan))) => router.subinterface(&parent, vlan).map(|s| s.nic.mac.clone()),
+            _ => None,
+        },
+        None => match name.to_lowercase().as_str() {
+            "eth0" => Some(node.clone()),
+            _ => None,
+        },
+    }
+}
+
+fn prompt(graph: &Graph, mode: &Mode) -> String {
+    match mode {
+        Mode::Global => "network-simulator> ".to_string(),
+        Mode::User(node) => format!("{}> ", node_name(graph, node)),
+        Mode::Privileged(node) => format!("{}# ", node_name(graph, node)),
+        Mode::Config(node) => format!("{}(config)# ", node_name(graph, node)),
+        Mode::Interface(node, _) => format!("{}(config-if)# ", node_name(graph, node)),
+    }
+}
+
+fn interfaces_of(graph: &Graph, node: &MAC) -> Vec<(String, NIC)> {
+    match graph.search_router_with_mac(node.clone()) {
+        Some((router, _)) => {
+            let mut interfaces = vec![
+                ("lan".to_string(), router.nic_lan.clone()),
+                ("wan".to_string(), router.nic_wan.clone()),
+            ];
+            for subinterface in &router.subinterfaces {
+                let name = format!("{}.{}", subinterface.parent.to_string().to_lowercase(), subinterface.vlan);
+                interfaces.push((name, subinterface.nic.clone()));
+            }
+            interfaces
+        },
+        None => match graph.nic_with_mac(node.clone()) {
+            Some(nic) => vec![("eth0".to_string(), nic)],
+            None => Vec::new(),
+        },
+    }
+}
+
+fn show_ip_route(graph: &Graph, node: &MAC) {
+    println!("Codes: C - connected");
+    println!();
+    for (name, nic) in interfaces_of(graph, node) {
+        if nic.is_localhost() {
+            continue;
+        }
+        println!("C    {}{}/{}{} is directly connected, {}", nic.network_address(), Colors::CYAN, nic.netmask_u8(), Colors::RESET, name);
+    }
+}
+
+fn show_interfaces(graph: &Graph, node: &MAC) {
+    if let Some(switch) = graph.search_switch_with_mac(node.clone()) {
+        return show_ports(graph, &switch);
+    }
+    for (name, nic) in interfaces_of(graph, node) {
+        // A subinterface is up with the cable of its parent interface
+        let subinterface = graph.search_subinterface_with_mac(nic.mac.clone());
+        let port = match &subinterface {
+            Some((router, s)) => router.nic(&s.parent).mac.clone(),
+            None => nic.mac.clone(),
+        };
+        let external = graph.neighbors(port.clone()).into_iter()
+            .filter(|n| interfaces_of(graph, node).iter().all(|(_, i)| i.mac != *n))
+            .filter(|n| graph.link_up(&port, n))
+            .count();
+        let status = if graph.is_shutdown(&Element::Interface(nic.mac.clone())) {
+            format!("{}administratively down{}", Colors::RED, Colors::RESET)
+        } else if external > 0 && graph.interface_up(&nic.mac) {
+            format!("{}up{}", Colors::GREEN, Colors::RESET)
+        } else {
+            format!("{}down{}", Colors::RED, Colors::RESET)
+        };
+        println!("{} is {}", name, status);
+        if let Some((_, s)) = &subinterface {
+            println!("  Encapsulation 802.1Q VLAN {}", s.vlan);
+        }
+        println!("  Hardware address is {}", nic.mac);
+        println!("  Internet address is {}{}/{}{}", nic.ip, Colors::CYAN, nic.netmask_u8(), Colors::RESET);
+        // Frames are counted on the cable, which a subinterface shares with its parent
+        if subinterface.is_none()
+            && let Some(peer) = graph.neighbors(port.clone()).into_iter().find(|n| interfaces_of(graph, node).iter().all(|(_, i)| i.mac != *n)) {
+            show_counter(graph, &port, &peer);
+        }
+    }
+}
+
+fn show_counter(graph: &Graph, node: &MAC, peer: &MAC) {
+    let counter = graph.tables.counter(node, peer);
+    println!("  {} frames input, {} bytes", counter.frames_in, counter.bytes_in);
+    println!("  {} frames output, {} bytes", counter.frames_out, counter.bytes_out);
+}
+
+// Ages in seconds, as entries are refreshed by every frame
+fn show_arp(graph: &Graph, node: &MAC) {
+    let now = graph.clock.now();
+    println!("{:<16} {:<8} {:<18} Interface", "Address", "Age (s)", "Hardware Addr");
+    for (name, nic) in interfaces_of(graph, node) {
+        for entry in graph.tables.arp(&nic.mac, now) {
+            println!("{:<16} {:<8} {:<18} {}", entry.ip.to_ddn(), now.saturating_sub(entry.learned) / 1000, entry.mac.to_hex(), name);
+        }
+    }
+}
+
+fn show_mac_table(graph: &Graph, switch: &MAC) {
+    let now = graph.clock.now();
+    println!("{:<6} {:<18} {:<12} Age (s)", "Vlan", "Mac Address", "Port");
+    let mut entries = graph.tables.mac_table(switch, now);
+    entries.sort_by_key(|e| (e.vlan, e.mac.to_hex()));
+    for entry in entries {
+        let port = graph.name_with_mac(entry.port.clone()).unwrap_or(entry.port.to_hex());
+        println!("{:<6} {:<18} {:<12} {}", entry.vlan, entry.mac.to_hex(), port, now.saturating_sub(entry.learned) / 1000);
+    }
+}
+
+// A port is up when its link is
+fn show_ports(graph: &Graph, switch: &Switch) {
+    let tree = graph.spanning_tree();
+    for port in graph.neighbors(switch.mac.clone()) {
+        let name = graph.name_with_mac(port.clone()).unwrap_or(port.to_hex());
+        let status = match graph.link_up(&switch.mac, &port) {
+            true => format!("{}up{}", Colors::GREEN, Colors::RESET),
+            false => format!("{}down{}", Colors::RED, Colors::RESET),
+        };
+        println!("{} is {}", name, status);
+        println!("  Switchport {}, spanning tree {}", switch.port_mode(&port), tree.role(&switch.mac, &port).state());
+        show_counter(graph, &switch.mac, &port);
+    }
+}
+
+fn show_running_config(graph: &Graph, node: &MAC) {
+    println!("hostname {}", node_name(graph, node));
+    if let Some(switch) = graph.search_switch_with_mac(node.clone()) {
+        if switch.priority != DEFAULT_PRIORITY {
+            println!("spanning-tree priority {}", switch.priority);
+        }
+        for (port, mode) in &switch.port_modes {
+            let name = graph.name_with_mac(port.clone()).unwrap_or(port.to_hex());
+            match mode {
+                PortMode::Access(vlan) => println!("switchport {} access {}", name, vlan),
+                PortMode::Trunk(_) => println!("switchport {} {}", name, mode),
+            }
+        }
+        return println!("end");
+    }
+    let router = graph.search_router_with_mac(node.clone()).map(|(r, _)| r);
+    if let Some(name_server) = graph.search_device_with_mac(node.clone()).and_then(|d| d.name_server) {
+        println!("ip name-server {}", name_server.to_ddn());
+    }
+    if let Some(zone) = dns::zone_of(graph, node) {
+        println!("ip dns server");
+        for record in zone {
+            println!("ip host {} {} {}", record.name, record.kind(), record.value());
+        }
+    }
+    for service in graph.search_device_with_mac(node.clone()).map(|d| d.services).unwrap_or_default() {
+        let prefix = format!("service {} {}", service.transport, service.port);
+        match &service.handler {
+            Handler::Http(pages) if !pages.is_empty() => {
+                for (path, body) in pages {
+                    println!("{} http page {} {}", prefix, path, body);
+                }
+            },
+            Handler::Custom(response) => println!("{} custom {}", prefix, response),
+            handler => println!("{} {}", prefix, handler),
+        }
+    }
+    for (name, nic) in interfaces_of(graph, node) {
+        println!("!");
+        println!("interface {}", name);
+        println!(" ip address {}/{}", nic.ip.to_ddn(), nic.netmask_u8());
+        let dhcp = match (&router, name.as_str()) {
+            (Some(r), "lan") => r.dhcp_lan.clone(),
+            (Some(r), "wan") => r.dhcp_wan.clone(),
+            (Some(_), _) => graph.search_subinterface_with_mac(nic.mac.clone()).and_then(|(_, s)| s.dhcp),
+            _ => None,
+        };
+        if let Some(dhcp) = dhcp {
+            println!(" ip dhcp pool {} {}", dhcp.first_ip.to_ddn(), dhcp.last_ip.to_ddn());
+            if let Some(name_server) = &dhcp.name_server {
+                println!(" ip dhcp name-server {}", name_server.to_ddn());
+            }
+        }
+        if graph.is_shutdown(&Element::Interface(nic.mac.clone())) {
+            println!(" shutdown");
+        }
+    }
+    println!("end");
+}
+
+fn show_nodes(graph: &Graph) {
+    for router in &graph.routers {
+        println!("{} (router) lan {} wan {}", router.name, router.nic_lan, router.nic_wan);
+        for subinterface in &router.subinterfaces {
+            println!("  {}.{} {}", subinterface.parent.to_string().to_lowercase(), subinterface.vlan, subinterface.nic);
+        }
+    }
+    for device in &graph.devices {
+        println!("{} (device) {}", device.name, device.nic);
+    }
+    for switch in &graph.switches {
+        println!("{} (switch) {} ports {}", switch.name, switch.ports, switch.mac);
+    }
+}
+
+// Every switch, or only `switch`
+fn show_vlans(graph: &Graph, switch: Option<&MAC>) {
+    for switch in graph.switches.iter().filter(|s| switch.is_none_or(|mac| s.mac == *mac)) {
+        println!("{}", switch.name);
+        let mut ports = graph.neighbors(switch.mac.clone());
+        ports.extend(switch.port_modes.iter().map(|(mac, _)| mac.clone()).filter(|mac| !graph.are_connected(switch.mac.clone(), mac.clone())));
+        for port in ports {
+            let name = graph.name_with_mac(port.clone()).unwrap_or(port.to_hex());
+            let connected = if graph.are_connected(switch.mac.clone(), port.clone()) { "" } else { " (not connected)" };
+            println!("  {:<16} {}{}{}{}", name, Colors::CYAN, switch.port_mode(&port), Colors::RESET, connected);
+        }
+    }
+}
+
+fn show_spanning_tree(graph: &Graph, switch: Option<&MAC>) {
+    let tree = graph.spanning_tree();
+    for bridge in tree.bridges.iter().filter(|b| switch.is_none_or(|mac| b.switch == *mac)) {
+        let name = graph.name_with_mac(bridge.switch.clone()).unwrap_or(bridge.switch.to_hex());
+        if bridge.root == bridge.id {
+            println!("{} bridge {} {}(root){}", name, bridge.id, Colors::YELLOW, Colors::RESET);
+        } else {
+            println!("{} bridge {} root {} cost {}", name, bridge.id, bridge.root, bridge.root_cost);
+        }
+        for port in graph.neighbors(bridge.switch.clone()) {
+            let role = tree.role(&bridge.switch, &port);
+            let color = if role.state() == PortState::Forwarding { Colors::GREEN } else { Colors::RED };
+            let port_name = graph.name_with_mac(port.clone()).unwrap_or(port.to_hex());
+            println!("  {:<16} {:<11} {}{}{}", port_name, role, color, role.state(), Colors::RESET);
+        }
+    }
+}
+
+fn state_color(state: State) -> &'static str {
+    if state == State::Up { Colors::GREEN } else { Colors::RED }
+}
+
+fn show_links(graph: &Graph) {
+    for (mac1, mac2) in graph.all_connections() {
+        if graph.router_with_mac(mac1).is_some_and(|r| r.has_mac(mac2)) {
+            continue;
+        }
+        let link = Element::Link(mac1.clone(), mac2.clone());
+        let name = |mac: &MAC| graph.name_with_mac(mac.clone()).unwrap_or(mac.to_hex());
+        let (admin, oper) = (graph.admin_state(&link), graph.oper_state(&link));
+        let failed = if graph.is_failed(&link) { " (failed)" } else { "" };
+        println!("{:<16} <-> {:<16} admin {}{:<4}{} oper {}{}{}{}", name(mac1), name(mac2),
+            state_color(admin), admin, Colors::RESET, state_color(oper), oper, Colors::RESET, failed);
+    }
+    for node in graph.routers.iter().map(|r| r.nic_lan.mac.clone())
+        .chain(graph.devices.iter().map(|d| d.nic.mac.clone()))
+        .chain(graph.switches.iter().map(|s| s.mac.clone())) {
+        if graph.is_failed(&Element::Node(node.clone())) {
+            println!("{} {}failed{}", node_name(graph, &node), Colors::RED, Colors::RESET);
+        }
+    }
+}
+
+fn set_link_shutdown(graph: &mut Graph, args: &[String], shutdown: bool) -> Result<(), String> {
+    let a = graph.resolve(&args[0]).map_err(|e| e.to_string())?;
+    let b = graph.resolve(&args[1]).map_err(|e| e.to_string())?;
+    graph.set_shutdown(Element::Link(a, b), shutdown).map_err(|e| e.to_string())
+}
+
+// The clock actions, typed as is to run them now or after `schedule <duration>`
+fn parse_action(command: &str, args: &[String]) -> Option<Action> {
+    let arg = |i: usize| args[i].clone();
+    match command.trim_start_matches("schedule <duration> ") {
+        "fail link <endpoint> <endpoint>" => Some(Action::FailLink { from: arg(0), to: arg(1) }),
+        "repair link <endpoint> <endpoint>" => Some(Action::RepairLink { from: arg(0), to: arg(1) }),
+        "fail node <node>" => Some(Action::FailNode { node: arg(0) }),
+        "repair node <node>" => Some(Action::RepairNode { node: arg(0) }),
+        "expire lease <node>" => Some(Action::ExpireLease { node: arg(0) }),
+        "ping <endpoint> <host>" => Some(Action::Ping { from: arg(0), to: arg(1) }),
+        _ => None,
+    }
+}
+
+fn schedule(graph: &mut Graph, command: &str, args: &[String]) -> Result<u32, String> {
+    let delay = parse_duration(&args[0]).ok_or(format!("Invalid duration {}", args[0]))?;
+    let action = parse_action(command, &args[1..]).ok_or("Unknown action".to_string())?;
+    Ok(graph.clock.schedule(delay, action))
+}
+
+fn show_schedule(graph: &Graph) {
+    let mut scheduled: Vec<_> = graph.clock.scheduled().iter().collect();
+    scheduled.sort_by_key(|s| (s.at, s.id));
+    for scheduled in scheduled {
+        println!("#{:<4} at {:>11}  {}", scheduled.id, format_time(scheduled.at), scheduled.event);
+    }
+}
+
+fn show_timeline(graph: &Graph) {
+    for entry in graph.clock.timeline() {
+        println!("{}", entry);
+    }
+}
+
+// The file is the last argument, after the interface or the two ends of the link
+fn start_capture(graph: &mut Graph, args: &[String]) -> Result<(), String> {
+    let (file, ends) = args.split_last().ok_or("Missing file".to_string())?;
+    let macs = ends.iter().map(|e| graph.resolve(e)).collect::<Result<Vec<MAC>, _>>().map_err(|e| e.to_string())?;
+    let point = match macs.as_slice() {
+        [mac] => CapturePoint::interface(graph, mac.clone()),
+        [mac1, mac2] => CapturePoint::link(graph, mac1.clone(), mac2.clone()),
+        _ => return Err("Invalid capture point".to_string()),
+    }.map_err(|e| e.to_string())?;
+    graph.captures.start(point, file.clone()).map_err(|e| e.to_string())
+}
+
+fn show_captures(graph: &Graph) {
+    let name = |mac: &MAC| graph.name_with_mac(mac.clone()).unwrap_or(mac.to_hex());
+    for capture in graph.captures.list() {
+        let point = match &capture.point {
+            CapturePoint::Interface { mac, .. } => name(mac),
+            CapturePoint::Link(mac1, mac2) => format!("{} <-> {}", name(mac1), name(mac2)),
+        };
+        match graph.captures.failure(&capture.file) {
+            Some(error) => println!("{:<36} -> {} {}(stopped: {}){}", point, capture.file, Colors::RED, error, Colors::RESET),
+            None => println!("{:<36} -> {}", point, capture.file),
+        }
+    }
+}
+
+// Filters add up to those of a trace already running
+fn start_trace(graph: &mut Graph, command: &str, args: &[String]) -> Result<(), String> {
+    let mut filter = graph.trace.filter().cloned().unwrap_or_default();
+    match command {
+        "trace node <node>" => filter.node = Some(graph.resolve_node(&args[0]).map_err(|e| e.to_string())?),
+        "trace protocol <protocol>" => filter.protocol = Some(Protocol::from_string(&args[0]).ok_or(format!("Unknown protocol {} (arp, ip, icmp, tcp, udp, dhcp or dns)", args[0]))?),
+        "trace ip <ip>" => filter.ip = Some(IP::from_string(&args[0]).ok_or(format!("Invalid IP address '{}'", args[0]))?),
+        _ => {},
+    }
+    graph.trace.start(filter);
+    Ok(())
+}
+
+fn show_trace(graph: &Graph) {
+    let Some(filter) = graph.trace.filter() else {
+        return println!("Trace off");
+    };
+    let mut filters: Vec<String> = Vec::new();
+    if let Some(node) = &filter.node {
+        // A router is designated by its LAN interface
+        let name = match graph.routers.iter().find(|r| r.nic_lan.mac == *node) {
+            Some(router) => router.name.clone(),
+            None => graph.name_with_mac(node.clone()).unwrap_or(node.to_hex()),
+        };
+        filters.push(format!("node {}", name));
+    }
+    if let Some(protocol) = &filter.protocol {
+        filters.push(format!("protocol {}", protocol));
+    }
+    if let Some(ip) = &filter.ip {
+        filters.push(format!("ip {}", ip.to_ddn()));
+    }
+    match filters.is_empty() {
+        true => println!("Trace on, everything"),
+        false => println!("Trace on, {}", filters.join(", ")),
+    }
+}
+
+fn parse_transport(transport: &str) -> Result<Transport, String> {
+    Transport::from_string(transport).ok_or(format!("Unknown transport {} (tcp or udp)", transport))
+}
+
+fn parse_port(port: &str) -> Result<u16, String> {
+    port.parse::<u16>().ok().filter(|p| *p != 0).ok_or(format!("Invalid port {}", port))
+}
+
+fn resolve_node(graph: &Graph, name: &str) -> Result<MAC, String> {
+    graph.resolve_node(name).map_err(|e| e.to_string())
+}
+
+// The arguments follow the node, given by name in the global mode and by the mode otherwise
+fn set_listening(graph: &mut Graph, host: MAC, args: &[String], listening: bool) -> Result<(), String> {
+    let (transport, port) = (parse_transport(&args[0])?, parse_port(&args[1])?);
+    match listening {
+        true => transport::listen(graph, host, transport, port),
+        false => graph.sockets.unlisten(&host, transport, port),
+    }.map_err(|e| e.to_string())
+}
+
+// The address is written ip:port
+fn open_connection(graph: &mut Graph, host: MAC, args: &[String]) -> Result<OpenOutcome, String> {
+    let transport = parse_transport(&args[0])?;
+    let (ip, port) = args[1].split_once(':').ok_or(format!("Invalid address {} (ip:port)", args[1]))?;
+    let ip = IP::from_string(ip).ok_or(format!("Invalid IP address '{}'", ip))?;
+    transport::open(graph, host, transport, ip, parse_port(port)?).map_err(|e| e.to_string())
+}
+
+fn close_connection(graph: &mut Graph, host: MAC, args: &[String]) -> Result<CloseOutcome, String> {
+    transport::close(graph, host, parse_port(&args[0])?).map_err(|e| e.to_string())
+}
+
+// The address is written host:port, the host being resolved from the requesting node
+fn send_request(graph: &mut Graph, host: MAC, args: &[String]) -> Result<RequestOutcome, String> {
+    let transport = parse_transport(&args[0])?;
+    let (name, port) = args[1].rsplit_once(':').ok_or(format!("Invalid address {} (host:port)", args[1]))?;
+    let port = parse_port(port)?;
+    let ip = dns::resolve(graph, &host, name).map_err(|e| e.to_string())?;
+    let data = args.get(2).map(|text| text.as_bytes().to_vec()).unwrap_or_default();
+    transport::request(graph, host, transport, ip, port, &data).map_err(|e| e.to_string())
+}
+
+fn show_services(graph: &Graph, host: Option<&MAC>) {
+    for device in graph.devices.iter().filter(|d| host.is_none_or(|mac| d.nic.mac == *mac)) {
+        for service in &device.services {
+            println!("{:<12} {}", device.name, service);
+        }
+    }
+}
+
+fn set_service(graph: &mut Graph, node: &MAC, command: &str, args: &[String]) -> Result<(), String> {
+    let (transport, port) = (parse_transport(&args[0])?, parse_port(&args[1])?);
+    let handler = match command {
+        "service <transport> <port> http page <path> <text>" if transport == Transport::Tcp =>
+            return service::add_page(graph, node, port, &args[2], &args[3]).map_err(|e| e.to_string()),
+        "service <transport> <port> echo" => Handler::Echo,
+        "service <transport> <port> custom <text>" => Handler::Custom(args[2].clone()),
+        _ => Handler::Http(Default::default()),
+    };
+    let service = Service::new(transport, port, handler).map_err(|e| e.to_string())?;
+    service::add_service(graph, node, service).map_err(|e| e.to_string())
+}
+
+fn show_sockets(graph: &Graph, host: Option<&MAC>) {
+    for socket in graph.sockets.list().iter().filter(|s| host.is_none_or(|mac| s.host == *mac)) {
+        let host = graph.name_with_mac(socket.host.clone()).unwrap_or(socket.host.to_hex());
+        let local = graph.search_device_with_mac(socket.host.clone())
+            .map(|d| format!("{}:{}", d.nic.ip.to_ddn(), socket.local_port))
+            .unwrap_or(format!("*:{}", socket.local_port));
+        let remote = match &socket.remote {
+            Some((ip, port)) => format!("{}:{}", ip.to_ddn(), port),
+            None => "*:*".to_string(),
+        };
+        println!("{:<12} {:<4} {:<22} {:<22} {}", host, socket.transport, local, remote, socket.state);
+    }
+}
+
+fn resolve_switch(graph: &Graph, name: &str) -> Result<MAC, String> {
+    let switch = graph.resolve(name).map_err(|e| e.to_string())?;
+    match graph.search_switch_with_mac(switch.clone()) {
+        Some(_) => Ok(switch),
+        None => Err(format!("{} is not a switch", name)),
+    }
+}
+
+fn set_bridge_priority(graph: &mut Graph, switch: MAC, priority: &str) -> Result<(), String> {
+    let priority = priority.parse::<u16>().map_err(|_| format!("Invalid priority {}", priority))?;
+    graph.set_bridge_priority(switch, priority).map_err(|e| e.to_string())
+}
+
+// `args` are the port and its VLANs
+fn set_port_mode(graph: &mut Graph, switch: MAC, args: &[String], mode: Option<PortMode>) -> Result<(), String> {
+    let port = graph.resolve(&args[0]).map_err(|e| e.to_string())?;
+    let mode = mode.ok_or(format!("Invalid VLAN {}", args[1]))?;
+    graph.set_port_mode(switch, port, mode).map_err(|e| e.to_string())
+}
+
+// Entering a subinterface that does not exist yet creates it, unconfigured
+fn enter_interface(graph: &mut Graph, history: &mut History, node: &MAC, line: &str, name: &str) -> Option<MAC> {
+    if let Some(iface) = find_interface(graph, node, name) {
+        return Some(iface);
+    }
+    let (parent, vlan) = match (graph.search_router_with_mac(node.clone()), parse_subinterface(name)) {
+        (Some(_), Some(subinterface)) => subinterface,
+        _ => {
+            println!("% Unknown interface {}", name);
+            return None;
+        }
+    };
+    let created = history.apply(graph, line, |g| {
+        let mac = g.new_mac(&NodeType::Router);
+        let nic = NIC::with_mac(IP::V4(127, 0, 0, 1), IP::from_cidr(8), mac.clone());
+        g.append_subinterface(node.clone(), SubInterface { parent, vlan, nic, dhcp: None }).map(|_| mac)
+    });
+    match created {
+        Ok(mac) => Some(mac),
+        Err(e) => {
+            println!("% {}", e);
+            None
+        }
+    }
+}
+
+fn help(graph: &Graph, mode: &Mode) {
+    for command in mode_commands(graph, mode) {
+        println!("  {}", command);
+    }
+}
+
+fn run_ping(graph: &Graph, node: &MAC, args: &[String]) {
+    let ip = match dns::resolve(graph, node, &args[0]) {
+        Ok(ip) => ip,
+        Err(e) => return println!("% {}", e),
+    };
+    if let Some(nic) = graph.nic_with_mac(node.clone()) {
+        println!("Ping {} : {}", ip, ping(graph, nic, ip.clone()));
+    }
+}
+
+fn run_traceroute(graph: &Graph, node: &MAC, args: &[String]) {
+    let ip = match dns::resolve(graph, node, &args[0]) {
+        Ok(ip) => ip,
+        Err(e) => return println!("% {}", e),
+    };
+    if let Some(nic) = graph.nic_with_mac(node.clone()) {
+        match traceroute(graph, nic, ip) {
+            Some(hops) => {
+                for (i, hop) in hops.iter().enumerate() {
+                    println!("  {}  {}", i + 1, hop);
+                }
+            },
+            None => println!("  1  * * *"),
+        }
+    }
+}
+
+fn set_ip_address(graph: &mut Graph, iface: &MAC, ip: Option<IP>, netmask: Option<IP>) -> Result<(), String> {
+    let (ip, netmask) = match (ip, netmask) {
+        (Some(ip), Some(netmask)) => (ip, netmask),
+        _ => return Err("Invalid address".to_string()),
+    };
+    graph.configure_nic(iface.clone(), ip, netmask).map_err(|e| e.to_string())?;
+    // A pool outside of the new network is no longer usable
+    if let Some(subinterface) = graph.subinterface_mut_with_mac(iface.clone())
+        && let Some(dhcp) = subinterface.dhcp.clone()
+        && subinterface.set_dhcp(Some((dhcp.first_ip.clone(), dhcp.last_ip.clone()))).is_err() {
+        let _ = subinterface.set_dhcp(None);
+        println!("% DHCP pool {} > {} removed, it does not match the new network", dhcp.first_ip, dhcp.last_ip);
+    }
+    if let Some((router, interface)) = graph.router_mut_with_mac(iface.clone()) {
+        let dhcp = match interface {
+            RouterInterface::LAN => router.dhcp_lan.clone(),
+            RouterInterface::WAN => router.dhcp_wan.clone(),
+        };
+        if let Some(dhcp) = dhcp && router.set_dhcp(interface.clone(), Some((dhcp.first_ip.clone(), dhcp.last_ip.clone()))).is_err() {
+            let _ = router.set_dhcp(interface, None);
+            println!("% DHCP pool {} > {} removed, it does not match the new network", dhcp.first_ip, dhcp.last_ip);
+        }
+    }
+    Ok(())
+}
+
+fn run_nslookup(graph: &Graph, node: &MAC, args: &[String]) {
+    match dns::lookup(graph, node, &args[0]) {
+        Ok(records) if records.is_empty() => println!("% {} has no address", args[0]),
+        Ok(records) => {
+            for record in records {
+                println!("{}", record);
+            }
+        },
+        Err(e) => println!("% {}", e),
+    }
+}
+
+fn show_dns(graph: &Graph) {
+    let servers = graph.routers.iter().map(|r| (&r.name, &r.zone)).chain(graph.devices.iter().map(|d| (&d.name, &d.zone)));
+    for (name, zone) in servers {
+        let Some(zone) = zone else {
+            continue;
+        };
+        println!("{} ({} records)", name, zone.len());
+        for record in zone {
+            println!("  {}", record);
+        }
+    }
+    for device in graph.devices.iter().filter(|d| d.name_server.is_some()) {
+        println!("{} resolves with {}", device.name, device.name_server.as_ref().map(IP::to_ddn).unwrap_or_default());
+    }
+}
+
+fn add_host(graph: &mut Graph, node: &MAC, args: &[String]) -> Result<(), String> {
+    let kind = RecordType::from_string(&args[1]).ok_or(format!("Unknown record type {} (A, AAAA, PTR or CNAME)", args[1]))?;
+    let record = Record::new(&args[0], kind, &args[2]).map_err(|e| e.to_string())?;
+    dns::add_record(graph, node, record).map_err(|e| e.to_string())
+}
+
+fn set_dhcp_name_server(graph: &mut Graph, iface: &MAC, name_server: Option<IP>) -> Result<(), String> {
+    let pool = match graph.search_subinterface_with_mac(iface.clone()) {
+        Some(_) => graph.subinterface_mut_with_mac(iface.clone()).and_then(|s| s.dhcp.as_mut()),
+        None => match graph.router_mut_with_mac(iface.clone()) {
+            Some((router, interface)) => router.dhcp_mut(&interface).as_mut(),
+            None => return Err("DHCP pools can only be configured on router interfaces".to_string()),
+        },
+    };
+    match pool {
+        Some(pool) => {
+            pool.name_server = name_server;
+            Ok(())
+        },
+        None => Err("No DHCP pool on this interface".to_string()),
+    }
+}
+
+fn set_dhcp_pool(graph: &mut Graph, iface: &MAC, range: Option<(IP, IP)>) -> Result<(), String> {
+    if let Some(subinterface) = graph.subinterface_mut_with_mac(iface.clone()) {
+        return subinterface.set_dhcp(range).map_err(|e| e.to_string());
+    }
+    match graph.router_mut_with_mac(iface.clone()) {
+        Some((router, interface)) => router.set_dhcp(interface, range).map_err(|e| e.to_string()),
+        None => Err("DHCP pools can only be configured on router interfaces".to_string()),
+    }
+}
+
+fn show_history(history: &History) {
+    for description in history.undo_list() {
+        println!("  {}", description);
+    }
+    for description in history.redo_list() {
+        println!("  {}{} (undone){}", Colors::DIM, description, Colors::RESET);
+    }
+}
+
+// `line` describes the command in the history of changes
+fn execute(graph: &mut Graph, history: &mut History, mode: &Mode, line: &str, command: &str, args: &[String]) -> Option<Mode> {
+    match (mode, command) {
+        (_, "help") => help(graph, mode),
+        (Mode::Global, "exit") | (Mode::Global, "quit") => return None,
+        (Mode::Global, "node <node>") => match find_node(graph, &args[0]) {
+            Some(mac) => return Some(Mode::User(mac)),
+            None => println!("% Unknown node {}", args[0]),
+        },
+        (Mode::Global, "create router <name>") => {
+            let localhost = IP::V4(127, 0, 0, 1);
+            let netmask = IP::from_cidr(8);
+            if let Err(e) = history.apply(graph, line, |g| create_router(args[0].clone(), g, localhost.clone(), netmask.clone(), localhost, netmask, None, None, None, None)) {
+                println!("% {}", e);
+            }
+        },
+        (Mode::Global, "create device <name>") => {
+            if let Err(e) = history.apply(graph, line, |g| create_device(args[0].clone(), g)) {
+                println!("% {}", e);
+            }
+        },
+        (Mode::Global, "create switch <name>") => {
+            if let Err(e) = history.apply(graph, line, |g| create_switch(args[0].clone(), g)) {
+                println!("% {}", e);
+            }
+        },
+        (Mode::Global, "connect <endpoint> <endpoint>") => {
+            match (graph.resolve(&args[0]), graph.resolve(&args[1])) {
+                (Ok(a), Ok(b)) => match history.apply(graph, line, |g| connection_any_direction(g, a, b)) {
+                    Ok(Some(ip)) => println!("DHCP attribution succeed : {}", ip),
+                    Ok(None) => {},
+                    Err(e) => println!("% {}", e),
+                },
+                (Err(e), _) | (_, Err(e)) => println!("% {}", e),
+            }
+        },
+        (Mode::Global, "disconnect <endpoint> <endpoint>") => {
+            match (graph.resolve(&args[0]), graph.resolve(&args[1])) {
+                (Ok(a), Ok(b)) => {
+                    if let Err(e) = history.apply(graph, line, |g| g.remove_connection(a, b)) {
+                        println!("% {}", e);
+                    }
+                },
+                (Err(e), _) | (_, Err(e)) => println!("% {}", e),
+            }
+        },
+        (Mode::Global, "switchport <switch> <endpoint> access <vlan>") => {
+            let mode = args[2].parse::<u16>().ok().map(PortMode::Access);
+            if let Err(e) = history.apply(graph, line, |g| set_port_mode(g, resolve_switch(g, &args[0])?, &args[1..], mode)) {
+                println!("% {}", e);
+            }
+        },
+        (Mode::Global, "switchport <switch> <endpoint> trunk <vlans>") => {
+            let mode = PortMode::trunk_from_string(&args[2]);
+            if let Err(e) = history.apply(graph, line, |g| set_port_mode(g, resolve_switch(g, &args[0])?, &args[1..], mode)) {
+                println!("% {}", e);
+            }
+        },
+        (Mode::Global, "spanning-tree <switch> priority <number>") => {
+            if let Err(e) = history.apply(graph, line, |g| set_bridge_priority(g, resolve_switch(g, &args[0])?, &args[1])) {
+                println!("% {}", e);
+            }
+        },
+        (Mode::Global, "shutdown link <endpoint> <endpoint>") | (Mode::Global, "no shutdown link <endpoint> <endpoint>") => {
+            let shutdown = !command.starts_with("no ");
+            if let Err(e) = history.apply(graph, line, |g| set_link_shutdown(g, args, shutdown)) {
+                println!("% {}", e);
+            }
+        },
+        (Mode::Global, "fail link <endpoint> <endpoint>") | (Mode::Global, "repair link <endpoint> <endpoint>")
+        | (Mode::Global, "fail node <node>") | (Mode::Global, "repair node <node>") | (Mode::Global, "expire lease <node>") => {
+            if let Some(action) = parse_action(command, args) {
+                match history.apply(graph, line, |g| clock::execute(g, &action)) {
+                    Ok(outcome) => println!("{}", outcome),
+                    Err(e) => println!("% {}", e),
+                }
+            }
+        },
+        (Mode::Global, "advance <duration>") => match parse_duration(&args[0]) {
+            Some(duration) => {
+                let entries = history.record(graph, line, |g| clock::advance(g, duration));
+                for entry in entries {
+                    println!("{}", entry);
+                }
+                println!("Clock at {}", graph.clock);
+            },
+            None => println!("% Invalid duration {}", args[0]),
+        },
+        // The clock, captures, trace and sockets are runtime state, which undo does not rewind
+        (Mode::Global, command) if command.starts_with("schedule ") => match schedule(graph, command, args) {
+            Ok(id) => println!("Scheduled #{}", id),
+            Err(e) => println!("% {}", e),
+        },
+        (Mode::Global, "no schedule <number>") => match args[0].parse::<u32>() {
+            Ok(id) => {
+                if !graph.clock.cancel(id) {
+                    println!("% No scheduled action #{}", id);
+                }
+            },
+            Err(_) => println!("% Invalid number {}", args[0]),
+        },
+        (Mode::Global, "capture <endpoint> <file>") | (Mode::Global, "capture link <endpoint> <endpoint> <file>") => {
+            if let Err(e) = start_capture(graph, args) {
+                println!("% {}", e);
+            }
+        },
+        (Mode::Global, "no capture <file>") => {
+            if let Err(e) = graph.captures.stop(&args[0]) {
+                println!("% {}", e);
+            }
+        },
+        (Mode::Global, "trace") | (Mode::Global, "trace node <node>") | (Mode::Global, "trace protocol <protocol>") | (Mode::Global, "trace ip <ip>") => {
+            if let Err(e) = start_trace(graph, command, args) {
+                println!("% {}", e);
+            }
+        },
+        (Mode::Global, "no trace") => graph.trace.stop(),
+        (Mode::Global, "listen <node> <transport> <port>") | (Mode::Global, "no listen <node> <transport> <port>") => {
+            let listening = !command.starts_with("no ");
+            if let Err(e) = resolve_node(graph, &args[0]).and_then(|host| set_listening(graph, host, &args[1..], listening)) {
+                println!("% {}", e);
+            }
+        },
+        (Mode::Global, "open <node> <transport> <address>") => match resolve_node(graph, &args[0]).and_then(|host| open_connection(graph, host, &args[1..])) {
+            Ok(outcome) => println!("{}", outcome),
+            Err(e) => println!("% {}", e),
+        },
+        (Mode::Global, "close <node> <port>") => match resolve_node(graph, &args[0]).and_then(|host| close_connection(graph, host, &args[1..])) {
+            Ok(outcome) => println!("{}", outcome),
+            Err(e) => println!("% {}", e),
+        },
+        (Mode::Global, "request <node> <transport> <address>") | (Mode::Global, "request <node> <transport> <address> <text>") => {
+            match resolve_node(graph, &args[0]).and_then(|host| send_request(graph, host, &args[1..])) {
+                Ok(outcome) => println!("{}", outcome.to_string().trim_end()),
+                Err(e) => println!("% {}", e),
+            }
+        },
+        (Mode::Global, "load <file>") => match load_data(&args[0]) {
+            Ok(data) => print_load_report(&history.record(graph, line, |g| g.load_data(data))),
+            Err(e) => println!("% {}", e),
+        },
+        (Mode::Global, "save <file>") => {
+            save_to(graph, &args[0], None);
+        },
+        (Mode::Global, "snapshot save <file>") => {
+            snapshot_to(graph, &args[0], None);
+        },
+        (Mode::Global, "snapshot restore <file>") => {
+            let _ = history.apply(graph, line, |g| restore_from(g, &args[0], None).then_some(()).ok_or(()));
+        },
+        (Mode::Global, "run <file>") => {
+            if let Ok(report) = history.apply(graph, line, |g| script_file(g, &args[0]).ok_or(())) {
+                print_script_report(&report);
+            }
+        },
+        (Mode::Global, "show nodes") => show_nodes(graph),
+        (Mode::Global, "show topology") => println!("{}", graph),
+        (Mode::Global, "show diagram") => println!("{}", render(graph)),
+        (Mode::Global, "show vlans") => show_vlans(graph, None),
+        (Mode::Global, "show spanning-tree") => show_spanning_tree(graph, None),
+        (Mode::Global, "show links") => show_links(graph),
+        (Mode::Global, "show clock") => println!("{}", graph.clock),
+        (Mode::Global, "show schedule") => show_schedule(graph),
+        (Mode::Global, "show timeline") => show_timeline(graph),
+        (Mode::Global, "show captures") => show_captures(graph),
+        (Mode::Global, "show trace") => show_trace(graph),
+        (Mode::Global, "show sockets") => show_sockets(graph, None),
+        (Mode::Global, "show dns") => show_dns(graph),
+        (Mode::Global, "show services") => show_services(graph, None),
+        (Mode::Global, "undo") => match history.undo(graph) {
+            Some(description) => println!("Undone: {}", description),
+            None => println!("% Nothing to undo"),
+        },
+        (Mode::Global, "redo") => match history.redo(graph) {
+            Some(description) => println!("Redone: {}", description),
+            None => println!("% Nothing to redo"),
+        },
+        (Mode::Global, "show history") => show_history(history),
+        (Mode::Global, "show seed") => println!("{}", graph.random),
+        (Mode::Global, "seed <number>") => match args[0].parse::<u64>() {
+            Ok(seed) => graph.random.reseed(seed),
+            Err(_) => println!("% Invalid seed {}", args[0]),
+        },
+        (Mode::Global, "validate") => print_validation_report(&validate_graph(graph)),
+        (Mode::Global, "export <format>") | (Mode::Global, "export <format> <file>") => match ExportFormat::from_string(&args[0]) {
+            Some(format) => {
+                export_to(graph, format, args.get(1).map(String::as_str));
+            },
+            None => println!("% Unknown format {}", args[0]),
+        },
+        (Mode::User(node), "enable") => return Some(Mode::Privileged(node.clone())),
+        (Mode::User(_), "exit") | (Mode::Privileged(_), "exit") => return Some(Mode::Global),
+        (Mode::Privileged(node), "disable") => return Some(Mode::User(node.clone())),
+        (Mode::Privileged(node), "configure") | (Mode::Privileged(node), "configure terminal") => return Some(Mode::Config(node.clone())),
+        (Mode::User(node), "ping <host>") | (Mode::Privileged(node), "ping <host>") => run_ping(graph, node, args),
+        (Mode::User(node), "traceroute <host>") | (Mode::Privileged(node), "traceroute <host>") => run_traceroute(graph, node, args),
+        (Mode::User(node), "nslookup <host>") | (Mode::Privileged(node), "nslookup <host>") => run_nslookup(graph, node, args),
+        (Mode::User(node), "show ip route") | (Mode::Privileged(node), "show ip route") => show_ip_route(graph, node),
+        (Mode::User(node), "show interfaces") | (Mode::Privileged(node), "show interfaces") => show_interfaces(graph, node),
+        (Mode::User(node), "show arp") | (Mode::Privileged(node), "show arp") => show_arp(graph, node),
+        (Mode::User(node), "show mac address-table") | (Mode::Privileged(node), "show mac address-table") => show_mac_table(graph, node),
+        (Mode::Privileged(node), "show running-config") => show_running_config(graph, node),
+        (Mode::User(node), "show vlans") | (Mode::Privileged(node), "show vlans") => show_vlans(graph, Some(node)),
+        (Mode::User(node), "show spanning-tree") | (Mode::Privileged(node), "show spanning-tree") => show_spanning_tree(graph, Some(node)),
+        (Mode::User(node), "show sockets") | (Mode::Privileged(node), "show sockets") => show_sockets(graph, Some(node)),
+        (Mode::User(node), "show services") | (Mode::Privileged(node), "show services") => show_services(graph, Some(node)),
+        (Mode::User(node), "listen <transport> <port>") | (Mode::Privileged(node), "listen <transport> <port>")
+        | (Mode::User(node), "no listen <transport> <port>") | (Mode::Privileged(node), "no listen <transport> <port>") => {
+            let listening = !command.starts_with("no ");
+            if let Err(e) = set_listening(graph, node.clone(), args, listening) {
+                println!("% {}", e);
+            }
+        },
+        (Mode::User(node), "open <transport> <address>") | (Mode::Privileged(node), "open <transport> <address>") => {
+            match open_connection(graph, node.clone(), args) {
+                Ok(outcome) => println!("{}", outcome),
+                Err(e) => println!("% {}", e),
+            }
+        },
+        (Mode::User(node), "close <port>") | (Mode::Privileged(node), "close <port>") => {
+            match close_connection(graph, node.clone(), args) {
+                Ok(outcome) => println!("{}", outcome),
+                Err(e) => println!("% {}", e),
+            }
+        },
+        (Mode::User(node), command) | (Mode::Privileged(node), command) if command.starts_with("request ") => {
+            match send_request(graph, node.clone(), args) {
+                Ok(outcome) => println!("{}", outcome.to_string().trim_end()),
+                Err(e) => println!("% {}", e),
+            }
+        },
+        (Mode::Config(node), "exit") | (Mode::Config(node), "end") => return Some(Mode::Privileged(node.clone())),
+        (Mode::Config(node), "hostname <name>") => {
+            if let Err(e) = history.apply(graph, line, |g| g.rename_node(node.clone(), args[0].clone())) {
+                println!("% {}", e);
+            }
+        },
+        (Mode::Config(node), "spanning-tree priority <number>") => {
+            if let Err(e) = history.apply(graph, line, |g| set_bridge_priority(g, node.clone(), &args[0])) {
+                println!("% {}", e);
+            }
+        },
+        (Mode::Config(node), "switchport <endpoint> access <vlan>") => {
+            let mode = args[1].parse::<u16>().ok().map(PortMode::Access);
+            if let Err(e) = history.apply(graph, line, |g| set_port_mode(g, node.clone(), args, mode)) {
+                println!("% {}", e);
+            }
+        },
+        (Mode::Config(node), "switchport <endpoint> trunk <vlans>") => {
+            let mode = PortMode::trunk_from_string(&args[1]);
+            if let Err(e) = history.apply(graph, line, |g| set_port_mode(g, node.clone(), args, mode)) {
+                println!("% {}", e);
+            }
+        },
+        (Mode::Config(node), "interface <interface>") => {
+            if let Some(iface) = enter_interface(graph, history, node, line, &args[0]) {
+                return Some(Mode::Interface(node.clone(), iface));
+            }
+        },
+        (Mode::Config(node), "no interface <interface>") => match find_interface(graph, node, &args[0]) {
+            Some(iface) if graph.search_subinterface_with_mac(iface.clone()).is_some() => {
+                if let Err(e) = history.apply(graph, line, |g| g.remove_subinterface(iface)) {
+                    println!("% {}", e);
+                }
+            },
+            Some(_) => println!("% Only subinterfaces can be removed"),
+            None => println!("% Unknown interface {}", args[0]),
+        },
+        (Mode::Config(node), "ip name-server <ip>") => match IP::from_string(&args[0]) {
+            Some(ip) => {
+                if let Err(e) = history.apply(graph, line, |g| dns::set_name_server(g, node, Some(ip))) {
+                    println!("% {}", e);
+                }
+            },
+            None => println!("% Invalid address"),
+        },
+        (Mode::Config(node), "no ip name-server") => {
+            if let Err(e) = history.apply(graph, line, |g| dns::set_name_server(g, node, None)) {
+                println!("% {}", e);
+            }
+        },
+        (Mode::Config(node), "ip dns server") | (Mode::Config(node), "no ip dns server") => {
+            let enabled = !command.starts_with("no ");
+            if let Err(e) = history.apply(graph, line, |g| dns::set_server(g, node, enabled)) {
+                println!("% {}", e);
+            }
+        },
+        (Mode::Config(node), "ip host <name> <type> <value>") => {
+            if let Err(e) = history.apply(graph, line, |g| add_host(g, node, args)) {
+                println!("% {}", e);
+            }
+        },
+        (Mode::Config(node), "no ip host <name>") => {
+            if let Err(e) = history.apply(graph, line, |g| dns::remove_records(g, node, &args[0])) {
+                println!("% {}", e);
+            }
+        },
+        (Mode::Config(node), "service <transport> <port> echo") | (Mode::Config(node), "service <transport> <port> http")
+        | (Mode::Config(node), "service <transport> <port> http page <path> <text>") | (Mode::Config(node), "service <transport> <port> custom <text>") => {
+            if let Err(e) = history.apply(graph, line, |g| set_service(g, node, command, args)) {
+                println!("% {}", e);
+            }
+        },
+        (Mode::Config(node), "no service <transport> <port>") => {
+            let removed = parse_transport(&args[0]).and_then(|transport| Ok((transport, parse_port(&args[1])?)))
+                .and_then(|(transport, port)| history.apply(graph, line, |g| service::remove_service(g, node, transport, port).map_err(|e| e.to_string())));
+            if let Err(e) = removed {
+                println!("% {}", e);
+            }
+        },
+        (Mode::Interface(node, _), "exit") => return Some(Mode::Config(node.clone())),
+        (Mode::Interface(node, _), "end") => return Some(Mode::Privileged(node.clone())),
+        (Mode::Interface(_, iface), "ip address <address>") => {
+            let (ip, netmask) = match IP::from_cidr_string(&args[0]) {
+                Some((ip, netmask)) => (Some(ip), Some(netmask)),
+                None => (None, None),
+            };
+            if let Err(e) = history.apply(graph, line, |g| set_ip_address(g, iface, ip, netmask)) {
+                println!("% {}", e);
+            }
+        },
+        (Mode::Interface(_, iface), "ip address <ip> <netmask>") => {
+            if let Err(e) = history.apply(graph, line, |g| set_ip_address(g, iface, IP::from_string(&args[0]), IP::from_string(&args[1]))) {
+                println!("% {}", e);
+            }
+        },
+        (Mode::Interface(_, iface), "ip dhcp pool <ip> <ip>") => match (IP::from_string(&args[0]), IP::from_string(&args[1])) {
+            (Some(first_ip), Some(last_ip)) => {
+                if let Err(e) = history.apply(graph, line, |g| set_dhcp_pool(g, iface, Some((first_ip, last_ip)))) {
+                    println!("% {}", e);
+                }
+            },
+            _ => println!("% Invalid address"),
+        },
+        (Mode::Interface(_, iface), "shutdown") | (Mode::Interface(_, iface), "no shutdown") => {
+            let shutdown = command == "shutdown";
+            if let Err(e) = history.apply(graph, line, |g| g.set_shutdown(Element::Interface(iface.clone()), shutdown)) {
+                println!("% {}", e);
+            }
+        },
+        (Mode::Interface(_, iface), "ip dhcp name-server <ip>") => match IP::from_string(&args[0]) {
+            Some(ip) => {
+                if let Err(e) = history.apply(graph, line, |g| set_dhcp_name_server(g, iface, Some(ip))) {
+                    println!("% {}", e);
+                }
+            },
+            None => println!("% Invalid address"),
+        },
+        (Mode::Interface(_, iface), "no ip dhcp name-server") => {
+            if let Err(e) = history.apply(graph, line, |g| set_dhcp_name_server(g, iface, None)) {
+                println!("% {}", e);
+            }
+        },
+        (Mode::Interface(_, iface), "no ip dhcp pool") => {
+            if let Err(e) = history.apply(graph, line, |g| set_dhcp_pool(g, iface, None)) {
+                println!("% {}", e);
+            }
+        },
+        _ => println!("% Invalid input detected"),
+    }
+    Some(mode.clone())
+}
+
+pub fn shell(graph: &mut Graph, history: &mut History) {
+    let mut editor: Editor<ShellHelper, DefaultHistory> = match Editor::new() {
+        Ok(editor) => editor,
+        Err(e) => {
+            println!("Error starting the shell: {}", e);
+            return;
+        }
+    };
+    let mut mode = Mode::Global;
+    println!("Type 'help' for the commands available in the current mode, TAB to complete.");
+    loop {
+        // Undone changes may bring back a graph from before the listener was set
+        graph.trace.listen(|line| println!("{}", line));
+        let mut nodes: Vec<String> = graph.routers.iter().map(|r| r.name.clone()).collect();
+        nodes.extend(graph.devices.iter().map(|d| d.name.clone()));
+        nodes.extend(graph.switches.iter().map(|s| s.name.clone()));
+        let mut endpoints: Vec<String> = graph.routers.iter()
+            .flat_map(|r| [format!("{}.lan", r.name), format!("{}.wan", r.name)])
+            .collect();
+        endpoints.extend(graph.devices.iter().map(|d| d.name.clone()));
+        endpoints.extend(graph.switches.iter().map(|s| s.name.clone()));
+        let interfaces = match &mode {
+            Mode::Config(node) => interface_names(graph, node),
+            _ => Vec::new(),
+        };
+        let switches: Vec<String> = graph.switches.iter().map(|s| s.name.clone()).collect();
+        editor.set_helper(Some(ShellHelper { commands: mode_commands(graph, &mode), nodes, endpoints, switches, interfaces }));
+
+        let line = match editor.readline(&prompt(graph, &mode)) {
+            Ok(line) => line,
+            Err(ReadlineError::Interrupted) => continue,
+            Err(ReadlineError::Eof) => break,
+            Err(e) => {
+                println!("Error reading input: {}", e);
+                break;
+            }
+        };
+        let line = line.trim();
+        if line.is_empty() {
+            continue;
+        }
+        let _ = editor.add_history_entry(line);
+        let (command, args) = match parse_command(&mode_commands(graph, &mode), line) {
+            Ok(parsed) => parsed,
+            Err(e) => {
+                println!("{}", e);
+                continue;
+            }
+        };
+        match execute(graph, history, &mode, line, &command, &args) {
+            Some(next) => mode = next,
+            None => break,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use network_simulator::load::{DataFormat, parse_data};
+
+    const OFFICE: &str = "
+routers:
+  - name: R1
+    lan: {ip: 192.168.1.1, netmask: 24, dhcp: {first_ip: 192.168.1.10, last_ip: 192.168.1.20}}
+    wan: {ip: 10.0.0.1, netmask: 30}
+devices:
+  - name: PC1
+switches:
+  - name: SW1
+connections:
+  - {from: R1.lan, to: SW1}
+  - {from: PC1, to: SW1}
+";
+
+    fn office() -> Graph {
+        let mut graph = Graph::new();
+        let report = graph.load_data(parse_data("test", OFFICE, DataFormat::Yaml).unwrap_or_else(|e| panic!("{}", e)));
+        assert!(report.rejected().is_empty());
+        graph
+    }
+
+    fn parse(commands: &[&str], input: &str) -> (String, Vec<String>) {
+        parse_command(commands, input).unwrap_or_else(|e| panic!("{}", e))
+    }
+
+    #[test]
+    fn keywords_can_be_abbreviated() {
+        assert_eq!(parse(PRIVILEGED_COMMANDS, "sh ip ro"), ("show ip route".to_string(), vec![]));
+        assert_eq!(parse(PRIVILEGED_COMMANDS, "conf t"), ("configure terminal".to_string(), vec![]));
+        assert_eq!(parse(GLOBAL_COMMANDS, "CONNECT PC1 R1.lan").1, vec!["PC1", "R1.lan"]);
+    }
+
+    #[test]
+    fn an_exact_keyword_wins_over_a_longer_one() {
+        assert_eq!(parse(PRIVILEGED_COMMANDS, "configure").0, "configure");
+    }
+
+    #[test]
+    fn text_takes_the_rest_of_the_line() {
+        let (command, args) = parse(GLOBAL_COMMANDS, "request PC1 tcp web:80 GET / HTTP/1.0");
+        assert_eq!(command, "request <node> <transport> <address> <text>");
+        assert_eq!(args, vec!["PC1", "tcp", "web:80", "GET / HTTP/1.0"]);
+        assert_eq!(parse(GLOBAL_COMMANDS, "request PC1 tcp web:80").0, "request <node> <transport> <address>");
+    }
+
+    #[test]
+    fn ambiguous_incomplete_and_invalid_input_is_refused() {
+        assert!(matches!(parse_command(PRIVILEGED_COMMANDS, "s"), Err(e) if e.starts_with("% Incomplete")));
+        assert!(matches!(parse_command(CONFIG_COMMANDS, "e"), Err(e) if e.starts_with("% Ambiguous")));
+        assert!(matches!(parse_command(PRIVILEGED_COMMANDS, "reboot"), Err(e) if e.starts_with("% Invalid")));
+    }
+
+    #[test]
+    fn switches_are_named_and_have_their_own_commands() {
+        let graph = office();
+        let switch = graph.resolve_node("SW1").unwrap_or_else(|e| panic!("{}", e));
+        assert_eq!(node_name(&graph, &switch), "SW1");
+        assert_eq!(prompt(&graph, &Mode::Config(switch.clone())), "SW1(config)# ");
+        let commands = mode_commands(&graph, &Mode::Config(switch));
+        assert!(commands.contains(&"spanning-tree priority <number>"));
+        assert!(!commands.contains(&"interface <interface>"));
+    }
+
+    #[test]
+    fn device_commands_act_on_the_selected_device() {
+        let graph = office();
+        let device = graph.resolve_node("PC1").unwrap_or_else(|e| panic!("{}", e));
+        let router = graph.resolve_node("R1").unwrap_or_else(|e| panic!("{}", e));
+        let commands = mode_commands(&graph, &Mode::User(device));
+        assert_eq!(parse(&commands, "li tcp 80"), ("listen <transport> <port>".to_string(), vec!["tcp".to_string(), "80".to_string()]));
+        assert!(!mode_commands(&graph, &Mode::User(router)).contains(&"show sockets"));
+    }
+}
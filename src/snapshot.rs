@@ -0,0 +1,345 @@
+use std::fs::read_to_string;
+use serde::{Deserialize, Serialize};
+
+use crate::clock::Clock;
+use crate::graph::Graph;
+use crate::ip::IP;
+use crate::load::{DataFormat, LoadError, LoadedData, deserialize, serialize};
+use crate::mac::MAC;
+use crate::random::Random;
+use crate::tables::{ArpEntry, Counter, MacEntry, Tables};
+use crate::transport::{Socket, SocketState, Transport};
+
+
+pub const SNAPSHOT_VERSION: u32 = 1;
+
+// The position is a 128-bit word count, written as text as not every format holds such integers
+#[derive(Serialize, Deserialize)]
+pub struct SavedRandom {
+    pub seed: u64,
+    pub position: String
+}
+
+// Hosts are designated by MAC, the topology keeping them; remote ends are written "ip:port"
+#[derive(Serialize, Deserialize)]
+pub struct SavedSocket {
+    pub host: String,
+    pub transport: Transport,
+    pub port: u16,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub remote: Option<String>,
+    pub state: SocketState,
+    pub sequence: u32,
+    pub acknowledgment: u32
+}
+
+impl SavedSocket {
+    fn of(socket: &Socket) -> Self {
+        SavedSocket {
+            host: socket.host.to_hex(),
+            transport: socket.transport,
+            port: socket.local_port,
+            remote: socket.remote.as_ref().map(|(ip, port)| format!("{}:{}", ip.to_ddn(), port)),
+            state: socket.state,
+            sequence: socket.sequence,
+            acknowledgment: socket.acknowledgment
+        }
+    }
+
+    fn to_socket(&self) -> Option<Socket> {
+        let remote = match &self.remote {
+            Some(remote) => {
+                let (ip, port) = remote.split_once(':')?;
+                Some((IP::from_string(ip)?, port.parse::<u16>().ok()?))
+            },
+            None => None,
+        };
+        Some(Socket {
+            host: MAC::from_string(&self.host)?,
+            transport: self.transport,
+            local_port: self.port,
+            remote,
+            state: self.state,
+            sequence: self.sequence,
+            acknowledgment: self.acknowledgment
+        })
+    }
+}
+
+#[derive(Serialize, Deserialize)]
+pub struct SavedArpEntry {
+    pub interface: String,
+    pub ip: String,
+    pub mac: String,
+    pub learned: u64
+}
+
+impl SavedArpEntry {
+    fn of(entry: &ArpEntry) -> Self {
+        SavedArpEntry { interface: entry.interface.to_hex(), ip: entry.ip.to_ddn(), mac: entry.mac.to_hex(), learned: entry.learned }
+    }
+
+    fn to_entry(&self) -> Option<ArpEntry> {
+        Some(ArpEntry {
+            interface: MAC::from_string(&self.interface)?,
+            ip: IP::from_string(&self.ip)?,
+            mac: MAC::from_string(&self.mac)?,
+            learned: self.learned
+        })
+    }
+}
+
+#[derive(Serialize, Deserialize)]
+pub struct SavedMacEntry {
+    pub switch: String,
+    pub vlan: u16,
+    pub mac: String,
+    pub port: String,
+    pub learned: u64
+}
+
+impl SavedMacEntry {
+    fn of(entry: &MacEntry) -> Self {
+        SavedMacEntry { switch: entry.switch.to_hex(), vlan: entry.vlan, mac: entry.mac.to_hex(), port: entry.port.to_hex(), learned: entry.learned }
+    }
+
+    fn to_entry(&self) -> Option<MacEntry> {
+        Some(MacEntry {
+            switch: MAC::from_string(&self.switch)?,
+            vlan: self.vlan,
+            mac: MAC::from_string(&self.mac)?,
+            port: MAC::from_string(&self.port)?,
+            learned: self.learned
+        })
+    }
+}
+
+#[derive(Serialize, Deserialize)]
+pub struct SavedCounter {
+    pub node: String,
+    pub peer: String,
+    pub frames_in: u64,
+    pub bytes_in: u64,
+    pub frames_out: u64,
+    pub bytes_out: u64
+}
+
+impl SavedCounter {
+    fn of(counter: &Counter) -> Self {
+        SavedCounter {
+            node: counter.node.to_hex(),
+            peer: counter.peer.to_hex(),
+            frames_in: counter.frames_in,
+            bytes_in: counter.bytes_in,
+            frames_out: counter.frames_out,
+            bytes_out: counter.bytes_out
+        }
+    }
+
+    fn to_counter(&self) -> Option<Counter> {
+        Some(Counter {
+            node: MAC::from_string(&self.node)?,
+            peer: MAC::from_string(&self.peer)?,
+            frames_in: self.frames_in,
+            bytes_in: self.bytes_in,
+            frames_out: self.frames_out,
+            bytes_out: self.bytes_out
+        })
+    }
+}
+
+// The network is saved as a topology, which already holds MACs and DHCP attributions,
+// completed by the runtime state that a topology does not describe. Routes are not saved: routers only
+// have connected routes, which follow from the addresses of the topology
+#[derive(Serialize, Deserialize)]
+pub struct Snapshot {
+    pub snapshot: u32,
+    pub random: SavedRandom,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub clock: Option<Clock>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub sockets: Option<Vec<SavedSocket>>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub arp: Option<Vec<SavedArpEntry>>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub mac_tables: Option<Vec<SavedMacEntry>>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub counters: Option<Vec<SavedCounter>>,
+    pub network: LoadedData
+}
+
+#[derive(Deserialize)]
+struct SnapshotProbe {
+    snapshot: Option<u32>
+}
+
+pub enum SnapshotError {
+    LoadError(LoadError),
+    NotASnapshot(String),
+    UnsupportedVersion(String, u32),
+    Incomplete(usize),
+    InvalidSocket(String, u16),
+    InvalidEntry(String)
+}
+
+impl std::fmt::Display for SnapshotError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            SnapshotError::LoadError(e) => write!(f, "{}", e),
+            SnapshotError::NotASnapshot(file) => write!(f, "{} is not a snapshot (topologies are loaded with load)", file),
+            SnapshotError::UnsupportedVersion(file, version) => write!(f, "{} uses snapshot version {}, this simulator only reads versions up to {}", file, version, SNAPSHOT_VERSION),
+            SnapshotError::Incomplete(rejected) => write!(f, "Snapshot could not be restored completely ({} entries rejected)", rejected),
+            SnapshotError::InvalidSocket(host, port) => write!(f, "Invalid socket on port {} of {}", port, host),
+            SnapshotError::InvalidEntry(entry) => write!(f, "Invalid {}", entry),
+        }
+    }
+}
+
+impl Snapshot {
+    pub fn take(graph: &Graph) -> Self {
+        let (arp, macs, counters) = graph.tables.entries();
+        Snapshot {
+            snapshot: SNAPSHOT_VERSION,
+            random: SavedRandom { seed: graph.random.seed(), position: graph.random.position().to_string() },
+            clock: Some(graph.clock.clone()),
+            sockets: Some(graph.sockets.list().iter().map(SavedSocket::of).collect::<Vec<_>>()).filter(|s| !s.is_empty()),
+            arp: Some(arp.iter().map(SavedArpEntry::of).collect::<Vec<_>>()).filter(|a| !a.is_empty()),
+            mac_tables: Some(macs.iter().map(SavedMacEntry::of).collect::<Vec<_>>()).filter(|m| !m.is_empty()),
+            counters: Some(counters.iter().map(SavedCounter::of).collect::<Vec<_>>()).filter(|c| !c.is_empty()),
+            network: graph.to_loaded_data()
+        }
+    }
+
+    // A snapshot restores into a new graph, the current one is only replaced on success
+    pub fn restore(self) -> Result<Graph, SnapshotError> {
+        let mut graph = Graph::new();
+        let report = graph.load_data(self.network);
+        let rejected = report.rejected().len();
+        if rejected > 0 {
+            return Err(SnapshotError::Incomplete(rejected));
+        }
+        let position = self.random.position.parse::<u128>()
+            .map_err(|_| SnapshotError::InvalidEntry(format!("random position {}", self.random.position)))?;
+        graph.random = Random::at(self.random.seed, position);
+        if let Some(clock) = self.clock {
+            graph.clock = clock;
+        }
+        for saved in self.sockets.unwrap_or_default() {
+            graph.sockets.insert(saved.to_socket().ok_or(SnapshotError::InvalidSocket(saved.host.clone(), saved.port))?);
+        }
+        // Restoring the topology may have sent frames, the saved tables replace what they taught
+        let arp = self.arp.unwrap_or_default().iter()
+            .map(|e| e.to_entry().ok_or(SnapshotError::InvalidEntry(format!("ARP entry {} of {}", e.ip, e.interface))))
+            .collect::<Result<Vec<_>, _>>()?;
+        let macs = self.mac_tables.unwrap_or_default().iter()
+            .map(|e| e.to_entry().ok_or(SnapshotError::InvalidEntry(format!("MAC table entry {} of {}", e.mac, e.switch))))
+            .collect::<Result<Vec<_>, _>>()?;
+        let counters = self.counters.unwrap_or_default().iter()
+            .map(|c| c.to_counter().ok_or(SnapshotError::InvalidEntry(format!("counter of {}", c.node))))
+            .collect::<Result<Vec<_>, _>>()?;
+        graph.tables = Tables::with(arp, macs, counters);
+        Ok(graph)
+    }
+}
+
+pub fn save_snapshot(file_path: &str, graph: &Graph, format: DataFormat) -> Result<(), SnapshotError> {
+    let content = serialize(&Snapshot::take(graph), format).map_err(SnapshotError::LoadError)?;
+    std::fs::write(file_path, content)
+        .map_err(|e| SnapshotError::LoadError(LoadError::WriteError(file_path.to_string(), e.to_string())))
+}
+
+pub fn read_snapshot(file_path: &str, format: DataFormat) -> Result<Snapshot, SnapshotError> {
+    let content = read_to_string(file_path)
+        .map_err(|_| SnapshotError::LoadError(LoadError::FileNotFound(file_path.to_string())))?;
+    let parse_error = |e: String| SnapshotError::LoadError(LoadError::ParseError(file_path.to_string(), e));
+    let probe: SnapshotProbe = deserialize(&content, format).map_err(parse_error)?;
+    match probe.snapshot {
+        None => Err(SnapshotError::NotASnapshot(file_path.to_string())),
+        Some(SNAPSHOT_VERSION) => deserialize(&content, format).map_err(parse_error),
+        Some(version) => Err(SnapshotError::UnsupportedVersion(file_path.to_string(), version)),
+    }
+}
+
+pub fn restore_snapshot(file_path: &str, format: DataFormat) -> Result<Graph, SnapshotError> {
+    read_snapshot(file_path, format)?.restore()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::clock::advance;
+    use crate::load::parse_data;
+    use crate::transport::{listen, open};
+
+    // PC3 lost its lease and is left without address on a link that DHCP could serve
+    const OFFICE: &str = "
+routers:
+  - name: R1
+    lan: {ip: 192.168.1.1, netmask: 24, dhcp: {first_ip: 192.168.1.10, last_ip: 192.168.1.20}}
+    wan: {ip: 10.0.0.1, netmask: 30, dhcp: {first_ip: 10.0.0.2, last_ip: 10.0.0.2}}
+devices:
+  - name: PC1
+  - name: PC2
+  - {name: PC3, ip: 127.0.0.1}
+switches:
+  - name: SW1
+connections:
+  - {from: R1.lan, to: SW1}
+  - {from: PC1, to: SW1}
+  - {from: PC2, to: SW1}
+  - {from: PC3, to: R1.wan}
+";
+
+    fn office() -> Graph {
+        let mut graph = Graph::new();
+        let report = graph.load_data(parse_data("test", OFFICE, DataFormat::Yaml).unwrap_or_else(|e| panic!("{}", e)));
+        assert!(report.rejected().is_empty());
+        graph
+    }
+
+    fn saved(graph: &Graph, format: DataFormat) -> String {
+        serialize(&Snapshot::take(graph), format).unwrap_or_else(|e| panic!("{}", e))
+    }
+
+    fn restored(content: &str, format: DataFormat) -> Graph {
+        let snapshot: Snapshot = deserialize(content, format).unwrap_or_else(|e| panic!("{}", e));
+        snapshot.restore().unwrap_or_else(|e| panic!("{}", e))
+    }
+
+    #[test]
+    fn a_restored_snapshot_saves_identically() {
+        let mut graph = office();
+        let mac = |graph: &Graph, name: &str| graph.resolve_node(name).unwrap_or_else(|e| panic!("{}", e));
+        let (pc1, pc2) = (mac(&graph, "PC1"), mac(&graph, "PC2"));
+        let server = graph.nic_with_mac(pc2.clone()).map(|nic| nic.ip).unwrap_or_else(|| panic!("no PC2"));
+        listen(&mut graph, pc2, Transport::Tcp, 80).unwrap_or_else(|e| panic!("{}", e));
+        open(&mut graph, pc1, Transport::Tcp, server, 80).unwrap_or_else(|e| panic!("{}", e));
+        advance(&mut graph, 90_000);
+        let (arp, macs, counters) = graph.tables.entries();
+        assert!(!arp.is_empty() && !macs.is_empty() && !counters.is_empty());
+        for format in [DataFormat::Yaml, DataFormat::Json, DataFormat::Toml] {
+            let content = saved(&graph, format);
+            assert!(content == saved(&restored(&content, format), format));
+        }
+    }
+
+    #[test]
+    fn the_random_position_is_kept_beyond_64_bits() {
+        let mut graph = office();
+        // ChaCha positions go up to 2^68 words
+        let position = (1u128 << 66) + 16;
+        graph.random = Random::at(7, position);
+        let content = saved(&graph, DataFormat::Toml);
+        assert_eq!(restored(&content, DataFormat::Toml).random.position(), position);
+    }
+
+    #[test]
+    fn a_device_without_address_stays_without_one() {
+        let graph = office();
+        let restored = restored(&saved(&graph, DataFormat::Yaml), DataFormat::Yaml);
+        let pc3 = restored.resolve_node("PC3").unwrap_or_else(|e| panic!("{}", e));
+        assert!(restored.nic_with_mac(pc3.clone()).is_some_and(|nic| nic.is_localhost()));
+        let wan = restored.resolve("R1.wan").unwrap_or_else(|e| panic!("{}", e));
+        assert!(restored.are_connected(pc3, wan));
+    }
+}
@@ -0,0 +1,199 @@
+use crate::mac::MAC;
+
+
+// A node is designated by the MAC of its first interface (the LAN interface of a router)
+#[derive(Clone)]
+pub enum Element {
+    Link(MAC, MAC),
+    Interface(MAC),
+    Node(MAC)
+}
+
+impl PartialEq for Element {
+    fn eq(&self, other: &Self) -> bool {
+        match (self, other) {
+            (Element::Link(a1, b1), Element::Link(a2, b2)) => (a1 == a2 && b1 == b2) || (a1 == b2 && b1 == a2),
+            (Element::Interface(a), Element::Interface(b)) => a == b,
+            (Element::Node(a), Element::Node(b)) => a == b,
+            _ => false,
+        }
+    }
+}
+
+impl Element {
+    pub fn involves(&self, mac: &MAC) -> bool {
+        match self {
+            Element::Link(a, b) => a == mac || b == mac,
+            Element::Interface(a) | Element::Node(a) => a == mac,
+        }
+    }
+}
+
+#[derive(Clone, Copy, PartialEq)]
+pub enum State {
+    Up,
+    Down
+}
+
+impl std::fmt::Display for State {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            State::Up => write!(f, "up"),
+            State::Down => write!(f, "down"),
+        }
+    }
+}
+
+impl State {
+    pub fn from_up(up: bool) -> Self {
+        if up { State::Up } else { State::Down }
+    }
+}
+
+// Shut down elements are disabled by configuration, failed ones by an injected fault.
+// Both only list what was changed, everything else is up.
+#[derive(Clone)]
+pub struct States {
+    shutdown: Vec<Element>,
+    failed: Vec<Element>
+}
+
+impl Default for States {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl States {
+    pub fn new() -> Self {
+        States { shutdown: Vec::new(), failed: Vec::new() }
+    }
+
+    fn set(list: &mut Vec<Element>, element: Element, value: bool) {
+        list.retain(|e| *e != element);
+        if value {
+            list.push(element);
+        }
+    }
+
+    pub fn set_shutdown(&mut self, element: Element, shutdown: bool) {
+        Self::set(&mut self.shutdown, element, shutdown);
+    }
+
+    pub fn set_failed(&mut self, element: Element, failed: bool) {
+        Self::set(&mut self.failed, element, failed);
+    }
+
+    pub fn is_shutdown(&self, element: &Element) -> bool {
+        self.shutdown.contains(element)
+    }
+
+    pub fn is_failed(&self, element: &Element) -> bool {
+        self.failed.contains(element)
+    }
+
+    pub fn is_down(&self, element: &Element) -> bool {
+        self.is_shutdown(element) || self.is_failed(element)
+    }
+
+    // Drops the state of an element that no longer exists
+    pub fn forget(&mut self, element: &Element) {
+        self.shutdown.retain(|e| e != element);
+        self.failed.retain(|e| e != element);
+    }
+
+    pub fn forget_mac(&mut self, mac: &MAC) {
+        self.shutdown.retain(|e| !e.involves(mac));
+        self.failed.retain(|e| !e.involves(mac));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::commands::{PingStatus, ping};
+    use crate::graph::Graph;
+    use crate::load::{DataFormat, parse_data};
+
+    const OFFICE: &str = "
+routers:
+  - name: R1
+    lan: {ip: 192.168.1.1, netmask: 24}
+    wan: {ip: 10.0.0.1, netmask: 30, shutdown: true}
+devices:
+  - {name: PC1, ip: 192.168.1.10, netmask: 24}
+  - {name: PC2, ip: 192.168.1.11, netmask: 24}
+switches:
+  - name: SW1
+connections:
+  - {from: R1.lan, to: SW1}
+  - {from: PC1, to: SW1}
+  - {from: PC2, to: SW1, failed: true}
+";
+
+    fn office() -> Graph {
+        let mut graph = Graph::new();
+        let report = graph.load_data(parse_data("test", OFFICE, DataFormat::Yaml).unwrap_or_else(|e| panic!("{}", e)));
+        assert!(report.rejected().is_empty());
+        graph
+    }
+
+    fn mac(graph: &Graph, name: &str) -> MAC {
+        graph.resolve(name).unwrap_or_else(|e| panic!("{}", e))
+    }
+
+    fn reaches(graph: &Graph, from: &str, to: &str) -> bool {
+        let nic = graph.nic_with_mac(mac(graph, from)).unwrap_or_else(|| panic!("no {}", from));
+        let ip = graph.nic_with_mac(mac(graph, to)).unwrap_or_else(|| panic!("no {}", to)).ip;
+        matches!(ping(graph, nic, ip), PingStatus::Success)
+    }
+
+    #[test]
+    fn links_are_the_same_both_ways() {
+        let (a, b) = (MAC::EUI48(2, 0, 0, 0, 0, 1), MAC::EUI48(2, 0, 0, 0, 0, 2));
+        let mut states = States::new();
+        states.set_failed(Element::Link(a.clone(), b.clone()), true);
+        assert!(states.is_down(&Element::Link(b.clone(), a.clone())));
+        assert!(!states.is_shutdown(&Element::Link(b.clone(), a.clone())));
+        states.set_shutdown(Element::Link(b.clone(), a.clone()), true);
+        states.set_failed(Element::Link(b.clone(), a.clone()), false);
+        assert!(states.is_down(&Element::Link(a.clone(), b.clone())));
+        states.forget_mac(&a);
+        assert!(!states.is_down(&Element::Link(a, b)));
+    }
+
+    #[test]
+    fn states_are_loaded() {
+        let graph = office();
+        let (pc2, switch, wan) = (mac(&graph, "PC2"), mac(&graph, "SW1"), mac(&graph, "R1.wan"));
+        assert!(graph.is_failed(&Element::Link(switch.clone(), pc2.clone())));
+        assert!(graph.oper_state(&Element::Link(pc2, switch)) == State::Down);
+        assert!(graph.admin_state(&Element::Interface(wan.clone())) == State::Down);
+        assert!(graph.admin_state(&Element::Interface(mac(&graph, "R1.lan"))) == State::Up);
+        assert!(!graph.interface_up(&wan));
+    }
+
+    #[test]
+    fn traffic_only_crosses_what_is_up() {
+        let mut graph = office();
+        assert!(reaches(&graph, "PC1", "R1.lan") && !reaches(&graph, "PC1", "PC2"));
+        graph.set_failed(Element::Link(mac(&graph, "PC2"), mac(&graph, "SW1")), false).unwrap_or_else(|e| panic!("{}", e));
+        assert!(reaches(&graph, "PC1", "PC2"));
+        graph.set_shutdown(Element::Interface(mac(&graph, "PC2")), true).unwrap_or_else(|e| panic!("{}", e));
+        assert!(!reaches(&graph, "PC1", "PC2"));
+        assert!(graph.admin_state(&Element::Link(mac(&graph, "PC2"), mac(&graph, "SW1"))) == State::Up);
+    }
+
+    #[test]
+    fn a_failed_node_takes_its_interfaces_down() {
+        let mut graph = office();
+        let router = graph.resolve_node("R1").unwrap_or_else(|e| panic!("{}", e));
+        graph.set_failed(Element::Node(router.clone()), true).unwrap_or_else(|e| panic!("{}", e));
+        assert!(!graph.interface_up(&mac(&graph, "R1.lan")));
+        assert!(!reaches(&graph, "PC1", "R1.lan"));
+        // The state of the interface itself is untouched
+        assert!(graph.admin_state(&Element::Interface(mac(&graph, "R1.lan"))) == State::Up);
+        graph.set_failed(Element::Node(router), false).unwrap_or_else(|e| panic!("{}", e));
+        assert!(reaches(&graph, "PC1", "R1.lan"));
+    }
+}
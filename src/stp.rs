@@ -0,0 +1,278 @@
+use crate::graph::Graph;
+use crate::mac::MAC;
+use crate::switch::Switch;
+
+
+// Cost of a 100 Mbit/s link
+pub const PORT_COST: u32 = 19;
+
+#[derive(Clone, PartialEq, Eq, PartialOrd, Ord)]
+pub struct BridgeId {
+    pub priority: u16,
+    mac: String
+}
+
+impl std::fmt::Display for BridgeId {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}.{}", self.priority, self.mac)
+    }
+}
+
+impl BridgeId {
+    pub fn of(switch: &Switch) -> Self {
+        BridgeId { priority: switch.priority, mac: switch.mac.to_hex() }
+    }
+}
+
+#[derive(Clone, Copy, PartialEq)]
+pub enum PortRole {
+    Root,
+    Designated,
+    Alternate,
+    Disabled
+}
+
+impl std::fmt::Display for PortRole {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            PortRole::Root => write!(f, "root"),
+            PortRole::Designated => write!(f, "designated"),
+            PortRole::Alternate => write!(f, "alternate"),
+            PortRole::Disabled => write!(f, "disabled"),
+        }
+    }
+}
+
+#[derive(Clone, Copy, PartialEq)]
+pub enum PortState {
+    Forwarding,
+    Blocking,
+    Disabled
+}
+
+impl std::fmt::Display for PortState {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            PortState::Forwarding => write!(f, "forwarding"),
+            PortState::Blocking => write!(f, "blocking"),
+            PortState::Disabled => write!(f, "disabled"),
+        }
+    }
+}
+
+impl PortRole {
+    pub fn state(&self) -> PortState {
+        match self {
+            PortRole::Alternate => PortState::Blocking,
+            PortRole::Disabled => PortState::Disabled,
+            _ => PortState::Forwarding,
+        }
+    }
+}
+
+#[derive(Clone)]
+pub struct Bridge {
+    pub switch: MAC,
+    pub id: BridgeId,
+    pub root: BridgeId,
+    pub root_cost: u32,
+    pub root_port: Option<MAC>
+}
+
+// Ports are designated by the MAC of the node plugged in, as for VLANs
+#[derive(Clone)]
+pub struct SpanningTree {
+    pub bridges: Vec<Bridge>,
+    roles: Vec<(MAC, MAC, PortRole)>
+}
+
+impl SpanningTree {
+    // The state 802.1D converges to over the links currently up, reached at once: no BPDUs, timers, nor listening
+    // and learning states. A single common tree covers every VLAN, as with plain 802.1D, rather than one per VLAN
+    pub fn compute(graph: &Graph) -> Self {
+        let switches = &graph.switches;
+        let neighbors: Vec<Vec<usize>> = switches.iter().map(|s| {
+            graph.neighbors(s.mac.clone()).iter()
+                .filter(|n| graph.link_up(&s.mac, n))
+                .filter_map(|n| switches.iter().position(|other| other.mac == *n))
+                .collect()
+        }).collect();
+
+        // Root election, then distances to the root, one switched domain at a time
+        let mut domain: Vec<Option<usize>> = vec![None; switches.len()];
+        let mut distance: Vec<u32> = vec![0; switches.len()];
+        for start in 0..switches.len() {
+            if domain[start].is_some() {
+                continue;
+            }
+            let mut members: Vec<usize> = vec![start];
+            let mut i = 0;
+            while i < members.len() {
+                for &n in &neighbors[members[i]] {
+                    if !members.contains(&n) {
+                        members.push(n);
+                    }
+                }
+                i += 1;
+            }
+            let root = members.iter().copied().min_by_key(|m| BridgeId::of(&switches[*m])).unwrap_or(start);
+            for &m in &members {
+                domain[m] = Some(root);
+            }
+            let mut queue: Vec<usize> = vec![root];
+            let mut reached: Vec<usize> = vec![root];
+            while !queue.is_empty() {
+                let mut next: Vec<usize> = Vec::new();
+                for current in queue {
+                    for &n in &neighbors[current] {
+                        if !reached.contains(&n) {
+                            reached.push(n);
+                            distance[n] = distance[current] + PORT_COST;
+                            next.push(n);
+                        }
+                    }
+                }
+                queue = next;
+            }
+        }
+
+        // Best path to the root, ties broken by the lowest upstream bridge
+        let priority = |i: usize| (distance[i], BridgeId::of(&switches[i]));
+        let mut root_port: Vec<Option<usize>> = vec![None; switches.len()];
+        for i in 0..switches.len() {
+            if domain[i] == Some(i) {
+                continue;
+            }
+            root_port[i] = neighbors[i].iter().copied()
+                .filter(|n| distance[*n] + PORT_COST == distance[i])
+                .min_by_key(|n| priority(*n));
+        }
+
+        let mut roles: Vec<(MAC, MAC, PortRole)> = Vec::new();
+        for i in 0..switches.len() {
+            for port in graph.neighbors(switches[i].mac.clone()) {
+                if !graph.link_up(&switches[i].mac, &port) {
+                    roles.push((switches[i].mac.clone(), port, PortRole::Disabled));
+                }
+            }
+            for &n in &neighbors[i] {
+                let role = if root_port[i] == Some(n) {
+                    PortRole::Root
+                } else if root_port[n] == Some(i) || priority(i) < priority(n) {
+                    PortRole::Designated
+                } else {
+                    PortRole::Alternate
+                };
+                roles.push((switches[i].mac.clone(), switches[n].mac.clone(), role));
+            }
+        }
+
+        let bridges = switches.iter().enumerate().map(|(i, s)| {
+            let root = domain[i].unwrap_or(i);
+            Bridge {
+                switch: s.mac.clone(),
+                id: BridgeId::of(s),
+                root: BridgeId::of(&switches[root]),
+                root_cost: distance[i],
+                root_port: root_port[i].map(|r| switches[r].mac.clone()),
+            }
+        }).collect();
+        SpanningTree { bridges, roles }
+    }
+
+    pub fn bridge(&self, switch: &MAC) -> Option<&Bridge> {
+        self.bridges.iter().find(|b| b.switch == *switch)
+    }
+
+    // Ports towards hosts and routers are always designated
+    pub fn role(&self, switch: &MAC, port: &MAC) -> PortRole {
+        match self.roles.iter().find(|(s, p, _)| s == switch && p == port) {
+            Some((_, _, role)) => *role,
+            None => PortRole::Designated,
+        }
+    }
+
+    // A link is unused as soon as one of its ends is blocking
+    pub fn blocked(&self, mac1: &MAC, mac2: &MAC) -> bool {
+        self.role(mac1, mac2).state() == PortState::Blocking || self.role(mac2, mac1).state() == PortState::Blocking
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::load::{DataFormat, parse_data};
+    use crate::state::Element;
+
+    // SW1 and SW2 both reach CORE directly, and are linked together as a backup
+    const TRIANGLE: &str = "
+switches:
+  - name: CORE
+    priority: 4096
+  - name: SW1
+  - name: SW2
+connections:
+  - {from: CORE, to: SW1}
+  - {from: CORE, to: SW2}
+  - {from: SW1, to: SW2}
+";
+
+    fn triangle() -> (Graph, MAC, MAC, MAC) {
+        let mut graph = Graph::new();
+        let report = graph.load_data(parse_data("test", TRIANGLE, DataFormat::Yaml).unwrap_or_else(|e| panic!("{}", e)));
+        assert!(report.rejected().is_empty());
+        let mac = |name: &str| graph.resolve_node(name).unwrap_or_else(|e| panic!("{}", e));
+        let (core, sw1, sw2) = (mac("CORE"), mac("SW1"), mac("SW2"));
+        (graph, core, sw1, sw2)
+    }
+
+    #[test]
+    fn the_lowest_priority_is_elected_root() {
+        let (graph, core, sw1, sw2) = triangle();
+        let tree = graph.spanning_tree();
+        for switch in [&core, &sw1, &sw2] {
+            let bridge = tree.bridge(switch).unwrap_or_else(|| panic!("no bridge"));
+            assert_eq!(bridge.root.priority, 4096);
+        }
+        let root = tree.bridge(&core).unwrap_or_else(|| panic!("no bridge"));
+        assert!(root.root_port.is_none());
+        assert_eq!(root.root_cost, 0);
+        assert!(tree.role(&core, &sw1) == PortRole::Designated && tree.role(&core, &sw2) == PortRole::Designated);
+    }
+
+    #[test]
+    fn the_loop_is_broken_on_the_higher_bridge() {
+        let (graph, core, sw1, sw2) = triangle();
+        let tree = graph.spanning_tree();
+        assert!(tree.role(&sw1, &core) == PortRole::Root && tree.role(&sw2, &core) == PortRole::Root);
+        assert_eq!(tree.bridge(&sw1).map(|b| b.root_cost), Some(PORT_COST));
+        // Equal costs, so the lower bridge ID keeps its port designated
+        let (low, high) = match BridgeId::of(&graph.switches[1]) < BridgeId::of(&graph.switches[2]) {
+            true => (&sw1, &sw2),
+            false => (&sw2, &sw1),
+        };
+        assert!(tree.role(low, high) == PortRole::Designated);
+        assert!(tree.role(high, low) == PortRole::Alternate);
+        assert!(tree.blocked(&sw1, &sw2) && !tree.blocked(&core, &sw1));
+    }
+
+    #[test]
+    fn a_failed_link_unblocks_its_backup() {
+        let (mut graph, core, sw1, sw2) = triangle();
+        assert!(graph.spanning_tree().blocked(&sw1, &sw2));
+        graph.set_failed(Element::Link(core.clone(), sw2.clone()), true).unwrap_or_else(|e| panic!("{}", e));
+        let tree = graph.spanning_tree();
+        assert!(!tree.blocked(&sw1, &sw2));
+        assert!(tree.role(&sw2, &sw1) == PortRole::Root && tree.role(&sw2, &core) == PortRole::Disabled);
+        assert_eq!(tree.bridge(&sw2).map(|b| b.root_cost), Some(2 * PORT_COST));
+    }
+
+    #[test]
+    fn a_new_priority_moves_the_root() {
+        let (mut graph, core, sw1, _) = triangle();
+        graph.set_bridge_priority(sw1.clone(), 0).unwrap_or_else(|e| panic!("{}", e));
+        let tree = graph.spanning_tree();
+        assert!(tree.bridge(&sw1).is_some_and(|b| b.root_port.is_none() && b.root.priority == 0));
+        assert!(tree.role(&core, &sw1) == PortRole::Root);
+    }
+}
@@ -0,0 +1,226 @@
+use crate::graph::{Graph, GraphError, NodeType};
+use crate::mac::MAC;
+
+
+pub const DEFAULT_PORTS: usize = 24;
+pub const DEFAULT_VLAN: u16 = 1;
+pub const DEFAULT_PRIORITY: u16 = 32768;
+
+// Bridge priorities go by steps of 4096, the VLAN bits of the extended bridge ID being unused
+pub fn valid_priority(priority: u16) -> bool {
+    priority.is_multiple_of(4096)
+}
+
+pub fn valid_vlan(vlan: u16) -> bool {
+    (1..=4094).contains(&vlan)
+}
+
+// Trunks carry the default VLAN untagged, as the native VLAN, and the allowed ones tagged
+#[derive(Clone, PartialEq)]
+pub enum PortMode {
+    Access(u16),
+    Trunk(Vec<u16>)
+}
+
+impl std::fmt::Display for PortMode {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            PortMode::Access(vlan) => write!(f, "access vlan {}", vlan),
+            PortMode::Trunk(vlans) => write!(f, "trunk {}", vlans.iter().map(u16::to_string).collect::<Vec<String>>().join(",")),
+        }
+    }
+}
+
+impl PortMode {
+    // VLAN of a frame entering the switch through this port, None when it is dropped
+    pub fn ingress(&self, tag: Option<u16>) -> Option<u16> {
+        match (self, tag) {
+            (PortMode::Access(vlan), None) => Some(*vlan),
+            (PortMode::Access(_), Some(_)) => None,
+            (PortMode::Trunk(_), None) => Some(DEFAULT_VLAN),
+            (PortMode::Trunk(vlans), Some(tag)) => vlans.contains(&tag).then_some(tag),
+        }
+    }
+
+    // Tag of a frame of `vlan` leaving through this port, None when it is not forwarded
+    pub fn egress(&self, vlan: u16) -> Option<Option<u16>> {
+        match self {
+            PortMode::Access(access) => (*access == vlan).then_some(None),
+            PortMode::Trunk(_) if vlan == DEFAULT_VLAN => Some(None),
+            PortMode::Trunk(vlans) => vlans.contains(&vlan).then_some(Some(vlan)),
+        }
+    }
+
+    // Parses "10,20,30"
+    pub fn trunk_from_string(s: &str) -> Option<Self> {
+        let mut vlans: Vec<u16> = Vec::new();
+        for part in s.split(',') {
+            match part.trim().parse::<u16>() {
+                Ok(vlan) if valid_vlan(vlan) => vlans.push(vlan),
+                _ => return None,
+            }
+        }
+        Some(PortMode::Trunk(vlans))
+    }
+}
+
+#[derive(Clone)]
+pub struct Switch {
+    pub name: String,
+    pub mac: MAC,
+    pub ports: usize,
+    // Spanning tree priority, the lowest bridge becomes the root
+    pub priority: u16,
+    // Ports are designated by the MAC of the node plugged in, ports left out are access ports of the default VLAN
+    pub port_modes: Vec<(MAC, PortMode)>
+}
+
+impl std::fmt::Display for Switch {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "SWITCH {} [{} ports - {}]", self.name, self.ports, self.mac)
+    }
+}
+
+impl Switch {
+    pub fn new(name: String, mac: MAC, ports: usize) -> Self {
+        Self {
+            name,
+            mac,
+            ports,
+            priority: DEFAULT_PRIORITY,
+            port_modes: Vec::new()
+        }
+    }
+
+    pub fn port_mode(&self, port: &MAC) -> PortMode {
+        match self.port_modes.iter().find(|(mac, _)| mac == port) {
+            Some((_, mode)) => mode.clone(),
+            None => PortMode::Access(DEFAULT_VLAN),
+        }
+    }
+
+    pub fn set_port_mode(&mut self, port: MAC, mode: PortMode) {
+        self.port_modes.retain(|(mac, _)| *mac != port);
+        if mode != PortMode::Access(DEFAULT_VLAN) {
+            self.port_modes.push((port, mode));
+        }
+    }
+}
+
+pub fn create_switch(name: String, graph: &mut Graph) -> Result<(), GraphError> {
+    let switch = Switch::new(name, graph.new_mac(&NodeType::Switch), DEFAULT_PORTS);
+    graph.append_switch(switch)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::commands::{PingStatus, ping};
+    use crate::load::{DataFormat, parse_data};
+
+    const STICK: &str = "
+routers:
+  - name: R1
+    lan:
+      ip: 192.168.1.1
+      netmask: 24
+      subinterfaces:
+        - {vlan: 10, ip: 192.168.10.1, netmask: 24}
+        - {vlan: 20, ip: 192.168.20.1, netmask: 24}
+    wan: {ip: 10.0.0.1, netmask: 30}
+devices:
+  - {name: PC1, ip: 192.168.10.10, netmask: 24}
+  - {name: PC2, ip: 192.168.20.10, netmask: 24}
+  - {name: PC3, ip: 192.168.10.11, netmask: 24}
+switches:
+  - name: SW1
+    vlans:
+      - {port: R1.lan, trunk: [10, 20]}
+      - {port: PC1, access: 10}
+      - {port: PC2, access: 20}
+      - {port: PC3, access: 10}
+connections:
+  - {from: R1.lan, to: SW1}
+  - {from: PC1, to: SW1}
+  - {from: PC2, to: SW1}
+  - {from: PC3, to: SW1}
+";
+
+    fn stick() -> Graph {
+        let mut graph = Graph::new();
+        let report = graph.load_data(parse_data("test", STICK, DataFormat::Yaml).unwrap_or_else(|e| panic!("{}", e)));
+        assert!(report.rejected().is_empty());
+        graph
+    }
+
+    fn mac(graph: &Graph, name: &str) -> MAC {
+        graph.resolve(name).unwrap_or_else(|e| panic!("{}", e))
+    }
+
+    fn reaches(graph: &Graph, from: &str, to: &str) -> bool {
+        let nic = graph.nic_with_mac(mac(graph, from)).unwrap_or_else(|| panic!("no {}", from));
+        let ip = graph.nic_with_mac(mac(graph, to)).unwrap_or_else(|| panic!("no {}", to)).ip;
+        matches!(ping(graph, nic, ip), PingStatus::Success)
+    }
+
+    #[test]
+    fn access_ports_drop_tagged_frames() {
+        let access = PortMode::Access(10);
+        assert_eq!(access.ingress(None), Some(10));
+        assert_eq!(access.ingress(Some(10)), None);
+        assert_eq!(access.egress(10), Some(None));
+        assert_eq!(access.egress(20), None);
+    }
+
+    #[test]
+    fn trunks_carry_the_native_vlan_untagged() {
+        let trunk = PortMode::Trunk(vec![10, 20]);
+        assert_eq!(trunk.ingress(None), Some(DEFAULT_VLAN));
+        assert_eq!(trunk.ingress(Some(20)), Some(20));
+        assert_eq!(trunk.ingress(Some(30)), None);
+        assert_eq!(trunk.egress(DEFAULT_VLAN), Some(None));
+        assert_eq!(trunk.egress(10), Some(Some(10)));
+        assert_eq!(trunk.egress(30), None);
+    }
+
+    #[test]
+    fn trunks_parse_from_a_list() {
+        assert!(PortMode::trunk_from_string("10, 20,30") == Some(PortMode::Trunk(vec![10, 20, 30])));
+        for invalid in ["", "10,", "0", "4095", "ten"] {
+            assert!(PortMode::trunk_from_string(invalid).is_none(), "{}", invalid);
+        }
+        assert!(valid_priority(0) && valid_priority(61440) && !valid_priority(1000));
+    }
+
+    #[test]
+    fn default_ports_are_not_stored() {
+        let mut switch = Switch::new("SW1".to_string(), MAC::EUI48(2, 0, 0, 0, 0, 1), DEFAULT_PORTS);
+        let port = MAC::EUI48(2, 0, 0, 0, 0, 2);
+        switch.set_port_mode(port.clone(), PortMode::Access(10));
+        assert!(switch.port_mode(&port) == PortMode::Access(10));
+        switch.set_port_mode(port.clone(), PortMode::Access(DEFAULT_VLAN));
+        assert!(switch.port_modes.is_empty());
+    }
+
+    #[test]
+    fn vlans_are_joined_through_a_router() {
+        let mut graph = stick();
+        assert!(reaches(&graph, "PC1", "PC3"));
+        assert!(reaches(&graph, "PC1", "PC2"));
+        assert!(reaches(&graph, "PC2", "R1.lan.20"));
+        // Without its trunk, the router only sees the native VLAN
+        let (switch, router) = (mac(&graph, "SW1"), mac(&graph, "R1.lan"));
+        graph.set_port_mode(switch, router, PortMode::Access(DEFAULT_VLAN)).unwrap_or_else(|e| panic!("{}", e));
+        assert!(!reaches(&graph, "PC1", "PC2"));
+        assert!(reaches(&graph, "PC1", "PC3"));
+    }
+
+    #[test]
+    fn ports_only_join_vlans_matching_their_address() {
+        let mut graph = stick();
+        let (switch, pc1) = (mac(&graph, "SW1"), mac(&graph, "PC1"));
+        assert!(matches!(graph.set_port_mode(switch.clone(), pc1.clone(), PortMode::Access(5000)), Err(GraphError::InvalidVlan(5000))));
+        assert!(graph.set_port_mode(switch.clone(), pc1.clone(), PortMode::Access(20)).is_err());
+        assert!(graph.switches[0].port_mode(&pc1) == PortMode::Access(10));
+    }
+}
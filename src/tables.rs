@@ -0,0 +1,256 @@
+use std::cell::RefCell;
+
+use crate::graph::Graph;
+use crate::ip::IP;
+use crate::mac::MAC;
+use crate::nic::NIC;
+use crate::packet::{BROADCAST, ETHERTYPE_ARP};
+use crate::traffic::Transmission;
+
+
+// Entries are forgotten after this many milliseconds without being refreshed, the defaults of common equipment
+pub const ARP_TIMEOUT: u64 = 4 * 3600 * 1000;
+pub const MAC_TIMEOUT: u64 = 300 * 1000;
+
+// `interface` maps `ip` to `mac`, `learned` being the time of the last frame confirming it
+#[derive(Clone)]
+pub struct ArpEntry {
+    pub interface: MAC,
+    pub ip: IP,
+    pub mac: MAC,
+    pub learned: u64
+}
+
+// Ports are designated by the MAC of the node plugged in, as for VLANs
+#[derive(Clone)]
+pub struct MacEntry {
+    pub switch: MAC,
+    pub vlan: u16,
+    pub mac: MAC,
+    pub port: MAC,
+    pub learned: u64
+}
+
+// The frames of the cable between `node` and `peer`, as seen from `node`
+#[derive(Clone)]
+pub struct Counter {
+    pub node: MAC,
+    pub peer: MAC,
+    pub frames_in: u64,
+    pub bytes_in: u64,
+    pub frames_out: u64,
+    pub bytes_out: u64
+}
+
+impl Counter {
+    fn new(node: MAC, peer: MAC) -> Self {
+        Counter { node, peer, frames_in: 0, bytes_in: 0, frames_out: 0, bytes_out: 0 }
+    }
+}
+
+// What the nodes learn from the frames they exchange. Like captures and the trace, the tables are fed
+// while the traffic is observed and do not change the network itself
+#[derive(Clone, Default)]
+pub struct Tables {
+    arp: RefCell<Vec<ArpEntry>>,
+    macs: RefCell<Vec<MacEntry>>,
+    counters: RefCell<Vec<Counter>>
+}
+
+impl Tables {
+    pub fn new() -> Self {
+        Tables::default()
+    }
+
+    pub fn with(arp: Vec<ArpEntry>, macs: Vec<MacEntry>, counters: Vec<Counter>) -> Self {
+        Tables { arp: RefCell::new(arp), macs: RefCell::new(macs), counters: RefCell::new(counters) }
+    }
+
+    // `start` in microseconds of simulated time, the transmissions being offset from it
+    pub fn learn(&self, graph: &Graph, start: u64, transmissions: &[Transmission]) {
+        for transmission in transmissions {
+            let time = (start + transmission.offset) / 1000;
+            let bytes = transmission.frame.encode(transmission.tag).len() as u64;
+            self.count(&transmission.from, &transmission.to, |c| {
+                c.frames_out += 1;
+                c.bytes_out += bytes;
+            });
+            self.count(&transmission.to, &transmission.from, |c| {
+                c.frames_in += 1;
+                c.bytes_in += bytes;
+            });
+            match graph.switches.iter().find(|s| s.mac == transmission.to) {
+                Some(switch) => {
+                    if let Some(vlan) = switch.port_mode(&transmission.from).ingress(transmission.tag) {
+                        self.learn_mac(MacEntry {
+                            switch: switch.mac.clone(), vlan, mac: transmission.frame.source.clone(), port: transmission.from.clone(), learned: time
+                        });
+                    }
+                },
+                None => {
+                    if let Some(nic) = receiver(graph, &transmission.to, transmission.tag) {
+                        self.learn_arp(&nic, transmission, time);
+                    }
+                },
+            }
+        }
+    }
+
+    fn count(&self, node: &MAC, peer: &MAC, update: impl FnOnce(&mut Counter)) {
+        let mut counters = self.counters.borrow_mut();
+        match counters.iter_mut().find(|c| c.node == *node && c.peer == *peer) {
+            Some(counter) => update(counter),
+            None => {
+                let mut counter = Counter::new(node.clone(), peer.clone());
+                update(&mut counter);
+                counters.push(counter);
+            },
+        }
+    }
+
+    fn learn_mac(&self, entry: MacEntry) {
+        if entry.mac == BROADCAST {
+            return;
+        }
+        let mut macs = self.macs.borrow_mut();
+        macs.retain(|e| !(e.switch == entry.switch && e.vlan == entry.vlan && e.mac == entry.mac) && entry.learned < e.learned + MAC_TIMEOUT);
+        macs.push(entry);
+    }
+
+    // An interface learns the sender of the ARP requests and replies aimed at its address
+    fn learn_arp(&self, nic: &NIC, transmission: &Transmission, time: u64) {
+        let frame = &transmission.frame;
+        if frame.ethertype != ETHERTYPE_ARP || frame.payload.len() < 28 || (frame.destination != nic.mac && frame.destination != BROADCAST) {
+            return;
+        }
+        let p = &frame.payload;
+        let target = IP::V4(p[24], p[25], p[26], p[27]);
+        if target != nic.ip {
+            return;
+        }
+        let entry = ArpEntry {
+            interface: nic.mac.clone(),
+            ip: IP::V4(p[14], p[15], p[16], p[17]),
+            mac: MAC::EUI48(p[8], p[9], p[10], p[11], p[12], p[13]),
+            learned: time
+        };
+        let mut arp = self.arp.borrow_mut();
+        arp.retain(|e| !(e.interface == entry.interface && e.ip == entry.ip) && entry.learned < e.learned + ARP_TIMEOUT);
+        arp.push(entry);
+    }
+
+    // Whether `interface` still knows `ip` as `mac` at `now`, in milliseconds
+    pub fn resolved(&self, interface: &MAC, ip: &IP, mac: &MAC, now: u64) -> bool {
+        self.arp.borrow().iter().any(|e| e.interface == *interface && e.ip == *ip && e.mac == *mac && now < e.learned + ARP_TIMEOUT)
+    }
+
+    pub fn arp(&self, interface: &MAC, now: u64) -> Vec<ArpEntry> {
+        self.arp.borrow().iter().filter(|e| e.interface == *interface && now < e.learned + ARP_TIMEOUT).cloned().collect()
+    }
+
+    pub fn mac_table(&self, switch: &MAC, now: u64) -> Vec<MacEntry> {
+        self.macs.borrow().iter().filter(|e| e.switch == *switch && now < e.learned + MAC_TIMEOUT).cloned().collect()
+    }
+
+    pub fn counter(&self, node: &MAC, peer: &MAC) -> Counter {
+        self.counters.borrow().iter().find(|c| c.node == *node && c.peer == *peer).cloned()
+            .unwrap_or(Counter::new(node.clone(), peer.clone()))
+    }
+
+    // Every entry, expired ones included, for snapshots
+    pub fn entries(&self) -> (Vec<ArpEntry>, Vec<MacEntry>, Vec<Counter>) {
+        (self.arp.borrow().clone(), self.macs.borrow().clone(), self.counters.borrow().clone())
+    }
+}
+
+// The interface a frame reaches through the port `port`, a router subinterface when it is tagged
+fn receiver(graph: &Graph, port: &MAC, tag: Option<u16>) -> Option<NIC> {
+    if let Some(vlan) = tag
+        && let Some((router, interface)) = graph.search_router_with_mac(port.clone()) {
+        return router.subinterface(&interface, vlan).map(|s| s.nic.clone());
+    }
+    graph.nic_with_mac(port.clone())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::commands::ping;
+    use crate::load::{DataFormat, parse_data};
+    use crate::traffic;
+
+    const OFFICE: &str = "
+routers:
+  - name: R1
+    lan: {ip: 192.168.1.1, netmask: 24}
+    wan: {ip: 10.0.0.1, netmask: 30}
+devices:
+  - {name: PC1, ip: 192.168.1.10, netmask: 24}
+  - {name: PC2, ip: 192.168.1.11, netmask: 24}
+switches:
+  - name: SW1
+connections:
+  - {from: R1.lan, to: SW1}
+  - {from: PC1, to: SW1}
+  - {from: PC2, to: SW1}
+";
+
+    fn office() -> (Graph, NIC, NIC, MAC) {
+        let mut graph = Graph::new();
+        let report = graph.load_data(parse_data("test", OFFICE, DataFormat::Yaml).unwrap_or_else(|e| panic!("{}", e)));
+        assert!(report.rejected().is_empty());
+        let nic = |name: &str| graph.resolve_node(name).ok().and_then(|mac| graph.nic_with_mac(mac)).unwrap_or_else(|| panic!("no {}", name));
+        let (pc1, pc2) = (nic("PC1"), nic("PC2"));
+        let switch = graph.resolve_node("SW1").unwrap_or_else(|e| panic!("{}", e));
+        (graph, pc1, pc2, switch)
+    }
+
+    fn arp_frames(transmissions: &[Transmission]) -> usize {
+        transmissions.iter().filter(|t| t.frame.ethertype == ETHERTYPE_ARP).count()
+    }
+
+    #[test]
+    fn both_ends_of_a_resolution_learn_each_other() {
+        let (graph, pc1, pc2, _) = office();
+        ping(&graph, pc1.clone(), pc2.ip.clone());
+        let learned = graph.tables.arp(&pc1.mac, 0);
+        assert!(learned.len() == 1 && learned[0].ip == pc2.ip && learned[0].mac == pc2.mac);
+        assert!(graph.tables.resolved(&pc2.mac, &pc1.ip, &pc1.mac, 0));
+        assert!(!graph.tables.resolved(&pc1.mac, &pc2.ip, &pc2.mac, ARP_TIMEOUT));
+    }
+
+    #[test]
+    fn a_known_address_is_not_resolved_again() {
+        let (graph, pc1, pc2, _) = office();
+        // The request is flooded to R1 and PC2, the reply comes back through the switch
+        assert_eq!(arp_frames(&traffic::ping(&graph, &pc1, &pc2.ip)), 5);
+        ping(&graph, pc1.clone(), pc2.ip.clone());
+        assert_eq!(arp_frames(&traffic::ping(&graph, &pc1, &pc2.ip)), 0);
+        assert_eq!(arp_frames(&traffic::ping(&graph, &pc2, &pc1.ip)), 0);
+    }
+
+    #[test]
+    fn switches_learn_the_port_of_every_source() {
+        let (graph, pc1, pc2, switch) = office();
+        ping(&graph, pc1.clone(), pc2.ip.clone());
+        let table = graph.tables.mac_table(&switch, 0);
+        assert_eq!(table.len(), 2);
+        for nic in [&pc1, &pc2] {
+            assert!(table.iter().any(|e| e.mac == nic.mac && e.port == nic.mac && e.vlan == 1));
+        }
+        assert!(graph.tables.mac_table(&switch, MAC_TIMEOUT).is_empty());
+    }
+
+    #[test]
+    fn both_ends_of_a_cable_count_its_frames() {
+        let (graph, pc1, pc2, switch) = office();
+        ping(&graph, pc1.clone(), pc2.ip.clone());
+        let sent = graph.tables.counter(&pc1.mac, &switch);
+        let received = graph.tables.counter(&switch, &pc1.mac);
+        // Request and echo request out, reply and echo reply in
+        assert!(sent.frames_out == 2 && sent.frames_in == 2);
+        assert!(received.frames_in == sent.frames_out && received.bytes_in == sent.bytes_out);
+        // An ARP request padded to the minimum frame size, and an echo request of 32 bytes
+        assert_eq!(sent.bytes_out, 60 + 74);
+    }
+}
@@ -0,0 +1,444 @@
+use std::net::Ipv6Addr;
+use std::rc::Rc;
+
+use crate::colors::Colors;
+use crate::graph::Graph;
+use crate::ip::IP;
+use crate::mac::MAC;
+use crate::packet::{ARP_REQUEST, DHCP_CLIENT_PORT, DHCP_SERVER_PORT, DNS_NXDOMAIN, DNS_PORT, DNS_TYPE_A, DNS_TYPE_AAAA, DNS_TYPE_CNAME, DNS_TYPE_PTR, ETHERTYPE_ARP, ETHERTYPE_IPV4, ICMP_ECHO_REPLY, ICMP_ECHO_REQUEST, ICMP_PORT_UNREACHABLE, ICMP_TIME_EXCEEDED, ICMP_UNREACHABLE, PROTOCOL_ICMP, PROTOCOL_TCP, PROTOCOL_UDP, TCP_ACK, TCP_FIN, TCP_PSH, TCP_RST, TCP_SYN};
+use crate::traffic::Transmission;
+
+
+#[derive(Clone, Copy, PartialEq)]
+pub enum Protocol {
+    Arp,
+    Ip,
+    Icmp,
+    Tcp,
+    Udp,
+    Dhcp,
+    Dns
+}
+
+impl std::fmt::Display for Protocol {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Protocol::Arp => write!(f, "arp"),
+            Protocol::Ip => write!(f, "ip"),
+            Protocol::Icmp => write!(f, "icmp"),
+            Protocol::Tcp => write!(f, "tcp"),
+            Protocol::Udp => write!(f, "udp"),
+            Protocol::Dhcp => write!(f, "dhcp"),
+            Protocol::Dns => write!(f, "dns"),
+        }
+    }
+}
+
+impl Protocol {
+    pub fn from_string(s: &str) -> Option<Self> {
+        match s.to_lowercase().as_str() {
+            "arp" => Some(Protocol::Arp),
+            "ip" => Some(Protocol::Ip),
+            "icmp" => Some(Protocol::Icmp),
+            "tcp" => Some(Protocol::Tcp),
+            "udp" => Some(Protocol::Udp),
+            "dhcp" => Some(Protocol::Dhcp),
+            "dns" => Some(Protocol::Dns),
+            _ => None,
+        }
+    }
+
+    // A filter on a protocol also lets through the ones it carries
+    fn matches(&self, decoded: Protocol) -> bool {
+        match self {
+            Protocol::Ip => decoded != Protocol::Arp,
+            Protocol::Udp => matches!(decoded, Protocol::Udp | Protocol::Dhcp | Protocol::Dns),
+            _ => *self == decoded,
+        }
+    }
+
+    fn color(&self) -> &'static str {
+        match self {
+            Protocol::Arp => Colors::YELLOW,
+            Protocol::Ip => Colors::WHITE,
+            Protocol::Icmp => Colors::CYAN,
+            Protocol::Tcp => Colors::BLUE,
+            Protocol::Udp => Colors::MAGENTA,
+            Protocol::Dhcp => Colors::GREEN,
+            Protocol::Dns => Colors::BRIGHT_MAGENTA,
+        }
+    }
+}
+
+// Filters left empty let everything through, the others must all match
+#[derive(Clone, Default)]
+pub struct TraceFilter {
+    pub node: Option<MAC>,
+    pub protocol: Option<Protocol>,
+    pub ip: Option<IP>
+}
+
+#[derive(Clone, Copy, PartialEq)]
+pub enum Direction {
+    Out,
+    In
+}
+
+impl std::fmt::Display for Direction {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Direction::Out => write!(f, ">"),
+            Direction::In => write!(f, "<"),
+        }
+    }
+}
+
+// `at` in microseconds of simulated time
+#[derive(Clone)]
+pub struct TraceLine {
+    pub at: u64,
+    pub interface: String,
+    pub direction: Direction,
+    pub protocol: Protocol,
+    pub summary: String
+}
+
+impl std::fmt::Display for TraceLine {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}{:>6}.{:06}{} {}{}{} {} {}{}{}", Colors::DIM, self.at / 1_000_000, self.at % 1_000_000, Colors::RESET,
+            Colors::BOLD, self.interface, Colors::RESET, self.direction, self.protocol.color(), self.summary, Colors::RESET)
+    }
+}
+
+fn ip(bytes: &[u8]) -> IP {
+    IP::V4(bytes[0], bytes[1], bytes[2], bytes[3])
+}
+
+fn mac(bytes: &[u8]) -> MAC {
+    MAC::EUI48(bytes[0], bytes[1], bytes[2], bytes[3], bytes[4], bytes[5])
+}
+
+fn u16_at(bytes: &[u8], idx: usize) -> u16 {
+    u16::from_be_bytes([bytes[idx], bytes[idx + 1]])
+}
+
+fn dns_type(kind: u16) -> String {
+    match kind {
+        DNS_TYPE_A => "A".to_string(),
+        DNS_TYPE_CNAME => "CNAME".to_string(),
+        DNS_TYPE_PTR => "PTR".to_string(),
+        DNS_TYPE_AAAA => "AAAA".to_string(),
+        kind => format!("type {}", kind),
+    }
+}
+
+// The labels of the name at `idx` and the index following it, a compression pointer ending the name
+fn dns_name_at(message: &[u8], mut idx: usize) -> Option<(String, usize)> {
+    let mut labels: Vec<String> = Vec::new();
+    loop {
+        let length = *message.get(idx)? as usize;
+        idx += 1;
+        if length == 0 {
+            break;
+        }
+        if length >= 0xC0 {
+            idx += 1;
+            break;
+        }
+        labels.push(String::from_utf8_lossy(message.get(idx..idx + length)?).to_string());
+        idx += length;
+    }
+    Some((format!("{}.", labels.join(".")), idx))
+}
+
+// As tcpdump shows it: the question of a query, the answers of a response
+fn dns_summary(message: &[u8]) -> Option<String> {
+    let header = message.get(..12)?;
+    let (id, flags, answers) = (u16_at(header, 0), u16_at(header, 2), u16_at(header, 6));
+    let (name, idx) = dns_name_at(message, 12)?;
+    let question = message.get(idx..idx + 4)?;
+    if flags & 0x8000 == 0 {
+        return Some(format!("{}+ {}? {}", id, dns_type(u16_at(question, 0)), name));
+    }
+    if (flags & 0x000F) as u8 == DNS_NXDOMAIN {
+        return Some(format!("{}* NXDomain {}", id, name));
+    }
+    let mut records: Vec<String> = Vec::new();
+    let mut idx = idx + 4;
+    for _ in 0..answers {
+        let (_, next) = dns_name_at(message, idx)?;
+        let fields = message.get(next..next + 10)?;
+        let (kind, length) = (u16_at(fields, 0), u16_at(fields, 8) as usize);
+        let data = message.get(next + 10..next + 10 + length)?;
+        let value = match (kind, data.len()) {
+            (DNS_TYPE_A, 4) => ip(data).to_ddn(),
+            (DNS_TYPE_AAAA, 16) => Ipv6Addr::from(<[u8; 16]>::try_from(data).ok()?).to_string(),
+            _ => dns_name_at(data, 0).map(|(name, _)| name).unwrap_or_default(),
+        };
+        records.push(format!("{} {}", dns_type(kind), value));
+        idx = next + 10 + length;
+    }
+    Some(format!("{}* {}/0/0 {}", id, answers, records.join(", ")).trim_end().to_string())
+}
+
+// Decodes what the frame carries, with the IP addresses it mentions
+fn decode(ethertype: u16, payload: &[u8]) -> Option<(Protocol, String, Vec<IP>)> {
+    match ethertype {
+        ETHERTYPE_ARP if payload.len() >= 28 => {
+            let (sender, target) = (ip(&payload[14..18]), ip(&payload[24..28]));
+            let summary = match u16_at(payload, 6) {
+                ARP_REQUEST => format!("ARP who-has {} tell {}", target.to_ddn(), sender.to_ddn()),
+                _ => format!("ARP reply {} is-at {}", sender.to_ddn(), mac(&payload[8..14]).to_hex()),
+            };
+            Some((Protocol::Arp, summary, vec![sender, target]))
+        },
+        ETHERTYPE_IPV4 if payload.len() >= 20 => {
+            let length = ((payload[0] & 0x0F) as usize * 4).min(payload.len());
+            let (ttl, protocol) = (payload[8], payload[9]);
+            let (source, destination) = (ip(&payload[12..16]), ip(&payload[16..20]));
+            let data = &payload[length..];
+            let ips = vec![source.clone(), destination.clone()];
+            let (decoded, summary) = match protocol {
+                PROTOCOL_ICMP if data.len() >= 8 => {
+                    let what = match (data[0], data[1]) {
+                        (ICMP_ECHO_REQUEST, _) => format!("echo request, id {}, seq {}", u16_at(data, 4), u16_at(data, 6)),
+                        (ICMP_ECHO_REPLY, _) => format!("echo reply, id {}, seq {}", u16_at(data, 4), u16_at(data, 6)),
+                        (ICMP_TIME_EXCEEDED, _) => "time exceeded in-transit".to_string(),
+                        (ICMP_UNREACHABLE, ICMP_PORT_UNREACHABLE) if data.len() >= 8 + 24 => {
+                            format!("{} udp port {} unreachable", ip(&data[8 + 16..8 + 20]).to_ddn(), u16_at(data, 8 + 22))
+                        },
+                        (kind, code) => format!("type {}, code {}", kind, code),
+                    };
+                    (Protocol::Icmp, format!("IP {} > {}: ICMP {}, ttl {}", source.to_ddn(), destination.to_ddn(), what, ttl))
+                },
+                PROTOCOL_TCP if data.len() >= 20 => {
+                    let offset = ((data[12] >> 4) as usize * 4).min(data.len());
+                    let flags = data[13];
+                    let mut names: String = [(TCP_SYN, 'S'), (TCP_FIN, 'F'), (TCP_RST, 'R'), (TCP_PSH, 'P'), (TCP_ACK, '.')].iter()
+                        .filter(|(flag, _)| flags & flag != 0)
+                        .map(|(_, name)| *name)
+                        .collect();
+                    if names.is_empty() {
+                        names.push_str("none");
+                    }
+                    let sequence = u32::from_be_bytes([data[4], data[5], data[6], data[7]]);
+                    let acknowledgment = u32::from_be_bytes([data[8], data[9], data[10], data[11]]);
+                    let ack = match flags & TCP_ACK {
+                        0 => String::new(),
+                        _ => format!(", ack {}", acknowledgment),
+                    };
+                    (Protocol::Tcp, format!("IP {}.{} > {}.{}: Flags [{}], seq {}{}, win {}, length {}", source.to_ddn(), u16_at(data, 0),
+                        destination.to_ddn(), u16_at(data, 2), names, sequence, ack, u16_at(data, 14), data.len() - offset))
+                },
+                PROTOCOL_UDP if data.len() >= 8 => {
+                    let (sport, dport) = (u16_at(data, 0), u16_at(data, 2));
+                    let ports = format!("IP {}.{} > {}.{}:", source.to_ddn(), sport, destination.to_ddn(), dport);
+                    let bootp = &data[8..];
+                    match (sport, dport) {
+                        (DHCP_CLIENT_PORT, DHCP_SERVER_PORT) | (DHCP_SERVER_PORT, DHCP_CLIENT_PORT) if bootp.len() >= 243 => {
+                            let message = match bootp[242] {
+                                1 => "Discover",
+                                2 => "Offer",
+                                3 => "Request",
+                                5 => "Ack",
+                                _ => "message",
+                            };
+                            let offered = ip(&bootp[16..20]);
+                            let summary = match offered.to_u32() {
+                                0 => format!("{} DHCP {}, client {}", ports, message, mac(&bootp[28..34]).to_hex()),
+                                _ => format!("{} DHCP {} {}, client {}", ports, message, offered.to_ddn(), mac(&bootp[28..34]).to_hex()),
+                            };
+                            (Protocol::Dhcp, summary)
+                        },
+                        (DNS_PORT, _) | (_, DNS_PORT) if let Some(summary) = dns_summary(&data[8..]) => {
+                            (Protocol::Dns, format!("{} DNS {}", ports, summary))
+                        },
+                        _ => (Protocol::Udp, format!("{} UDP, length {}, ttl {}", ports, data.len() - 8, ttl)),
+                    }
+                },
+                _ => (Protocol::Ip, format!("IP {} > {}: proto {}, ttl {}", source.to_ddn(), destination.to_ddn(), protocol, ttl)),
+            };
+            Some((decoded, summary, ips))
+        },
+        _ => None,
+    }
+}
+
+// A port is named after the subinterface the tag of the frame designates on it
+fn interface_name(graph: &Graph, port: &MAC, tag: Option<u16>) -> String {
+    let mac = match (graph.router_with_mac(port), tag) {
+        (Some(router), Some(vlan)) => router.subinterfaces.iter()
+            .find(|s| s.vlan == vlan && router.nic(&s.parent).mac == *port)
+            .map(|s| s.nic.mac.clone())
+            .unwrap_or(port.clone()),
+        _ => port.clone(),
+    };
+    graph.name_with_mac(mac.clone()).unwrap_or(mac.to_hex())
+}
+
+pub type Listener = Rc<dyn Fn(&TraceLine)>;
+
+// Lines are handed to a listener as the frames are sent, so that the trace shows up live
+#[derive(Clone)]
+pub struct Trace {
+    filter: Option<TraceFilter>,
+    listener: Option<Listener>
+}
+
+impl Default for Trace {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Trace {
+    pub fn new() -> Self {
+        Trace { filter: None, listener: None }
+    }
+
+    pub fn listen(&mut self, listener: impl Fn(&TraceLine) + 'static) {
+        self.listener = Some(Rc::new(listener));
+    }
+
+    pub fn start(&mut self, filter: TraceFilter) {
+        self.filter = Some(filter);
+    }
+
+    pub fn stop(&mut self) {
+        self.filter = None;
+    }
+
+    pub fn filter(&self) -> Option<&TraceFilter> {
+        self.filter.as_ref()
+    }
+
+    pub fn is_active(&self) -> bool {
+        self.filter.is_some() && self.listener.is_some()
+    }
+
+    // `start` in microseconds of simulated time, the transmissions being offset from it
+    pub fn emit(&self, graph: &Graph, start: u64, transmissions: &[Transmission]) {
+        let (Some(filter), Some(listener)) = (&self.filter, &self.listener) else {
+            return;
+        };
+        for transmission in transmissions {
+            let Some((protocol, summary, ips)) = decode(transmission.frame.ethertype, &transmission.frame.payload) else {
+                continue;
+            };
+            if filter.protocol.is_some_and(|p| !p.matches(protocol)) || filter.ip.as_ref().is_some_and(|ip| !ips.contains(ip)) {
+                continue;
+            }
+            let on = |port: &MAC| filter.node.as_ref().is_none_or(|node| port == node || graph.node_key(port) == *node);
+            // Seen from the sender, unless only the receiving end is traced
+            let (port, direction) = match (on(&transmission.from), on(&transmission.to)) {
+                (true, _) => (&transmission.from, Direction::Out),
+                (false, true) => (&transmission.to, Direction::In),
+                (false, false) => continue,
+            };
+            let summary = match transmission.tag {
+                Some(vlan) => format!("vlan {}, {}", vlan, summary),
+                None => summary,
+            };
+            listener(&TraceLine { at: start + transmission.offset, interface: interface_name(graph, port, transmission.tag), direction, protocol, summary });
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::cell::RefCell;
+    use crate::commands::ping;
+    use crate::load::{DataFormat, parse_data};
+    use crate::packet::{Ipv4, Tcp, arp, icmp_echo, udp};
+
+    const OFFICE: &str = "
+routers:
+  - name: R1
+    lan: {ip: 192.168.1.1, netmask: 24}
+    wan: {ip: 10.0.0.1, netmask: 30}
+devices:
+  - {name: PC1, ip: 192.168.1.10, netmask: 24}
+  - {name: PC2, ip: 192.168.1.11, netmask: 24}
+switches:
+  - name: SW1
+connections:
+  - {from: R1.lan, to: SW1}
+  - {from: PC1, to: SW1}
+  - {from: PC2, to: SW1}
+";
+
+    fn packet(protocol: u8, data: &[u8]) -> Vec<u8> {
+        let header = Ipv4 { source: IP::V4(10, 0, 0, 1), destination: IP::V4(10, 0, 0, 2), ttl: 64, protocol, identification: 0 };
+        header.encode(data).unwrap_or_else(|| panic!("too long"))
+    }
+
+    fn summary(ethertype: u16, payload: &[u8]) -> (Protocol, String) {
+        let (protocol, summary, _) = decode(ethertype, payload).unwrap_or_else(|| panic!("not decoded"));
+        (protocol, summary)
+    }
+
+    // Every line the trace of `filter` prints while PC1 pings PC2
+    fn traced(filter: impl Fn(&Graph) -> TraceFilter) -> Vec<TraceLine> {
+        let mut graph = Graph::new();
+        let report = graph.load_data(parse_data("test", OFFICE, DataFormat::Yaml).unwrap_or_else(|e| panic!("{}", e)));
+        assert!(report.rejected().is_empty());
+        let lines: Rc<RefCell<Vec<TraceLine>>> = Rc::new(RefCell::new(Vec::new()));
+        let sink = lines.clone();
+        graph.trace.listen(move |line| sink.borrow_mut().push(line.clone()));
+        graph.trace.start(filter(&graph));
+        let nic = |name: &str| graph.resolve_node(name).ok().and_then(|mac| graph.nic_with_mac(mac)).unwrap_or_else(|| panic!("no {}", name));
+        ping(&graph, nic("PC1"), nic("PC2").ip);
+        lines.take()
+    }
+
+    #[test]
+    fn frames_are_summarized_as_tcpdump_does() {
+        let sender = MAC::EUI48(2, 0, 0, 0, 0, 1);
+        let request = arp(ARP_REQUEST, &sender, &IP::V4(10, 0, 0, 1), &MAC::EUI48(0, 0, 0, 0, 0, 0), &IP::V4(10, 0, 0, 2));
+        assert_eq!(summary(ETHERTYPE_ARP, &request).1, "ARP who-has 10.0.0.2 tell 10.0.0.1");
+        let echo = packet(PROTOCOL_ICMP, &icmp_echo(ICMP_ECHO_REQUEST, 1, 2, b"abcd"));
+        assert_eq!(summary(ETHERTYPE_IPV4, &echo).1, "IP 10.0.0.1 > 10.0.0.2: ICMP echo request, id 1, seq 2, ttl 64");
+        let syn = Tcp { source_port: 49152, destination_port: 80, sequence: 7, acknowledgment: 0, flags: TCP_SYN };
+        let segment = packet(PROTOCOL_TCP, &syn.encode(&IP::V4(10, 0, 0, 1), &IP::V4(10, 0, 0, 2), &[]));
+        assert_eq!(summary(ETHERTYPE_IPV4, &segment).1, "IP 10.0.0.1.49152 > 10.0.0.2.80: Flags [S], seq 7, win 65535, length 0");
+        let datagram = packet(PROTOCOL_UDP, &udp(&IP::V4(10, 0, 0, 1), &IP::V4(10, 0, 0, 2), 49152, 7, b"hello"));
+        let (protocol, text) = summary(ETHERTYPE_IPV4, &datagram);
+        assert!(protocol == Protocol::Udp && text == "IP 10.0.0.1.49152 > 10.0.0.2.7: UDP, length 5, ttl 64");
+        assert!(decode(ETHERTYPE_ARP, &request[..20]).is_none());
+    }
+
+    #[test]
+    fn protocol_filters_include_what_they_carry() {
+        assert!(Protocol::Ip.matches(Protocol::Icmp) && !Protocol::Ip.matches(Protocol::Arp));
+        assert!(Protocol::Udp.matches(Protocol::Dhcp) && Protocol::Udp.matches(Protocol::Dns));
+        assert!(!Protocol::Tcp.matches(Protocol::Udp));
+        assert!(Protocol::from_string("ICMP") == Some(Protocol::Icmp) && Protocol::from_string("ipv6").is_none());
+    }
+
+    #[test]
+    fn a_ping_is_traced_hop_by_hop() {
+        let lines = traced(|_| TraceFilter::default());
+        // Request flooded to R1 and PC2, reply, then the echo request and reply through SW1
+        assert_eq!(lines.len(), 9);
+        assert!(lines.windows(2).all(|w| w[0].at <= w[1].at));
+        assert!(lines[0].interface == "PC1" && lines[0].direction == Direction::Out && lines[0].protocol == Protocol::Arp);
+    }
+
+    #[test]
+    fn nodes_see_what_they_receive() {
+        let lines = traced(|graph| TraceFilter { node: graph.resolve_node("R1").ok(), ..Default::default() });
+        // Only the flooded ARP request reaches the router
+        assert!(lines.len() == 1 && lines[0].interface == "R1.lan" && lines[0].direction == Direction::In);
+    }
+
+    #[test]
+    fn filters_all_apply() {
+        let icmp = traced(|_| TraceFilter { protocol: Some(Protocol::Icmp), ..Default::default() });
+        assert_eq!(icmp.len(), 4);
+        assert!(icmp.iter().all(|l| l.protocol == Protocol::Icmp));
+        let on_pc2 = traced(|_| TraceFilter { protocol: Some(Protocol::Icmp), ip: Some(IP::V4(192, 168, 1, 11)), node: None });
+        assert_eq!(on_pc2.len(), 4);
+        assert!(traced(|_| TraceFilter { ip: Some(IP::V4(192, 168, 1, 1)), ..Default::default() }).is_empty());
+    }
+}
@@ -0,0 +1,272 @@
+use crate::graph::{Graph, Hop};
+use crate::ip::IP;
+use crate::mac::MAC;
+use crate::nic::NIC;
+use crate::packet::{ARP_REPLY, ARP_REQUEST, BROADCAST, DEFAULT_TTL, DHCP_CLIENT_PORT, DHCP_SERVER_PORT, Dhcp, DhcpMessage, ETHERTYPE_ARP, ETHERTYPE_IPV4, Frame, ICMP_ECHO_REPLY, ICMP_ECHO_REQUEST, ICMP_PORT_UNREACHABLE, ICMP_TIME_EXCEEDED, ICMP_UNREACHABLE, Ipv4, PROTOCOL_ICMP, PROTOCOL_UDP, arp, icmp_echo, icmp_error, udp};
+
+
+pub const TRACEROUTE_PORT: u16 = 33434;
+const EPHEMERAL_PORT: u16 = 49152;
+
+// Frames of an exchange are this many microseconds apart
+const FRAME_INTERVAL: u64 = 10;
+
+// A frame crossing one cable, `from` and `to` being the ports at its ends
+#[derive(Clone)]
+pub struct Transmission {
+    pub from: MAC,
+    pub to: MAC,
+    pub tag: Option<u16>,
+    pub frame: Frame,
+    pub offset: u64
+}
+
+// Splits a path where it is routed: every leg goes from an IP interface to the next one through switches only
+fn legs(graph: &Graph, path: &[Hop]) -> Vec<Vec<Hop>> {
+    let mut legs: Vec<Vec<Hop>> = Vec::new();
+    let mut current: Vec<Hop> = path.iter().take(1).cloned().collect();
+    for pair in path.windows(2) {
+        if graph.wire(&pair[0], &pair[1]).is_none() {
+            legs.push(std::mem::take(&mut current));
+        }
+        current.push(pair[1].clone());
+    }
+    legs.push(current);
+    legs.retain(|leg| leg.len() > 1);
+    legs
+}
+
+fn reversed(legs: &[Vec<Hop>]) -> Vec<Vec<Hop>> {
+    legs.iter().rev().map(|leg| leg.iter().rev().cloned().collect()).collect()
+}
+
+fn nic(graph: &Graph, hop: &Hop) -> Option<NIC> {
+    graph.nic_with_mac(hop.0.clone())
+}
+
+struct Exchange<'a> {
+    graph: &'a Graph,
+    transmissions: Vec<Transmission>,
+    elapsed: u64
+}
+
+impl<'a> Exchange<'a> {
+    fn new(graph: &'a Graph) -> Self {
+        Exchange { graph, transmissions: Vec::new(), elapsed: 0 }
+    }
+
+    fn send(&mut self, (from, to, tag): (MAC, MAC, Option<u16>), frame: &Frame) {
+        self.transmissions.push(Transmission { from, to, tag, frame: frame.clone(), offset: self.elapsed });
+        self.elapsed += FRAME_INTERVAL;
+    }
+
+    fn wait(&mut self, duration: u64) {
+        self.elapsed += duration;
+    }
+
+    fn unicast(&mut self, hops: &[Hop], frame: &Frame) {
+        for pair in hops.windows(2) {
+            if let Some(wire) = self.graph.wire(&pair[0], &pair[1]) {
+                self.send(wire, frame);
+            }
+        }
+    }
+
+    fn broadcast(&mut self, start: &MAC, frame: &Frame) {
+        let flood = self.graph.flood(start.clone());
+        for (hop, parent) in &flood {
+            if let Some(parent) = parent
+                && let Some(wire) = self.graph.wire(&flood[*parent].0, hop) {
+                self.send(wire, frame);
+            }
+        }
+    }
+
+    fn arp_request(&mut self, sender: &NIC, target: &IP) {
+        let unknown = MAC::EUI48(0, 0, 0, 0, 0, 0);
+        let request = arp(ARP_REQUEST, &sender.mac, &sender.ip, &unknown, target);
+        self.broadcast(&sender.mac, &Frame::new(BROADCAST, sender.mac.clone(), ETHERTYPE_ARP, request));
+    }
+
+    // Address resolution of the far end of a leg, answered back along it, unless the sender already knows it
+    fn resolve(&mut self, leg: &[Hop]) {
+        let (Some(sender), Some(target)) = (leg.first().and_then(|h| nic(self.graph, h)), leg.last().and_then(|h| nic(self.graph, h))) else {
+            return;
+        };
+        if self.graph.tables.resolved(&sender.mac, &target.ip, &target.mac, self.graph.clock.now()) {
+            return;
+        }
+        self.arp_request(&sender, &target.ip);
+        let reply = arp(ARP_REPLY, &target.mac, &target.ip, &sender.mac, &sender.ip);
+        let back: Vec<Hop> = leg.iter().rev().cloned().collect();
+        self.unicast(&back, &Frame::new(sender.mac.clone(), target.mac.clone(), ETHERTYPE_ARP, reply));
+    }
+
+    // An IP packet along its legs, its TTL decremented by every router; returns it as last sent.
+    // A packet longer than IPv4 allows is never sent
+    fn route(&mut self, legs: &[Vec<Hop>], header: &Ipv4, payload: &[u8], resolve: bool) -> Vec<u8> {
+        let mut packet: Vec<u8> = Vec::new();
+        for (i, leg) in legs.iter().enumerate() {
+            let (Some(first), Some(last)) = (leg.first(), leg.last()) else {
+                continue;
+            };
+            let ip = Ipv4 { ttl: header.ttl.saturating_sub(i as u8), ..header.clone() };
+            let Some(encoded) = ip.encode(payload) else {
+                return Vec::new();
+            };
+            if resolve {
+                self.resolve(leg);
+            }
+            packet = encoded;
+            self.unicast(leg, &Frame::new(last.0.clone(), first.0.clone(), ETHERTYPE_IPV4, packet.clone()));
+        }
+        packet
+    }
+}
+
+fn header(source: &IP, destination: &IP, protocol: u8, identification: u16) -> Ipv4 {
+    Ipv4 { source: source.clone(), destination: destination.clone(), ttl: DEFAULT_TTL, protocol, identification }
+}
+
+pub enum Payload {
+    Data(u8, Vec<u8>),
+    // Answers the previous packet, quoting it as it arrived
+    PortUnreachable
+}
+
+// A packet between two hosts, `reply` going back from the destination, sent `after` microseconds
+pub struct Packet {
+    pub reply: bool,
+    pub after: u64,
+    pub payload: Payload
+}
+
+// The frames of packets exchanged with `dest`, the first one resolving the addresses on its way.
+// When `dest` cannot be reached, every packet of the source ends in an unanswered resolution
+pub fn conversation(graph: &Graph, src: &NIC, dest: &IP, packets: &[Packet]) -> Vec<Transmission> {
+    let mut exchange = Exchange::new(graph);
+    let Some(path) = graph.hop_path_to_ip(src.mac.clone(), dest.clone()) else {
+        for packet in packets.iter().filter(|p| !p.reply) {
+            exchange.wait(packet.after);
+            exchange.arp_request(src, dest);
+        }
+        return exchange.transmissions;
+    };
+    let forward = legs(graph, &path);
+    let backward = reversed(&forward);
+    let mut resolved = false;
+    let mut arrived: Vec<u8> = Vec::new();
+    for (i, packet) in packets.iter().enumerate() {
+        exchange.wait(packet.after);
+        let (legs, source, destination) = match packet.reply {
+            true => (&backward, dest, &src.ip),
+            false => (&forward, &src.ip, dest),
+        };
+        let (protocol, payload) = match &packet.payload {
+            Payload::Data(protocol, data) => (*protocol, data.clone()),
+            Payload::PortUnreachable => (PROTOCOL_ICMP, icmp_error(ICMP_UNREACHABLE, ICMP_PORT_UNREACHABLE, &arrived)),
+        };
+        arrived = exchange.route(legs, &header(source, destination, protocol, i as u16 + 1), &payload, !packet.reply && !resolved);
+        resolved |= !packet.reply;
+    }
+    exchange.transmissions
+}
+
+// The frames of a single echo request and its reply, or of the unanswered resolution of the destination
+pub fn ping(graph: &Graph, src: &NIC, dest: &IP) -> Vec<Transmission> {
+    let data: Vec<u8> = (0..32u8).map(|i| b'a' + i % 23).collect();
+    conversation(graph, src, dest, &[
+        Packet { reply: false, after: 0, payload: Payload::Data(PROTOCOL_ICMP, icmp_echo(ICMP_ECHO_REQUEST, 1, 1, &data)) },
+        Packet { reply: true, after: 0, payload: Payload::Data(PROTOCOL_ICMP, icmp_echo(ICMP_ECHO_REPLY, 1, 1, &data)) },
+    ])
+}
+
+// UDP probes of increasing TTL, each router answering the one that expires at its ingress interface
+pub fn traceroute(graph: &Graph, src: &NIC, dest: &IP) -> Vec<Transmission> {
+    let mut exchange = Exchange::new(graph);
+    let Some(path) = graph.hop_path_to_ip(src.mac.clone(), dest.clone()) else {
+        exchange.arp_request(src, dest);
+        return exchange.transmissions;
+    };
+    let legs = legs(graph, &path);
+    for ttl in 1..=legs.len() {
+        let port = TRACEROUTE_PORT + ttl as u16 - 1;
+        let probe = udp(&src.ip, dest, EPHEMERAL_PORT, port, &[0; 32]);
+        let crossed = &legs[..ttl];
+        let sent = Ipv4 { ttl: ttl as u8, ..header(&src.ip, dest, PROTOCOL_UDP, ttl as u16) };
+        // Every leg is resolved by the first probe crossing it
+        exchange.route(&crossed[..ttl - 1], &sent, &probe, false);
+        exchange.resolve(&crossed[ttl - 1]);
+        let arrived = exchange.route(&crossed[ttl - 1..], &Ipv4 { ttl: 1, ..sent.clone() }, &probe, false);
+        let (answer, source) = match ttl < legs.len() {
+            true => match crossed[ttl - 1].last().and_then(|h| nic(graph, h)) {
+                Some(ingress) => (icmp_error(ICMP_TIME_EXCEEDED, 0, &arrived), ingress.ip),
+                None => continue,
+            },
+            false => (icmp_error(ICMP_UNREACHABLE, ICMP_PORT_UNREACHABLE, &arrived), dest.clone()),
+        };
+        exchange.route(&reversed(crossed), &header(&source, &src.ip, PROTOCOL_ICMP, ttl as u16), &answer, false);
+    }
+    exchange.transmissions
+}
+
+// The server of the pool an address was leased from
+fn dhcp_server(graph: &Graph, client: &MAC, ip: &IP) -> Option<(NIC, crate::dhcp::DHCP)> {
+    let mut pools: Vec<(NIC, &crate::dhcp::DHCP)> = Vec::new();
+    for router in &graph.routers {
+        if let Some(dhcp) = &router.dhcp_lan {
+            pools.push((router.nic_lan.clone(), dhcp));
+        }
+        if let Some(dhcp) = &router.dhcp_wan {
+            pools.push((router.nic_wan.clone(), dhcp));
+        }
+        for subinterface in &router.subinterfaces {
+            if let Some(dhcp) = &subinterface.dhcp {
+                pools.push((subinterface.nic.clone(), dhcp));
+            }
+        }
+    }
+    pools.into_iter()
+        .filter(|(_, dhcp)| dhcp.first_ip.to_u32() <= ip.to_u32() && ip.to_u32() <= dhcp.last_ip.to_u32())
+        .find(|(nic, _)| graph.hop_path_to_mac(nic.mac.clone(), client).is_some())
+        .map(|(nic, dhcp)| (nic, dhcp.clone()))
+}
+
+// Discover, Offer, Request and Ack of the lease `client` just obtained
+pub fn dhcp(graph: &Graph, client: &MAC, offered: &IP) -> Vec<Transmission> {
+    let mut exchange = Exchange::new(graph);
+    let Some((server, pool)) = dhcp_server(graph, client, offered) else {
+        return exchange.transmissions;
+    };
+    let Some(path) = graph.hop_path_to_mac(server.mac.clone(), client) else {
+        return exchange.transmissions;
+    };
+    let legs = legs(graph, &path);
+    let [_, _, c, d, e, f] = client.octets();
+    let transaction = u32::from_be_bytes([c, d, e, f]);
+    let unspecified = IP::V4(0, 0, 0, 0);
+    let everyone = IP::V4(255, 255, 255, 255);
+    let message = |message: DhcpMessage| Dhcp {
+        message, transaction, client: client.clone(), offered: Some(offered.clone()), server: server.ip.clone(),
+        netmask: pool.netmask.clone(), name_server: pool.name_server.clone()
+    }.encode();
+    for (asked, answered) in [(DhcpMessage::Discover, DhcpMessage::Offer), (DhcpMessage::Request, DhcpMessage::Ack)] {
+        let datagram = udp(&unspecified, &everyone, DHCP_CLIENT_PORT, DHCP_SERVER_PORT, &message(asked));
+        if let Some(packet) = header(&unspecified, &everyone, PROTOCOL_UDP, asked as u16).encode(&datagram) {
+            exchange.broadcast(client, &Frame::new(BROADCAST, client.clone(), ETHERTYPE_IPV4, packet));
+        }
+        let datagram = udp(&server.ip, offered, DHCP_SERVER_PORT, DHCP_CLIENT_PORT, &message(answered));
+        exchange.route(&legs, &header(&server.ip, offered, PROTOCOL_UDP, answered as u16), &datagram, false);
+    }
+    exchange.transmissions
+}
+
+// The frames of an exchange feed the tables of the nodes, then the captures and the trace
+pub fn observe(graph: &Graph, generate: impl FnOnce(&Graph) -> Vec<Transmission>) {
+    let transmissions = generate(graph);
+    let start = graph.captures.stamp(graph.clock.now(), &transmissions);
+    graph.tables.learn(graph, start, &transmissions);
+    graph.trace.emit(graph, start, &transmissions);
+    // Failed captures stop writing, `show captures` reporting why
+    graph.captures.record(start, &transmissions);
+}
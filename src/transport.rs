@@ -0,0 +1,520 @@
+use rand::Rng;
+use serde::{Deserialize, Serialize};
+
+use crate::graph::Graph;
+use crate::ip::IP;
+use crate::mac::MAC;
+use crate::nic::NIC;
+use crate::packet::{PROTOCOL_TCP, PROTOCOL_UDP, TCP_ACK, TCP_FIN, TCP_PSH, TCP_RST, TCP_SYN, Tcp, udp};
+use crate::service::service_at;
+use crate::traffic::{self, Packet, Payload};
+
+
+pub const EPHEMERAL_PORTS: std::ops::RangeInclusive<u16> = 49152..=65535;
+
+// A SYN or FIN left unanswered is sent again after 1s, 2s then 4s before giving up
+pub const RETRANSMISSIONS: u32 = 3;
+const RETRANSMISSION_TIMEOUT: u64 = 1_000_000;
+
+#[derive(Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Transport {
+    Tcp,
+    Udp
+}
+
+impl std::fmt::Display for Transport {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Transport::Tcp => write!(f, "tcp"),
+            Transport::Udp => write!(f, "udp"),
+        }
+    }
+}
+
+impl Transport {
+    pub fn from_string(s: &str) -> Option<Self> {
+        match s.to_lowercase().as_str() {
+            "tcp" => Some(Transport::Tcp),
+            "udp" => Some(Transport::Udp),
+            _ => None,
+        }
+    }
+}
+
+#[derive(Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum SocketState {
+    Listen,
+    Established
+}
+
+impl std::fmt::Display for SocketState {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            SocketState::Listen => write!(f, "LISTEN"),
+            SocketState::Established => write!(f, "ESTABLISHED"),
+        }
+    }
+}
+
+// `sequence` is the next sequence number to send and `acknowledgment` the next one expected
+#[derive(Clone)]
+pub struct Socket {
+    pub host: MAC,
+    pub transport: Transport,
+    pub local_port: u16,
+    pub remote: Option<(IP, u16)>,
+    pub state: SocketState,
+    pub sequence: u32,
+    pub acknowledgment: u32
+}
+
+pub enum TransportError {
+    NotADevice(MAC),
+    NoAddress(MAC),
+    PortInUse(Transport, u16),
+    NotListening(Transport, u16),
+    NotConnected(u16),
+    NoFreePort(MAC)
+}
+
+impl std::fmt::Display for TransportError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            TransportError::NotADevice(mac) => write!(f, "{} is not a device", mac),
+            TransportError::NoAddress(mac) => write!(f, "{} has no IP address", mac),
+            TransportError::PortInUse(transport, port) => write!(f, "Port {}/{} already in use", port, transport),
+            TransportError::NotListening(transport, port) => write!(f, "Nothing listens on port {}/{}", port, transport),
+            TransportError::NotConnected(port) => write!(f, "No connection on port {}", port),
+            TransportError::NoFreePort(mac) => write!(f, "No ephemeral port left on {}", mac),
+        }
+    }
+}
+
+pub enum OpenOutcome {
+    Connected(u16),
+    Delivered,
+    Refused,
+    Timeout(u32)
+}
+
+impl std::fmt::Display for OpenOutcome {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            OpenOutcome::Connected(port) => write!(f, "connected from port {}", port),
+            OpenOutcome::Delivered => write!(f, "delivered"),
+            OpenOutcome::Refused => write!(f, "refused"),
+            OpenOutcome::Timeout(attempts) => write!(f, "timed out after {} attempts", attempts),
+        }
+    }
+}
+
+pub enum CloseOutcome {
+    Closed,
+    Reset,
+    Timeout(u32)
+}
+
+impl std::fmt::Display for CloseOutcome {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            CloseOutcome::Closed => write!(f, "closed"),
+            CloseOutcome::Reset => write!(f, "reset by the peer"),
+            CloseOutcome::Timeout(attempts) => write!(f, "peer unreachable after {} attempts, closed locally", attempts),
+        }
+    }
+}
+
+pub enum RequestOutcome {
+    Response(Vec<u8>),
+    NoResponse,
+    Refused,
+    Timeout(u32)
+}
+
+impl std::fmt::Display for RequestOutcome {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            RequestOutcome::Response(data) => write!(f, "{}", String::from_utf8_lossy(data)),
+            RequestOutcome::NoResponse => write!(f, "no response"),
+            RequestOutcome::Refused => write!(f, "refused"),
+            RequestOutcome::Timeout(attempts) => write!(f, "timed out after {} attempts", attempts),
+        }
+    }
+}
+
+// The sockets of every host, connections having one socket at each end
+#[derive(Clone)]
+pub struct Sockets {
+    sockets: Vec<Socket>
+}
+
+impl Default for Sockets {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Sockets {
+    pub fn new() -> Self {
+        Sockets { sockets: Vec::new() }
+    }
+
+    pub fn list(&self) -> &Vec<Socket> {
+        &self.sockets
+    }
+
+    pub fn of(&self, host: &MAC) -> Vec<&Socket> {
+        self.sockets.iter().filter(|s| s.host == *host).collect()
+    }
+
+    pub fn insert(&mut self, socket: Socket) {
+        self.sockets.push(socket);
+    }
+
+    pub fn retain(&mut self, keep: impl Fn(&Socket) -> bool) {
+        self.sockets.retain(keep);
+    }
+
+    pub fn listen(&mut self, host: MAC, transport: Transport, port: u16) -> Result<(), TransportError> {
+        if self.in_use(&host, transport, port) {
+            return Err(TransportError::PortInUse(transport, port));
+        }
+        self.sockets.push(Socket { host, transport, local_port: port, remote: None, state: SocketState::Listen, sequence: 0, acknowledgment: 0 });
+        Ok(())
+    }
+
+    // Connections already accepted on the port stay open
+    pub fn unlisten(&mut self, host: &MAC, transport: Transport, port: u16) -> Result<(), TransportError> {
+        match self.listener(host, transport, port) {
+            Some(idx) => {
+                self.sockets.remove(idx);
+                Ok(())
+            },
+            None => Err(TransportError::NotListening(transport, port))
+        }
+    }
+
+    pub fn is_listening(&self, host: &MAC, transport: Transport, port: u16) -> bool {
+        self.listener(host, transport, port).is_some()
+    }
+
+    fn listener(&self, host: &MAC, transport: Transport, port: u16) -> Option<usize> {
+        self.sockets.iter().position(|s| s.host == *host && s.transport == transport && s.local_port == port && s.state == SocketState::Listen)
+    }
+
+    fn in_use(&self, host: &MAC, transport: Transport, port: u16) -> bool {
+        self.sockets.iter().any(|s| s.host == *host && s.transport == transport && s.local_port == port)
+    }
+
+    fn connection(&self, host: &MAC, port: u16, remote: Option<&(IP, u16)>) -> Option<usize> {
+        self.sockets.iter().position(|s| s.host == *host && s.transport == Transport::Tcp && s.local_port == port
+            && s.state == SocketState::Established && remote.is_none_or(|r| s.remote.as_ref() == Some(r)))
+    }
+
+    pub fn ephemeral_port(&self, host: &MAC, transport: Transport) -> Result<u16, TransportError> {
+        EPHEMERAL_PORTS.into_iter().find(|p| !self.in_use(host, transport, *p)).ok_or(TransportError::NoFreePort(host.clone()))
+    }
+}
+
+fn addressed_device(graph: &Graph, host: &MAC) -> Result<NIC, TransportError> {
+    let device = graph.search_device_with_mac(host.clone()).ok_or(TransportError::NotADevice(host.clone()))?;
+    match device.nic.is_localhost() {
+        true => Err(TransportError::NoAddress(host.clone())),
+        false => Ok(device.nic),
+    }
+}
+
+// The device answering for `ip` from `host`, None when nothing answers or a router does
+fn peer(graph: &Graph, host: &MAC, ip: &IP) -> Option<Option<MAC>> {
+    let path = graph.hop_path_to_ip(host.clone(), ip.clone())?;
+    let (mac, _) = path.last()?;
+    Some(graph.search_device_with_mac(mac.clone()).map(|d| d.nic.mac))
+}
+
+// Sent at once, then retransmitted with a doubling timeout
+fn attempts(packet: impl Fn() -> Payload) -> Vec<Packet> {
+    (0..=RETRANSMISSIONS).map(|i| Packet {
+        reply: false,
+        after: if i == 0 { 0 } else { RETRANSMISSION_TIMEOUT << (i - 1) },
+        payload: packet()
+    }).collect()
+}
+
+fn segment(src: &IP, dest: &IP, ports: (u16, u16), sequence: u32, acknowledgment: u32, flags: u8) -> Payload {
+    data_segment(src, dest, ports, sequence, acknowledgment, flags, &[])
+}
+
+fn data_segment(src: &IP, dest: &IP, ports: (u16, u16), sequence: u32, acknowledgment: u32, flags: u8, data: &[u8]) -> Payload {
+    let tcp = Tcp { source_port: ports.0, destination_port: ports.1, sequence, acknowledgment, flags };
+    Payload::Data(PROTOCOL_TCP, tcp.encode(src, dest, data))
+}
+
+fn packet(reply: bool, payload: Payload) -> Packet {
+    Packet { reply, after: 0, payload }
+}
+
+// A port is open when a socket listens on it or a service runs there
+fn accepts(graph: &Graph, host: &MAC, transport: Transport, port: u16) -> bool {
+    graph.sockets.is_listening(host, transport, port) || service_at(graph, host, transport, port).is_some()
+}
+
+// Any device can listen, even before it has an address
+pub fn listen(graph: &mut Graph, host: MAC, transport: Transport, port: u16) -> Result<(), TransportError> {
+    if graph.search_device_with_mac(host.clone()).is_none() {
+        return Err(TransportError::NotADevice(host));
+    }
+    if service_at(graph, &host, transport, port).is_some() {
+        return Err(TransportError::PortInUse(transport, port));
+    }
+    graph.sockets.listen(host, transport, port)
+}
+
+// Opens a TCP connection with a three-way handshake, or sends a single UDP datagram
+pub fn open(graph: &mut Graph, host: MAC, transport: Transport, dest: IP, port: u16) -> Result<OpenOutcome, TransportError> {
+    let nic = addressed_device(graph, &host)?;
+    let source_port = graph.sockets.ephemeral_port(&host, transport)?;
+    let peer = peer(graph, &host, &dest);
+    let listening = peer.as_ref().and_then(|p| p.as_ref()).is_some_and(|p| accepts(graph, p, transport, port));
+    let ports = (source_port, port);
+    let back = (port, source_port);
+    let (packets, outcome) = match transport {
+        Transport::Udp => {
+            let datagram = Payload::Data(PROTOCOL_UDP, udp(&nic.ip, &dest, source_port, port, &[]));
+            match (&peer, listening) {
+                (None, _) => (vec![packet(false, datagram)], OpenOutcome::Timeout(1)),
+                (Some(_), true) => (vec![packet(false, datagram)], OpenOutcome::Delivered),
+                (Some(_), false) => (vec![packet(false, datagram), packet(true, Payload::PortUnreachable)], OpenOutcome::Refused),
+            }
+        },
+        Transport::Tcp => {
+            let isn: u32 = graph.random.rng().random();
+            let syn = || segment(&nic.ip, &dest, ports, isn, 0, TCP_SYN);
+            match (&peer, listening) {
+                (None, _) => (attempts(syn), OpenOutcome::Timeout(RETRANSMISSIONS + 1)),
+                (Some(Some(server)), true) => {
+                    let server_isn: u32 = graph.random.rng().random();
+                    let client = Socket {
+                        host: host.clone(), transport, local_port: source_port, remote: Some((dest.clone(), port)),
+                        state: SocketState::Established, sequence: isn.wrapping_add(1), acknowledgment: server_isn.wrapping_add(1)
+                    };
+                    let accepted = Socket {
+                        host: server.clone(), transport, local_port: port, remote: Some((nic.ip.clone(), source_port)),
+                        state: SocketState::Established, sequence: server_isn.wrapping_add(1), acknowledgment: isn.wrapping_add(1)
+                    };
+                    graph.sockets.insert(client);
+                    graph.sockets.insert(accepted);
+                    (vec![
+                        packet(false, syn()),
+                        packet(true, segment(&dest, &nic.ip, back, server_isn, isn.wrapping_add(1), TCP_SYN | TCP_ACK)),
+                        packet(false, segment(&nic.ip, &dest, ports, isn.wrapping_add(1), server_isn.wrapping_add(1), TCP_ACK)),
+                    ], OpenOutcome::Connected(source_port))
+                },
+                (Some(_), _) => (vec![
+                    packet(false, syn()),
+                    packet(true, segment(&dest, &nic.ip, back, 0, isn.wrapping_add(1), TCP_RST | TCP_ACK)),
+                ], OpenOutcome::Refused),
+            }
+        },
+    };
+    traffic::observe(graph, |graph| traffic::conversation(graph, &nic, &dest, &packets));
+    Ok(outcome)
+}
+
+// Closes a TCP connection from `host` with a FIN in each direction; the local end is closed whatever the peer does
+pub fn close(graph: &mut Graph, host: MAC, port: u16) -> Result<CloseOutcome, TransportError> {
+    let nic = addressed_device(graph, &host)?;
+    let idx = graph.sockets.connection(&host, port, None).ok_or(TransportError::NotConnected(port))?;
+    let socket = graph.sockets.sockets.remove(idx);
+    let Some((dest, remote_port)) = socket.remote.clone() else {
+        return Err(TransportError::NotConnected(port));
+    };
+    let (ports, back) = ((port, remote_port), (remote_port, port));
+    let (seq, ack) = (socket.sequence, socket.acknowledgment);
+    let fin = || segment(&nic.ip, &dest, ports, seq, ack, TCP_FIN | TCP_ACK);
+    let accepted = match peer(graph, &host, &dest) {
+        Some(Some(server)) => graph.sockets.connection(&server, remote_port, Some(&(nic.ip.clone(), port))),
+        _ => None,
+    };
+    let (packets, outcome) = match (peer(graph, &host, &dest), accepted) {
+        (None, _) => (attempts(fin), CloseOutcome::Timeout(RETRANSMISSIONS + 1)),
+        (Some(_), Some(idx)) => {
+            graph.sockets.sockets.remove(idx);
+            (vec![
+                packet(false, fin()),
+                packet(true, segment(&dest, &nic.ip, back, ack, seq.wrapping_add(1), TCP_ACK)),
+                packet(true, segment(&dest, &nic.ip, back, ack, seq.wrapping_add(1), TCP_FIN | TCP_ACK)),
+                packet(false, segment(&nic.ip, &dest, ports, seq.wrapping_add(1), ack.wrapping_add(1), TCP_ACK)),
+            ], CloseOutcome::Closed)
+        },
+        (Some(_), None) => (vec![
+            packet(false, fin()),
+            packet(true, segment(&dest, &nic.ip, back, ack, 0, TCP_RST)),
+        ], CloseOutcome::Reset),
+    };
+    traffic::observe(graph, |graph| traffic::conversation(graph, &nic, &dest, &packets));
+    Ok(outcome)
+}
+
+// Sends `data` to a port and waits for what the service running there answers. Over TCP the
+// connection only lasts for the exchange: handshake, request, response, then a FIN in each direction
+pub fn request(graph: &mut Graph, host: MAC, transport: Transport, dest: IP, port: u16, data: &[u8]) -> Result<RequestOutcome, TransportError> {
+    let nic = addressed_device(graph, &host)?;
+    let source_port = graph.sockets.ephemeral_port(&host, transport)?;
+    let peer = peer(graph, &host, &dest);
+    let server = peer.as_ref().and_then(|p| p.as_ref()).filter(|p| accepts(graph, p, transport, port));
+    let response = server.and_then(|p| service_at(graph, p, transport, port)).and_then(|s| s.respond(data));
+    let ports = (source_port, port);
+    let back = (port, source_port);
+    let outcome = match (&peer, server, &response) {
+        (None, _, _) => RequestOutcome::Timeout(match transport {
+            Transport::Udp => 1,
+            Transport::Tcp => RETRANSMISSIONS + 1,
+        }),
+        (Some(_), None, _) => RequestOutcome::Refused,
+        (Some(_), Some(_), Some(response)) => RequestOutcome::Response(response.clone()),
+        (Some(_), Some(_), None) => RequestOutcome::NoResponse,
+    };
+    let packets = match transport {
+        Transport::Udp => {
+            let datagram = packet(false, Payload::Data(PROTOCOL_UDP, udp(&nic.ip, &dest, source_port, port, data)));
+            match &outcome {
+                RequestOutcome::Response(response) => vec![datagram, packet(true, Payload::Data(PROTOCOL_UDP, udp(&dest, &nic.ip, port, source_port, response)))],
+                RequestOutcome::Refused => vec![datagram, packet(true, Payload::PortUnreachable)],
+                _ => vec![datagram],
+            }
+        },
+        Transport::Tcp => {
+            let isn: u32 = graph.random.rng().random();
+            let syn = || segment(&nic.ip, &dest, ports, isn, 0, TCP_SYN);
+            match &outcome {
+                RequestOutcome::Timeout(_) => attempts(syn),
+                RequestOutcome::Refused => vec![
+                    packet(false, syn()),
+                    packet(true, segment(&dest, &nic.ip, back, 0, isn.wrapping_add(1), TCP_RST | TCP_ACK)),
+                ],
+                _ => {
+                    let server_isn: u32 = graph.random.rng().random();
+                    let response = response.unwrap_or_default();
+                    // Next sequence numbers of the client and the server once the exchange is over
+                    let client_seq = isn.wrapping_add(1).wrapping_add(data.len() as u32);
+                    let server_seq = server_isn.wrapping_add(1).wrapping_add(response.len() as u32);
+                    let mut packets = vec![
+                        packet(false, syn()),
+                        packet(true, segment(&dest, &nic.ip, back, server_isn, isn.wrapping_add(1), TCP_SYN | TCP_ACK)),
+                        packet(false, segment(&nic.ip, &dest, ports, isn.wrapping_add(1), server_isn.wrapping_add(1), TCP_ACK)),
+                    ];
+                    if !data.is_empty() {
+                        packets.push(packet(false, data_segment(&nic.ip, &dest, ports, isn.wrapping_add(1), server_isn.wrapping_add(1), TCP_PSH | TCP_ACK, data)));
+                    }
+                    // The response acknowledges the request, a bare ACK does when there is none
+                    match response.is_empty() {
+                        true if !data.is_empty() => packets.push(packet(true, segment(&dest, &nic.ip, back, server_seq, client_seq, TCP_ACK))),
+                        true => {},
+                        false => {
+                            packets.push(packet(true, data_segment(&dest, &nic.ip, back, server_isn.wrapping_add(1), client_seq, TCP_PSH | TCP_ACK, &response)));
+                            packets.push(packet(false, segment(&nic.ip, &dest, ports, client_seq, server_seq, TCP_ACK)));
+                        },
+                    }
+                    packets.extend([
+                        packet(false, segment(&nic.ip, &dest, ports, client_seq, server_seq, TCP_FIN | TCP_ACK)),
+                        packet(true, segment(&dest, &nic.ip, back, server_seq, client_seq.wrapping_add(1), TCP_ACK)),
+                        packet(true, segment(&dest, &nic.ip, back, server_seq, client_seq.wrapping_add(1), TCP_FIN | TCP_ACK)),
+                        packet(false, segment(&nic.ip, &dest, ports, client_seq.wrapping_add(1), server_seq.wrapping_add(1), TCP_ACK)),
+                    ]);
+                    packets
+                },
+            }
+        },
+    };
+    traffic::observe(graph, |graph| traffic::conversation(graph, &nic, &dest, &packets));
+    Ok(outcome)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::load::{DataFormat, parse_data};
+
+    const OFFICE: &str = "
+routers:
+  - name: R1
+    lan: {ip: 192.168.1.1, netmask: 24}
+    wan: {ip: 10.0.0.1, netmask: 30}
+devices:
+  - {name: PC1, ip: 192.168.1.10, netmask: 24}
+  - {name: PC2, ip: 192.168.1.11, netmask: 24}
+switches:
+  - name: SW1
+connections:
+  - {from: R1.lan, to: SW1}
+  - {from: PC1, to: SW1}
+  - {from: PC2, to: SW1}
+";
+
+    fn office() -> (Graph, NIC, NIC) {
+        let mut graph = Graph::new();
+        let report = graph.load_data(parse_data("test", OFFICE, DataFormat::Yaml).unwrap_or_else(|e| panic!("{}", e)));
+        assert!(report.rejected().is_empty());
+        let nic = |name: &str| graph.resolve_node(name).ok().and_then(|mac| graph.nic_with_mac(mac)).unwrap_or_else(|| panic!("no {}", name));
+        let (pc1, pc2) = (nic("PC1"), nic("PC2"));
+        (graph, pc1, pc2)
+    }
+
+    #[test]
+    fn a_handshake_establishes_a_socket_at_each_end() {
+        let (mut graph, pc1, pc2) = office();
+        listen(&mut graph, pc2.mac.clone(), Transport::Tcp, 80).unwrap_or_else(|e| panic!("{}", e));
+        let outcome = open(&mut graph, pc1.mac.clone(), Transport::Tcp, pc2.ip.clone(), 80).unwrap_or_else(|e| panic!("{}", e));
+        assert!(matches!(outcome, OpenOutcome::Connected(49152)));
+        let client = graph.sockets.of(&pc1.mac);
+        let server = graph.sockets.of(&pc2.mac);
+        assert!(client.len() == 1 && client[0].remote == Some((pc2.ip.clone(), 80)));
+        // The listener stays next to the accepted connection
+        assert_eq!(server.len(), 2);
+        let accepted = server.iter().find(|s| s.state == SocketState::Established).unwrap_or_else(|| panic!("not accepted"));
+        assert!(accepted.remote == Some((pc1.ip.clone(), 49152)));
+        assert!(accepted.sequence == client[0].acknowledgment && accepted.acknowledgment == client[0].sequence);
+    }
+
+    #[test]
+    fn closed_ports_refuse() {
+        let (mut graph, pc1, pc2) = office();
+        for transport in [Transport::Tcp, Transport::Udp] {
+            let outcome = open(&mut graph, pc1.mac.clone(), transport, pc2.ip.clone(), 80).unwrap_or_else(|e| panic!("{}", e));
+            assert!(matches!(outcome, OpenOutcome::Refused));
+        }
+        assert!(graph.sockets.list().is_empty());
+    }
+
+    #[test]
+    fn unreachable_hosts_time_out() {
+        let (mut graph, pc1, _) = office();
+        let outcome = open(&mut graph, pc1.mac.clone(), Transport::Tcp, IP::V4(192, 168, 1, 99), 80).unwrap_or_else(|e| panic!("{}", e));
+        assert!(matches!(outcome, OpenOutcome::Timeout(attempts) if attempts == RETRANSMISSIONS + 1));
+    }
+
+    #[test]
+    fn closing_removes_both_ends() {
+        let (mut graph, pc1, pc2) = office();
+        listen(&mut graph, pc2.mac.clone(), Transport::Tcp, 80).unwrap_or_else(|e| panic!("{}", e));
+        open(&mut graph, pc1.mac.clone(), Transport::Tcp, pc2.ip.clone(), 80).unwrap_or_else(|e| panic!("{}", e));
+        let outcome = close(&mut graph, pc1.mac.clone(), 49152).unwrap_or_else(|e| panic!("{}", e));
+        assert!(matches!(outcome, CloseOutcome::Closed));
+        assert!(graph.sockets.of(&pc1.mac).is_empty());
+        assert!(graph.sockets.of(&pc2.mac).iter().all(|s| s.state == SocketState::Listen));
+        assert!(matches!(close(&mut graph, pc1.mac.clone(), 49152), Err(TransportError::NotConnected(49152))));
+    }
+
+    #[test]
+    fn ports_are_taken_once_per_transport() {
+        let (mut graph, pc1, _) = office();
+        listen(&mut graph, pc1.mac.clone(), Transport::Tcp, 80).unwrap_or_else(|e| panic!("{}", e));
+        assert!(matches!(listen(&mut graph, pc1.mac.clone(), Transport::Tcp, 80), Err(TransportError::PortInUse(Transport::Tcp, 80))));
+        assert!(listen(&mut graph, pc1.mac.clone(), Transport::Udp, 80).is_ok());
+        graph.sockets.listen(pc1.mac.clone(), Transport::Tcp, 49152).unwrap_or_else(|e| panic!("{}", e));
+        assert!(matches!(graph.sockets.ephemeral_port(&pc1.mac, Transport::Tcp), Ok(49153)));
+        assert!(matches!(graph.sockets.ephemeral_port(&pc1.mac, Transport::Udp), Ok(49152)));
+    }
+}
@@ -0,0 +1,613 @@
+use crate::dhcp::DHCP;
+use crate::graph::Graph;
+use crate::ip::IP;
+use crate::load::{LoadedData, LoadedRouterDHCP};
+use crate::mac::MAC;
+use crate::nic::NIC;
+
+
+#[derive(Clone, PartialEq)]
+pub enum Severity {
+    Error,
+    Warning
+}
+
+impl std::fmt::Display for Severity {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Severity::Error => write!(f, "error"),
+            Severity::Warning => write!(f, "warning"),
+        }
+    }
+}
+
+#[derive(Clone, PartialEq)]
+pub enum DiagnosticKind {
+    InvalidEntry,
+    DuplicateIp,
+    OverlappingSubnets,
+    StaticAddressInDhcpPool,
+    Localhost,
+    IsolatedNode,
+    UnreachableSubnet
+}
+
+impl std::fmt::Display for DiagnosticKind {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            DiagnosticKind::InvalidEntry => write!(f, "invalid-entry"),
+            DiagnosticKind::DuplicateIp => write!(f, "duplicate-ip"),
+            DiagnosticKind::OverlappingSubnets => write!(f, "overlapping-subnets"),
+            DiagnosticKind::StaticAddressInDhcpPool => write!(f, "static-address-in-dhcp-pool"),
+            DiagnosticKind::Localhost => write!(f, "localhost"),
+            DiagnosticKind::IsolatedNode => write!(f, "isolated-node"),
+            DiagnosticKind::UnreachableSubnet => write!(f, "unreachable-subnet"),
+        }
+    }
+}
+
+pub struct Diagnostic {
+    pub severity: Severity,
+    pub kind: DiagnosticKind,
+    pub subject: String,
+    pub message: String
+}
+
+impl std::fmt::Display for Diagnostic {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{} [{}] {}: {}", self.severity, self.kind, self.subject, self.message)
+    }
+}
+
+pub struct ValidationReport {
+    pub diagnostics: Vec<Diagnostic>
+}
+
+impl ValidationReport {
+    fn push(&mut self, severity: Severity, kind: DiagnosticKind, subject: &str, message: String) {
+        self.diagnostics.push(Diagnostic { severity, kind, subject: subject.to_string(), message });
+    }
+
+    pub fn errors(&self) -> usize {
+        self.diagnostics.iter().filter(|d| d.severity == Severity::Error).count()
+    }
+
+    pub fn warnings(&self) -> usize {
+        self.diagnostics.iter().filter(|d| d.severity == Severity::Warning).count()
+    }
+
+    pub fn has_errors(&self) -> bool {
+        self.errors() > 0
+    }
+
+    pub fn exit_code(&self) -> i32 {
+        if self.has_errors() { 1 } else { 0 }
+    }
+}
+
+// Common view of a graph or of a topology file, one entry per NIC
+struct Interface {
+    node: String,
+    name: String,
+    mac: Option<MAC>,
+    address: Option<(IP, IP)>,
+    router: Option<usize>,
+    pool: Option<(IP, IP)>,
+    static_address: bool,
+    switch: bool,
+    // A switch with VLANs holds one network per VLAN
+    vlans: bool
+}
+
+impl Interface {
+    // Switches take part in links without having an address
+    fn switch(name: &str, mac: Option<MAC>, vlans: bool) -> Self {
+        Interface {
+            node: name.to_string(),
+            name: name.to_string(),
+            mac,
+            address: None,
+            router: None,
+            pool: None,
+            static_address: false,
+            switch: true,
+            vlans,
+        }
+    }
+}
+
+struct Topology {
+    interfaces: Vec<Interface>,
+    links: Vec<(usize, usize)>
+}
+
+fn subnet(ip: &IP, netmask: &IP) -> (u32, u32) {
+    (ip.to_u32() & netmask.to_u32(), netmask.to_u32())
+}
+
+fn subnet_string(ip: &IP, netmask: &IP) -> String {
+    let (network, mask) = subnet(ip, netmask);
+    format!("{}/{}", IP::from_u32(network).to_ddn(), mask.count_ones())
+}
+
+fn is_localhost(ip: &IP) -> bool {
+    ip.to_u32() >> 24 == 127
+}
+
+impl Topology {
+    fn from_graph(graph: &Graph) -> Self {
+        let mut interfaces: Vec<Interface> = Vec::new();
+        for (idx, router) in graph.routers.iter().enumerate() {
+            for (suffix, nic, dhcp) in [("lan", &router.nic_lan, &router.dhcp_lan), ("wan", &router.nic_wan, &router.dhcp_wan)] {
+                interfaces.push(Interface {
+                    node: router.name.clone(),
+                    name: format!("{}.{}", router.name, suffix),
+                    mac: Some(nic.mac.clone()),
+                    address: Some((nic.ip.clone(), nic.netmask.clone())),
+                    router: Some(idx),
+                    pool: dhcp.as_ref().map(|d| (d.first_ip.clone(), d.last_ip.clone())),
+                    static_address: true,
+                    switch: false,
+                    vlans: false,
+                });
+            }
+            for subinterface in &router.subinterfaces {
+                interfaces.push(Interface {
+                    node: router.name.clone(),
+                    name: graph.name_with_mac(subinterface.nic.mac.clone()).unwrap_or_default(),
+                    mac: Some(subinterface.nic.mac.clone()),
+                    address: Some((subinterface.nic.ip.clone(), subinterface.nic.netmask.clone())),
+                    router: Some(idx),
+                    pool: subinterface.dhcp.as_ref().map(|d| (d.first_ip.clone(), d.last_ip.clone())),
+                    static_address: true,
+                    switch: false,
+                    vlans: false,
+                });
+            }
+        }
+        for device in &graph.devices {
+            interfaces.push(Interface {
+                node: device.name.clone(),
+                name: device.name.clone(),
+                mac: Some(device.nic.mac.clone()),
+                address: Some((device.nic.ip.clone(), device.nic.netmask.clone())),
+                router: None,
+                pool: None,
+                // Addresses in a running graph may come from DHCP
+                static_address: false,
+                switch: false,
+                vlans: false,
+            });
+        }
+        for switch in &graph.switches {
+            interfaces.push(Interface::switch(&switch.name, Some(switch.mac.clone()), !switch.port_modes.is_empty()));
+        }
+        let mut links: Vec<(usize, usize)> = Vec::new();
+        for (mac1, mac2) in graph.all_connections() {
+            let i = interfaces.iter().position(|iface| iface.mac.as_ref() == Some(mac1));
+            let j = interfaces.iter().position(|iface| iface.mac.as_ref() == Some(mac2));
+            if let (Some(i), Some(j)) = (i, j) && (interfaces[i].router.is_none() || interfaces[i].router != interfaces[j].router) {
+                links.push((i, j));
+            }
+        }
+        Topology { interfaces, links }
+    }
+
+    fn from_data(data: &LoadedData, report: &mut ValidationReport) -> Self {
+        let mut topology = Topology { interfaces: Vec::new(), links: Vec::new() };
+        let mut names: Vec<String> = Vec::new();
+
+        for (idx, r) in data.routers.as_ref().unwrap_or(&Vec::new()).iter().enumerate() {
+            if names.contains(&r.name) {
+                report.push(Severity::Error, DiagnosticKind::InvalidEntry, &r.name, "Duplicate node name".to_string());
+            }
+            names.push(r.name.clone());
+            for (suffix, iface) in [("lan", &r.lan), ("wan", &r.wan)] {
+                let name = format!("{}.{}", r.name, suffix);
+                if let Some(interface) = Self::data_router_interface(&name, &iface.ip, iface.netmask, &iface.mac, &iface.dhcp, report) {
+                    topology.interfaces.push(Interface { node: r.name.clone(), router: Some(idx), ..interface });
+                }
+                for s in iface.subinterfaces.iter().flatten() {
+                    let name = format!("{}.{}", name, s.vlan);
+                    if let Some(interface) = Self::data_router_interface(&name, &s.ip, s.netmask, &s.mac, &s.dhcp, report) {
+                        topology.interfaces.push(Interface { node: r.name.clone(), router: Some(idx), ..interface });
+                    }
+                }
+            }
+        }
+
+        for d in data.devices.as_ref().unwrap_or(&Vec::new()) {
+            if names.contains(&d.name) {
+                report.push(Severity::Error, DiagnosticKind::InvalidEntry, &d.name, "Duplicate node name".to_string());
+            }
+            names.push(d.name.clone());
+            let mac = match &d.mac {
+                Some(s) => match MAC::from_string(s) {
+                    Some(mac) => Some(mac),
+                    None => {
+                        report.push(Severity::Error, DiagnosticKind::InvalidEntry, &d.name, format!("Invalid MAC address '{}'", s));
+                        continue;
+                    }
+                },
+                None => None,
+            };
+            let address = match &d.ip {
+                Some(s) => match (IP::from_string(s), d.netmask.unwrap_or(8)) {
+                    (Some(ip), cidr) if cidr <= 32 => Some((ip, IP::from_cidr(cidr))),
+                    _ => {
+                        report.push(Severity::Error, DiagnosticKind::InvalidEntry, &d.name, format!("Invalid IP address '{}'", s));
+                        continue;
+                    }
+                },
+                None => None,
+            };
+            topology.interfaces.push(Interface {
+                node: d.name.clone(),
+                name: d.name.clone(),
+                mac,
+                static_address: address.is_some(),
+                address,
+                router: None,
+                pool: None,
+                switch: false,
+                vlans: false,
+            });
+        }
+
+        for sw in data.switches.as_ref().unwrap_or(&Vec::new()) {
+            if names.contains(&sw.name) {
+                report.push(Severity::Error, DiagnosticKind::InvalidEntry, &sw.name, "Duplicate node name".to_string());
+            }
+            names.push(sw.name.clone());
+            let mac = match &sw.mac {
+                Some(s) => match MAC::from_string(s) {
+                    Some(mac) => Some(mac),
+                    None => {
+                        report.push(Severity::Error, DiagnosticKind::InvalidEntry, &sw.name, format!("Invalid MAC address '{}'", s));
+                        continue;
+                    }
+                },
+                None => None,
+            };
+            topology.interfaces.push(Interface::switch(&sw.name, mac, sw.vlans.is_some()));
+        }
+
+        let macs: Vec<&MAC> = topology.interfaces.iter().filter_map(|i| i.mac.as_ref()).collect();
+        for (i, mac) in macs.iter().enumerate() {
+            if macs[..i].contains(mac) {
+                report.push(Severity::Error, DiagnosticKind::InvalidEntry, &mac.to_hex(), "Duplicate MAC address".to_string());
+            }
+        }
+
+        for c in data.connections.as_ref().unwrap_or(&Vec::new()) {
+            let subject = format!("{} <-> {}", c.from, c.to);
+            match (topology.find(&c.from), topology.find(&c.to)) {
+                (Some(i), Some(j)) => topology.links.push((i, j)),
+                (None, _) => report.push(Severity::Error, DiagnosticKind::InvalidEntry, &subject, format!("Unknown endpoint '{}'", c.from)),
+                (_, None) => report.push(Severity::Error, DiagnosticKind::InvalidEntry, &subject, format!("Unknown endpoint '{}'", c.to)),
+            }
+        }
+        for (i, j) in topology.links.clone() {
+            if let (Some((ip1, mask1)), Some((ip2, mask2))) = (&topology.interfaces[i].address, &topology.interfaces[j].address)
+                && subnet(ip1, mask1) != subnet(ip2, mask2) {
+                let subject = format!("{} <-> {}", topology.interfaces[i].name, topology.interfaces[j].name);
+                report.push(Severity::Error, DiagnosticKind::InvalidEntry, &subject, "Endpoints are on different networks".to_string());
+            }
+        }
+        // Everything plugged into a switch shares one network, unless VLANs split the segment
+        let segments = topology.groups(false);
+        let split: Vec<usize> = topology.interfaces.iter().enumerate().filter(|(_, i)| i.vlans).map(|(k, _)| segments[k]).collect();
+        for (k, sw) in topology.interfaces.iter().enumerate().filter(|(k, i)| i.switch && !split.contains(&segments[*k])) {
+            let subnets: Vec<String> = topology.interfaces.iter().enumerate()
+                .filter(|(i, _)| segments[*i] == segments[k])
+                .filter_map(|(_, iface)| iface.address.as_ref())
+                .filter(|(ip, _)| !is_localhost(ip))
+                .map(|(ip, netmask)| subnet_string(ip, netmask))
+                .collect();
+            if let Some(other) = subnets.iter().find(|s| **s != subnets[0]) {
+                report.push(Severity::Error, DiagnosticKind::InvalidEntry, &sw.name, format!("Segment mixes subnets {} and {}", subnets[0], other));
+            }
+        }
+        topology
+    }
+
+    fn data_router_interface(name: &str, ip: &str, netmask: u8, mac: &Option<String>, dhcp: &Option<LoadedRouterDHCP>, report: &mut ValidationReport) -> Option<Interface> {
+        let ip = match IP::from_string(ip) {
+            Some(address) => address,
+            None => {
+                report.push(Severity::Error, DiagnosticKind::InvalidEntry, name, format!("Invalid IP address '{}'", ip));
+                return None;
+            }
+        };
+        if netmask > 32 {
+            report.push(Severity::Error, DiagnosticKind::InvalidEntry, name, format!("Invalid netmask /{}", netmask));
+            return None;
+        }
+        let netmask = IP::from_cidr(netmask);
+        let mac = match mac {
+            Some(s) => match MAC::from_string(s) {
+                Some(mac) => Some(mac),
+                None => {
+                    report.push(Severity::Error, DiagnosticKind::InvalidEntry, name, format!("Invalid MAC address '{}'", s));
+                    return None;
+                }
+            },
+            None => None,
+        };
+        let pool = match dhcp {
+            Some(dhcp) => match (IP::from_string(&dhcp.first_ip), IP::from_string(&dhcp.last_ip)) {
+                (Some(first_ip), Some(last_ip)) => match DHCP::new(NIC::with_mac(ip.clone(), netmask.clone(), MAC::EUI48(0, 0, 0, 0, 0, 0)), first_ip.clone(), last_ip.clone()) {
+                    Ok(_) => Some((first_ip, last_ip)),
+                    Err(e) => {
+                        report.push(Severity::Error, DiagnosticKind::InvalidEntry, name, format!("Invalid DHCP pool: {}", e));
+                        None
+                    }
+                },
+                _ => {
+                    report.push(Severity::Error, DiagnosticKind::InvalidEntry, name, "Invalid DHCP pool addresses".to_string());
+                    None
+                }
+            },
+            None => None,
+        };
+        Some(Interface {
+            node: String::new(),
+            name: name.to_string(),
+            mac,
+            address: Some((ip, netmask)),
+            router: None,
+            pool,
+            static_address: true,
+            switch: false,
+            vlans: false,
+        })
+    }
+
+
+    fn find(&self, reference: &str) -> Option<usize> {
+        if let Some(i) = self.interfaces.iter().position(|iface| iface.name == reference) {
+            return Some(i);
+        }
+        let mac = MAC::from_string(reference)?;
+        self.interfaces.iter().position(|iface| iface.mac.as_ref() == Some(&mac))
+    }
+
+    fn siblings(&self, i: usize, j: usize) -> bool {
+        i != j && self.interfaces[i].router.is_some() && self.interfaces[i].router == self.interfaces[j].router
+    }
+
+    // Labels every interface with the smallest index of its group
+    fn groups(&self, through_routers: bool) -> Vec<usize> {
+        let mut group: Vec<usize> = (0..self.interfaces.len()).collect();
+        let mut changed = true;
+        while changed {
+            changed = false;
+            let mut pairs = self.links.clone();
+            if through_routers {
+                for i in 0..self.interfaces.len() {
+                    for j in 0..self.interfaces.len() {
+                        if self.siblings(i, j) {
+                            pairs.push((i, j));
+                        }
+                    }
+                }
+            }
+            for (i, j) in pairs {
+                let min = group[i].min(group[j]);
+                if group[i] != min || group[j] != min {
+                    group[i] = min;
+                    group[j] = min;
+                    changed = true;
+                }
+            }
+        }
+        group
+    }
+
+    fn linked(&self, i: usize) -> bool {
+        self.links.iter().any(|(a, b)| *a == i || *b == i)
+    }
+}
+
+fn check(topology: &Topology, report: &mut ValidationReport, pending_dhcp: bool) {
+    let interfaces = &topology.interfaces;
+    let segments = topology.groups(false);
+    let components = topology.groups(true);
+
+    for (j, b) in interfaces.iter().enumerate() {
+        let (ip_b, mask_b) = match &b.address {
+            Some(address) if !is_localhost(&address.0) => address,
+            _ => continue,
+        };
+        for (i, a) in interfaces[..j].iter().enumerate() {
+            let (ip_a, mask_a) = match &a.address {
+                Some(address) if !is_localhost(&address.0) => address,
+                _ => continue,
+            };
+            if ip_a == ip_b {
+                report.push(Severity::Error, DiagnosticKind::DuplicateIp, &b.name, format!("Same IP address {} as {}", ip_b.to_ddn(), a.name));
+            }
+            if a.router.is_none() || b.router.is_none() {
+                continue;
+            }
+            let mask = mask_a.to_u32() & mask_b.to_u32();
+            if ip_a.to_u32() & mask != ip_b.to_u32() & mask {
+                continue;
+            }
+            let (subnet_a, subnet_b) = (subnet_string(ip_a, mask_a), subnet_string(ip_b, mask_b));
+            if topology.siblings(i, j) {
+                report.push(Severity::Error, DiagnosticKind::OverlappingSubnets, &b.node, format!("LAN {} and WAN {} subnets overlap", subnet_a, subnet_b));
+            } else if mask_a != mask_b {
+                report.push(Severity::Error, DiagnosticKind::OverlappingSubnets, &b.name, format!("Subnet {} overlaps {} of {}", subnet_b, subnet_a, a.name));
+            } else if segments[i] != segments[j] {
+                report.push(Severity::Error, DiagnosticKind::OverlappingSubnets, &b.name, format!("Subnet {} is also used by {} on a separate segment", subnet_b, a.name));
+            }
+        }
+    }
+
+    for pool_owner in interfaces {
+        let ((first_ip, last_ip), (ip, netmask)) = match (&pool_owner.pool, &pool_owner.address) {
+            (Some(pool), Some(address)) => (pool, address),
+            _ => continue,
+        };
+        for iface in interfaces.iter().filter(|i| i.static_address) {
+            if let Some((static_ip, _)) = &iface.address
+                && subnet(static_ip, netmask) == subnet(ip, netmask)
+                && !first_ip.is_greater_than(static_ip) && !static_ip.is_greater_than(last_ip) {
+                report.push(Severity::Warning, DiagnosticKind::StaticAddressInDhcpPool, &iface.name,
+                    format!("Static address {} is inside the DHCP pool of {} ({} > {})", static_ip.to_ddn(), pool_owner.name, first_ip.to_ddn(), last_ip.to_ddn()));
+            }
+        }
+    }
+
+    for (i, iface) in interfaces.iter().enumerate() {
+        match &iface.address {
+            Some((ip, _)) if is_localhost(ip) => {
+                report.push(Severity::Warning, DiagnosticKind::Localhost, &iface.name, "Still on 127.0.0.1, the interface is not configured".to_string());
+            },
+            None if pending_dhcp && !iface.switch => {
+                let dhcp_reachable = interfaces.iter().enumerate().any(|(j, other)| other.pool.is_some() && components[j] == components[i]);
+                if !dhcp_reachable {
+                    report.push(Severity::Warning, DiagnosticKind::Localhost, &iface.name, "No address and no reachable DHCP server, it will stay on 127.0.0.1".to_string());
+                }
+            },
+            _ => {}
+        }
+    }
+
+    let mut reported_routers: Vec<usize> = Vec::new();
+    for (i, iface) in interfaces.iter().enumerate() {
+        let isolated = match iface.router {
+            Some(router) => {
+                if reported_routers.contains(&router) {
+                    continue;
+                }
+                reported_routers.push(router);
+                (0..interfaces.len()).filter(|j| interfaces[*j].router == Some(router)).all(|j| !topology.linked(j))
+            },
+            None => !topology.linked(i),
+        };
+        if isolated {
+            report.push(Severity::Warning, DiagnosticKind::IsolatedNode, &iface.node, "Not connected to anything".to_string());
+        }
+    }
+
+    // The largest connected part of the network is considered the reference
+    let main = match (0..interfaces.len()).max_by_key(|i| components.iter().filter(|c| **c == components[*i]).count()) {
+        Some(i) => components[i],
+        None => return,
+    };
+    let mut reported_subnets: Vec<String> = Vec::new();
+    for (i, iface) in interfaces.iter().enumerate() {
+        if components[i] == main || !topology.linked(i) {
+            continue;
+        }
+        if let Some((ip, netmask)) = &iface.address && !is_localhost(ip) {
+            let subnet = subnet_string(ip, netmask);
+            if !reported_subnets.contains(&subnet) {
+                report.push(Severity::Warning, DiagnosticKind::UnreachableSubnet, &iface.name, format!("Subnet {} is not reachable from the rest of the network", subnet));
+                reported_subnets.push(subnet);
+            }
+        }
+    }
+}
+
+pub fn validate_graph(graph: &Graph) -> ValidationReport {
+    let mut report = ValidationReport { diagnostics: Vec::new() };
+    let topology = Topology::from_graph(graph);
+    check(&topology, &mut report, false);
+    report
+}
+
+pub fn validate_data(data: &LoadedData) -> ValidationReport {
+    let mut report = ValidationReport { diagnostics: Vec::new() };
+    let topology = Topology::from_data(data, &mut report);
+    check(&topology, &mut report, true);
+    report
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::load::{DataFormat, parse_data};
+
+    const OFFICE: &str = "
+routers:
+  - name: R1
+    lan: {ip: 192.168.1.1, netmask: 24, dhcp: {first_ip: 192.168.1.100, last_ip: 192.168.1.110}}
+    wan: {ip: 10.0.0.1, netmask: 30}
+  - name: R2
+    lan: {ip: 192.168.2.1, netmask: 24}
+    wan: {ip: 10.0.0.2, netmask: 30}
+devices:
+  - name: PC1
+  - {name: PC2, ip: 192.168.2.10, netmask: 24}
+switches:
+  - name: SW1
+connections:
+  - {from: R1.wan, to: R2.wan}
+  - {from: R1.lan, to: SW1}
+  - {from: PC1, to: SW1}
+  - {from: PC2, to: R2.lan}
+";
+
+    fn data(content: &str) -> LoadedData {
+        parse_data("test", content, DataFormat::Yaml).unwrap_or_else(|e| panic!("{}", e))
+    }
+
+    // The kind and subject of every diagnostic
+    fn diagnostics(content: &str) -> Vec<(DiagnosticKind, String)> {
+        validate_data(&data(content)).diagnostics.into_iter().map(|d| (d.kind, d.subject)).collect()
+    }
+
+    fn has(diagnostics: &[(DiagnosticKind, String)], kind: DiagnosticKind, subject: &str) -> bool {
+        diagnostics.iter().any(|(k, s)| *k == kind && s == subject)
+    }
+
+    #[test]
+    fn a_consistent_topology_is_clean() {
+        let report = validate_data(&data(OFFICE));
+        assert!(report.diagnostics.is_empty() && report.exit_code() == 0);
+        let mut graph = Graph::new();
+        assert!(graph.load_data(data(OFFICE)).rejected().is_empty());
+        assert!(validate_graph(&graph).diagnostics.is_empty());
+    }
+
+    #[test]
+    fn addressing_mistakes_are_errors() {
+        let found = diagnostics(&OFFICE.replace("ip: 192.168.2.10", "ip: 192.168.2.1").replace("lan: {ip: 192.168.2.1, netmask: 24}", "lan: {ip: 192.168.1.1, netmask: 24}"));
+        assert!(has(&found, DiagnosticKind::DuplicateIp, "R2.lan"));
+        assert!(has(&found, DiagnosticKind::OverlappingSubnets, "R2.lan"));
+        let report = validate_data(&data(&OFFICE.replace("to: R2.lan}", "to: R9.lan}")));
+        assert!(report.has_errors() && report.exit_code() == 1);
+        assert!(report.diagnostics.iter().any(|d| d.kind == DiagnosticKind::InvalidEntry && d.message == "Unknown endpoint 'R9.lan'"));
+    }
+
+    #[test]
+    fn segments_hold_a_single_subnet() {
+        let mixed = OFFICE.replace("  - name: PC1\n", "  - name: PC1\n  - {name: PC3, ip: 172.20.0.1, netmask: 24}\n") + "  - {from: PC3, to: SW1}\n";
+        let found = diagnostics(&mixed);
+        assert!(has(&found, DiagnosticKind::InvalidEntry, "SW1"));
+        let crossed = OFFICE.replace("{from: PC2, to: R2.lan}", "{from: PC2, to: SW1}");
+        assert!(has(&diagnostics(&crossed), DiagnosticKind::InvalidEntry, "SW1"));
+    }
+
+    #[test]
+    fn doubtful_setups_are_warnings() {
+        let pc4 = "  - {name: PC4, ip: 192.168.1.105, netmask: 24}\nswitches:";
+        let found = validate_data(&data(&OFFICE.replacen("switches:", pc4, 1).replace("  - {from: PC1, to: SW1}\n", "")));
+        assert!(!found.has_errors());
+        let found: Vec<(DiagnosticKind, String)> = found.diagnostics.into_iter().map(|d| (d.kind, d.subject)).collect();
+        assert!(has(&found, DiagnosticKind::StaticAddressInDhcpPool, "PC4"));
+        assert!(has(&found, DiagnosticKind::IsolatedNode, "PC1") && has(&found, DiagnosticKind::IsolatedNode, "PC4"));
+        // PC1 is left without a DHCP server
+        assert!(has(&found, DiagnosticKind::Localhost, "PC1"));
+    }
+
+    #[test]
+    fn cut_off_subnets_are_reported() {
+        let found = diagnostics(&OFFICE.replace("  - {from: R1.wan, to: R2.wan}\n", ""));
+        assert!(has(&found, DiagnosticKind::UnreachableSubnet, "R2.lan") ^ has(&found, DiagnosticKind::UnreachableSubnet, "R1.lan"));
+        assert!(!found.iter().any(|(k, _)| *k == DiagnosticKind::IsolatedNode));
+    }
+}